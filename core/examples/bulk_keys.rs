@@ -4,13 +4,12 @@
 
 use keva_core::core::KevaCore;
 use keva_core::types::{Config, Key};
-use std::path::PathBuf;
 use std::time::SystemTime;
 
 const KEY_COUNT: usize = 9000;
 
 fn main() {
-    let base_path = get_default_data_path();
+    let base_path = Config::default_data_dir().expect("no data directory for this platform");
     println!("Using data path: {}", base_path.display());
 
     let config = Config {
@@ -56,13 +55,3 @@ fn main() {
         trashed.len()
     );
 }
-
-fn get_default_data_path() -> PathBuf {
-    #[cfg(windows)]
-    {
-        std::env::var("LOCALAPPDATA")
-            .map(PathBuf::from)
-            .expect("LOCALAPPDATA not set")
-            .join("keva")
-    }
-}
@@ -3,13 +3,13 @@
 //! Run with: `cargo run -q --example seed_data -p keva_core`
 
 use keva_core::core::KevaCore;
-use keva_core::types::{Config, Key};
+use keva_core::types::{AttachmentConflictResolution, AttachmentName, Config, Key};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::Path;
 use std::time::SystemTime;
 
 fn main() {
-    let base_path = get_default_data_path();
+    let base_path = Config::default_data_dir().expect("no data directory for this platform");
     println!("Using data path: {}", base_path.display());
 
     let config = Config {
@@ -38,7 +38,7 @@ fn main() {
     );
 }
 
-fn seed_content_keys(keva: &mut KevaCore, now: SystemTime, _base_path: &PathBuf) {
+fn seed_content_keys(keva: &mut KevaCore, now: SystemTime, _base_path: &Path) {
     let keys = [
         (
             "todo",
@@ -80,7 +80,7 @@ fn seed_content_keys(keva: &mut KevaCore, now: SystemTime, _base_path: &PathBuf)
     }
 }
 
-fn seed_attachment_keys(keva: &mut KevaCore, now: SystemTime, base_path: &PathBuf) {
+fn seed_attachment_keys(keva: &mut KevaCore, now: SystemTime, base_path: &Path) {
     let temp_dir = base_path.join("_seed_temp");
     std::fs::create_dir_all(&temp_dir).ok();
 
@@ -108,8 +108,9 @@ fn seed_attachment_keys(keva: &mut KevaCore, now: SystemTime, base_path: &PathBu
                 let files: Vec<_> = file_paths
                     .into_iter()
                     .map(|p| {
-                        let name = p.file_name().unwrap().to_string_lossy().into_owned();
-                        (p, name)
+                        let name =
+                            AttachmentName::sanitize(&p.file_name().unwrap().to_string_lossy());
+                        (p, name, AttachmentConflictResolution::Rename)
                     })
                     .collect();
                 let count = files.len();
@@ -129,7 +130,7 @@ fn seed_attachment_keys(keva: &mut KevaCore, now: SystemTime, base_path: &PathBu
     std::fs::remove_dir_all(&temp_dir).ok();
 }
 
-fn seed_trashed(keva: &mut KevaCore, now: SystemTime, _base_path: &PathBuf) {
+fn seed_trashed(keva: &mut KevaCore, now: SystemTime, _base_path: &Path) {
     let keys = [
         ("old-draft", "This is an old draft that was deleted"),
         ("deprecated/config", "old_setting=true"),
@@ -155,13 +156,3 @@ fn seed_trashed(keva: &mut KevaCore, now: SystemTime, _base_path: &PathBuf) {
         }
     }
 }
-
-fn get_default_data_path() -> PathBuf {
-    #[cfg(windows)]
-    {
-        std::env::var("LOCALAPPDATA")
-            .map(PathBuf::from)
-            .expect("LOCALAPPDATA not set")
-            .join("keva")
-    }
-}
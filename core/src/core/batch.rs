@@ -0,0 +1,191 @@
+//! Grouping several key operations into one transaction.
+
+use super::KevaCore;
+use crate::core::db::Transaction;
+use crate::core::error::KevaError;
+use crate::core::file_storage::FileStorage;
+use crate::core::file_storage::error::FileStorageError;
+use crate::types::Key;
+use crate::types::value::PublicValue as Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// A change applied by a [`Batch`], in the order it was made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Created(Key),
+    Touched(Key),
+    Renamed { from: Key, to: Key },
+    Trashed(Key),
+    Restored(Key),
+    Purged(Key),
+}
+
+/// File operation deferred until the batch commits.
+enum FileOp {
+    CreateContent(PathBuf),
+    RemoveAll(PathBuf),
+    RenameAll(PathBuf, PathBuf),
+}
+
+impl FileOp {
+    fn apply(&self, file: &FileStorage) -> Result<(), FileStorageError> {
+        match self {
+            FileOp::CreateContent(key_hash) => file.create_content(key_hash),
+            FileOp::RemoveAll(key_hash) => file.remove_all(key_hash),
+            FileOp::RenameAll(old_hash, new_hash) => file.rename_all(old_hash, new_hash),
+        }
+    }
+}
+
+/// Key operations sharing a single write transaction, created by [`KevaCore::batch`].
+///
+/// Reads see the batch's own uncommitted changes, and report sealed keys as locked. File system
+/// changes are only made once the transaction has committed.
+pub struct Batch {
+    tx: Transaction,
+    file_ops: Vec<FileOp>,
+    changes: Vec<Change>,
+}
+
+impl Batch {
+    pub fn get(&self, key: &Key) -> Result<Option<Value>, KevaError> {
+        let value = self.tx.get(key)?;
+        Ok(value.map(Value::from_latest_value))
+    }
+
+    pub fn create(&mut self, key: &Key, now: SystemTime) -> Result<Value, KevaError> {
+        let value = self.tx.create(key, now)?;
+        self.file_ops
+            .push(FileOp::CreateContent(KevaCore::key_to_path(key)));
+        self.changes.push(Change::Created(key.clone()));
+        Ok(Value::from_latest_value(value))
+    }
+
+    /// Updates last_accessed timestamp and increments the access count.
+    pub fn touch(&mut self, key: &Key, now: SystemTime) -> Result<Value, KevaError> {
+        let value = self.tx.touch(key, now)?;
+        self.changes.push(Change::Touched(key.clone()));
+        Ok(Value::from_latest_value(value))
+    }
+
+    pub fn rename(
+        &mut self,
+        old_key: &Key,
+        new_key: &Key,
+        now: SystemTime,
+    ) -> Result<(), KevaError> {
+        if old_key == new_key {
+            return Ok(());
+        }
+
+        if self.tx.get(new_key)?.is_some() {
+            return Err(KevaError::DestinationExists);
+        }
+
+        self.tx.rename(old_key, new_key, now)?;
+        self.file_ops.push(FileOp::RenameAll(
+            KevaCore::key_to_path(old_key),
+            KevaCore::key_to_path(new_key),
+        ));
+        self.changes.push(Change::Renamed {
+            from: old_key.clone(),
+            to: new_key.clone(),
+        });
+        Ok(())
+    }
+
+    /// Moves a key to trash.
+    pub fn trash(&mut self, key: &Key, now: SystemTime) -> Result<(), KevaError> {
+        self.tx.trash(key, now)?;
+        self.changes.push(Change::Trashed(key.clone()));
+        Ok(())
+    }
+
+    /// Restores a key from trash.
+    pub fn restore(&mut self, key: &Key, now: SystemTime) -> Result<(), KevaError> {
+        self.tx.restore(key, now)?;
+        self.changes.push(Change::Restored(key.clone()));
+        Ok(())
+    }
+
+    /// Permanently deletes a key.
+    pub fn purge(&mut self, key: &Key) -> Result<(), KevaError> {
+        self.tx.purge(key)?;
+        self.file_ops
+            .push(FileOp::RemoveAll(KevaCore::key_to_path(key)));
+        self.changes.push(Change::Purged(key.clone()));
+        Ok(())
+    }
+}
+
+/// Batch operations.
+impl KevaCore {
+    /// Runs `f` against a single write transaction.
+    ///
+    /// All operations succeed or none do: if `f` returns an error the transaction is aborted and
+    /// no files are touched. On success, deferred file operations run after commit and the
+    /// changes are returned in order, e.g. for updating a search index.
+    ///
+    /// Every file operation is attempted even if an earlier one fails; failures are then returned
+    /// together as `Err(FilesNotUpdated)`, with the database changes already committed.
+    pub fn batch<T>(
+        &mut self,
+        f: impl FnOnce(&mut Batch) -> Result<T, KevaError>,
+    ) -> Result<(T, Vec<Change>), KevaError> {
+        let mut batch = Batch {
+            tx: self.db.begin()?,
+            file_ops: Vec::new(),
+            changes: Vec::new(),
+        };
+
+        let output = f(&mut batch)?;
+
+        let Batch {
+            tx,
+            file_ops,
+            changes,
+        } = batch;
+        tx.commit()?;
+
+        // Pending thumbnails are cancelled before their files move, and follow their key through
+        // later renames of the batch.
+        let mut pending: HashMap<Key, Vec<String>> = HashMap::new();
+        for change in &changes {
+            match change {
+                Change::Renamed { from, to } => {
+                    let mut filenames = pending.remove(from).unwrap_or_default();
+                    filenames.extend(self.thumbnails.cancel_key(from));
+                    pending.insert(to.clone(), filenames);
+                }
+                Change::Purged(key) => {
+                    self.thumbnails.cancel_key(key);
+                    pending.remove(key);
+                }
+                _ => {}
+            }
+        }
+
+        let errors: Vec<FileStorageError> = file_ops
+            .iter()
+            .filter_map(|op| op.apply(&self.file).err())
+            .collect();
+
+        for change in &changes {
+            match change {
+                Change::Renamed { from, to } => {
+                    let filenames = pending.remove(to).unwrap_or_default();
+                    self.key_moved(from, to, filenames);
+                }
+                Change::Purged(key) => self.key_purged(key),
+                _ => {}
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(KevaError::FilesNotUpdated(errors));
+        }
+        Ok((output, changes))
+    }
+}
@@ -0,0 +1,478 @@
+//! Encryption at rest.
+//!
+//! A random data key encrypts redb values and files with XChaCha20-Poly1305. The data key is
+//! wrapped by a key derived from the passphrase with Argon2id and stored in the key file, so
+//! changing the passphrase only rewrites the key file.
+//!
+//! Every ciphertext starts with the id of the data key that produced it. During key rotation the
+//! key file holds both the new and the retired key, so a store interrupted mid-rotation stays
+//! readable.
+//!
+//! Formats:
+//! - Sealed value: `key_id (8) | nonce (24) | ciphertext`
+//! - File: `"KVE1" | key_id (8) | stream nonce (19) | chunks`, each chunk holding up to 64 KiB of
+//!   plaintext (STREAM construction, so truncation and reordering are detected)
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use error::CryptoError;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum CryptoError {
+        #[error("IO error: {0}")]
+        Io(#[from] std::io::Error),
+
+        #[error("Wrong passphrase")]
+        WrongPassphrase,
+
+        #[error("Key derivation failed: {0}")]
+        KeyDerivation(argon2::Error),
+
+        #[error("Invalid key file")]
+        InvalidKeyFile,
+
+        #[error("Data is encrypted with an unknown key")]
+        UnknownKey,
+
+        #[error("Decryption failed: data is corrupted or was tampered with")]
+        Corrupted,
+    }
+}
+
+type KeyId = [u8; KEY_ID_LEN];
+
+const KEY_ID_LEN: usize = 8;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const STREAM_NONCE_LEN: usize = 19;
+const TAG_LEN: usize = 16;
+const CHUNK_LEN: usize = 64 * 1024;
+const FILE_MAGIC: &[u8; 4] = b"KVE1";
+const KEY_FILE_VERSION: u8 = 1;
+
+/// A data key and its id.
+#[derive(Clone)]
+struct DataKey {
+    id: KeyId,
+    key: chacha20poly1305::Key,
+}
+
+impl DataKey {
+    fn generate() -> Self {
+        let mut id = [0; KEY_ID_LEN];
+        OsRng.fill_bytes(&mut id);
+        Self {
+            id,
+            key: XChaCha20Poly1305::generate_key(&mut OsRng),
+        }
+    }
+
+    fn aead(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.key)
+    }
+}
+
+/// Key file contents. The first wrapped key is the current one; any others are retired keys
+/// kept until a rotation completes.
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    version: u8,
+    salt: [u8; SALT_LEN],
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    keys: Vec<WrappedKey>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WrappedKey {
+    id: KeyId,
+    nonce: [u8; NONCE_LEN],
+    ciphertext: Vec<u8>,
+}
+
+/// Argon2id cost parameters for new key files.
+fn kdf_params() -> argon2::Params {
+    if cfg!(test) {
+        // Keep the test suite fast; real stores use the library defaults.
+        argon2::Params::new(64, 1, 1, Some(32)).expect("valid argon2 params")
+    } else {
+        argon2::Params::default()
+    }
+}
+
+fn derive_wrapping_key(
+    passphrase: &str,
+    salt: &[u8],
+    params: argon2::Params,
+) -> Result<XChaCha20Poly1305, CryptoError> {
+    let argon2 = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+    let mut key = chacha20poly1305::Key::default();
+    argon2
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(CryptoError::KeyDerivation)?;
+    Ok(XChaCha20Poly1305::new(&key))
+}
+
+/// Encrypts and decrypts store data with the unlocked data keys.
+#[derive(Clone)]
+pub struct Cipher {
+    /// The current key first, followed by retired keys.
+    keys: Vec<DataKey>,
+}
+
+/// Key file operations.
+impl Cipher {
+    /// Generates a new data key and writes a key file protected by `passphrase`.
+    pub fn create(key_file: &Path, passphrase: &str) -> Result<Self, CryptoError> {
+        let cipher = Self::generate();
+        cipher.save(key_file, passphrase)?;
+        Ok(cipher)
+    }
+
+    /// Unlocks the data keys in `key_file`.
+    ///
+    /// Returns `Err(WrongPassphrase)` if `passphrase` doesn't match.
+    pub fn unlock(key_file: &Path, passphrase: &str) -> Result<Self, CryptoError> {
+        Self::unwrap(&std::fs::read(key_file)?, passphrase)
+    }
+
+    /// Writes all data keys to `key_file`, wrapped under `passphrase` with a fresh salt.
+    ///
+    /// The file is replaced atomically.
+    pub fn save(&self, key_file: &Path, passphrase: &str) -> Result<(), CryptoError> {
+        let bytes = self.wrap(passphrase)?;
+
+        let tmp_path = key_file.with_extension("tmp");
+        {
+            let mut tmp = std::fs::File::create(&tmp_path)?;
+            tmp.write_all(&bytes)?;
+            tmp.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, key_file)?;
+        Ok(())
+    }
+}
+
+/// Key wrapping.
+impl Cipher {
+    /// Generates a cipher with a single new data key.
+    pub fn generate() -> Self {
+        Self {
+            keys: vec![DataKey::generate()],
+        }
+    }
+
+    /// Unwraps data keys produced by [`Cipher::wrap`].
+    ///
+    /// Returns `Err(WrongPassphrase)` if `passphrase` doesn't match.
+    pub fn unwrap(bytes: &[u8], passphrase: &str) -> Result<Self, CryptoError> {
+        let file: KeyFile = postcard::from_bytes(bytes).map_err(|_| CryptoError::InvalidKeyFile)?;
+        if file.version != KEY_FILE_VERSION || file.keys.is_empty() {
+            return Err(CryptoError::InvalidKeyFile);
+        }
+
+        let params = argon2::Params::new(file.m_cost, file.t_cost, file.p_cost, Some(32))
+            .map_err(|_| CryptoError::InvalidKeyFile)?;
+        let wrapping = derive_wrapping_key(passphrase, &file.salt, params)?;
+
+        let keys = file
+            .keys
+            .iter()
+            .map(|wrapped| {
+                let key = wrapping
+                    .decrypt(
+                        XNonce::from_slice(&wrapped.nonce),
+                        wrapped.ciphertext.as_slice(),
+                    )
+                    .map_err(|_| CryptoError::WrongPassphrase)?;
+                if key.len() != 32 {
+                    return Err(CryptoError::InvalidKeyFile);
+                }
+                Ok(DataKey {
+                    id: wrapped.id,
+                    key: *chacha20poly1305::Key::from_slice(&key),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { keys })
+    }
+
+    /// Wraps all data keys under `passphrase` with a fresh salt.
+    pub fn wrap(&self, passphrase: &str) -> Result<Vec<u8>, CryptoError> {
+        let params = kdf_params();
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let wrapping = derive_wrapping_key(passphrase, &salt, params.clone())?;
+
+        let keys = self
+            .keys
+            .iter()
+            .map(|data_key| {
+                let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+                let ciphertext = wrapping
+                    .encrypt(&nonce, data_key.key.as_slice())
+                    .expect("in-memory encryption cannot fail");
+                WrappedKey {
+                    id: data_key.id,
+                    nonce: nonce.into(),
+                    ciphertext,
+                }
+            })
+            .collect();
+
+        let file = KeyFile {
+            version: KEY_FILE_VERSION,
+            salt,
+            m_cost: params.m_cost(),
+            t_cost: params.t_cost(),
+            p_cost: params.p_cost(),
+            keys,
+        };
+        Ok(postcard::to_allocvec(&file).expect("key file serialization cannot fail"))
+    }
+
+    /// Returns a cipher with a newly generated current key, keeping the existing keys as retired.
+    pub fn with_new_key(&self) -> Self {
+        let mut keys = vec![DataKey::generate()];
+        keys.extend(self.keys.iter().cloned());
+        Self { keys }
+    }
+
+    /// Returns a cipher holding only the current key.
+    pub fn without_retired_keys(&self) -> Self {
+        Self {
+            keys: vec![self.keys[0].clone()],
+        }
+    }
+
+    fn current(&self) -> &DataKey {
+        &self.keys[0]
+    }
+
+    fn find(&self, id: &[u8]) -> Result<&DataKey, CryptoError> {
+        self.keys
+            .iter()
+            .find(|key| key.id == id)
+            .ok_or(CryptoError::UnknownKey)
+    }
+}
+
+/// Value operations.
+impl Cipher {
+    /// Encrypts a small value in one piece.
+    pub fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let key = self.current();
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = key
+            .aead()
+            .encrypt(&nonce, plaintext)
+            .expect("in-memory encryption cannot fail");
+
+        let mut sealed = Vec::with_capacity(KEY_ID_LEN + NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&key.id);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    /// Decrypts a value produced by [`Cipher::seal`].
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        if sealed.len() < KEY_ID_LEN + NONCE_LEN {
+            return Err(CryptoError::Corrupted);
+        }
+        let (id, rest) = sealed.split_at(KEY_ID_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        self.find(id)?
+            .aead()
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| CryptoError::Corrupted)
+    }
+}
+
+/// Stream operations.
+impl Cipher {
+    /// Writes the file header to `inner` and returns a writer encrypting everything after it.
+    ///
+    /// [`EncryptWriter::finish`] must be called to write the final chunk.
+    pub fn encrypt_writer<W: Write>(&self, mut inner: W) -> io::Result<EncryptWriter<W>> {
+        let key = self.current();
+        let mut nonce = [0; STREAM_NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+
+        inner.write_all(FILE_MAGIC)?;
+        inner.write_all(&key.id)?;
+        inner.write_all(&nonce)?;
+
+        Ok(EncryptWriter {
+            inner,
+            encryptor: EncryptorBE32::from_aead(key.aead(), (&nonce).into()),
+            buffer: Vec::with_capacity(CHUNK_LEN),
+        })
+    }
+
+    /// Reads the file header from `inner` and returns a reader decrypting the rest.
+    pub fn decrypt_reader<R: Read>(&self, mut inner: R) -> Result<DecryptReader<R>, CryptoError> {
+        let id = read_header(&mut inner)?.ok_or(CryptoError::Corrupted)?;
+        let mut nonce = [0; STREAM_NONCE_LEN];
+        inner.read_exact(&mut nonce)?;
+
+        let key = self.find(&id)?;
+        Ok(DecryptReader {
+            inner,
+            decryptor: Some(DecryptorBE32::from_aead(key.aead(), (&nonce).into())),
+            lookahead: None,
+            plaintext: Vec::new(),
+            position: 0,
+        })
+    }
+
+    /// Returns whether the file at `path` is encrypted with a key other than the current one.
+    pub fn needs_reencryption(&self, path: &Path) -> Result<bool, CryptoError> {
+        let mut file = std::fs::File::open(path)?;
+        let id = read_header(&mut file)?.ok_or(CryptoError::Corrupted)?;
+        Ok(id != self.current().id)
+    }
+}
+
+/// Returns whether the file at `path` starts with the encrypted file header.
+pub fn is_encrypted_file(path: &Path) -> io::Result<bool> {
+    let mut magic = Vec::with_capacity(FILE_MAGIC.len());
+    std::fs::File::open(path)?
+        .take(FILE_MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    Ok(magic == FILE_MAGIC)
+}
+
+/// Returns `N` bytes from the operating system's random number generator.
+pub fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/// Reads the magic and key id, returning `None` if the magic doesn't match.
+fn read_header(inner: &mut impl Read) -> io::Result<Option<KeyId>> {
+    let mut magic = [0; FILE_MAGIC.len()];
+    inner.read_exact(&mut magic)?;
+    if &magic != FILE_MAGIC {
+        return Ok(None);
+    }
+    let mut id = [0; KEY_ID_LEN];
+    inner.read_exact(&mut id)?;
+    Ok(Some(id))
+}
+
+fn stream_error(_: chacha20poly1305::aead::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, CryptoError::Corrupted)
+}
+
+/// Encrypts written data in fixed-size chunks.
+pub struct EncryptWriter<W: Write> {
+    inner: W,
+    encryptor: EncryptorBE32<XChaCha20Poly1305>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> EncryptWriter<W> {
+    /// Writes the final chunk and returns the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        let Self {
+            mut inner,
+            encryptor,
+            buffer,
+        } = self;
+        let ciphertext = encryptor
+            .encrypt_last(buffer.as_slice())
+            .map_err(stream_error)?;
+        inner.write_all(&ciphertext)?;
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        // Only emit a chunk once more data follows it, so the last chunk is never empty unless
+        // the whole stream is.
+        while self.buffer.len() > CHUNK_LEN {
+            let ciphertext = self
+                .encryptor
+                .encrypt_next(&self.buffer[..CHUNK_LEN])
+                .map_err(stream_error)?;
+            self.inner.write_all(&ciphertext)?;
+            self.buffer.drain(..CHUNK_LEN);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts a stream written by [`EncryptWriter`].
+pub struct DecryptReader<R: Read> {
+    inner: R,
+    /// `None` once the last chunk has been decrypted.
+    decryptor: Option<DecryptorBE32<XChaCha20Poly1305>>,
+    /// First byte of the next chunk, read ahead to detect the last chunk.
+    lookahead: Option<u8>,
+    plaintext: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> DecryptReader<R> {
+    fn read_chunk(&mut self, decryptor: DecryptorBE32<XChaCha20Poly1305>) -> io::Result<()> {
+        // Read one byte past a full chunk to tell whether this chunk is the last one.
+        let full = CHUNK_LEN + TAG_LEN;
+        let mut chunk = Vec::with_capacity(full + 1);
+        chunk.extend(self.lookahead.take());
+        let remaining = (full + 1 - chunk.len()) as u64;
+        (&mut self.inner).take(remaining).read_to_end(&mut chunk)?;
+
+        if chunk.len() > full {
+            self.lookahead = chunk.pop();
+            let mut decryptor = decryptor;
+            self.plaintext = decryptor
+                .decrypt_next(chunk.as_slice())
+                .map_err(stream_error)?;
+            self.decryptor = Some(decryptor);
+        } else {
+            self.plaintext = decryptor
+                .decrypt_last(chunk.as_slice())
+                .map_err(stream_error)?;
+        }
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plaintext.len() {
+            let Some(decryptor) = self.decryptor.take() else {
+                return Ok(0);
+            };
+            self.read_chunk(decryptor)?;
+        }
+
+        let n = buf.len().min(self.plaintext.len() - self.position);
+        buf[..n].copy_from_slice(&self.plaintext[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests;
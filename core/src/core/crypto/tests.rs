@@ -0,0 +1,201 @@
+use super::*;
+use tempfile::TempDir;
+
+fn encrypt(cipher: &Cipher, plaintext: &[u8]) -> Vec<u8> {
+    let mut writer = cipher.encrypt_writer(Vec::new()).unwrap();
+    writer.write_all(plaintext).unwrap();
+    writer.finish().unwrap()
+}
+
+fn decrypt(cipher: &Cipher, ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+    let mut reader = cipher.decrypt_reader(ciphertext).unwrap();
+    let mut plaintext = Vec::new();
+    reader.read_to_end(&mut plaintext)?;
+    Ok(plaintext)
+}
+
+mod key_file {
+    use super::*;
+
+    #[test]
+    fn test_unlock_with_correct_passphrase() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("keyfile");
+
+        let cipher = Cipher::create(&path, "secret").unwrap();
+        let sealed = cipher.seal(b"hello");
+
+        let unlocked = Cipher::unlock(&path, "secret").unwrap();
+        assert_eq!(unlocked.open(&sealed).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_unlock_with_wrong_passphrase() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("keyfile");
+
+        Cipher::create(&path, "secret").unwrap();
+
+        let result = Cipher::unlock(&path, "wrong");
+        assert!(matches!(result, Err(CryptoError::WrongPassphrase)));
+    }
+
+    #[test]
+    fn test_unlock_invalid_key_file() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("keyfile");
+        std::fs::write(&path, b"garbage").unwrap();
+
+        let result = Cipher::unlock(&path, "secret");
+        assert!(matches!(result, Err(CryptoError::InvalidKeyFile)));
+    }
+
+    #[test]
+    fn test_wrap_roundtrip() {
+        let cipher = Cipher::generate();
+        let sealed = cipher.seal(b"hello");
+
+        let wrapped = cipher.wrap("secret").unwrap();
+        assert!(matches!(
+            Cipher::unwrap(&wrapped, "wrong"),
+            Err(CryptoError::WrongPassphrase)
+        ));
+        let unwrapped = Cipher::unwrap(&wrapped, "secret").unwrap();
+        assert_eq!(unwrapped.open(&sealed).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_save_with_new_passphrase() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("keyfile");
+
+        let cipher = Cipher::create(&path, "old").unwrap();
+        let sealed = cipher.seal(b"hello");
+        cipher.save(&path, "new").unwrap();
+
+        assert!(Cipher::unlock(&path, "old").is_err());
+        let unlocked = Cipher::unlock(&path, "new").unwrap();
+        assert_eq!(unlocked.open(&sealed).unwrap(), b"hello");
+    }
+}
+
+mod seal {
+    use super::*;
+
+    #[test]
+    fn test_seal_roundtrip() {
+        let cipher = Cipher {
+            keys: vec![DataKey::generate()],
+        };
+
+        let sealed = cipher.seal(b"value");
+        assert_ne!(&sealed[KEY_ID_LEN + NONCE_LEN..], b"value");
+        assert_eq!(cipher.open(&sealed).unwrap(), b"value");
+    }
+
+    #[test]
+    fn test_open_detects_tampering() {
+        let cipher = Cipher {
+            keys: vec![DataKey::generate()],
+        };
+
+        let mut sealed = cipher.seal(b"value");
+        *sealed.last_mut().unwrap() ^= 1;
+
+        assert!(matches!(cipher.open(&sealed), Err(CryptoError::Corrupted)));
+    }
+
+    #[test]
+    fn test_open_with_unknown_key() {
+        let cipher = Cipher {
+            keys: vec![DataKey::generate()],
+        };
+        let other = Cipher {
+            keys: vec![DataKey::generate()],
+        };
+
+        let sealed = cipher.seal(b"value");
+        assert!(matches!(other.open(&sealed), Err(CryptoError::UnknownKey)));
+    }
+
+    #[test]
+    fn test_retired_key_still_opens() {
+        let cipher = Cipher {
+            keys: vec![DataKey::generate()],
+        };
+        let sealed = cipher.seal(b"value");
+
+        let rotated = cipher.with_new_key();
+        assert_eq!(rotated.open(&sealed).unwrap(), b"value");
+
+        let finished = rotated.without_retired_keys();
+        assert!(matches!(
+            finished.open(&sealed),
+            Err(CryptoError::UnknownKey)
+        ));
+    }
+}
+
+mod stream {
+    use super::*;
+
+    #[test]
+    fn test_stream_roundtrip_sizes() {
+        let cipher = Cipher {
+            keys: vec![DataKey::generate()],
+        };
+
+        for len in [0, 1, CHUNK_LEN - 1, CHUNK_LEN, CHUNK_LEN + 1, 3 * CHUNK_LEN] {
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let ciphertext = encrypt(&cipher, &plaintext);
+            assert_eq!(
+                decrypt(&cipher, &ciphertext).unwrap(),
+                plaintext,
+                "len {len}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_stream_detects_truncation() {
+        let cipher = Cipher {
+            keys: vec![DataKey::generate()],
+        };
+
+        let plaintext = vec![7u8; 2 * CHUNK_LEN + 10];
+        let ciphertext = encrypt(&cipher, &plaintext);
+
+        // Drop the final chunk; the previous chunk was not sealed as the last one.
+        let header = FILE_MAGIC.len() + KEY_ID_LEN + STREAM_NONCE_LEN;
+        let truncated = &ciphertext[..header + 2 * (CHUNK_LEN + TAG_LEN)];
+        assert!(decrypt(&cipher, truncated).is_err());
+    }
+
+    #[test]
+    fn test_stream_detects_tampering() {
+        let cipher = Cipher {
+            keys: vec![DataKey::generate()],
+        };
+
+        let mut ciphertext = encrypt(&cipher, b"some content");
+        let last = ciphertext.len() - 1;
+        ciphertext[last] ^= 1;
+
+        assert!(decrypt(&cipher, &ciphertext).is_err());
+    }
+
+    #[test]
+    fn test_needs_reencryption() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("file");
+        let cipher = Cipher {
+            keys: vec![DataKey::generate()],
+        };
+
+        std::fs::write(&path, encrypt(&cipher, b"data")).unwrap();
+        assert!(!cipher.needs_reencryption(&path).unwrap());
+
+        let rotated = cipher.with_new_key();
+        assert!(rotated.needs_reencryption(&path).unwrap());
+    }
+}
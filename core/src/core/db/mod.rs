@@ -5,15 +5,14 @@
 //! - TTL tracking tables for garbage collection
 //! - Metadata storage (JSON strings)
 
+use crate::core::crypto::Cipher;
+use crate::core::crypto::error::CryptoError;
 use crate::core::db::error::DatabaseError;
 use crate::core::db::ttl_table::TtlTable;
-use crate::core::file_storage::FileStorage;
 use crate::types::metadata::MaintenanceMetadata;
-use crate::types::value::versioned_value::VersionedValue;
-use crate::types::value::versioned_value::latest_value::{
-    Attachment, LifecycleState, Metadata, Value,
-};
-use crate::types::{Config, GcConfig, Key, TtlKey};
+use crate::types::value::versioned_value::latest_value::{Attachment, LifecycleState, Value};
+use crate::types::value::versioned_value::{VersionedValue, v2, v3, v4};
+use crate::types::{AccessStats, Config, GcConfig, Key, TtlKey};
 use redb::{ReadableDatabase, ReadableTable, TableDefinition};
 use std::time::{Duration, SystemTime};
 
@@ -40,6 +39,9 @@ pub mod error {
         #[error("IO error: {0}")]
         Io(#[from] std::io::Error),
 
+        #[error("Crypto error: {0}")]
+        Crypto(#[from] crate::core::crypto::error::CryptoError),
+
         #[error("Key not found")]
         NotFound,
 
@@ -57,11 +59,19 @@ pub mod error {
 
         #[error("Attachment already exists: {0}")]
         AttachmentExists(String),
+
+        #[error("Operation log compacted; oldest retained sequence is {oldest}")]
+        OplogCompacted { oldest: u64 },
     }
 }
 
+mod oplog;
+mod transaction;
 mod ttl_table;
 
+pub use oplog::{OpLogEntry, Operation};
+pub use transaction::Transaction;
+
 /// Main table: Key → VersionedValue
 const MAIN_TABLE: TableDefinition<Key, VersionedValue> = TableDefinition::new("main");
 
@@ -71,6 +81,12 @@ const METADATA_TABLE: TableDefinition<&str, &str> = TableDefinition::new("metada
 /// Metadata key for maintenance tracking.
 const METADATA_KEY_MAINTENANCE: &str = "maintenance";
 
+/// Metadata key for the generation counter.
+const METADATA_KEY_GENERATION: &str = "generation";
+
+/// Metadata key for the random id identifying this store to sync peers.
+const METADATA_KEY_STORE_ID: &str = "store_id";
+
 /// TTL table tracking when Active keys expire to Trash.
 const ACTIVE_EXPIRY: TtlTable = TtlTable::new("ttl_trashed");
 
@@ -80,6 +96,7 @@ const TRASH_EXPIRY: TtlTable = TtlTable::new("ttl_purged");
 /// The main database struct wrapping redb.
 pub struct Database {
     db: redb::Database,
+    codec: Codec,
 }
 
 /// Result of garbage collection.
@@ -93,7 +110,9 @@ pub struct GcResult {
 
 impl Database {
     /// Creates or opens a database using paths and settings from the config.
-    pub fn new(config: Config) -> Result<Self, DatabaseError> {
+    ///
+    /// Values are sealed with `cipher` when the store is encrypted.
+    pub fn new(config: Config, cipher: Option<Cipher>) -> Result<Self, DatabaseError> {
         std::fs::create_dir_all(&config.base_path)?;
 
         let db = redb::Database::create(config.db_path())?;
@@ -103,12 +122,16 @@ impl Database {
         {
             let _ = write_txn.open_table(MAIN_TABLE)?;
             let _ = write_txn.open_table(METADATA_TABLE)?;
+            let _ = write_txn.open_table(oplog::OPLOG_TABLE)?;
             ACTIVE_EXPIRY.init(&write_txn)?;
             TRASH_EXPIRY.init(&write_txn)?;
         }
         write_txn.commit()?;
 
-        Ok(Self { db })
+        Ok(Self {
+            db,
+            codec: Codec { cipher },
+        })
     }
 }
 
@@ -118,29 +141,24 @@ impl Database {
     ///
     /// Returns `Err(AlreadyExists)` if the key already exists.
     pub fn create(&mut self, key: &Key, now: SystemTime) -> Result<Value, DatabaseError> {
-        let write_txn = self.db.begin_write()?;
-
-        let new_value = Value {
-            metadata: Metadata {
-                lifecycle_state: LifecycleState::Active { last_accessed: now },
-            },
-            attachments: vec![],
-            thumb_version: FileStorage::THUMB_VER,
-        };
-
-        {
-            let mut main_table = write_txn.open_table(MAIN_TABLE)?;
-
-            if main_table.get(key)?.is_some() {
-                return Err(DatabaseError::AlreadyExists);
-            }
-
-            Self::insert_active_ttl(&write_txn, key, now)?;
-            main_table.insert(key, &VersionedValue::V1(new_value.clone()))?;
-        }
+        let mut tx = self.begin()?;
+        let value = tx.create(key, now)?;
+        tx.commit()?;
+        Ok(value)
+    }
 
-        write_txn.commit()?;
-        Ok(new_value)
+    /// Puts back a previously purged value unchanged.
+    ///
+    /// Returns `Err(AlreadyExists)` if the key already exists.
+    pub fn reinsert(
+        &mut self,
+        key: &Key,
+        value: Value,
+        now: SystemTime,
+    ) -> Result<(), DatabaseError> {
+        let mut tx = self.begin()?;
+        tx.reinsert(key, value, now)?;
+        tx.commit()
     }
 }
 
@@ -153,7 +171,7 @@ impl Database {
 
         match table.get(key)? {
             None => Ok(None),
-            Some(guard) => Ok(Some(Self::extract_latest(guard.value()))),
+            Some(guard) => Ok(Some(self.codec.decode(guard.value())?)),
         }
     }
 
@@ -168,40 +186,63 @@ impl Database {
         let read_txn = self.db.begin_read()?;
         TRASH_EXPIRY.all_keys(&read_txn)
     }
+
+    /// Returns all sealed keys, Active or Trash.
+    pub fn sealed_keys(&self) -> Result<Vec<Key>, DatabaseError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(MAIN_TABLE)?;
+        let mut keys = Vec::new();
+
+        for entry in table.iter()? {
+            let (key_guard, value_guard) = entry?;
+            if self
+                .codec
+                .decode(value_guard.value())?
+                .metadata
+                .seal
+                .is_some()
+            {
+                keys.push(key_guard.value());
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Returns access statistics for all Active keys in a single table scan.
+    pub fn access_stats(&self) -> Result<Vec<(Key, AccessStats)>, DatabaseError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(MAIN_TABLE)?;
+        let mut stats = Vec::new();
+
+        for entry in table.iter()? {
+            let (key_guard, value_guard) = entry?;
+            let value = self.codec.decode(value_guard.value())?;
+            if let LifecycleState::Active { last_accessed } = value.metadata.lifecycle_state {
+                stats.push((
+                    key_guard.value(),
+                    AccessStats {
+                        last_accessed,
+                        access_count: value.metadata.access_count,
+                    },
+                ));
+            }
+        }
+
+        Ok(stats)
+    }
 }
 
 /// Update operations.
 impl Database {
-    /// Updates `last_accessed` timestamp only.
+    /// Updates `last_accessed` timestamp and increments `access_count`.
     ///
     /// Returns `Err(NotFound)` if the key doesn't exist.
     /// Returns `Err(Trashed)` if the key is trashed.
     pub fn touch(&mut self, key: &Key, now: SystemTime) -> Result<Value, DatabaseError> {
-        let write_txn = self.db.begin_write()?;
-
-        let mut value;
-
-        {
-            let mut main_table = write_txn.open_table(MAIN_TABLE)?;
-
-            value = main_table
-                .get(key)?
-                .map(|g| Self::extract_latest(g.value()))
-                .ok_or(DatabaseError::NotFound)?;
-
-            let LifecycleState::Active { last_accessed } = value.metadata.lifecycle_state else {
-                return Err(DatabaseError::Trashed);
-            };
-
-            Self::remove_active_ttl(&write_txn, key, last_accessed)?;
-            Self::insert_active_ttl(&write_txn, key, now)?;
-
-            value.metadata.lifecycle_state = LifecycleState::Active { last_accessed: now };
-
-            main_table.insert(key, &VersionedValue::V1(value.clone()))?;
-        }
-
-        write_txn.commit()?;
+        let mut tx = self.begin()?;
+        let value = tx.touch(key, now)?;
+        tx.commit()?;
         Ok(value)
     }
 
@@ -216,13 +257,15 @@ impl Database {
         now: SystemTime,
     ) -> Result<(), DatabaseError> {
         let write_txn = self.db.begin_write()?;
+        let filename = attachment.filename.clone();
 
         {
             let mut main_table = write_txn.open_table(MAIN_TABLE)?;
 
             let mut value = main_table
                 .get(key)?
-                .map(|g| Self::extract_latest(g.value()))
+                .map(|g| self.codec.decode(g.value()))
+                .transpose()?
                 .ok_or(DatabaseError::NotFound)?;
 
             let LifecycleState::Active { last_accessed } = value.metadata.lifecycle_state else {
@@ -236,8 +279,17 @@ impl Database {
 
             value.metadata.lifecycle_state = LifecycleState::Active { last_accessed: now };
 
-            main_table.insert(key, &VersionedValue::V1(value))?;
+            main_table.insert(key, &self.codec.encode(value))?;
         }
+        Self::append_op(
+            &write_txn,
+            &self.codec,
+            now,
+            Operation::AttachmentAdded {
+                key: key.clone(),
+                filename,
+            },
+        )?;
 
         write_txn.commit()?;
         Ok(())
@@ -261,7 +313,8 @@ impl Database {
 
             let mut value = main_table
                 .get(key)?
-                .map(|g| Self::extract_latest(g.value()))
+                .map(|g| self.codec.decode(g.value()))
+                .transpose()?
                 .ok_or(DatabaseError::NotFound)?;
 
             let LifecycleState::Active { last_accessed } = value.metadata.lifecycle_state else {
@@ -281,8 +334,17 @@ impl Database {
 
             value.metadata.lifecycle_state = LifecycleState::Active { last_accessed: now };
 
-            main_table.insert(key, &VersionedValue::V1(value))?;
+            main_table.insert(key, &self.codec.encode(value))?;
         }
+        Self::append_op(
+            &write_txn,
+            &self.codec,
+            now,
+            Operation::AttachmentRemoved {
+                key: key.clone(),
+                filename: filename.to_string(),
+            },
+        )?;
 
         write_txn.commit()?;
         Ok(())
@@ -309,7 +371,8 @@ impl Database {
 
             let mut value = main_table
                 .get(key)?
-                .map(|g| Self::extract_latest(g.value()))
+                .map(|g| self.codec.decode(g.value()))
+                .transpose()?
                 .ok_or(DatabaseError::NotFound)?;
 
             let LifecycleState::Active { last_accessed } = value.metadata.lifecycle_state else {
@@ -337,8 +400,18 @@ impl Database {
 
             value.metadata.lifecycle_state = LifecycleState::Active { last_accessed: now };
 
-            main_table.insert(key, &VersionedValue::V1(value))?;
+            main_table.insert(key, &self.codec.encode(value))?;
         }
+        Self::append_op(
+            &write_txn,
+            &self.codec,
+            now,
+            Operation::AttachmentRenamed {
+                key: key.clone(),
+                from: old_filename.to_string(),
+                to: new_filename.to_string(),
+            },
+        )?;
 
         write_txn.commit()?;
         Ok(())
@@ -355,150 +428,245 @@ impl Database {
 
             let mut value = main_table
                 .get(key)?
-                .map(|g| Self::extract_latest(g.value()))
+                .map(|g| self.codec.decode(g.value()))
+                .transpose()?
                 .ok_or(DatabaseError::NotFound)?;
 
             value.thumb_version = version;
-            main_table.insert(key, &VersionedValue::V1(value))?;
+            main_table.insert(key, &self.codec.encode(value))?;
         }
 
         write_txn.commit()?;
         Ok(())
     }
 
-    /// Renames a key, optionally overwriting destination.
+    /// Sets or clears the wrapped per-key data key of a sealed key.
     ///
-    /// Returns `Err(NotFound)` if src doesn't exist.
-    pub fn rename(&mut self, src: &Key, dst: &Key, now: SystemTime) -> Result<(), DatabaseError> {
+    /// Returns `Err(NotFound)` if the key doesn't exist.
+    /// Returns `Err(Trashed)` if the key is trashed.
+    pub fn set_seal(&mut self, key: &Key, seal: Option<Vec<u8>>) -> Result<(), DatabaseError> {
         let write_txn = self.db.begin_write()?;
+        let op = match seal {
+            Some(_) => Operation::Sealed { key: key.clone() },
+            None => Operation::Unsealed { key: key.clone() },
+        };
 
         {
             let mut main_table = write_txn.open_table(MAIN_TABLE)?;
 
-            // Extract destination lifecycle state before mutating
-            let dest_state = main_table
-                .get(dst)?
-                .map(|g| Self::extract_latest(g.value()).metadata.lifecycle_state);
-
-            // Clean up destination if it exists
-            if let Some(state) = dest_state {
-                match state {
-                    LifecycleState::Active { last_accessed } => {
-                        Self::remove_active_ttl(&write_txn, dst, last_accessed)?;
-                    }
-                    LifecycleState::Trash { trashed_at } => {
-                        Self::remove_trash_ttl(&write_txn, dst, trashed_at)?;
-                    }
-                }
-                main_table.remove(dst)?;
-            }
-
-            // Get and remove source
             let mut value = main_table
-                .remove(src)?
-                .map(|g| Self::extract_latest(g.value()))
+                .get(key)?
+                .map(|g| self.codec.decode(g.value()))
+                .transpose()?
                 .ok_or(DatabaseError::NotFound)?;
 
-            // Remove old TTL entry and insert new one
-            match value.metadata.lifecycle_state {
-                LifecycleState::Active { last_accessed } => {
-                    Self::remove_active_ttl(&write_txn, src, last_accessed)?;
-                    Self::insert_active_ttl(&write_txn, dst, now)?;
-                    value.metadata.lifecycle_state = LifecycleState::Active { last_accessed: now };
-                }
-                LifecycleState::Trash { trashed_at } => {
-                    Self::remove_trash_ttl(&write_txn, src, trashed_at)?;
-                    Self::insert_trash_ttl(&write_txn, dst, trashed_at)?;
-                }
+            if matches!(value.metadata.lifecycle_state, LifecycleState::Trash { .. }) {
+                return Err(DatabaseError::Trashed);
             }
 
-            main_table.insert(dst, &VersionedValue::V1(value))?;
+            value.metadata.seal = seal;
+            main_table.insert(key, &self.codec.encode(value))?;
         }
+        Self::append_op(&write_txn, &self.codec, SystemTime::now(), op)?;
 
         write_txn.commit()?;
         Ok(())
     }
+
+    /// Renames a key, optionally overwriting destination.
+    ///
+    /// Returns `Err(NotFound)` if src doesn't exist.
+    pub fn rename(&mut self, src: &Key, dst: &Key, now: SystemTime) -> Result<(), DatabaseError> {
+        let mut tx = self.begin()?;
+        tx.rename(src, dst, now)?;
+        tx.commit()
+    }
 }
 
-/// Delete operations.
+/// Subtree operations.
+///
+/// Keys form a virtual tree split on `/`. The subtree of `prefix` is the key equal to `prefix`
+/// (if any) plus every key starting with `prefix/`. Bulk mutations run in a single transaction.
 impl Database {
-    /// Soft-deletes a key by moving it from Active to Trash state.
-    pub fn trash(&mut self, key: &Key, now: SystemTime) -> Result<(), DatabaseError> {
-        let write_txn = self.db.begin_write()?;
-
-        {
-            let mut main_table = write_txn.open_table(MAIN_TABLE)?;
-
-            let mut value = main_table
-                .get(key)?
-                .map(|g| Self::extract_latest(g.value()))
-                .ok_or(DatabaseError::NotFound)?;
+    /// Returns the subtree of `prefix` in key order. An empty prefix returns every key.
+    pub fn subtree(&self, prefix: &str) -> Result<Vec<(Key, LifecycleState)>, DatabaseError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(MAIN_TABLE)?;
+        let mut entries = Vec::new();
 
-            let LifecycleState::Active { last_accessed } = value.metadata.lifecycle_state else {
-                return Err(DatabaseError::Trashed);
-            };
+        let mut push = |key: Key, value: VersionedValue| -> Result<(), DatabaseError> {
+            let state = self.codec.decode(value)?.metadata.lifecycle_state;
+            entries.push((key, state));
+            Ok(())
+        };
 
-            Self::remove_active_ttl(&write_txn, key, last_accessed)?;
-            Self::insert_trash_ttl(&write_txn, key, now)?;
+        if prefix.is_empty() {
+            for entry in table.iter()? {
+                let (key_guard, value_guard) = entry?;
+                push(key_guard.value(), value_guard.value())?;
+            }
+            return Ok(entries);
+        }
 
-            value.metadata.lifecycle_state = LifecycleState::Trash { trashed_at: now };
+        // SAFETY: These keys are only used for lookup and range bounds, never stored.
+        let (exact, start, end) = unsafe {
+            (
+                Key::new_unchecked(prefix.to_string()),
+                Key::new_unchecked(format!("{prefix}/")),
+                // '0' is the byte after '/', so this bounds every key starting with "{prefix}/".
+                Key::new_unchecked(format!("{prefix}0")),
+            )
+        };
 
-            main_table.insert(key, &VersionedValue::V1(value))?;
+        if let Some(guard) = table.get(&exact)? {
+            push(exact.clone(), guard.value())?;
+        }
+        for entry in table.range(start..end)? {
+            let (key_guard, value_guard) = entry?;
+            push(key_guard.value(), value_guard.value())?;
         }
 
-        write_txn.commit()?;
-        Ok(())
+        Ok(entries)
     }
 
-    /// Restores a key from Trash to Active state.
-    pub fn restore(&mut self, key: &Key, now: SystemTime) -> Result<(), DatabaseError> {
+    /// Renames several keys at once.
+    ///
+    /// Destinations may coincide with other sources in the same call (e.g. moving `a` to `a/b`
+    /// while `a/b` moves to `a/b/b`).
+    ///
+    /// Returns `Err(NotFound)` if any source doesn't exist.
+    /// Returns `Err(AlreadyExists)` if any destination exists and isn't itself being moved.
+    pub fn rename_many(
+        &mut self,
+        moves: &[(Key, Key)],
+        now: SystemTime,
+    ) -> Result<(), DatabaseError> {
         let write_txn = self.db.begin_write()?;
 
         {
             let mut main_table = write_txn.open_table(MAIN_TABLE)?;
 
-            let mut value = main_table
-                .get(key)?
-                .map(|g| Self::extract_latest(g.value()))
-                .ok_or(DatabaseError::NotFound)?;
-
-            let LifecycleState::Trash { trashed_at } = value.metadata.lifecycle_state else {
-                return Err(DatabaseError::NotTrashed);
-            };
+            for (_, dst) in moves {
+                let moving = moves.iter().any(|(src, _)| src == dst);
+                if !moving && main_table.get(dst)?.is_some() {
+                    return Err(DatabaseError::AlreadyExists);
+                }
+            }
 
-            Self::remove_trash_ttl(&write_txn, key, trashed_at)?;
-            Self::insert_active_ttl(&write_txn, key, now)?;
+            // Remove every source first so destinations that are also sources don't collide.
+            let mut values = Vec::with_capacity(moves.len());
+            for (src, _) in moves {
+                let value = main_table
+                    .remove(src)?
+                    .map(|g| self.codec.decode(g.value()))
+                    .transpose()?
+                    .ok_or(DatabaseError::NotFound)?;
 
-            value.metadata.lifecycle_state = LifecycleState::Active { last_accessed: now };
+                match value.metadata.lifecycle_state {
+                    LifecycleState::Active { last_accessed } => {
+                        Self::remove_active_ttl(&write_txn, src, last_accessed)?;
+                    }
+                    LifecycleState::Trash { trashed_at } => {
+                        Self::remove_trash_ttl(&write_txn, src, trashed_at)?;
+                    }
+                }
+                values.push(value);
+            }
 
-            main_table.insert(key, &VersionedValue::V1(value))?;
+            for ((_, dst), mut value) in moves.iter().zip(values) {
+                match value.metadata.lifecycle_state {
+                    LifecycleState::Active { .. } => {
+                        Self::insert_active_ttl(&write_txn, dst, now)?;
+                        value.metadata.lifecycle_state =
+                            LifecycleState::Active { last_accessed: now };
+                    }
+                    LifecycleState::Trash { trashed_at } => {
+                        Self::insert_trash_ttl(&write_txn, dst, trashed_at)?;
+                    }
+                }
+                main_table.insert(dst, &self.codec.encode(value))?;
+            }
         }
 
+        Self::bump_generation(&write_txn)?;
         write_txn.commit()?;
         Ok(())
     }
 
+    /// Moves several Active keys to Trash at once.
+    ///
+    /// Returns `Err(NotFound)` if any key doesn't exist.
+    /// Returns `Err(Trashed)` if any key is already trashed.
+    pub fn trash_many(&mut self, keys: &[Key], now: SystemTime) -> Result<(), DatabaseError> {
+        let mut tx = self.begin()?;
+        for key in keys {
+            tx.trash(key, now)?;
+        }
+        tx.commit()
+    }
+
+    /// Restores several Trash keys at once.
+    ///
+    /// Returns `Err(NotFound)` if any key doesn't exist.
+    /// Returns `Err(NotTrashed)` if any key is not trashed.
+    pub fn restore_many(&mut self, keys: &[Key], now: SystemTime) -> Result<(), DatabaseError> {
+        let mut tx = self.begin()?;
+        for key in keys {
+            tx.restore(key, now)?;
+        }
+        tx.commit()
+    }
+}
+
+/// Delete operations.
+impl Database {
+    /// Soft-deletes a key by moving it from Active to Trash state.
+    pub fn trash(&mut self, key: &Key, now: SystemTime) -> Result<(), DatabaseError> {
+        let mut tx = self.begin()?;
+        tx.trash(key, now)?;
+        tx.commit()
+    }
+
+    /// Restores a key from Trash to Active state.
+    pub fn restore(&mut self, key: &Key, now: SystemTime) -> Result<(), DatabaseError> {
+        let mut tx = self.begin()?;
+        tx.restore(key, now)?;
+        tx.commit()
+    }
+
     /// Permanently deletes a key from the database.
     pub fn purge(&mut self, key: &Key) -> Result<(), DatabaseError> {
+        let mut tx = self.begin()?;
+        tx.purge(key)?;
+        tx.commit()
+    }
+}
+
+/// Encryption operations.
+impl Database {
+    /// Replaces the cipher used for subsequent reads and writes.
+    pub fn set_cipher(&mut self, cipher: Cipher) {
+        self.codec.cipher = Some(cipher);
+    }
+
+    /// Re-seals every value with the cipher's current key, in one transaction.
+    pub fn reseal_all(&mut self) -> Result<(), DatabaseError> {
         let write_txn = self.db.begin_write()?;
 
         {
             let mut main_table = write_txn.open_table(MAIN_TABLE)?;
 
-            let value = main_table
-                .remove(key)?
-                .map(|g| Self::extract_latest(g.value()))
-                .ok_or(DatabaseError::NotFound)?;
+            let mut values = Vec::new();
+            for entry in main_table.iter()? {
+                let (key_guard, value_guard) = entry?;
+                values.push((key_guard.value(), self.codec.decode(value_guard.value())?));
+            }
 
-            match value.metadata.lifecycle_state {
-                LifecycleState::Active { last_accessed } => {
-                    Self::remove_active_ttl(&write_txn, key, last_accessed)?;
-                }
-                LifecycleState::Trash { trashed_at } => {
-                    Self::remove_trash_ttl(&write_txn, key, trashed_at)?;
-                }
+            for (key, value) in values {
+                main_table.insert(&key, &self.codec.encode(value))?;
             }
         }
+        self.reseal_oplog(&write_txn)?;
 
         write_txn.commit()?;
         Ok(())
@@ -533,7 +701,8 @@ impl Database {
             for key in to_trash {
                 let value_opt = main_table
                     .get(&key)?
-                    .map(|guard| Self::extract_latest(guard.value()));
+                    .map(|guard| self.codec.decode(guard.value()))
+                    .transpose()?;
 
                 if let Some(mut value) = value_opt
                     && let LifecycleState::Active { last_accessed } = value.metadata.lifecycle_state
@@ -543,7 +712,13 @@ impl Database {
 
                     value.metadata.lifecycle_state = LifecycleState::Trash { trashed_at: now };
 
-                    main_table.insert(&key, &VersionedValue::V1(value))?;
+                    main_table.insert(&key, &self.codec.encode(value))?;
+                    Self::append_op(
+                        &write_txn,
+                        &self.codec,
+                        now,
+                        Operation::Trashed { key: key.clone() },
+                    )?;
                     result.trashed.push(key);
                 }
             }
@@ -552,17 +727,26 @@ impl Database {
             for key in to_purge {
                 if let Some(value) = main_table
                     .remove(&key)?
-                    .map(|guard| Self::extract_latest(guard.value()))
+                    .map(|guard| self.codec.decode(guard.value()))
+                    .transpose()?
                 {
                     let LifecycleState::Trash { trashed_at } = value.metadata.lifecycle_state
                     else {
                         continue;
                     };
                     Self::remove_trash_ttl(&write_txn, &key, trashed_at)?;
+                    Self::append_op(
+                        &write_txn,
+                        &self.codec,
+                        now,
+                        Operation::Purged { key: key.clone() },
+                    )?;
                     result.purged.push(key);
                 }
             }
 
+            Self::bump_generation(&write_txn)?;
+
             // Update maintenance timestamp
             let metadata = MaintenanceMetadata {
                 last_run_at: Some(now),
@@ -577,11 +761,39 @@ impl Database {
     }
 }
 
-/// Internal helpers.
-impl Database {
-    fn extract_latest(versioned: VersionedValue) -> Value {
+/// Converts between stored values and the latest value version, sealing them when the store is
+/// encrypted.
+#[derive(Clone, Default)]
+struct Codec {
+    cipher: Option<Cipher>,
+}
+
+impl Codec {
+    fn decode(&self, versioned: VersionedValue) -> Result<Value, DatabaseError> {
         match versioned {
-            VersionedValue::V1(v) => v,
+            VersionedValue::V1(v) => {
+                Ok(v4::Value::from(v3::Value::from(v2::Value::from(v))).into())
+            }
+            VersionedValue::V2(v) => Ok(v4::Value::from(v3::Value::from(v)).into()),
+            VersionedValue::V3(v) => Ok(v4::Value::from(v).into()),
+            VersionedValue::V4(v) => Ok(v.into()),
+            VersionedValue::V5(v) => Ok(v),
+            VersionedValue::Sealed(sealed) => {
+                let cipher = self.cipher.as_ref().ok_or(CryptoError::UnknownKey)?;
+                let bytes = cipher.open(&sealed)?;
+                self.decode(<VersionedValue as redb::Value>::from_bytes(&bytes))
+            }
+        }
+    }
+
+    fn encode(&self, value: Value) -> VersionedValue {
+        let plain = VersionedValue::V5(value);
+        match &self.cipher {
+            None => plain,
+            Some(cipher) => {
+                let bytes = <VersionedValue as redb::Value>::as_bytes(&plain);
+                VersionedValue::Sealed(cipher.seal(&bytes))
+            }
         }
     }
 }
@@ -664,6 +876,56 @@ impl Database {
         Ok(())
     }
 
+    /// Returns this store's id, generating a random one on first use.
+    pub fn store_id(&mut self) -> Result<String, DatabaseError> {
+        let write_txn = self.db.begin_write()?;
+        let id = {
+            let mut table = write_txn.open_table(METADATA_TABLE)?;
+            let existing = table
+                .get(METADATA_KEY_STORE_ID)?
+                .map(|guard| guard.value().to_string());
+            match existing {
+                Some(id) => id,
+                None => {
+                    let id: String = crate::core::crypto::random_bytes::<16>()
+                        .iter()
+                        .map(|b| format!("{b:02x}"))
+                        .collect();
+                    table.insert(METADATA_KEY_STORE_ID, id.as_str())?;
+                    id
+                }
+            }
+        };
+        write_txn.commit()?;
+        Ok(id)
+    }
+
+    /// Returns the store generation.
+    ///
+    /// The counter increases whenever a key is created, renamed, trashed, restored, purged or
+    /// touched, so derived state (e.g. a persisted search index and its access stats) can tell
+    /// whether it is still current.
+    pub fn generation(&self) -> Result<u64, DatabaseError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(METADATA_TABLE)?;
+        let generation = table
+            .get(METADATA_KEY_GENERATION)?
+            .and_then(|guard| guard.value().parse().ok())
+            .unwrap_or(0);
+        Ok(generation)
+    }
+
+    fn bump_generation(txn: &redb::WriteTransaction) -> Result<(), DatabaseError> {
+        let mut table = txn.open_table(METADATA_TABLE)?;
+        let generation: u64 = table
+            .get(METADATA_KEY_GENERATION)?
+            .and_then(|guard| guard.value().parse().ok())
+            .unwrap_or(0);
+        let next = (generation + 1).to_string();
+        table.insert(METADATA_KEY_GENERATION, next.as_str())?;
+        Ok(())
+    }
+
     fn last_maintenance_at(&self) -> Option<SystemTime> {
         self.get_maintenance_metadata()?.last_run_at
     }
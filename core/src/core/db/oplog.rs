@@ -0,0 +1,244 @@
+//! Ordered, durable log of key mutations.
+//!
+//! Every mutation appends an entry with the next sequence number in the same write transaction,
+//! so the log never disagrees with the main table. Entries are sealed like values when the store
+//! is encrypted.
+
+use super::{Codec, Database, METADATA_TABLE};
+use crate::core::crypto::error::CryptoError;
+use crate::core::db::error::DatabaseError;
+use crate::types::Key;
+use redb::{ReadableDatabase, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+/// Operation log table: sequence number → encoded [`OpLogEntry`].
+pub(super) const OPLOG_TABLE: TableDefinition<u64, &[u8]> = TableDefinition::new("oplog");
+
+/// Metadata key for the last assigned sequence number.
+const METADATA_KEY_OPLOG_SEQ: &str = "oplog_seq";
+
+/// Metadata key for the oldest sequence number not yet compacted away.
+const METADATA_KEY_OPLOG_START: &str = "oplog_start";
+
+/// Metadata key for the time of the latest entry, in microseconds since the Unix epoch.
+const METADATA_KEY_OPLOG_AT: &str = "oplog_at";
+
+/// A mutation recorded in the operation log.
+///
+/// Content written through [`KevaCore::write_content`](crate::core::KevaCore::write_content) or
+/// by sync is logged once the write finishes; edits made to the content file directly are not.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Operation {
+    Created {
+        key: Key,
+    },
+    Touched {
+        key: Key,
+    },
+    Renamed {
+        from: Key,
+        to: Key,
+    },
+    Trashed {
+        key: Key,
+    },
+    Restored {
+        key: Key,
+    },
+    Purged {
+        key: Key,
+    },
+    /// A purged key was put back unchanged by undo.
+    Reinserted {
+        key: Key,
+    },
+    AttachmentAdded {
+        key: Key,
+        filename: String,
+    },
+    AttachmentRemoved {
+        key: Key,
+        filename: String,
+    },
+    AttachmentRenamed {
+        key: Key,
+        from: String,
+        to: String,
+    },
+    Sealed {
+        key: Key,
+    },
+    Unsealed {
+        key: Key,
+    },
+    ContentWritten {
+        key: Key,
+    },
+}
+
+/// An operation log entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpLogEntry {
+    /// Position in the log, starting at 1 and increasing by one per entry.
+    pub seq: u64,
+    /// When the operation happened: the `now` passed by the caller, or the system clock for
+    /// operations that don't take one (purge, seal, unseal, content writes).
+    ///
+    /// Never earlier than the previous entry's: an operation reported with an earlier time is
+    /// logged at the previous entry's time, so entries older than any time form a prefix of the
+    /// log.
+    pub at: SystemTime,
+    pub op: Operation,
+}
+
+impl Codec {
+    fn encode_entry(&self, entry: &OpLogEntry) -> Vec<u8> {
+        let bytes = postcard::to_allocvec(entry).expect("oplog entry serialization cannot fail");
+        match &self.cipher {
+            None => bytes,
+            Some(cipher) => cipher.seal(&bytes),
+        }
+    }
+
+    fn decode_entry(&self, bytes: &[u8]) -> Result<OpLogEntry, DatabaseError> {
+        let plain = match &self.cipher {
+            None => bytes.to_vec(),
+            Some(cipher) => cipher.open(bytes)?,
+        };
+        Ok(postcard::from_bytes(&plain).map_err(|_| CryptoError::Corrupted)?)
+    }
+}
+
+fn read_counter(
+    table: &impl ReadableTable<&'static str, &'static str>,
+    name: &str,
+) -> Result<Option<u64>, DatabaseError> {
+    Ok(table
+        .get(name)?
+        .and_then(|guard| guard.value().parse().ok()))
+}
+
+/// Operation log.
+impl Database {
+    /// Appends `op` to the log within `txn`, returning its sequence number.
+    pub(super) fn append_op(
+        txn: &redb::WriteTransaction,
+        codec: &Codec,
+        at: SystemTime,
+        op: Operation,
+    ) -> Result<u64, DatabaseError> {
+        let mut meta_table = txn.open_table(METADATA_TABLE)?;
+        let seq = read_counter(&meta_table, METADATA_KEY_OPLOG_SEQ)?.unwrap_or(0) + 1;
+        meta_table.insert(METADATA_KEY_OPLOG_SEQ, seq.to_string().as_str())?;
+
+        let micros = at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        let at = match read_counter(&meta_table, METADATA_KEY_OPLOG_AT)? {
+            Some(last) if last > micros => SystemTime::UNIX_EPOCH + Duration::from_micros(last),
+            _ => {
+                meta_table.insert(METADATA_KEY_OPLOG_AT, micros.to_string().as_str())?;
+                at
+            }
+        };
+
+        let mut oplog_table = txn.open_table(OPLOG_TABLE)?;
+        oplog_table.insert(
+            seq,
+            codec.encode_entry(&OpLogEntry { seq, at, op }).as_slice(),
+        )?;
+        Ok(seq)
+    }
+
+    /// Logs that the content of `key` was replaced.
+    pub fn log_content_written(&self, key: &Key, at: SystemTime) -> Result<(), DatabaseError> {
+        let write_txn = self.db.begin_write()?;
+        Self::append_op(
+            &write_txn,
+            &self.codec,
+            at,
+            Operation::ContentWritten { key: key.clone() },
+        )?;
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Returns the sequence number of the latest entry, or 0 if nothing was logged yet.
+    pub fn last_seq(&self) -> Result<u64, DatabaseError> {
+        let read_txn = self.db.begin_read()?;
+        let table = read_txn.open_table(METADATA_TABLE)?;
+        Ok(read_counter(&table, METADATA_KEY_OPLOG_SEQ)?.unwrap_or(0))
+    }
+
+    /// Returns up to `limit` entries with a sequence number greater than `after`, in order.
+    ///
+    /// Returns `Err(OplogCompacted)` if entries right after `after` were compacted away.
+    pub fn oplog_since(&self, after: u64, limit: usize) -> Result<Vec<OpLogEntry>, DatabaseError> {
+        let read_txn = self.db.begin_read()?;
+        let meta_table = read_txn.open_table(METADATA_TABLE)?;
+        let oldest = read_counter(&meta_table, METADATA_KEY_OPLOG_START)?.unwrap_or(1);
+        if after.saturating_add(1) < oldest {
+            return Err(DatabaseError::OplogCompacted { oldest });
+        }
+
+        let table = read_txn.open_table(OPLOG_TABLE)?;
+        let mut entries = Vec::new();
+        for entry in table.range(after.saturating_add(1)..)?.take(limit) {
+            let (_, bytes) = entry?;
+            entries.push(self.codec.decode_entry(bytes.value())?);
+        }
+        Ok(entries)
+    }
+
+    /// Removes entries logged before `cutoff`. Since entry times never decrease, these are the
+    /// oldest entries up to the first newer one.
+    ///
+    /// Returns the number of entries removed.
+    pub fn compact_oplog(&mut self, cutoff: SystemTime) -> Result<usize, DatabaseError> {
+        let write_txn = self.db.begin_write()?;
+        let mut removed = 0;
+
+        {
+            let mut table = write_txn.open_table(OPLOG_TABLE)?;
+
+            let mut expired = Vec::new();
+            for entry in table.iter()? {
+                let (seq, bytes) = entry?;
+                if self.codec.decode_entry(bytes.value())?.at >= cutoff {
+                    break;
+                }
+                expired.push(seq.value());
+            }
+
+            if let Some(&last) = expired.last() {
+                for seq in &expired {
+                    table.remove(seq)?;
+                }
+                let mut meta_table = write_txn.open_table(METADATA_TABLE)?;
+                meta_table.insert(METADATA_KEY_OPLOG_START, (last + 1).to_string().as_str())?;
+                removed = expired.len();
+            }
+        }
+
+        write_txn.commit()?;
+        Ok(removed)
+    }
+
+    /// Re-encodes every log entry with the current cipher.
+    pub(super) fn reseal_oplog(&self, txn: &redb::WriteTransaction) -> Result<(), DatabaseError> {
+        let mut table = txn.open_table(OPLOG_TABLE)?;
+
+        let mut entries = Vec::new();
+        for entry in table.iter()? {
+            let (_, bytes) = entry?;
+            entries.push(self.codec.decode_entry(bytes.value())?);
+        }
+
+        for entry in entries {
+            table.insert(entry.seq, self.codec.encode_entry(&entry).as_slice())?;
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
 use super::*;
+use crate::core::file_storage::FileStorage;
 use crate::types::TtlKey;
+use crate::types::value::versioned_value::latest_value::Metadata;
 use common::{create_test_db, make_gc_config, make_key};
 use std::time::Duration;
 use tempfile::TempDir;
@@ -12,7 +14,7 @@ mod common {
         let config = Config {
             base_path: temp_dir.path().to_path_buf(),
         };
-        let db = Database::new(config).unwrap();
+        let db = Database::new(config, None).unwrap();
         (db, temp_dir)
     }
 
@@ -20,6 +22,7 @@ mod common {
         GcConfig {
             trash_ttl: Duration::from_secs(trash_ttl_secs),
             purge_ttl: Duration::from_secs(purge_ttl_secs),
+            oplog_retention: Duration::from_secs(30 * 24 * 60 * 60),
         }
     }
 
@@ -46,6 +49,8 @@ mod create {
             value.metadata,
             Metadata {
                 lifecycle_state: LifecycleState::Active { last_accessed: now },
+                access_count: 0,
+                seal: None,
             }
         )
     }
@@ -109,6 +114,8 @@ mod get {
             value.metadata,
             Metadata {
                 lifecycle_state: LifecycleState::Active { last_accessed: now },
+                access_count: 0,
+                seal: None,
             }
         );
     }
@@ -140,11 +147,59 @@ mod get {
                 lifecycle_state: LifecycleState::Trash {
                     trashed_at: trash_time
                 },
+                access_count: 0,
+                seal: None,
             }
         );
     }
 }
 
+mod access_stats {
+    use super::*;
+
+    #[test]
+    fn test_access_stats_lists_active_keys_only() {
+        let (mut db, _temp) = create_test_db();
+        let active = make_key("active");
+        let trashed = make_key("trashed");
+        let now = SystemTime::now();
+        let touch_time = now + Duration::from_secs(5);
+
+        db.create(&active, now).unwrap();
+        db.create(&trashed, now).unwrap();
+        db.touch(&active, touch_time).unwrap();
+        db.trash(&trashed, now).unwrap();
+
+        let stats = db.access_stats().unwrap();
+        assert_eq!(
+            stats,
+            vec![(
+                active,
+                AccessStats {
+                    last_accessed: touch_time,
+                    access_count: 1,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_rename_preserves_access_count() {
+        let (mut db, _temp) = create_test_db();
+        let src = make_key("src");
+        let dst = make_key("dst");
+        let now = SystemTime::now();
+
+        db.create(&src, now).unwrap();
+        db.touch(&src, now).unwrap();
+        db.touch(&src, now).unwrap();
+        db.rename(&src, &dst, now).unwrap();
+
+        let value = db.get(&dst).unwrap().unwrap();
+        assert_eq!(value.metadata.access_count, 2);
+    }
+}
+
 mod touch {
     use super::*;
 
@@ -166,10 +221,27 @@ mod touch {
                 lifecycle_state: LifecycleState::Active {
                     last_accessed: touch_time
                 },
+                access_count: 1,
+                seal: None,
             }
         );
     }
 
+    #[test]
+    fn test_touch_increments_access_count() {
+        let (mut db, _temp) = create_test_db();
+        let key = make_key("test/touch");
+        let now = SystemTime::now();
+
+        db.create(&key, now).unwrap();
+        for _ in 0..3 {
+            db.touch(&key, now).unwrap();
+        }
+
+        let value = db.get(&key).unwrap().unwrap();
+        assert_eq!(value.metadata.access_count, 3);
+    }
+
     #[test]
     fn test_touch_nonexistent_key() {
         let (mut db, _temp) = create_test_db();
@@ -210,6 +282,11 @@ mod add_attachment {
             Attachment {
                 filename: "test.txt".to_string(),
                 size: 100,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             add_time,
         )
@@ -225,6 +302,8 @@ mod add_attachment {
                 lifecycle_state: LifecycleState::Active {
                     last_accessed: add_time
                 },
+                access_count: 0,
+                seal: None,
             }
         );
     }
@@ -241,6 +320,11 @@ mod add_attachment {
             Attachment {
                 filename: "file1.txt".to_string(),
                 size: 100,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             now,
         )
@@ -250,6 +334,11 @@ mod add_attachment {
             Attachment {
                 filename: "file2.txt".to_string(),
                 size: 200,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             now,
         )
@@ -269,6 +358,11 @@ mod add_attachment {
             Attachment {
                 filename: "test.txt".to_string(),
                 size: 100,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             SystemTime::now(),
         );
@@ -289,6 +383,11 @@ mod add_attachment {
             Attachment {
                 filename: "test.txt".to_string(),
                 size: 100,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             now,
         );
@@ -309,6 +408,11 @@ mod add_attachment {
             Attachment {
                 filename: "test.txt".to_string(),
                 size: 100,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             add_time,
         )
@@ -343,6 +447,11 @@ mod remove_attachment {
             Attachment {
                 filename: "test.txt".to_string(),
                 size: 100,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             create_time,
         )
@@ -359,6 +468,8 @@ mod remove_attachment {
                 lifecycle_state: LifecycleState::Active {
                     last_accessed: remove_time
                 },
+                access_count: 0,
+                seal: None,
             }
         );
     }
@@ -396,6 +507,11 @@ mod remove_attachment {
             Attachment {
                 filename: "test.txt".to_string(),
                 size: 100,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             now,
         )
@@ -418,6 +534,11 @@ mod remove_attachment {
             Attachment {
                 filename: "test.txt".to_string(),
                 size: 100,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             create_time,
         )
@@ -455,6 +576,11 @@ mod rename_attachment {
             Attachment {
                 filename: "old.txt".to_string(),
                 size: 100,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             create_time,
         )
@@ -474,6 +600,8 @@ mod rename_attachment {
                 lifecycle_state: LifecycleState::Active {
                     last_accessed: rename_time
                 },
+                access_count: 0,
+                seal: None,
             }
         );
     }
@@ -502,6 +630,11 @@ mod rename_attachment {
             Attachment {
                 filename: "a.txt".to_string(),
                 size: 100,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             now,
         )
@@ -511,6 +644,11 @@ mod rename_attachment {
             Attachment {
                 filename: "b.txt".to_string(),
                 size: 200,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             now,
         )
@@ -536,6 +674,11 @@ mod rename_attachment {
             Attachment {
                 filename: "old.txt".to_string(),
                 size: 100,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             create_time,
         )
@@ -579,6 +722,8 @@ mod rename {
             value.metadata,
             Metadata {
                 lifecycle_state: LifecycleState::Active { last_accessed: now },
+                access_count: 0,
+                seal: None,
             }
         );
     }
@@ -604,6 +749,8 @@ mod rename {
                 lifecycle_state: LifecycleState::Trash {
                     trashed_at: trash_time
                 },
+                access_count: 0,
+                seal: None,
             }
         );
     }
@@ -716,6 +863,39 @@ mod update_thumb_version {
     }
 }
 
+mod set_seal {
+    use super::*;
+
+    #[test]
+    fn test_set_and_clear_seal() {
+        let (mut db, _temp) = create_test_db();
+        let key = make_key("test/seal");
+
+        db.create(&key, SystemTime::now()).unwrap();
+
+        db.set_seal(&key, Some(vec![1, 2, 3])).unwrap();
+        let value = db.get(&key).unwrap().unwrap();
+        assert_eq!(value.metadata.seal, Some(vec![1, 2, 3]));
+
+        db.set_seal(&key, None).unwrap();
+        let value = db.get(&key).unwrap().unwrap();
+        assert_eq!(value.metadata.seal, None);
+    }
+
+    #[test]
+    fn test_set_seal_trashed() {
+        let (mut db, _temp) = create_test_db();
+        let key = make_key("test/seal");
+        let now = SystemTime::now();
+
+        db.create(&key, now).unwrap();
+        db.trash(&key, now).unwrap();
+
+        let result = db.set_seal(&key, Some(vec![1]));
+        assert!(matches!(result, Err(DatabaseError::Trashed)));
+    }
+}
+
 mod trash {
     use super::*;
 
@@ -737,6 +917,8 @@ mod trash {
                 lifecycle_state: LifecycleState::Trash {
                     trashed_at: trash_time
                 },
+                access_count: 0,
+                seal: None,
             }
         );
 
@@ -800,6 +982,8 @@ mod restore {
                 lifecycle_state: LifecycleState::Active {
                     last_accessed: restore_time
                 },
+                access_count: 0,
+                seal: None,
             }
         );
 
@@ -871,6 +1055,57 @@ mod purge {
     }
 }
 
+mod reinsert {
+    use super::*;
+
+    #[test]
+    fn test_reinsert_restores_purged_value() {
+        let (mut db, _temp) = create_test_db();
+        let key = make_key("test/reinsert");
+        let now = SystemTime::now();
+
+        db.create(&key, now).unwrap();
+        db.add_attachment(
+            &key,
+            Attachment {
+                filename: "a.txt".to_string(),
+                size: 3,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
+            },
+            now,
+        )
+        .unwrap();
+        let value = db.get(&key).unwrap().unwrap();
+        db.purge(&key).unwrap();
+
+        db.reinsert(&key, value.clone(), now).unwrap();
+        assert_eq!(db.get(&key).unwrap(), Some(value));
+        assert_eq!(db.active_keys().unwrap(), vec![key.clone()]);
+
+        // The TTL entry is restored too
+        let result = db
+            .gc(now + Duration::from_secs(20), make_gc_config(10, 100))
+            .unwrap();
+        assert_eq!(result.trashed, vec![key]);
+    }
+
+    #[test]
+    fn test_reinsert_existing_key_fails() {
+        let (mut db, _temp) = create_test_db();
+        let key = make_key("test/reinsert");
+        let now = SystemTime::now();
+
+        let value = db.create(&key, now).unwrap();
+
+        let result = db.reinsert(&key, value, now);
+        assert!(matches!(result, Err(DatabaseError::AlreadyExists)));
+    }
+}
+
 mod gc {
     use super::*;
 
@@ -910,6 +1145,8 @@ mod gc {
                 lifecycle_state: LifecycleState::Trash {
                     trashed_at: gc_time
                 },
+                access_count: 0,
+                seal: None,
             }
         );
     }
@@ -963,6 +1200,8 @@ mod gc {
             value.metadata,
             Metadata {
                 lifecycle_state: LifecycleState::Trash { trashed_at: t2 },
+                access_count: 0,
+                seal: None,
             }
         );
 
@@ -1000,6 +1239,484 @@ mod gc {
     }
 }
 
+mod subtree {
+    use super::*;
+
+    fn keys(entries: Vec<(Key, LifecycleState)>) -> Vec<String> {
+        entries.into_iter().map(|(k, _)| k.to_string()).collect()
+    }
+
+    #[test]
+    fn test_subtree_includes_exact_and_descendants() {
+        let (mut db, _temp) = create_test_db();
+        let now = SystemTime::now();
+
+        for k in ["a", "a/b", "a/b/c", "a-x", "a0", "ab", "b"] {
+            db.create(&make_key(k), now).unwrap();
+        }
+
+        assert_eq!(keys(db.subtree("a").unwrap()), vec!["a", "a/b", "a/b/c"]);
+        assert_eq!(keys(db.subtree("a/b").unwrap()), vec!["a/b", "a/b/c"]);
+    }
+
+    #[test]
+    fn test_subtree_empty_prefix_returns_all() {
+        let (mut db, _temp) = create_test_db();
+        let now = SystemTime::now();
+
+        db.create(&make_key("x"), now).unwrap();
+        db.create(&make_key("y/z"), now).unwrap();
+
+        assert_eq!(keys(db.subtree("").unwrap()), vec!["x", "y/z"]);
+    }
+
+    #[test]
+    fn test_subtree_reports_lifecycle_state() {
+        let (mut db, _temp) = create_test_db();
+        let now = SystemTime::now();
+        let key = make_key("a/b");
+
+        db.create(&key, now).unwrap();
+        db.trash(&key, now).unwrap();
+
+        let entries = db.subtree("a").unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(matches!(entries[0].1, LifecycleState::Trash { .. }));
+    }
+
+    #[test]
+    fn test_rename_many_moves_all() {
+        let (mut db, _temp) = create_test_db();
+        let now = SystemTime::now();
+        let later = now + Duration::from_secs(10);
+
+        db.create(&make_key("a"), now).unwrap();
+        db.create(&make_key("a/b"), now).unwrap();
+        db.trash(&make_key("a/b"), now).unwrap();
+
+        let moves = vec![
+            (make_key("a"), make_key("z")),
+            (make_key("a/b"), make_key("z/b")),
+        ];
+        db.rename_many(&moves, later).unwrap();
+
+        assert!(db.subtree("a").unwrap().is_empty());
+        let entries = db.subtree("z").unwrap();
+        assert_eq!(
+            entries[0].1,
+            LifecycleState::Active {
+                last_accessed: later
+            }
+        );
+        assert_eq!(entries[1].1, LifecycleState::Trash { trashed_at: now });
+    }
+
+    #[test]
+    fn test_rename_many_into_own_subtree() {
+        let (mut db, _temp) = create_test_db();
+        let now = SystemTime::now();
+
+        db.create(&make_key("a"), now).unwrap();
+        db.create(&make_key("a/b"), now).unwrap();
+
+        let moves = vec![
+            (make_key("a"), make_key("a/b")),
+            (make_key("a/b"), make_key("a/b/b")),
+        ];
+        db.rename_many(&moves, now).unwrap();
+
+        assert_eq!(keys(db.subtree("").unwrap()), vec!["a/b", "a/b/b"]);
+    }
+
+    #[test]
+    fn test_rename_many_destination_exists() {
+        let (mut db, _temp) = create_test_db();
+        let now = SystemTime::now();
+
+        db.create(&make_key("a"), now).unwrap();
+        db.create(&make_key("b"), now).unwrap();
+
+        let result = db.rename_many(&[(make_key("a"), make_key("b"))], now);
+        assert!(matches!(result, Err(DatabaseError::AlreadyExists)));
+        assert!(db.get(&make_key("a")).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_trash_many_is_atomic() {
+        let (mut db, _temp) = create_test_db();
+        let now = SystemTime::now();
+
+        db.create(&make_key("a"), now).unwrap();
+        db.create(&make_key("b"), now).unwrap();
+        db.trash(&make_key("b"), now).unwrap();
+
+        let result = db.trash_many(&[make_key("a"), make_key("b")], now);
+        assert!(matches!(result, Err(DatabaseError::Trashed)));
+
+        // The failed batch left "a" untouched
+        let value = db.get(&make_key("a")).unwrap().unwrap();
+        assert!(matches!(
+            value.metadata.lifecycle_state,
+            LifecycleState::Active { .. }
+        ));
+    }
+
+    #[test]
+    fn test_trash_and_restore_many() {
+        let (mut db, _temp) = create_test_db();
+        let now = SystemTime::now();
+        let keys = [make_key("a/1"), make_key("a/2")];
+
+        for key in &keys {
+            db.create(key, now).unwrap();
+        }
+
+        db.trash_many(&keys, now).unwrap();
+        assert_eq!(db.trashed_keys().unwrap().len(), 2);
+
+        db.restore_many(&keys, now).unwrap();
+        assert_eq!(db.active_keys().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_bulk_ops_bump_generation_once() {
+        let (mut db, _temp) = create_test_db();
+        let now = SystemTime::now();
+        let keys = [make_key("a/1"), make_key("a/2")];
+
+        for key in &keys {
+            db.create(key, now).unwrap();
+        }
+        let before = db.generation().unwrap();
+
+        db.trash_many(&keys, now).unwrap();
+        assert_eq!(db.generation().unwrap(), before + 1);
+    }
+}
+
+mod store_id {
+    use super::*;
+
+    #[test]
+    fn test_store_id_is_stable() {
+        let (mut db, temp) = create_test_db();
+        let id = db.store_id().unwrap();
+        assert_eq!(id.len(), 32);
+        assert_eq!(db.store_id().unwrap(), id);
+        drop(db);
+
+        let config = Config {
+            base_path: temp.path().to_path_buf(),
+        };
+        let mut reopened = Database::new(config, None).unwrap();
+        assert_eq!(reopened.store_id().unwrap(), id);
+
+        let (mut other, _other_temp) = create_test_db();
+        assert_ne!(other.store_id().unwrap(), id);
+    }
+}
+
+mod oplog {
+    use super::*;
+
+    fn ops(db: &Database) -> Vec<Operation> {
+        db.oplog_since(0, usize::MAX)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.op)
+            .collect()
+    }
+
+    #[test]
+    fn test_mutations_are_logged_in_order() {
+        let (mut db, _temp) = create_test_db();
+        let key = make_key("key");
+        let renamed = make_key("renamed");
+        let now = SystemTime::now();
+
+        db.create(&key, now).unwrap();
+        db.touch(&key, now).unwrap();
+        db.add_attachment(
+            &key,
+            Attachment {
+                filename: "a.txt".to_string(),
+                size: 1,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
+            },
+            now,
+        )
+        .unwrap();
+        db.rename_attachment(&key, "a.txt", "b.txt", now).unwrap();
+        db.remove_attachment(&key, "b.txt", now).unwrap();
+        db.rename(&key, &renamed, now).unwrap();
+        db.trash(&renamed, now).unwrap();
+        db.restore(&renamed, now).unwrap();
+        db.purge(&renamed).unwrap();
+
+        assert_eq!(
+            ops(&db),
+            vec![
+                Operation::Created { key: key.clone() },
+                Operation::Touched { key: key.clone() },
+                Operation::AttachmentAdded {
+                    key: key.clone(),
+                    filename: "a.txt".to_string()
+                },
+                Operation::AttachmentRenamed {
+                    key: key.clone(),
+                    from: "a.txt".to_string(),
+                    to: "b.txt".to_string()
+                },
+                Operation::AttachmentRemoved {
+                    key: key.clone(),
+                    filename: "b.txt".to_string()
+                },
+                Operation::Renamed {
+                    from: key.clone(),
+                    to: renamed.clone()
+                },
+                Operation::Trashed {
+                    key: renamed.clone()
+                },
+                Operation::Restored {
+                    key: renamed.clone()
+                },
+                Operation::Purged {
+                    key: renamed.clone()
+                },
+            ]
+        );
+        assert_eq!(db.last_seq().unwrap(), 9);
+    }
+
+    #[test]
+    fn test_oplog_since_and_limit() {
+        let (mut db, _temp) = create_test_db();
+        let now = SystemTime::now();
+
+        for name in ["a", "b", "c", "d"] {
+            db.create(&make_key(name), now).unwrap();
+        }
+
+        let entries = db.oplog_since(1, 2).unwrap();
+        let seqs: Vec<u64> = entries.iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, vec![2, 3]);
+        assert_eq!(entries[0].op, Operation::Created { key: make_key("b") });
+        assert_eq!(entries[0].at, now);
+
+        assert!(db.oplog_since(4, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_failed_operation_is_not_logged() {
+        let (mut db, _temp) = create_test_db();
+        let key = make_key("key");
+        let now = SystemTime::now();
+
+        db.create(&key, now).unwrap();
+        assert!(db.create(&key, now).is_err());
+        assert!(db.restore(&key, now).is_err());
+
+        assert_eq!(db.last_seq().unwrap(), 1);
+        assert_eq!(ops(&db).len(), 1);
+    }
+
+    #[test]
+    fn test_gc_is_logged() {
+        let (mut db, _temp) = create_test_db();
+        let key = make_key("key");
+        let t0 = SystemTime::now();
+
+        db.create(&key, t0).unwrap();
+        let t1 = t0 + Duration::from_secs(20);
+        db.gc(t1, make_gc_config(10, 10)).unwrap();
+        let t2 = t1 + Duration::from_secs(20);
+        db.gc(t2, make_gc_config(10, 10)).unwrap();
+
+        let entries = db.oplog_since(1, usize::MAX).unwrap();
+        assert_eq!(entries[0].op, Operation::Trashed { key: key.clone() });
+        assert_eq!(entries[0].at, t1);
+        assert_eq!(entries[1].op, Operation::Purged { key: key.clone() });
+        assert_eq!(entries[1].at, t2);
+    }
+
+    #[test]
+    fn test_compaction() {
+        let (mut db, _temp) = create_test_db();
+        let t0 = SystemTime::now();
+
+        db.create(&make_key("old"), t0).unwrap();
+        db.create(&make_key("new"), t0 + Duration::from_secs(100))
+            .unwrap();
+
+        assert_eq!(db.compact_oplog(t0 + Duration::from_secs(50)).unwrap(), 1);
+        assert_eq!(db.compact_oplog(t0 + Duration::from_secs(50)).unwrap(), 0);
+
+        assert!(matches!(
+            db.oplog_since(0, 10),
+            Err(DatabaseError::OplogCompacted { oldest: 2 })
+        ));
+        let entries = db.oplog_since(1, 10).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].seq, 2);
+
+        // Sequence numbers keep increasing after compaction
+        db.create(&make_key("newer"), t0 + Duration::from_secs(200))
+            .unwrap();
+        assert_eq!(db.last_seq().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_times_never_decrease() {
+        let (mut db, _temp) = create_test_db();
+        let t0 = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+        db.create(&make_key("late"), t0 + Duration::from_secs(100))
+            .unwrap();
+        db.create(&make_key("early"), t0).unwrap();
+        db.log_content_written(&make_key("late"), t0 + Duration::from_secs(150))
+            .unwrap();
+
+        let times: Vec<SystemTime> = db
+            .oplog_since(0, usize::MAX)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.at)
+            .collect();
+        assert_eq!(
+            times,
+            vec![
+                t0 + Duration::from_secs(100),
+                t0 + Duration::from_secs(100),
+                t0 + Duration::from_secs(150),
+            ]
+        );
+
+        // The early entry logged after the late one isn't compacted ahead of it
+        assert_eq!(db.compact_oplog(t0 + Duration::from_secs(50)).unwrap(), 0);
+        assert_eq!(db.compact_oplog(t0 + Duration::from_secs(120)).unwrap(), 2);
+    }
+}
+
+mod generation {
+    use super::*;
+
+    #[test]
+    fn test_generation_starts_at_zero() {
+        let (db, _temp) = create_test_db();
+        assert_eq!(db.generation().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_key_set_changes_bump_generation() {
+        let (mut db, _temp) = create_test_db();
+        let key = make_key("key");
+        let renamed = make_key("renamed");
+        let now = SystemTime::now();
+
+        db.create(&key, now).unwrap();
+        assert_eq!(db.generation().unwrap(), 1);
+
+        db.trash(&key, now).unwrap();
+        assert_eq!(db.generation().unwrap(), 2);
+
+        db.restore(&key, now).unwrap();
+        assert_eq!(db.generation().unwrap(), 3);
+
+        db.rename(&key, &renamed, now).unwrap();
+        assert_eq!(db.generation().unwrap(), 4);
+
+        db.purge(&renamed).unwrap();
+        assert_eq!(db.generation().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_touch_bumps_generation() {
+        let (mut db, _temp) = create_test_db();
+        let key = make_key("key");
+        let now = SystemTime::now();
+
+        db.create(&key, now).unwrap();
+        db.touch(&key, now).unwrap();
+
+        assert_eq!(db.generation().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_attachments_do_not_bump_generation() {
+        let (mut db, _temp) = create_test_db();
+        let key = make_key("key");
+        let now = SystemTime::now();
+
+        db.create(&key, now).unwrap();
+        db.add_attachment(
+            &key,
+            Attachment {
+                filename: "file.txt".to_string(),
+                size: 1,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
+            },
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(db.generation().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_failed_operation_does_not_bump_generation() {
+        let (mut db, _temp) = create_test_db();
+        let key = make_key("key");
+        let now = SystemTime::now();
+
+        db.create(&key, now).unwrap();
+        db.create(&key, now).unwrap_err();
+        db.restore(&key, now).unwrap_err();
+
+        assert_eq!(db.generation().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_gc_bumps_generation_only_when_keys_change() {
+        let (mut db, _temp) = create_test_db();
+        let gc_config = make_gc_config(100, 50);
+        let key = make_key("key");
+        let now = SystemTime::now();
+
+        db.create(&key, now).unwrap();
+
+        db.gc(now, gc_config).unwrap();
+        assert_eq!(db.generation().unwrap(), 1);
+
+        db.gc(now + Duration::from_secs(101), gc_config).unwrap();
+        assert_eq!(db.generation().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_generation_persists_across_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = Config {
+            base_path: temp_dir.path().to_path_buf(),
+        };
+
+        {
+            let mut db = Database::new(config.clone(), None).unwrap();
+            db.create(&make_key("key"), SystemTime::now()).unwrap();
+        }
+
+        let db = Database::new(config, None).unwrap();
+        assert_eq!(db.generation().unwrap(), 1);
+    }
+}
+
 mod edge_cases {
     use super::*;
     use common::{create_test_db, make_gc_config, make_key};
@@ -1016,6 +1733,11 @@ mod edge_cases {
             Attachment {
                 filename: "file1.txt".to_string(),
                 size: 100,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             now,
         )
@@ -1025,6 +1747,11 @@ mod edge_cases {
             Attachment {
                 filename: "file2.txt".to_string(),
                 size: 200,
+                image: None,
+                mime: None,
+                hash: None,
+                added_at: None,
+                modified_at: None,
             },
             now,
         )
@@ -1083,6 +1810,8 @@ mod edge_cases {
                 lifecycle_state: LifecycleState::Active {
                     last_accessed: stale_time
                 },
+                access_count: 1,
+                seal: None,
             }
         );
 
@@ -1113,6 +1842,8 @@ mod edge_cases {
                 lifecycle_state: LifecycleState::Active {
                     last_accessed: stale_time
                 },
+                access_count: 0,
+                seal: None,
             }
         );
 
@@ -0,0 +1,297 @@
+//! Write transactions grouping several key operations.
+
+use super::{Codec, Database, MAIN_TABLE, Operation};
+use crate::core::db::error::DatabaseError;
+use crate::core::file_storage::FileStorage;
+use crate::types::Key;
+use crate::types::value::versioned_value::latest_value::{LifecycleState, Metadata, Value};
+use redb::ReadableTable;
+use std::time::SystemTime;
+
+/// A redb write transaction over key operations.
+///
+/// Nothing is persisted until [`Transaction::commit`]; dropping the transaction aborts it.
+pub struct Transaction {
+    txn: redb::WriteTransaction,
+    codec: Codec,
+    generation_changed: bool,
+}
+
+impl Database {
+    /// Begins a write transaction.
+    pub fn begin(&mut self) -> Result<Transaction, DatabaseError> {
+        Ok(Transaction {
+            txn: self.db.begin_write()?,
+            codec: self.codec.clone(),
+            generation_changed: false,
+        })
+    }
+}
+
+impl Transaction {
+    /// Commits the transaction, bumping the generation if the key set or access stats changed.
+    pub fn commit(self) -> Result<(), DatabaseError> {
+        if self.generation_changed {
+            Database::bump_generation(&self.txn)?;
+        }
+        self.txn.commit()?;
+        Ok(())
+    }
+
+    fn log(&self, at: SystemTime, op: Operation) -> Result<u64, DatabaseError> {
+        Database::append_op(&self.txn, &self.codec, at, op)
+    }
+
+    /// Retrieves a value by key, including uncommitted changes.
+    pub fn get(&self, key: &Key) -> Result<Option<Value>, DatabaseError> {
+        let table = self.txn.open_table(MAIN_TABLE)?;
+        let value = table
+            .get(key)?
+            .map(|g| self.codec.decode(g.value()))
+            .transpose()?;
+        Ok(value)
+    }
+
+    /// Creates a new key with empty attachments.
+    ///
+    /// Returns `Err(AlreadyExists)` if the key already exists.
+    pub fn create(&mut self, key: &Key, now: SystemTime) -> Result<Value, DatabaseError> {
+        let new_value = Value {
+            metadata: Metadata {
+                lifecycle_state: LifecycleState::Active { last_accessed: now },
+                access_count: 0,
+                seal: None,
+            },
+            attachments: vec![],
+            thumb_version: FileStorage::THUMB_VER,
+        };
+
+        {
+            let mut main_table = self.txn.open_table(MAIN_TABLE)?;
+
+            if main_table.get(key)?.is_some() {
+                return Err(DatabaseError::AlreadyExists);
+            }
+
+            Database::insert_active_ttl(&self.txn, key, now)?;
+            main_table.insert(key, &self.codec.encode(new_value.clone()))?;
+        }
+        self.log(now, Operation::Created { key: key.clone() })?;
+
+        self.generation_changed = true;
+        Ok(new_value)
+    }
+
+    /// Puts back a previously purged value unchanged, e.g. when undoing a purge.
+    ///
+    /// Returns `Err(AlreadyExists)` if the key already exists.
+    pub fn reinsert(
+        &mut self,
+        key: &Key,
+        value: Value,
+        now: SystemTime,
+    ) -> Result<(), DatabaseError> {
+        {
+            let mut main_table = self.txn.open_table(MAIN_TABLE)?;
+
+            if main_table.get(key)?.is_some() {
+                return Err(DatabaseError::AlreadyExists);
+            }
+
+            match value.metadata.lifecycle_state {
+                LifecycleState::Active { last_accessed } => {
+                    Database::insert_active_ttl(&self.txn, key, last_accessed)?;
+                }
+                LifecycleState::Trash { trashed_at } => {
+                    Database::insert_trash_ttl(&self.txn, key, trashed_at)?;
+                }
+            }
+            main_table.insert(key, &self.codec.encode(value))?;
+        }
+        self.log(now, Operation::Reinserted { key: key.clone() })?;
+
+        self.generation_changed = true;
+        Ok(())
+    }
+
+    /// Updates `last_accessed` timestamp and increments `access_count`.
+    ///
+    /// Returns `Err(NotFound)` if the key doesn't exist.
+    /// Returns `Err(Trashed)` if the key is trashed.
+    pub fn touch(&mut self, key: &Key, now: SystemTime) -> Result<Value, DatabaseError> {
+        let mut main_table = self.txn.open_table(MAIN_TABLE)?;
+
+        let mut value = main_table
+            .get(key)?
+            .map(|g| self.codec.decode(g.value()))
+            .transpose()?
+            .ok_or(DatabaseError::NotFound)?;
+
+        let LifecycleState::Active { last_accessed } = value.metadata.lifecycle_state else {
+            return Err(DatabaseError::Trashed);
+        };
+
+        Database::remove_active_ttl(&self.txn, key, last_accessed)?;
+        Database::insert_active_ttl(&self.txn, key, now)?;
+
+        value.metadata.lifecycle_state = LifecycleState::Active { last_accessed: now };
+        value.metadata.access_count += 1;
+
+        main_table.insert(key, &self.codec.encode(value.clone()))?;
+        self.log(now, Operation::Touched { key: key.clone() })?;
+        // Persisted search indexes carry access stats for frecency ranking
+        self.generation_changed = true;
+        Ok(value)
+    }
+
+    /// Renames a key, optionally overwriting destination.
+    ///
+    /// Returns `Err(NotFound)` if src doesn't exist.
+    pub fn rename(&mut self, src: &Key, dst: &Key, now: SystemTime) -> Result<(), DatabaseError> {
+        {
+            let mut main_table = self.txn.open_table(MAIN_TABLE)?;
+
+            // Extract destination lifecycle state before mutating
+            let dest_state = main_table
+                .get(dst)?
+                .map(|g| self.codec.decode(g.value()))
+                .transpose()?
+                .map(|value| value.metadata.lifecycle_state);
+
+            // Clean up destination if it exists
+            if let Some(state) = dest_state {
+                match state {
+                    LifecycleState::Active { last_accessed } => {
+                        Database::remove_active_ttl(&self.txn, dst, last_accessed)?;
+                    }
+                    LifecycleState::Trash { trashed_at } => {
+                        Database::remove_trash_ttl(&self.txn, dst, trashed_at)?;
+                    }
+                }
+                main_table.remove(dst)?;
+            }
+
+            // Get and remove source
+            let mut value = main_table
+                .remove(src)?
+                .map(|g| self.codec.decode(g.value()))
+                .transpose()?
+                .ok_or(DatabaseError::NotFound)?;
+
+            // Remove old TTL entry and insert new one
+            match value.metadata.lifecycle_state {
+                LifecycleState::Active { last_accessed } => {
+                    Database::remove_active_ttl(&self.txn, src, last_accessed)?;
+                    Database::insert_active_ttl(&self.txn, dst, now)?;
+                    value.metadata.lifecycle_state = LifecycleState::Active { last_accessed: now };
+                }
+                LifecycleState::Trash { trashed_at } => {
+                    Database::remove_trash_ttl(&self.txn, src, trashed_at)?;
+                    Database::insert_trash_ttl(&self.txn, dst, trashed_at)?;
+                }
+            }
+
+            main_table.insert(dst, &self.codec.encode(value))?;
+        }
+        self.log(
+            now,
+            Operation::Renamed {
+                from: src.clone(),
+                to: dst.clone(),
+            },
+        )?;
+
+        self.generation_changed = true;
+        Ok(())
+    }
+
+    /// Soft-deletes a key by moving it from Active to Trash state.
+    ///
+    /// Returns `Err(NotFound)` if the key doesn't exist.
+    /// Returns `Err(Trashed)` if the key is already trashed.
+    pub fn trash(&mut self, key: &Key, now: SystemTime) -> Result<(), DatabaseError> {
+        {
+            let mut main_table = self.txn.open_table(MAIN_TABLE)?;
+
+            let mut value = main_table
+                .get(key)?
+                .map(|g| self.codec.decode(g.value()))
+                .transpose()?
+                .ok_or(DatabaseError::NotFound)?;
+
+            let LifecycleState::Active { last_accessed } = value.metadata.lifecycle_state else {
+                return Err(DatabaseError::Trashed);
+            };
+
+            Database::remove_active_ttl(&self.txn, key, last_accessed)?;
+            Database::insert_trash_ttl(&self.txn, key, now)?;
+
+            value.metadata.lifecycle_state = LifecycleState::Trash { trashed_at: now };
+
+            main_table.insert(key, &self.codec.encode(value))?;
+        }
+        self.log(now, Operation::Trashed { key: key.clone() })?;
+
+        self.generation_changed = true;
+        Ok(())
+    }
+
+    /// Restores a key from Trash to Active state.
+    ///
+    /// Returns `Err(NotFound)` if the key doesn't exist.
+    /// Returns `Err(NotTrashed)` if the key is not trashed.
+    pub fn restore(&mut self, key: &Key, now: SystemTime) -> Result<(), DatabaseError> {
+        {
+            let mut main_table = self.txn.open_table(MAIN_TABLE)?;
+
+            let mut value = main_table
+                .get(key)?
+                .map(|g| self.codec.decode(g.value()))
+                .transpose()?
+                .ok_or(DatabaseError::NotFound)?;
+
+            let LifecycleState::Trash { trashed_at } = value.metadata.lifecycle_state else {
+                return Err(DatabaseError::NotTrashed);
+            };
+
+            Database::remove_trash_ttl(&self.txn, key, trashed_at)?;
+            Database::insert_active_ttl(&self.txn, key, now)?;
+
+            value.metadata.lifecycle_state = LifecycleState::Active { last_accessed: now };
+
+            main_table.insert(key, &self.codec.encode(value))?;
+        }
+        self.log(now, Operation::Restored { key: key.clone() })?;
+
+        self.generation_changed = true;
+        Ok(())
+    }
+
+    /// Permanently deletes a key from the database.
+    ///
+    /// Returns `Err(NotFound)` if the key doesn't exist.
+    pub fn purge(&mut self, key: &Key) -> Result<(), DatabaseError> {
+        {
+            let mut main_table = self.txn.open_table(MAIN_TABLE)?;
+
+            let value = main_table
+                .remove(key)?
+                .map(|g| self.codec.decode(g.value()))
+                .transpose()?
+                .ok_or(DatabaseError::NotFound)?;
+
+            match value.metadata.lifecycle_state {
+                LifecycleState::Active { last_accessed } => {
+                    Database::remove_active_ttl(&self.txn, key, last_accessed)?;
+                }
+                LifecycleState::Trash { trashed_at } => {
+                    Database::remove_trash_ttl(&self.txn, key, trashed_at)?;
+                }
+            }
+        }
+        self.log(SystemTime::now(), Operation::Purged { key: key.clone() })?;
+
+        self.generation_changed = true;
+        Ok(())
+    }
+}
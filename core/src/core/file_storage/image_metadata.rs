@@ -0,0 +1,208 @@
+//! Image properties read when an attachment is added.
+
+use crate::types::value::versioned_value::latest_value::ImageMetadata;
+use image::metadata::Orientation;
+use image::{ImageDecoder, ImageReader};
+use std::io::{BufRead, Seek};
+
+/// Extensions whose header is read by [`read_image_metadata`].
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "bmp", "tif", "tiff"];
+
+const TAG_MAKE: u16 = 0x010f;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+
+/// Reads the dimensions of an image attachment and, from its EXIF data, when it was taken and
+/// with which camera. Only the header is decoded.
+///
+/// Returns `None` for files that aren't images by extension or can't be decoded.
+pub(crate) fn read_image_metadata(
+    filename: &str,
+    reader: impl BufRead + Seek,
+) -> Option<ImageMetadata> {
+    let (_, extension) = filename.rsplit_once('.')?;
+    if !IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()) {
+        return None;
+    }
+
+    let mut decoder = ImageReader::new(reader)
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    let (width, height) = decoder.dimensions();
+    let exif = decoder.exif_metadata().ok().flatten();
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let (width, height) = match orientation {
+        Orientation::Rotate90
+        | Orientation::Rotate270
+        | Orientation::Rotate90FlipH
+        | Orientation::Rotate270FlipH => (height, width),
+        _ => (width, height),
+    };
+
+    let tags = exif.as_deref().map(ExifTags::parse).unwrap_or_default();
+    Some(ImageMetadata {
+        width,
+        height,
+        captured_at: tags.captured_at,
+        camera: tags.camera,
+    })
+}
+
+/// The EXIF tags kept in [`ImageMetadata`].
+#[derive(Default)]
+struct ExifTags {
+    captured_at: Option<String>,
+    camera: Option<String>,
+}
+
+impl ExifTags {
+    /// Parses a raw EXIF chunk, which is laid out like a TIFF file. Unreadable tags are skipped.
+    fn parse(chunk: &[u8]) -> Self {
+        let Some(tiff) = Tiff::new(chunk) else {
+            return Self::default();
+        };
+
+        let (mut make, mut model, mut exif_ifd) = (None, None, None);
+        for entry in tiff.entries(tiff.u32(4)) {
+            match entry.tag {
+                TAG_MAKE => make = tiff.ascii(&entry),
+                TAG_MODEL => model = tiff.ascii(&entry),
+                TAG_EXIF_IFD if entry.kind == TYPE_LONG => exif_ifd = tiff.u32(entry.value_at),
+                _ => {}
+            }
+        }
+        let captured_at = exif_ifd
+            .and_then(|offset| {
+                tiff.entries(Some(offset))
+                    .into_iter()
+                    .find(|entry| entry.tag == TAG_DATE_TIME_ORIGINAL)
+            })
+            .and_then(|entry| tiff.ascii(&entry))
+            .and_then(|date| format_exif_date(&date));
+
+        Self {
+            captured_at,
+            camera: camera_name(make, model),
+        }
+    }
+}
+
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: usize,
+    /// Position of the value, or of the offset to it if it doesn't fit in 4 bytes.
+    value_at: usize,
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let big_endian = match data.get(..4)? {
+            b"II*\0" => false,
+            b"MM\0*" => true,
+            _ => return None,
+        };
+        Some(Self { data, big_endian })
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes = self.data.get(at..at.checked_add(2)?)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, at: usize) -> Option<usize> {
+        let bytes = self.data.get(at..at.checked_add(4)?)?.try_into().ok()?;
+        let value = if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        };
+        usize::try_from(value).ok()
+    }
+
+    /// Entries of the directory at `offset`; empty if it is out of bounds.
+    fn entries(&self, offset: Option<usize>) -> Vec<Entry> {
+        let Some(offset) = offset else {
+            return Vec::new();
+        };
+        let count = self.u16(offset).unwrap_or(0);
+        (0..usize::from(count))
+            .map_while(|i| {
+                let at = offset + 2 + i * 12;
+                Some(Entry {
+                    tag: self.u16(at)?,
+                    kind: self.u16(at + 2)?,
+                    count: self.u32(at + 4)?,
+                    value_at: at + 8,
+                })
+            })
+            .collect()
+    }
+
+    /// Reads an ASCII entry, trimmed of padding; `None` if empty.
+    fn ascii(&self, entry: &Entry) -> Option<String> {
+        if entry.kind != TYPE_ASCII {
+            return None;
+        }
+        let len = entry.count;
+        let start = match len {
+            ..=4 => entry.value_at,
+            _ => self.u32(entry.value_at)?,
+        };
+        let bytes = self.data.get(start..start.checked_add(len)?)?;
+        let text = String::from_utf8_lossy(bytes);
+        let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        (!text.is_empty()).then(|| text.to_string())
+    }
+}
+
+/// Converts an EXIF date (`YYYY:MM:DD HH:MM:SS`) to `YYYY-MM-DDTHH:MM:SS`. Cameras without a
+/// clock write zeros or spaces, which give `None`.
+fn format_exif_date(date: &str) -> Option<String> {
+    let bytes = date.as_bytes().get(..19)?;
+    let layout_ok = bytes.iter().enumerate().all(|(i, &b)| match i {
+        4 | 7 => b == b':',
+        10 => b == b' ',
+        13 | 16 => b == b':',
+        _ => b.is_ascii_digit(),
+    });
+    if !layout_ok || date.starts_with("0000") {
+        return None;
+    }
+    let (day, time) = date[..19].split_at(10);
+    Some(format!("{}T{}", day.replace(':', "-"), time.trim_start()))
+}
+
+/// Joins make and model, leaving out the make when the model already starts with it (e.g.
+/// `Canon` + `Canon EOS R5`, or `NIKON CORPORATION` + `NIKON D750`).
+fn camera_name(make: Option<String>, model: Option<String>) -> Option<String> {
+    match (make, model) {
+        (Some(make), Some(model)) => {
+            let brand = make.split_whitespace().next().unwrap_or_default();
+            let prefixed = model
+                .get(..brand.len())
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case(brand));
+            Some(if prefixed {
+                model
+            } else {
+                format!("{make} {model}")
+            })
+        }
+        (make, model) => make.or(model),
+    }
+}
@@ -0,0 +1,106 @@
+//! MIME type detection from file contents.
+
+/// Number of leading bytes [`sniff_mime`] looks at.
+pub(crate) const SNIFF_LEN: usize = 512;
+
+/// Used when nothing matches.
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Leading magic bytes and the MIME type they identify.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"II*\0", "image/tiff"),
+    (b"MM\0*", "image/tiff"),
+    (b"\0\0\x01\0", "image/x-icon"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (b"Rar!\x1a\x07", "application/vnd.rar"),
+    (b"ID3\x02", "audio/mpeg"),
+    (b"ID3\x03", "audio/mpeg"),
+    (b"ID3\x04", "audio/mpeg"),
+    (b"fLaC", "audio/flac"),
+    (b"OggS", "audio/ogg"),
+    (b"\x1a\x45\xdf\xa3", "video/webm"),
+    (b"SQLite format 3\0", "application/vnd.sqlite3"),
+    (b"wOFF", "font/woff"),
+    (b"wOF2", "font/woff2"),
+];
+
+/// ISO base media (`ftyp`) brands: MP4, QuickTime, HEIF and AVIF.
+const FTYP_BRANDS: &[(&[u8], &str)] = &[
+    (b"avif", "image/avif"),
+    (b"heic", "image/heic"),
+    (b"heix", "image/heic"),
+    (b"mif1", "image/heif"),
+    (b"qt  ", "video/quicktime"),
+    (b"M4A ", "audio/mp4"),
+];
+
+/// Detects the MIME type of a file from its first [`SNIFF_LEN`] bytes, ignoring its name.
+///
+/// Binary formats are recognized by their signature. Other files are `text/plain` if they are
+/// UTF-8 without NUL bytes (SVG is told apart by its root element), and
+/// `application/octet-stream` otherwise.
+pub(crate) fn sniff_mime(head: &[u8]) -> &'static str {
+    let head = &head[..head.len().min(SNIFF_LEN)];
+
+    if head.get(4..8) == Some(b"ftyp") {
+        let brand = head.get(8..12).unwrap_or_default();
+        return FTYP_BRANDS
+            .iter()
+            .find(|(known, _)| *known == brand)
+            .map_or("video/mp4", |&(_, mime)| mime);
+    }
+    if head.starts_with(b"RIFF") {
+        match head.get(8..12) {
+            Some(b"WEBP") => return "image/webp",
+            Some(b"WAVE") => return "audio/wav",
+            Some(b"AVI ") => return "video/x-msvideo",
+            _ => {}
+        }
+    }
+    if let Some(&(_, mime)) = SIGNATURES.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return mime;
+    }
+    // "BM" alone would match text; the header's reserved bytes are zero.
+    if head.starts_with(b"BM") && head.get(6..10) == Some(&[0; 4]) {
+        return "image/bmp";
+    }
+
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        // A multi-byte character cut off by the end of `head`
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()])
+            .expect("prefix up to valid_up_to is UTF-8"),
+        Err(_) => return OCTET_STREAM,
+    };
+    if text.contains('\0') {
+        return OCTET_STREAM;
+    }
+    if is_svg(text) {
+        return "image/svg+xml";
+    }
+    "text/plain; charset=utf-8"
+}
+
+/// Whether the first element of an XML document is `<svg`, skipping the declaration,
+/// comments and doctype before it.
+fn is_svg(text: &str) -> bool {
+    let mut rest = text.trim_start_matches('\u{feff}').trim_start();
+    while let Some(tail) = rest.strip_prefix("<?").or_else(|| rest.strip_prefix("<!")) {
+        let end = match tail.strip_prefix("--") {
+            Some(comment) => comment.find("-->").map(|i| i + 5),
+            None => tail.find('>').map(|i| i + 1),
+        };
+        let Some(end) = end else {
+            return false;
+        };
+        rest = tail[end..].trim_start();
+    }
+    rest.starts_with("<svg")
+}
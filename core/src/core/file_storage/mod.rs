@@ -1,4 +1,24 @@
+use crate::core::crypto::{self, Cipher, DecryptReader, EncryptWriter};
+use crate::core::usage::Usage;
+use crate::types::value::versioned_value::latest_value::ImageMetadata;
+use crate::types::{ThumbnailConfig, ThumbnailFormat, ThumbnailPreset};
+use image::DynamicImage;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+mod image_metadata;
+mod mime;
+mod pdf_render;
+mod text;
+mod thumbnail;
+
+pub(crate) use image_metadata::read_image_metadata;
+pub use text::{MAX_PDF_LEN, MAX_TEXT_LEN};
+pub use thumbnail::{ThumbnailRegistry, ThumbnailRenderer};
 
 pub mod error {
     use thiserror::Error;
@@ -20,17 +40,96 @@ pub mod error {
         #[error("Resize error: {0}")]
         Resize(#[from] fast_image_resize::ResizeError),
 
+        #[error("SVG error: {0}")]
+        Svg(#[from] resvg::usvg::Error),
+
+        #[error("PDF error: {0}")]
+        Pdf(#[from] lopdf::Error),
+
         #[error("Unsupported image format")]
         UnsupportedFormat,
+
+        #[error("File is larger than {limit} bytes")]
+        TooLarge { size: u64, limit: u64 },
+
+        #[error("Crypto error: {0}")]
+        Crypto(#[from] crate::core::crypto::error::CryptoError),
     }
 }
 
 use error::FileStorageError;
 
+#[derive(Clone)]
 pub struct FileStorage {
     pub content_path: PathBuf,
     pub blobs_path: PathBuf,
     pub thumbnails_path: PathBuf,
+    pub text_path: PathBuf,
+    /// Encrypts content, blobs, thumbnails and extracted text when set.
+    pub cipher: Option<Cipher>,
+    /// Renderers for the attachment formats that get thumbnails.
+    pub thumbnailers: Arc<ThumbnailRegistry>,
+    /// Sizes and encoding of generated thumbnails.
+    pub thumbnail_config: ThumbnailConfig,
+}
+
+/// What is recorded about an attachment's contents when it is added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub hash: blake3::Hash,
+    /// Sniffed from the first bytes, not the filename.
+    pub mime: &'static str,
+    pub size: u64,
+}
+
+/// Copies `reader` into `writer`, fingerprinting the data as it passes.
+///
+/// Stops with `Err(TooLarge)` as soon as more than `max_size` bytes have been read, so an
+/// oversized upload is never written out in full.
+fn copy_fingerprinted(
+    mut reader: impl Read,
+    writer: &mut impl Write,
+    max_size: Option<u64>,
+) -> Result<Fingerprint, FileStorageError> {
+    let mut hasher = blake3::Hasher::new();
+    let mut head = Vec::with_capacity(mime::SNIFF_LEN);
+    let mut size = 0;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let chunk = &buf[..n];
+        hasher.update(chunk);
+        let missing = mime::SNIFF_LEN - head.len();
+        head.extend_from_slice(&chunk[..missing.min(n)]);
+        size += n as u64;
+        if let Some(limit) = max_size.filter(|&limit| size > limit) {
+            return Err(FileStorageError::TooLarge { size, limit });
+        }
+        writer.write_all(chunk)?;
+    }
+    Ok(Fingerprint {
+        hash: hasher.finalize(),
+        mime: mime::sniff_mime(&head),
+        size,
+    })
+}
+
+/// An attachment written to a temporary file, which replaces the attachment on
+/// [`StagedAttachment::finish`]. Dropping it discards the data.
+pub struct StagedAttachment {
+    pub fingerprint: Fingerprint,
+    writer: FileWriter,
+}
+
+impl StagedAttachment {
+    pub fn finish(self) -> Result<(), FileStorageError> {
+        self.writer.finish()
+    }
 }
 
 fn remove_dir_if_empty(path: &Path) -> Result<(), FileStorageError> {
@@ -40,6 +139,41 @@ fn remove_dir_if_empty(path: &Path) -> Result<(), FileStorageError> {
     Ok(())
 }
 
+/// Returns a unique temporary path beside `path` to write it at before renaming into place.
+///
+/// The name starts with a dot and doesn't depend on `path`'s, so it can't be the name of an
+/// attachment stored beside it, such as `a.pdf.tmp` next to `a.pdf`.
+fn staging_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    path.with_file_name(format!(".{:x}-{n:x}.tmp", std::process::id()))
+}
+
+/// Exchanges the files or directories at `a` and `b`, either of which may be missing.
+fn swap(a: &Path, b: &Path) -> Result<(), FileStorageError> {
+    let mut tmp = b.as_os_str().to_owned();
+    tmp.push(".swap");
+    let tmp = PathBuf::from(tmp);
+
+    let had_a = a.exists();
+    if had_a {
+        if let Some(parent) = b.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(a, &tmp)?;
+    }
+    if b.exists() {
+        if let Some(parent) = a.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(b, a)?;
+    }
+    if had_a {
+        std::fs::rename(&tmp, b)?;
+    }
+    Ok(())
+}
+
 /// Content file operations.
 impl FileStorage {
     pub fn create_content(&self, key_hash: &Path) -> Result<(), FileStorageError> {
@@ -47,7 +181,10 @@ impl FileStorage {
         if let Some(parent) = content_file.parent() {
             std::fs::create_dir_all(parent)?;
         }
-        std::fs::File::create(&content_file)?;
+        let file = File::create(&content_file)?;
+        if let Some(cipher) = &self.cipher {
+            cipher.encrypt_writer(file)?.finish()?;
+        }
         Ok(())
     }
 
@@ -66,24 +203,46 @@ impl FileStorage {
 
 /// Attachment file operations.
 impl FileStorage {
-    pub fn add_attachment(
+    /// Streams `reader` into a temporary file next to the attachment, hashing and measuring it
+    /// on the way. The attachment itself is only replaced by [`StagedAttachment::finish`].
+    ///
+    /// Fails with `Err(TooLarge)`, discarding what was written, once the data exceeds
+    /// `max_size` bytes.
+    pub fn write_attachment(
         &self,
         key_hash: &Path,
-        source: &Path,
         filename: &str,
-    ) -> Result<u64, FileStorageError> {
-        let metadata = std::fs::metadata(source)?;
-        if metadata.is_dir() {
+        reader: impl Read,
+        max_size: Option<u64>,
+    ) -> Result<StagedAttachment, FileStorageError> {
+        let mut writer = self.create_file(&self.attachment_path(key_hash, filename))?;
+        let fingerprint = copy_fingerprinted(reader, &mut writer, max_size)?;
+        Ok(StagedAttachment {
+            fingerprint,
+            writer,
+        })
+    }
+
+    /// Returns the hash, MIME type and size of a file outside the store.
+    pub fn fingerprint(source: &Path) -> Result<Fingerprint, FileStorageError> {
+        if std::fs::metadata(source)?.is_dir() {
             return Err(FileStorageError::IsDirectory);
         }
+        copy_fingerprinted(File::open(source)?, &mut io::sink(), None)
+    }
 
-        let dest_dir = self.blobs_path.join(key_hash);
-        std::fs::create_dir_all(&dest_dir)?;
-
-        let dest_path = dest_dir.join(filename);
-        std::fs::copy(source, &dest_path)?;
-
-        Ok(metadata.len())
+    /// Reads the image metadata of a stored attachment, decrypting it if needed.
+    pub(crate) fn read_attachment_image_metadata(
+        &self,
+        key_hash: &Path,
+        filename: &str,
+    ) -> Option<ImageMetadata> {
+        let mut bytes = Vec::new();
+        self.open_file(&self.attachment_path(key_hash, filename))
+            .ok()?
+            .read_to_end(&mut bytes)
+            .ok()?;
+        read_image_metadata(filename, io::Cursor::new(bytes))
     }
 
     pub fn attachment_path(&self, key_hash: &Path, filename: &str) -> PathBuf {
@@ -132,84 +291,165 @@ impl FileStorage {
 }
 
 /// Thumbnail operations.
+///
+/// An attachment's thumbnails live in `thumbnails/{key_hash}/{filename}.thumbs/`, one file per
+/// preset and scale named `{preset}[@{scale}x].{ext}`, e.g. `grid.png` and `grid@2x.png`.
+/// Versions before 3 stored a single 200px PNG at `thumbnails/{key_hash}/{filename}.thumb`,
+/// which is served as the 1x grid thumbnail until regenerated.
 impl FileStorage {
-    pub(crate) const THUMB_VER: u32 = 1;
-    const THUMB_SIZE: u32 = 200;
-    const SUPPORTED_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp"];
+    /// Increment when adding new format support or changing thumbnail generation.
+    ///
+    /// 2: BMP, TIFF, SVG and PDF renderers.
+    /// 3: Size presets, 2x variants and WebP/JPEG encoding.
+    /// 4: EXIF orientation.
+    /// 5: PDF first pages rendered rather than taken from embedded images.
+    pub(crate) const THUMB_VER: u32 = 5;
 
-    pub fn is_supported_image(filename: &str) -> bool {
-        let ext = filename
-            .rsplit('.')
-            .next()
-            .map(str::to_ascii_lowercase)
-            .unwrap_or_default();
-        Self::SUPPORTED_EXTENSIONS.contains(&ext.as_str())
+    /// Whether a renderer is registered for the file's extension.
+    pub fn supports_thumbnail(&self, filename: &str) -> bool {
+        self.thumbnailers.supports(filename)
     }
 
-    /// Returns relative path: `{key_hash}/{filename}.thumb`
-    pub fn thumbnail_rel_path(key_hash: &Path, filename: &str) -> PathBuf {
-        key_hash.join(format!("{}.thumb", filename))
+    /// Returns the relative directory of an attachment's thumbnails:
+    /// `{key_hash}/{filename}.thumbs`
+    pub fn thumbnail_rel_dir(key_hash: &Path, filename: &str) -> PathBuf {
+        key_hash.join(format!("{filename}.thumbs"))
     }
 
-    pub fn thumbnail_path(&self, key_hash: &Path, filename: &str) -> PathBuf {
+    pub fn thumbnail_dir(&self, key_hash: &Path, filename: &str) -> PathBuf {
         self.thumbnails_path
-            .join(Self::thumbnail_rel_path(key_hash, filename))
+            .join(Self::thumbnail_rel_dir(key_hash, filename))
     }
 
-    /// Returns `Err(UnsupportedFormat)` if the file is not a supported image.
-    pub fn generate_thumbnail(
-        &self,
-        key_hash: &Path,
-        filename: &str,
-    ) -> Result<(), FileStorageError> {
-        if !Self::is_supported_image(filename) {
-            return Err(FileStorageError::UnsupportedFormat);
+    /// Path of the single thumbnail stored before version 3.
+    fn legacy_thumbnail_path(&self, key_hash: &Path, filename: &str) -> PathBuf {
+        self.thumbnails_path
+            .join(key_hash)
+            .join(format!("{filename}.thumb"))
+    }
+
+    /// Returns the stored thumbnails of an attachment as `(pixels, path)`, where `pixels` bounds
+    /// the longer side. Paths are relative to the thumbnails directory.
+    pub fn thumbnail_variants(&self, key_hash: &Path, filename: &str) -> Vec<(u32, PathBuf)> {
+        let mut variants = Vec::new();
+        if let Ok(entries) = std::fs::read_dir(self.thumbnail_dir(key_hash, filename)) {
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                if let Some(pixels) = name.to_str().and_then(variant_pixels) {
+                    let rel_dir = Self::thumbnail_rel_dir(key_hash, filename);
+                    variants.push((pixels, rel_dir.join(name)));
+                }
+            }
+        }
+        if self.legacy_thumbnail_path(key_hash, filename).exists() {
+            let rel_path = key_hash.join(format!("{filename}.thumb"));
+            variants.push((ThumbnailPreset::Grid.size(), rel_path));
         }
+        variants
+    }
 
-        let source_path = self.attachment_path(key_hash, filename);
-        let thumb_path = self.thumbnail_path(key_hash, filename);
+    /// Returns every stored thumbnail file of an attachment, as absolute paths.
+    pub fn thumbnail_files(&self, key_hash: &Path, filename: &str) -> Vec<PathBuf> {
+        self.thumbnail_variants(key_hash, filename)
+            .into_iter()
+            .map(|(_, rel_path)| self.thumbnails_path.join(rel_path))
+            .collect()
+    }
 
-        // Load source image
-        let src_image = image::open(&source_path)?;
-        let (src_width, src_height) = (src_image.width(), src_image.height());
+    /// Returns the stored thumbnail that best fits `preset` at `scale`: the smallest at least
+    /// as large, or else the largest. The path is relative to the thumbnails directory.
+    pub fn best_thumbnail(
+        &self,
+        key_hash: &Path,
+        filename: &str,
+        preset: ThumbnailPreset,
+        scale: u32,
+    ) -> Option<PathBuf> {
+        let wanted = preset.size() * scale.max(1);
+        let variants = self.thumbnail_variants(key_hash, filename);
+        let large_enough = variants
+            .iter()
+            .filter(|(pixels, _)| *pixels >= wanted)
+            .min_by_key(|(pixels, _)| *pixels);
+        large_enough
+            .or_else(|| variants.iter().max_by_key(|(pixels, _)| *pixels))
+            .map(|(_, rel_path)| rel_path.clone())
+    }
 
-        // Calculate target dimensions preserving aspect ratio
-        let scale = (Self::THUMB_SIZE as f32 / src_width.max(src_height) as f32).min(1.0);
-        let dst_width = ((src_width as f32 * scale) as u32).max(1);
-        let dst_height = ((src_height as f32 * scale) as u32).max(1);
+    /// Renders the attachment with the renderer registered for its extension and scales it to
+    /// every configured preset and scale, returning `(file name, encoded image)` pairs; store
+    /// them with [`FileStorage::write_thumbnails`].
+    ///
+    /// Returns `Err(UnsupportedFormat)` if no renderer is registered for the file.
+    pub fn render_thumbnails(
+        &self,
+        key_hash: &Path,
+        filename: &str,
+    ) -> Result<Vec<(String, Vec<u8>)>, FileStorageError> {
+        let renderer = self
+            .thumbnailers
+            .renderer_for(filename)
+            .ok_or(FileStorageError::UnsupportedFormat)?;
 
-        // Create destination image
-        let mut dst_image = image::DynamicImage::new(dst_width, dst_height, src_image.color());
+        let config = &self.thumbnail_config;
+        let mut targets: Vec<(ThumbnailPreset, u32)> = config
+            .presets
+            .iter()
+            .flat_map(|&preset| config.scales().iter().map(move |&scale| (preset, scale)))
+            .collect();
+        // Largest first, so each variant is scaled down from the previous one.
+        targets.sort_by_key(|&(preset, scale)| std::cmp::Reverse(preset.size() * scale));
+        targets.dedup();
+        let Some(&(largest, largest_scale)) = targets.first() else {
+            return Ok(Vec::new());
+        };
 
-        // Resize using fast_image_resize
-        let mut resizer = fast_image_resize::Resizer::new();
-        resizer.resize(
-            &src_image,
-            &mut dst_image,
-            Some(&fast_image_resize::ResizeOptions::new().resize_alg(
-                fast_image_resize::ResizeAlg::Convolution(fast_image_resize::FilterType::Lanczos3),
-            )),
-        )?;
+        // Render source image
+        let mut bytes = Vec::new();
+        self.open_file(&self.attachment_path(key_hash, filename))?
+            .read_to_end(&mut bytes)?;
+        let mut image = renderer.render(&bytes, largest.size() * largest_scale)?;
 
-        // Ensure thumbnail directory exists
-        if let Some(parent) = thumb_path.parent() {
-            std::fs::create_dir_all(parent)?;
+        let mut variants = Vec::with_capacity(targets.len());
+        for (preset, scale) in targets {
+            image = resize_to_fit(&image, preset.size() * scale)?;
+            let name = variant_name(preset, scale, config.format);
+            variants.push((name, encode(&image, config)?));
         }
+        Ok(variants)
+    }
 
-        // Save as PNG
-        dst_image.save_with_format(&thumb_path, image::ImageFormat::Png)?;
-
+    /// Stores thumbnails produced by [`FileStorage::render_thumbnails`], replacing all previous
+    /// ones of the attachment.
+    pub fn write_thumbnails(
+        &self,
+        key_hash: &Path,
+        filename: &str,
+        variants: &[(String, Vec<u8>)],
+    ) -> Result<(), FileStorageError> {
+        self.remove_thumbnail(key_hash, filename)?;
+        let dir = self.thumbnail_dir(key_hash, filename);
+        for (name, data) in variants {
+            let mut writer = self.create_file(&dir.join(name))?;
+            writer.write_all(data)?;
+            writer.finish()?;
+        }
         Ok(())
     }
 
+    /// Removes all thumbnails of an attachment.
     pub fn remove_thumbnail(
         &self,
         key_hash: &Path,
         filename: &str,
     ) -> Result<(), FileStorageError> {
-        let thumb_path = self.thumbnail_path(key_hash, filename);
-        if thumb_path.exists() {
-            std::fs::remove_file(&thumb_path)?;
+        let dir = self.thumbnail_dir(key_hash, filename);
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        let legacy_path = self.legacy_thumbnail_path(key_hash, filename);
+        if legacy_path.exists() {
+            std::fs::remove_file(&legacy_path)?;
         }
 
         // Clean up empty key directory
@@ -225,11 +465,19 @@ impl FileStorage {
         old_filename: &str,
         new_filename: &str,
     ) -> Result<(), FileStorageError> {
-        let old_path = self.thumbnail_path(key_hash, old_filename);
-        let new_path = self.thumbnail_path(key_hash, new_filename);
-
-        if old_path.exists() {
-            std::fs::rename(old_path, new_path)?;
+        for (old_path, new_path) in [
+            (
+                self.thumbnail_dir(key_hash, old_filename),
+                self.thumbnail_dir(key_hash, new_filename),
+            ),
+            (
+                self.legacy_thumbnail_path(key_hash, old_filename),
+                self.legacy_thumbnail_path(key_hash, new_filename),
+            ),
+        ] {
+            if old_path.exists() {
+                std::fs::rename(old_path, new_path)?;
+            }
         }
 
         Ok(())
@@ -244,12 +492,470 @@ impl FileStorage {
     }
 }
 
+/// Text operations.
+///
+/// Text extracted from an attachment for search lives in `text/{key_hash}/{filename}.txt`.
+/// Attachments of supported formats have the file once extracted, empty if nothing could be, so
+/// a missing file means the text hasn't been extracted yet.
+impl FileStorage {
+    pub fn supports_text(filename: &str) -> bool {
+        text::supports_text(filename)
+    }
+
+    pub fn text_file_path(&self, key_hash: &Path, filename: &str) -> PathBuf {
+        self.text_path
+            .join(key_hash)
+            .join(format!("{filename}.txt"))
+    }
+
+    /// Brings the text file of a just stored attachment up to date: plain text is extracted
+    /// right away, while formats that are read whole lose any stale text and are left for
+    /// `KevaCore::extract_missing_text`.
+    pub fn store_text(&self, key_hash: &Path, filename: &str) -> Result<(), FileStorageError> {
+        if text::extracts_on_store(filename) {
+            self.write_text(key_hash, filename)
+        } else {
+            self.remove_text(key_hash, filename)
+        }
+    }
+
+    /// Extracts the text of a stored attachment into its text file. Attachments of unsupported
+    /// formats get none.
+    pub fn write_text(&self, key_hash: &Path, filename: &str) -> Result<(), FileStorageError> {
+        if !Self::supports_text(filename) {
+            return self.remove_text(key_hash, filename);
+        }
+        let reader = self.open_file(&self.attachment_path(key_hash, filename))?;
+        let text = text::extract_text(filename, reader)?.unwrap_or_default();
+
+        let mut writer = self.create_file(&self.text_file_path(key_hash, filename))?;
+        writer.write_all(text.as_bytes())?;
+        writer.finish()
+    }
+
+    /// Reads the extracted text of an attachment, or `None` if there is no text file.
+    pub fn read_text(
+        &self,
+        key_hash: &Path,
+        filename: &str,
+    ) -> Result<Option<String>, FileStorageError> {
+        let path = self.text_file_path(key_hash, filename);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut bytes = Vec::new();
+        self.open_file(&path)?.read_to_end(&mut bytes)?;
+        Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+    }
+
+    pub fn remove_text(&self, key_hash: &Path, filename: &str) -> Result<(), FileStorageError> {
+        let path = self.text_file_path(key_hash, filename);
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        remove_dir_if_empty(&self.text_path.join(key_hash))
+    }
+
+    pub fn rename_text(
+        &self,
+        key_hash: &Path,
+        old_filename: &str,
+        new_filename: &str,
+    ) -> Result<(), FileStorageError> {
+        let old_path = self.text_file_path(key_hash, old_filename);
+        if old_path.exists() {
+            std::fs::rename(old_path, self.text_file_path(key_hash, new_filename))?;
+        }
+        Ok(())
+    }
+
+    pub fn remove_all_text(&self, key_hash: &Path) -> Result<(), FileStorageError> {
+        let dir_path = self.text_path.join(key_hash);
+        if dir_path.exists() {
+            std::fs::remove_dir_all(&dir_path)?;
+        }
+        Ok(())
+    }
+}
+
+/// File name of a thumbnail variant, e.g. `grid@2x.png`.
+fn variant_name(preset: ThumbnailPreset, scale: u32, format: ThumbnailFormat) -> String {
+    let extension = format.extension();
+    match scale {
+        1 => format!("{}.{extension}", preset.name()),
+        _ => format!("{}@{scale}x.{extension}", preset.name()),
+    }
+}
+
+/// Parses a variant file name back into the pixel bound it was rendered for.
+fn variant_pixels(name: &str) -> Option<u32> {
+    let (stem, _extension) = name.rsplit_once('.')?;
+    let (preset, scale) = match stem.split_once('@') {
+        Some((preset, scale)) => (preset, scale.strip_suffix('x')?.parse().ok()?),
+        None => (stem, 1),
+    };
+    Some(ThumbnailPreset::from_name(preset)?.size() * scale)
+}
+
+/// Scales `image` down to fit `max_size`, preserving the aspect ratio. Smaller images are kept
+/// as they are.
+fn resize_to_fit(image: &DynamicImage, max_size: u32) -> Result<DynamicImage, FileStorageError> {
+    let (src_width, src_height) = (image.width(), image.height());
+    let scale = (max_size as f32 / src_width.max(src_height) as f32).min(1.0);
+    let dst_width = ((src_width as f32 * scale) as u32).max(1);
+    let dst_height = ((src_height as f32 * scale) as u32).max(1);
+    if (dst_width, dst_height) == (src_width, src_height) {
+        return Ok(image.clone());
+    }
+
+    let mut resized = DynamicImage::new(dst_width, dst_height, image.color());
+    let mut resizer = fast_image_resize::Resizer::new();
+    resizer.resize(
+        image,
+        &mut resized,
+        Some(&fast_image_resize::ResizeOptions::new().resize_alg(
+            fast_image_resize::ResizeAlg::Convolution(fast_image_resize::FilterType::Lanczos3),
+        )),
+    )?;
+    Ok(resized)
+}
+
+fn encode(image: &DynamicImage, config: &ThumbnailConfig) -> Result<Vec<u8>, FileStorageError> {
+    let mut data = Vec::new();
+    let mut cursor = io::Cursor::new(&mut data);
+    match config.format {
+        ThumbnailFormat::Png => image.write_to(&mut cursor, image::ImageFormat::Png)?,
+        ThumbnailFormat::Webp => {
+            // The WebP encoder only takes 8-bit samples.
+            let image = if image.color().has_alpha() {
+                DynamicImage::ImageRgba8(image.to_rgba8())
+            } else {
+                DynamicImage::ImageRgb8(image.to_rgb8())
+            };
+            image.write_to(&mut cursor, image::ImageFormat::WebP)?;
+        }
+        ThumbnailFormat::Jpeg => {
+            let mut rgba = image.to_rgba8();
+            for pixel in rgba.pixels_mut() {
+                let alpha = pixel[3] as u16;
+                for channel in &mut pixel.0[..3] {
+                    *channel = ((*channel as u16 * alpha + 255 * (255 - alpha)) / 255) as u8;
+                }
+            }
+            let rgb = DynamicImage::ImageRgba8(rgba).to_rgb8();
+            image::codecs::jpeg::JpegEncoder::new_with_quality(&mut cursor, config.jpeg_quality)
+                .encode_image(&rgb)?;
+        }
+    }
+    Ok(data)
+}
+
+/// Holding operations.
+///
+/// Undoable removals move files into a holding directory instead of deleting them. Undo and redo
+/// swap the held files with the live ones, so the same call both removes and restores.
+impl FileStorage {
+    /// Swaps a key's content file, attachments, thumbnails and extracted text with those held in
+    /// `holding`.
+    pub fn swap_key_files(&self, key_hash: &Path, holding: &Path) -> Result<(), FileStorageError> {
+        swap(
+            &self.content_file_path(key_hash),
+            &holding.join("content.md"),
+        )?;
+        swap(&self.blobs_path.join(key_hash), &holding.join("blobs"))?;
+        swap(
+            &self.thumbnails_path.join(key_hash),
+            &holding.join("thumbnails"),
+        )?;
+        swap(&self.text_path.join(key_hash), &holding.join("text"))
+    }
+
+    /// Swaps an attachment, its thumbnail and its extracted text with those held in `holding`.
+    pub fn swap_attachment_files(
+        &self,
+        key_hash: &Path,
+        filename: &str,
+        holding: &Path,
+    ) -> Result<(), FileStorageError> {
+        swap(
+            &self.attachment_path(key_hash, filename),
+            &holding.join("blob"),
+        )?;
+        swap(
+            &self.thumbnail_dir(key_hash, filename),
+            &holding.join("thumbs"),
+        )?;
+        swap(
+            &self.legacy_thumbnail_path(key_hash, filename),
+            &holding.join("thumb"),
+        )?;
+        swap(
+            &self.text_file_path(key_hash, filename),
+            &holding.join("text.txt"),
+        )?;
+
+        // Clean up empty key directories
+        remove_dir_if_empty(&self.blobs_path.join(key_hash))?;
+        remove_dir_if_empty(&self.thumbnails_path.join(key_hash))?;
+        remove_dir_if_empty(&self.text_path.join(key_hash))
+    }
+}
+
+/// Stream operations.
+///
+/// These read and write plaintext whether or not the store is encrypted.
+impl FileStorage {
+    pub fn open_file(&self, path: &Path) -> Result<FileReader, FileStorageError> {
+        let file = File::open(path)?;
+        let inner = match &self.cipher {
+            None => ReaderInner::Plain(file),
+            Some(cipher) => ReaderInner::Decrypting(cipher.decrypt_reader(BufReader::new(file))?),
+        };
+        Ok(FileReader(inner))
+    }
+
+    /// Returns a writer that replaces `path` atomically once finished.
+    pub fn create_file(&self, path: &Path) -> Result<FileWriter, FileStorageError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let tmp_path = staging_path(path);
+        let file = BufWriter::new(File::create(&tmp_path)?);
+        let inner = match &self.cipher {
+            None => WriterInner::Plain(file),
+            Some(cipher) => WriterInner::Encrypting(cipher.encrypt_writer(file)?),
+        };
+        Ok(FileWriter {
+            inner: Some(inner),
+            tmp_path,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Returns storage over the same directories that uses `cipher` instead.
+    pub fn with_cipher(&self, cipher: Option<Cipher>) -> Self {
+        Self {
+            content_path: self.content_path.clone(),
+            blobs_path: self.blobs_path.clone(),
+            thumbnails_path: self.thumbnails_path.clone(),
+            text_path: self.text_path.clone(),
+            cipher,
+            thumbnailers: self.thumbnailers.clone(),
+            thumbnail_config: self.thumbnail_config.clone(),
+        }
+    }
+
+    /// Rewrites `path` in place, reading it through `self` and writing it through `target`.
+    pub fn transcode_file(&self, path: &Path, target: &Self) -> Result<(), FileStorageError> {
+        self.copy_file(path, target, path)
+    }
+
+    /// Copies `src`, read through `self`, to `dst`, written through `target`.
+    pub fn copy_file(&self, src: &Path, target: &Self, dst: &Path) -> Result<(), FileStorageError> {
+        let mut reader = self.open_file(src)?;
+        let mut writer = target.create_file(dst)?;
+        io::copy(&mut reader, &mut writer)?;
+        writer.finish()
+    }
+
+    /// Returns the blake3 hash of the file's contents as read through `self`.
+    pub fn hash_file(&self, path: &Path) -> Result<blake3::Hash, FileStorageError> {
+        let mut hasher = blake3::Hasher::new();
+        io::copy(&mut self.open_file(path)?, &mut hasher)?;
+        Ok(hasher.finalize())
+    }
+
+    /// Re-encrypts every file not yet encrypted with the cipher's current key, and encrypts
+    /// plaintext files.
+    ///
+    /// Files of the keys in `skip` (sealed keys, which use their own cipher) are left alone.
+    /// Returns the number of files rewritten.
+    pub fn reencrypt_all(&self, skip: &HashSet<PathBuf>) -> Result<usize, FileStorageError> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(0);
+        };
+
+        let mut count = 0;
+        let roots = [
+            &self.content_path,
+            &self.blobs_path,
+            &self.thumbnails_path,
+            &self.text_path,
+        ];
+        for root in roots {
+            for path in list_files(root)? {
+                // The first component below the root is `{key_hash}` or `{key_hash}.md`.
+                let key_hash = path
+                    .strip_prefix(root)
+                    .ok()
+                    .and_then(|rel| rel.components().next())
+                    .map(|c| Path::new(c.as_os_str()).with_extension(""));
+                if key_hash.is_some_and(|hash| skip.contains(&hash)) {
+                    continue;
+                }
+                if !crypto::is_encrypted_file(&path)? {
+                    // Plaintext, such as a file written by a version without encryption support:
+                    // encrypt it rather than failing the whole rotation on it.
+                    self.with_cipher(None).copy_file(&path, self, &path)?;
+                } else if cipher.needs_reencryption(&path)? {
+                    self.transcode_file(&path, self)?;
+                } else {
+                    continue;
+                }
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// Disk usage.
+impl FileStorage {
+    /// Returns the bytes used by a key's content, attachment and thumbnail files.
+    pub fn key_usage(&self, key_hash: &Path) -> Result<Usage, FileStorageError> {
+        let content = match std::fs::metadata(self.content_file_path(key_hash)) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Usage {
+            content,
+            blobs: dir_size(&self.blobs_path.join(key_hash))?,
+            thumbnails: dir_size(&self.thumbnails_path.join(key_hash))?,
+        })
+    }
+
+    /// Returns the bytes used by every content, attachment and thumbnail file, including
+    /// orphans and unfinished writes.
+    pub fn usage(&self) -> Result<Usage, FileStorageError> {
+        Ok(Usage {
+            content: dir_size(&self.content_path)?,
+            blobs: dir_size(&self.blobs_path)?,
+            thumbnails: dir_size(&self.thumbnails_path)?,
+        })
+    }
+}
+
+/// Sums the sizes of the files below `dir`, skipping entries removed while it is walked, such as
+/// temporary files of thumbnails being written.
+fn dir_size(dir: &Path) -> Result<u64, FileStorageError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut size = 0;
+    for entry in entries {
+        let entry = entry?;
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>, FileStorageError> {
+    let mut files = Vec::new();
+    if !dir.exists() {
+        return Ok(files);
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(list_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Reads a stored file, decrypting it if the store is encrypted.
+pub struct FileReader(ReaderInner);
+
+enum ReaderInner {
+    Plain(File),
+    Decrypting(DecryptReader<BufReader<File>>),
+}
+
+impl Read for FileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            ReaderInner::Plain(file) => file.read(buf),
+            ReaderInner::Decrypting(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// Writes a stored file, encrypting it if the store is encrypted.
+///
+/// Data goes to a temporary file that replaces the target on [`FileWriter::finish`]. Dropping the
+/// writer without finishing discards the data.
+pub struct FileWriter {
+    inner: Option<WriterInner>,
+    tmp_path: PathBuf,
+    path: PathBuf,
+}
+
+enum WriterInner {
+    Plain(BufWriter<File>),
+    Encrypting(EncryptWriter<BufWriter<File>>),
+}
+
+impl FileWriter {
+    pub fn finish(mut self) -> Result<(), FileStorageError> {
+        let writer = match self.inner.take().expect("writer not finished") {
+            WriterInner::Plain(writer) => writer,
+            WriterInner::Encrypting(writer) => writer.finish()?,
+        };
+        let file = writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        file.sync_all()?;
+        std::fs::rename(&self.tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.inner.as_mut().expect("writer not finished") {
+            WriterInner::Plain(writer) => writer.write(buf),
+            WriterInner::Encrypting(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.inner.as_mut().expect("writer not finished") {
+            WriterInner::Plain(writer) => writer.flush(),
+            WriterInner::Encrypting(writer) => writer.flush(),
+        }
+    }
+}
+
+impl Drop for FileWriter {
+    fn drop(&mut self) {
+        if self.inner.take().is_some() {
+            let _ = std::fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
 /// Cleanup operations.
 impl FileStorage {
     pub fn remove_all(&self, key_hash: &Path) -> Result<(), FileStorageError> {
         self.remove_content(key_hash)?;
         self.remove_all_attachments(key_hash)?;
         self.remove_all_thumbnails(key_hash)?;
+        self.remove_all_text(key_hash)?;
         Ok(())
     }
 
@@ -288,6 +994,16 @@ impl FileStorage {
             std::fs::rename(old_thumbs, new_thumbs)?;
         }
 
+        // Rename extracted text directory
+        let old_text = self.text_path.join(old_key_hash);
+        let new_text = self.text_path.join(new_key_hash);
+        if old_text.exists() {
+            if new_text.exists() {
+                std::fs::remove_dir_all(&new_text)?;
+            }
+            std::fs::rename(old_text, new_text)?;
+        }
+
         Ok(())
     }
 
@@ -0,0 +1,1240 @@
+//! Rasterizing the first page of a PDF, for its thumbnail.
+//!
+//! Covers what a page preview needs: paths with solid colours, clipping, images, form XObjects
+//! and text. Text in embedded TrueType and OpenType fonts is drawn from the font's outlines; text
+//! in other fonts (the standard 14, Type 1, Type 3) is drawn as a bar per glyph, which reads as a
+//! line of text at thumbnail size. Shadings, patterns and blend modes are not drawn.
+
+use super::error::FileStorageError;
+use image::DynamicImage;
+use image::imageops::FilterType;
+use lopdf::content::{Content, Operation};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use resvg::tiny_skia::{
+    Color, FillRule, IntSize, LineCap, LineJoin, Mask, Paint, PathBuilder, Pixmap, PixmapPaint,
+    Rect, Stroke, StrokeDash, Transform,
+};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Deepest nesting of form XObjects drawn; deeper ones are usually cycles.
+const MAX_FORM_DEPTH: usize = 8;
+
+/// Opacity of the bars standing in for glyphs without outlines.
+const GLYPH_BAR_OPACITY: f32 = 0.4;
+
+/// Renders the first page of `doc` with its longer side `max_size` pixels, on white.
+pub(super) fn render_first_page(
+    doc: &Document,
+    max_size: u32,
+) -> Result<DynamicImage, FileStorageError> {
+    let page_id = *doc
+        .get_pages()
+        .values()
+        .next()
+        .ok_or(FileStorageError::UnsupportedFormat)?;
+    let page_box = inherited(doc, page_id, b"CropBox")
+        .or_else(|| inherited(doc, page_id, b"MediaBox"))
+        .and_then(|object| numbers(object.as_array().ok()?).try_into().ok())
+        .unwrap_or([0.0, 0.0, 612.0, 792.0]);
+    let rotate = inherited(doc, page_id, b"Rotate")
+        .and_then(|object| object.as_i64().ok())
+        .unwrap_or(0)
+        .rem_euclid(360);
+
+    let [x0, y0, x1, y1] = page_box;
+    let (left, right) = (x0.min(x1), x0.max(x1));
+    let (bottom, top) = (y0.min(y1), y0.max(y1));
+    let (width, height) = (right - left, top - bottom);
+    if width <= 0.0 || height <= 0.0 {
+        return Err(FileStorageError::UnsupportedFormat);
+    }
+    let scale = max_size as f32 / width.max(height);
+    let (w, h) = (width * scale, height * scale);
+
+    // PDF space is y-up from the page box's corner; turn it y-down, then apply the page rotation
+    // (clockwise when displayed).
+    let upright = Transform::from_row(scale, 0.0, 0.0, -scale, -left * scale, top * scale);
+    let (base, canvas_w, canvas_h) = match rotate {
+        90 => (
+            upright.post_concat(Transform::from_row(0.0, 1.0, -1.0, 0.0, h, 0.0)),
+            h,
+            w,
+        ),
+        180 => (
+            upright.post_concat(Transform::from_row(-1.0, 0.0, 0.0, -1.0, w, h)),
+            w,
+            h,
+        ),
+        270 => (
+            upright.post_concat(Transform::from_row(0.0, -1.0, 1.0, 0.0, 0.0, w)),
+            h,
+            w,
+        ),
+        _ => (upright, w, h),
+    };
+    let mut pixmap = Pixmap::new(
+        (canvas_w.round() as u32).max(1),
+        (canvas_h.round() as u32).max(1),
+    )
+    .ok_or(FileStorageError::UnsupportedFormat)?;
+    pixmap.fill(Color::WHITE);
+
+    let mut content = Vec::new();
+    for id in doc.get_page_contents(page_id) {
+        if let Ok(stream) = doc.get_object(id).and_then(Object::as_stream) {
+            content.extend_from_slice(&stream_data(stream));
+            content.push(b'\n');
+        }
+    }
+    let content = Content::decode(&content)?;
+
+    let resources: Vec<&Dictionary> = page_tree(doc, page_id)
+        .filter_map(|node| {
+            node.get_deref(b"Resources", doc)
+                .and_then(Object::as_dict)
+                .ok()
+        })
+        .collect();
+    let mut renderer = Renderer {
+        doc,
+        pixmap,
+        fonts: HashMap::new(),
+    };
+    let mut state = GraphicsState::new(base);
+    renderer.run(&content.operations, &resources, &mut state, 0);
+
+    Ok(super::thumbnail::pixmap_to_image(&renderer.pixmap))
+}
+
+/// Decodes a JPEG image stream, or an uncompressed or Flate-compressed 8-bit Gray, RGB or CMYK
+/// one. Other encodings (JPEG 2000, CCITT, indexed colour) fail with `UnsupportedFormat`.
+fn decode_image(doc: &Document, stream: &Stream) -> Result<DynamicImage, FileStorageError> {
+    let filters = stream.filters().unwrap_or_default();
+    match filters.as_slice() {
+        [b"DCTDecode"] => {
+            return Ok(image::load_from_memory_with_format(
+                &stream.content,
+                image::ImageFormat::Jpeg,
+            )?);
+        }
+        [] | [b"FlateDecode"] => {}
+        _ => return Err(FileStorageError::UnsupportedFormat),
+    }
+
+    let dict = &stream.dict;
+    let (width, height) = image_size(dict).ok_or(FileStorageError::UnsupportedFormat)?;
+    if dict.get(b"BitsPerComponent").and_then(Object::as_i64).ok() != Some(8) {
+        return Err(FileStorageError::UnsupportedFormat);
+    }
+    let data = if filters.is_empty() {
+        stream.content.clone()
+    } else {
+        stream.decompressed_content()?
+    };
+
+    let unsupported = || FileStorageError::UnsupportedFormat;
+    let image = match color_components(doc, dict).ok_or_else(unsupported)? {
+        1 => DynamicImage::ImageLuma8(
+            image::GrayImage::from_raw(width, height, data).ok_or_else(unsupported)?,
+        ),
+        3 => DynamicImage::ImageRgb8(
+            image::RgbImage::from_raw(width, height, data).ok_or_else(unsupported)?,
+        ),
+        4 => {
+            let rgb = data
+                .chunks_exact(4)
+                .flat_map(|cmyk| {
+                    let k = 255 - cmyk[3] as u16;
+                    cmyk[..3]
+                        .iter()
+                        .map(move |&c| ((255 - c as u16) * k / 255) as u8)
+                })
+                .collect();
+            DynamicImage::ImageRgb8(
+                image::RgbImage::from_raw(width, height, rgb).ok_or_else(unsupported)?,
+            )
+        }
+        _ => return Err(unsupported()),
+    };
+    Ok(image)
+}
+
+/// Number of colour components of an image's `ColorSpace`: device spaces, or ICC profiles by
+/// their `N`.
+fn color_components(doc: &Document, dict: &Dictionary) -> Option<i64> {
+    let space = dict.get_deref(b"ColorSpace", doc).ok()?;
+    let (name, params) = match space {
+        Object::Name(name) => (name.as_slice(), None),
+        Object::Array(array) => (array.first()?.as_name().ok()?, array.get(1)),
+        _ => return None,
+    };
+    match name {
+        b"DeviceGray" | b"CalGray" => Some(1),
+        b"DeviceRGB" | b"CalRGB" => Some(3),
+        b"DeviceCMYK" => Some(4),
+        b"ICCBased" => {
+            let (_, profile) = doc.dereference(params?).ok()?;
+            profile.as_stream().ok()?.dict.get(b"N").ok()?.as_i64().ok()
+        }
+        _ => None,
+    }
+}
+
+fn image_size(dict: &Dictionary) -> Option<(u32, u32)> {
+    let dimension = |name: &[u8]| {
+        dict.get(name)
+            .and_then(Object::as_i64)
+            .ok()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|&v| v > 0)
+    };
+    Some((dimension(b"Width")?, dimension(b"Height")?))
+}
+
+/// The page node and its ancestors, nearest first. lopdf's own lookup misses inline dictionaries
+/// on parent nodes.
+fn page_tree(doc: &Document, page_id: ObjectId) -> impl Iterator<Item = &Dictionary> {
+    let mut seen = HashSet::new();
+    let mut node = doc.get_dictionary(page_id).ok();
+    std::iter::from_fn(move || {
+        let current = node?;
+        node = current
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .ok()
+            .filter(|id| seen.insert(*id))
+            .and_then(|id| doc.get_dictionary(id).ok());
+        Some(current)
+    })
+}
+
+/// Looks up a page attribute that may be inherited from the page tree.
+fn inherited<'a>(doc: &'a Document, page_id: ObjectId, name: &[u8]) -> Option<&'a Object> {
+    page_tree(doc, page_id).find_map(|node| node.get_deref(name, doc).ok())
+}
+
+/// The decoded data of a stream, or its raw data if a filter isn't supported.
+fn stream_data(stream: &Stream) -> Vec<u8> {
+    if stream.filters().is_ok_and(|filters| !filters.is_empty()) {
+        stream
+            .decompressed_content()
+            .unwrap_or_else(|_| stream.content.clone())
+    } else {
+        stream.content.clone()
+    }
+}
+
+fn deref<'a>(doc: &'a Document, object: &'a Object) -> &'a Object {
+    doc.dereference(object).map_or(object, |(_, object)| object)
+}
+
+fn numbers(objects: &[Object]) -> Vec<f32> {
+    objects.iter().filter_map(|o| o.as_float().ok()).collect()
+}
+
+fn matrix(values: &[f32]) -> Option<Transform> {
+    match *values {
+        [a, b, c, d, e, f] => Some(Transform::from_row(a, b, c, d, e, f)),
+        _ => None,
+    }
+}
+
+/// How the operands of `sc`/`scn` and friends turn into a colour.
+#[derive(Clone)]
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// Separation and DeviceN: one tint of ink, drawn as grey.
+    Tint,
+    /// Base colours of an 8-bit palette, in the base space's components.
+    Indexed(Rc<(Box<ColorSpace>, Vec<u8>)>),
+    /// Not drawn.
+    Pattern,
+}
+
+/// Deepest nesting of colour spaces followed (`Indexed` bases, named resources), guarding
+/// against reference cycles.
+const MAX_SPACE_DEPTH: usize = 4;
+
+impl ColorSpace {
+    fn from_object(doc: &Document, object: &Object, resources: &[&Dictionary]) -> Self {
+        Self::parse(doc, object, resources, 0)
+    }
+
+    fn parse(doc: &Document, object: &Object, resources: &[&Dictionary], depth: usize) -> Self {
+        if depth > MAX_SPACE_DEPTH {
+            return Self::Gray;
+        }
+        let (name, params) = match deref(doc, object) {
+            Object::Name(name) => (name.as_slice(), &[][..]),
+            Object::Array(array) => match array.split_first() {
+                Some((first, rest)) => (first.as_name().unwrap_or_default(), rest),
+                None => return Self::Gray,
+            },
+            _ => return Self::Gray,
+        };
+        match name {
+            b"DeviceGray" | b"CalGray" | b"G" => Self::Gray,
+            b"DeviceRGB" | b"CalRGB" | b"RGB" => Self::Rgb,
+            b"DeviceCMYK" | b"CMYK" => Self::Cmyk,
+            b"Separation" | b"DeviceN" => Self::Tint,
+            b"Pattern" => Self::Pattern,
+            b"ICCBased" => {
+                let components = params
+                    .first()
+                    .and_then(|p| deref(doc, p).as_stream().ok())
+                    .and_then(|s| s.dict.get(b"N").and_then(Object::as_i64).ok());
+                match components {
+                    Some(1) => Self::Gray,
+                    Some(4) => Self::Cmyk,
+                    _ => Self::Rgb,
+                }
+            }
+            b"Indexed" | b"I" => {
+                let base = params.first().map_or(Self::Rgb, |base| {
+                    Self::parse(doc, base, resources, depth + 1)
+                });
+                let lookup = match params.get(2).map(|l| deref(doc, l)) {
+                    Some(Object::String(bytes, _)) => bytes.clone(),
+                    Some(Object::Stream(stream)) => stream_data(stream),
+                    _ => Vec::new(),
+                };
+                Self::Indexed(Rc::new((Box::new(base), lookup)))
+            }
+            _ => match resource(doc, resources, b"ColorSpace", name) {
+                Some((_, object)) => Self::parse(doc, object, resources, depth + 1),
+                None => Self::Gray,
+            },
+        }
+    }
+
+    fn components(&self) -> usize {
+        match self {
+            Self::Gray | Self::Tint | Self::Indexed(_) | Self::Pattern => 1,
+            Self::Rgb => 3,
+            Self::Cmyk => 4,
+        }
+    }
+
+    /// The colour of `values`, or transparent for patterns.
+    fn color(&self, values: &[f32]) -> Color {
+        let v = |i: usize| values.get(i).copied().unwrap_or(0.0).clamp(0.0, 1.0);
+        match self {
+            Self::Gray => Color::from_rgba(v(0), v(0), v(0), 1.0).unwrap_or(Color::BLACK),
+            Self::Tint => {
+                let gray = 1.0 - v(0);
+                Color::from_rgba(gray, gray, gray, 1.0).unwrap_or(Color::BLACK)
+            }
+            Self::Rgb => Color::from_rgba(v(0), v(1), v(2), 1.0).unwrap_or(Color::BLACK),
+            Self::Cmyk => {
+                let k = 1.0 - v(3);
+                Color::from_rgba((1.0 - v(0)) * k, (1.0 - v(1)) * k, (1.0 - v(2)) * k, 1.0)
+                    .unwrap_or(Color::BLACK)
+            }
+            Self::Indexed(palette) => {
+                let (base, lookup) = palette.as_ref();
+                let n = base.components();
+                let index = values.first().copied().unwrap_or(0.0).max(0.0) as usize;
+                let entry = lookup
+                    .get(index * n..(index + 1) * n)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|&b| b as f32 / 255.0)
+                    .collect::<Vec<_>>();
+                base.color(&entry)
+            }
+            Self::Pattern => Color::TRANSPARENT,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct TextState {
+    font: Option<Rc<Font>>,
+    size: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    horizontal_scale: f32,
+    leading: f32,
+    rise: f32,
+    render_mode: i64,
+}
+
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Transform,
+    clip: Option<Rc<Mask>>,
+    fill_space: ColorSpace,
+    stroke_space: ColorSpace,
+    fill: Color,
+    stroke: Color,
+    fill_alpha: f32,
+    stroke_alpha: f32,
+    stroke_style: Stroke,
+    text: TextState,
+}
+
+impl GraphicsState {
+    fn new(ctm: Transform) -> Self {
+        Self {
+            ctm,
+            clip: None,
+            fill_space: ColorSpace::Gray,
+            stroke_space: ColorSpace::Gray,
+            fill: Color::BLACK,
+            stroke: Color::BLACK,
+            fill_alpha: 1.0,
+            stroke_alpha: 1.0,
+            stroke_style: Stroke::default(),
+            text: TextState {
+                font: None,
+                size: 0.0,
+                char_spacing: 0.0,
+                word_spacing: 0.0,
+                horizontal_scale: 1.0,
+                leading: 0.0,
+                rise: 0.0,
+                render_mode: 0,
+            },
+        }
+    }
+
+    fn paint(color: Color, alpha: f32) -> Paint<'static> {
+        let mut color = color;
+        color.apply_opacity(alpha);
+        let mut paint = Paint::default();
+        paint.set_color(color);
+        paint.anti_alias = true;
+        paint
+    }
+
+    fn fill_paint(&self) -> Paint<'static> {
+        Self::paint(self.fill, self.fill_alpha)
+    }
+
+    fn stroke_paint(&self) -> Paint<'static> {
+        Self::paint(self.stroke, self.stroke_alpha)
+    }
+}
+
+/// Looks up a named resource of `category` (e.g. `Font`), returning its object id if it is
+/// indirect.
+fn resource<'a>(
+    doc: &'a Document,
+    resources: &[&'a Dictionary],
+    category: &[u8],
+    name: &[u8],
+) -> Option<(Option<ObjectId>, &'a Object)> {
+    resources.iter().find_map(|dict| {
+        let entries = dict
+            .get_deref(category, doc)
+            .and_then(Object::as_dict)
+            .ok()?;
+        match entries.get(name).ok()? {
+            Object::Reference(id) => Some((Some(*id), doc.get_object(*id).ok()?)),
+            object => Some((None, object)),
+        }
+    })
+}
+
+struct Renderer<'a> {
+    doc: &'a Document,
+    pixmap: Pixmap,
+    fonts: HashMap<ObjectId, Rc<Font>>,
+}
+
+impl<'a> Renderer<'a> {
+    fn run(
+        &mut self,
+        operations: &[Operation],
+        resources: &[&'a Dictionary],
+        state: &mut GraphicsState,
+        depth: usize,
+    ) {
+        let mut saved = Vec::new();
+        let mut path = PathBuilder::new();
+        let mut current = (0.0, 0.0);
+        let mut pending_clip = None;
+        let mut text_matrix = Transform::identity();
+        let mut line_matrix = Transform::identity();
+
+        for Operation { operator, operands } in operations {
+            let nums = numbers(operands);
+            match (operator.as_str(), nums.as_slice()) {
+                ("q", _) => saved.push(state.clone()),
+                ("Q", _) => {
+                    if let Some(restored) = saved.pop() {
+                        *state = restored;
+                    }
+                }
+                ("cm", values) => {
+                    if let Some(m) = matrix(values) {
+                        state.ctm = state.ctm.pre_concat(m);
+                    }
+                }
+                ("w", [width]) => state.stroke_style.width = width.max(0.0),
+                ("J", [cap]) => {
+                    state.stroke_style.line_cap = match *cap as i64 {
+                        1 => LineCap::Round,
+                        2 => LineCap::Square,
+                        _ => LineCap::Butt,
+                    }
+                }
+                ("j", [join]) => {
+                    state.stroke_style.line_join = match *join as i64 {
+                        1 => LineJoin::Round,
+                        2 => LineJoin::Bevel,
+                        _ => LineJoin::Miter,
+                    }
+                }
+                ("M", [limit]) => state.stroke_style.miter_limit = limit.max(1.0),
+                ("d", _) => {
+                    let mut dashes = operands
+                        .first()
+                        .and_then(|o| o.as_array().ok())
+                        .map(|a| numbers(a))
+                        .unwrap_or_default();
+                    if dashes.len() % 2 == 1 {
+                        dashes.extend_from_within(..);
+                    }
+                    let phase = operands.get(1).and_then(|o| o.as_float().ok());
+                    state.stroke_style.dash = StrokeDash::new(dashes, phase.unwrap_or(0.0));
+                }
+                ("gs", _) => {
+                    if let Some(name) = operands.first().and_then(|o| o.as_name().ok()) {
+                        self.apply_ext_gstate(resources, name, state);
+                    }
+                }
+                ("g", values) => {
+                    state.fill_space = ColorSpace::Gray;
+                    state.fill = state.fill_space.color(values);
+                }
+                ("G", values) => {
+                    state.stroke_space = ColorSpace::Gray;
+                    state.stroke = state.stroke_space.color(values);
+                }
+                ("rg", values) => {
+                    state.fill_space = ColorSpace::Rgb;
+                    state.fill = state.fill_space.color(values);
+                }
+                ("RG", values) => {
+                    state.stroke_space = ColorSpace::Rgb;
+                    state.stroke = state.stroke_space.color(values);
+                }
+                ("k", values) => {
+                    state.fill_space = ColorSpace::Cmyk;
+                    state.fill = state.fill_space.color(values);
+                }
+                ("K", values) => {
+                    state.stroke_space = ColorSpace::Cmyk;
+                    state.stroke = state.stroke_space.color(values);
+                }
+                ("cs" | "CS", _) => {
+                    if let Some(space) = operands.first() {
+                        let space = ColorSpace::from_object(self.doc, space, resources);
+                        // Setting a space resets the colour to black (or its first entry).
+                        let initial = space.color(&[0.0, 0.0, 0.0, 1.0]);
+                        if operator == "cs" {
+                            (state.fill_space, state.fill) = (space, initial);
+                        } else {
+                            (state.stroke_space, state.stroke) = (space, initial);
+                        }
+                    }
+                }
+                ("sc" | "scn", values) => state.fill = state.fill_space.color(values),
+                ("SC" | "SCN", values) => state.stroke = state.stroke_space.color(values),
+
+                ("m", [x, y]) => {
+                    path.move_to(*x, *y);
+                    current = (*x, *y);
+                }
+                ("l", [x, y]) => {
+                    path.line_to(*x, *y);
+                    current = (*x, *y);
+                }
+                ("c", [x1, y1, x2, y2, x, y]) => {
+                    path.cubic_to(*x1, *y1, *x2, *y2, *x, *y);
+                    current = (*x, *y);
+                }
+                ("v", [x2, y2, x, y]) => {
+                    path.cubic_to(current.0, current.1, *x2, *y2, *x, *y);
+                    current = (*x, *y);
+                }
+                ("y", [x1, y1, x, y]) => {
+                    path.cubic_to(*x1, *y1, *x, *y, *x, *y);
+                    current = (*x, *y);
+                }
+                ("h", _) => path.close(),
+                ("re", [x, y, w, h]) => {
+                    path.move_to(*x, *y);
+                    path.line_to(x + w, *y);
+                    path.line_to(x + w, y + h);
+                    path.line_to(*x, y + h);
+                    path.close();
+                    current = (*x, *y);
+                }
+                ("W", _) => pending_clip = Some(FillRule::Winding),
+                ("W*", _) => pending_clip = Some(FillRule::EvenOdd),
+                ("S" | "s" | "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" | "n", _) => {
+                    if matches!(operator.as_str(), "s" | "b" | "b*") {
+                        path.close();
+                    }
+                    let built = std::mem::replace(&mut path, PathBuilder::new()).finish();
+                    let clip = state.clip.clone();
+                    if let Some(built) = &built {
+                        let fill_rule = match operator.as_str() {
+                            "f" | "F" | "B" | "b" => Some(FillRule::Winding),
+                            "f*" | "B*" | "b*" => Some(FillRule::EvenOdd),
+                            _ => None,
+                        };
+                        if let Some(rule) = fill_rule {
+                            self.pixmap.fill_path(
+                                built,
+                                &state.fill_paint(),
+                                rule,
+                                state.ctm,
+                                clip.as_deref(),
+                            );
+                        }
+                        if matches!(operator.as_str(), "S" | "s" | "B" | "B*" | "b" | "b*") {
+                            self.pixmap.stroke_path(
+                                built,
+                                &state.stroke_paint(),
+                                &state.stroke_style,
+                                state.ctm,
+                                clip.as_deref(),
+                            );
+                        }
+                    }
+                    if let Some(rule) = pending_clip.take() {
+                        self.clip(state, built.as_ref(), rule);
+                    }
+                }
+
+                ("BT", _) => {
+                    text_matrix = Transform::identity();
+                    line_matrix = Transform::identity();
+                }
+                ("Tc", [spacing]) => state.text.char_spacing = *spacing,
+                ("Tw", [spacing]) => state.text.word_spacing = *spacing,
+                ("Tz", [scale]) => state.text.horizontal_scale = scale / 100.0,
+                ("TL", [leading]) => state.text.leading = *leading,
+                ("Ts", [rise]) => state.text.rise = *rise,
+                ("Tr", [mode]) => state.text.render_mode = *mode as i64,
+                ("Tf", [size]) => {
+                    state.text.size = *size;
+                    state.text.font = operands
+                        .first()
+                        .and_then(|o| o.as_name().ok())
+                        .and_then(|name| self.font(resources, name));
+                }
+                ("Td", [x, y]) => {
+                    line_matrix = line_matrix.pre_translate(*x, *y);
+                    text_matrix = line_matrix;
+                }
+                ("TD", [x, y]) => {
+                    state.text.leading = -y;
+                    line_matrix = line_matrix.pre_translate(*x, *y);
+                    text_matrix = line_matrix;
+                }
+                ("Tm", values) => {
+                    if let Some(m) = matrix(values) {
+                        line_matrix = m;
+                        text_matrix = m;
+                    }
+                }
+                ("T*", _) => {
+                    line_matrix = line_matrix.pre_translate(0.0, -state.text.leading);
+                    text_matrix = line_matrix;
+                }
+                ("Tj", _) => {
+                    if let Some(Object::String(bytes, _)) = operands.first() {
+                        self.show_text(bytes, state, &mut text_matrix);
+                    }
+                }
+                ("TJ", _) => {
+                    let parts = operands.first().and_then(|o| o.as_array().ok());
+                    for part in parts.into_iter().flatten() {
+                        match part {
+                            Object::String(bytes, _) => {
+                                self.show_text(bytes, state, &mut text_matrix)
+                            }
+                            other => {
+                                if let Ok(adjust) = other.as_float() {
+                                    let text = &state.text;
+                                    let x = -adjust / 1000.0 * text.size * text.horizontal_scale;
+                                    text_matrix = text_matrix.pre_translate(x, 0.0);
+                                }
+                            }
+                        }
+                    }
+                }
+                ("'" | "\"", _) => {
+                    if operator == "\""
+                        && let [word, char, ..] = nums.as_slice()
+                    {
+                        state.text.word_spacing = *word;
+                        state.text.char_spacing = *char;
+                    }
+                    line_matrix = line_matrix.pre_translate(0.0, -state.text.leading);
+                    text_matrix = line_matrix;
+                    if let Some(Object::String(bytes, _)) = operands.last() {
+                        self.show_text(bytes, state, &mut text_matrix);
+                    }
+                }
+
+                ("Do", _) => {
+                    let xobject = operands
+                        .first()
+                        .and_then(|o| o.as_name().ok())
+                        .and_then(|name| resource(self.doc, resources, b"XObject", name))
+                        .and_then(|(_, object)| object.as_stream().ok());
+                    if let Some(stream) = xobject {
+                        match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+                            Ok(b"Image") => self.draw_image(stream, state),
+                            Ok(b"Form") => self.draw_form(stream, resources, state, depth),
+                            _ => {}
+                        }
+                    }
+                }
+                ("BI", _) => {
+                    if let Some(Object::Stream(stream)) = operands.first() {
+                        self.draw_image(&expand_inline_image(stream), state);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn apply_ext_gstate(&self, resources: &[&Dictionary], name: &[u8], state: &mut GraphicsState) {
+        let Some(dict) = resource(self.doc, resources, b"ExtGState", name)
+            .and_then(|(_, object)| object.as_dict().ok())
+        else {
+            return;
+        };
+        let number = |key: &[u8]| dict.get(key).and_then(Object::as_float).ok();
+        if let Some(alpha) = number(b"CA") {
+            state.stroke_alpha = alpha.clamp(0.0, 1.0);
+        }
+        if let Some(alpha) = number(b"ca") {
+            state.fill_alpha = alpha.clamp(0.0, 1.0);
+        }
+        if let Some(width) = number(b"LW") {
+            state.stroke_style.width = width.max(0.0);
+        }
+    }
+
+    /// Intersects the clip with `path`; a missing path clips everything away.
+    fn clip(
+        &self,
+        state: &mut GraphicsState,
+        path: Option<&resvg::tiny_skia::Path>,
+        rule: FillRule,
+    ) {
+        let mut mask = match &state.clip {
+            Some(mask) => Mask::clone(mask),
+            None => {
+                let size = IntSize::from_wh(self.pixmap.width(), self.pixmap.height())
+                    .expect("pixmap size is valid");
+                let full = vec![255; (size.width() * size.height()) as usize];
+                Mask::from_vec(full, size).expect("data matches the size")
+            }
+        };
+        match path {
+            Some(path) => mask.intersect_path(path, rule, true, state.ctm),
+            None => mask.clear(),
+        }
+        state.clip = Some(Rc::new(mask));
+    }
+
+    fn font(&mut self, resources: &[&'a Dictionary], name: &[u8]) -> Option<Rc<Font>> {
+        let (id, object) = resource(self.doc, resources, b"Font", name)?;
+        let dict = object.as_dict().ok()?;
+        if let Some(font) = id.and_then(|id| self.fonts.get(&id)) {
+            return Some(font.clone());
+        }
+        let font = Rc::new(Font::load(self.doc, dict));
+        if let Some(id) = id {
+            self.fonts.insert(id, font.clone());
+        }
+        Some(font)
+    }
+
+    fn show_text(&mut self, bytes: &[u8], state: &GraphicsState, text_matrix: &mut Transform) {
+        let text = &state.text;
+        let Some(font) = text.font.clone() else {
+            return;
+        };
+        let face = font
+            .program
+            .as_deref()
+            .and_then(|data| ttf_parser::Face::parse(data, 0).ok());
+        // Modes 3 and 7 are invisible, e.g. the text layer over a scan.
+        let visible = !matches!(text.render_mode, 3 | 7);
+        let paint = state.fill_paint();
+
+        for (code, is_space) in font.codes(bytes) {
+            let advance = font.width(code) / 1000.0;
+            if visible {
+                let glyph_space =
+                    state
+                        .ctm
+                        .pre_concat(*text_matrix)
+                        .pre_concat(Transform::from_row(
+                            text.size * text.horizontal_scale,
+                            0.0,
+                            0.0,
+                            text.size,
+                            0.0,
+                            text.rise,
+                        ));
+                match &face {
+                    Some(face) => {
+                        if let Some(glyph) = font.glyph_id(face, code)
+                            && let Some(outline) = glyph_outline(face, glyph)
+                        {
+                            let scale = 1.0 / face.units_per_em() as f32;
+                            self.pixmap.fill_path(
+                                &outline,
+                                &paint,
+                                FillRule::Winding,
+                                glyph_space.pre_scale(scale, scale),
+                                state.clip.as_deref(),
+                            );
+                        }
+                    }
+                    None if !is_space && advance > 0.0 => {
+                        if let Some(bar) = Rect::from_xywh(0.05 * advance, 0.0, 0.9 * advance, 0.5)
+                        {
+                            self.pixmap.fill_rect(
+                                bar,
+                                &GraphicsState::paint(
+                                    state.fill,
+                                    state.fill_alpha * GLYPH_BAR_OPACITY,
+                                ),
+                                glyph_space,
+                                state.clip.as_deref(),
+                            );
+                        }
+                    }
+                    None => {}
+                }
+            }
+
+            let word_spacing = if is_space { text.word_spacing } else { 0.0 };
+            let x =
+                (advance * text.size + text.char_spacing + word_spacing) * text.horizontal_scale;
+            *text_matrix = text_matrix.pre_translate(x, 0.0);
+        }
+    }
+
+    fn draw_image(&mut self, stream: &Stream, state: &GraphicsState) {
+        let dict = &stream.dict;
+        let is_mask = dict
+            .get(b"ImageMask")
+            .and_then(Object::as_bool)
+            .unwrap_or(false);
+        let image = if is_mask {
+            stencil_image(stream, state.fill)
+        } else {
+            decode_image(self.doc, stream).ok().map(|image| {
+                let mut rgba = image.to_rgba8();
+                let soft_mask = dict
+                    .get_deref(b"SMask", self.doc)
+                    .and_then(Object::as_stream)
+                    .ok()
+                    .and_then(|mask| decode_image(self.doc, mask).ok());
+                if let Some(mask) = soft_mask {
+                    let mask = mask
+                        .resize_exact(rgba.width(), rgba.height(), FilterType::Triangle)
+                        .to_luma8();
+                    for (pixel, alpha) in rgba.pixels_mut().zip(mask.pixels()) {
+                        pixel[3] = alpha[0];
+                    }
+                }
+                rgba
+            })
+        };
+        let Some(mut image) = image else {
+            return;
+        };
+
+        // Images map the unit square, top row first. Scale large ones down to about their size on
+        // the page first, since bilinear sampling alone would alias them.
+        let ctm = state.ctm;
+        let target_w = ctm.sx.hypot(ctm.ky).ceil().max(1.0) as u32;
+        let target_h = ctm.kx.hypot(ctm.sy).ceil().max(1.0) as u32;
+        if image.width() > target_w * 2 || image.height() > target_h * 2 {
+            image = image::imageops::resize(
+                &image,
+                target_w.min(image.width()),
+                target_h.min(image.height()),
+                FilterType::Triangle,
+            );
+        }
+        let (width, height) = image.dimensions();
+        let premultiplied = image
+            .pixels()
+            .flat_map(|p| {
+                let [r, g, b, a] = p.0;
+                let premultiply = |c: u8| (c as u16 * a as u16 / 255) as u8;
+                [premultiply(r), premultiply(g), premultiply(b), a]
+            })
+            .collect();
+        let Some(pixmap) =
+            IntSize::from_wh(width, height).and_then(|size| Pixmap::from_vec(premultiplied, size))
+        else {
+            return;
+        };
+
+        let transform = ctm.pre_concat(Transform::from_row(
+            1.0 / width as f32,
+            0.0,
+            0.0,
+            -1.0 / height as f32,
+            0.0,
+            1.0,
+        ));
+        let paint = PixmapPaint {
+            opacity: state.fill_alpha,
+            quality: resvg::tiny_skia::FilterQuality::Bilinear,
+            ..PixmapPaint::default()
+        };
+        self.pixmap.draw_pixmap(
+            0,
+            0,
+            pixmap.as_ref(),
+            &paint,
+            transform,
+            state.clip.as_deref(),
+        );
+    }
+
+    fn draw_form(
+        &mut self,
+        stream: &'a Stream,
+        resources: &[&'a Dictionary],
+        state: &GraphicsState,
+        depth: usize,
+    ) {
+        if depth >= MAX_FORM_DEPTH {
+            return;
+        }
+        let Ok(content) = Content::decode(&stream_data(stream)) else {
+            return;
+        };
+        let dict = &stream.dict;
+        let mut state = state.clone();
+        if let Some(m) = dict
+            .get(b"Matrix")
+            .and_then(Object::as_array)
+            .ok()
+            .and_then(|m| matrix(&numbers(m)))
+        {
+            state.ctm = state.ctm.pre_concat(m);
+        }
+        if let Some([x0, y0, x1, y1]) = dict
+            .get(b"BBox")
+            .and_then(Object::as_array)
+            .ok()
+            .and_then(|b| <[f32; 4]>::try_from(numbers(b)).ok())
+        {
+            let mut bbox = PathBuilder::new();
+            bbox.move_to(x0, y0);
+            bbox.line_to(x1, y0);
+            bbox.line_to(x1, y1);
+            bbox.line_to(x0, y1);
+            bbox.close();
+            self.clip(&mut state, bbox.finish().as_ref(), FillRule::Winding);
+        }
+
+        let own = dict
+            .get_deref(b"Resources", self.doc)
+            .and_then(Object::as_dict)
+            .ok();
+        let resources = match own {
+            Some(own) => vec![own],
+            None => resources.to_vec(),
+        };
+        self.run(&content.operations, &resources, &mut state, depth + 1);
+    }
+}
+
+/// Expands the abbreviated keys and names of an inline image (`BI … ID … EI`).
+fn expand_inline_image(stream: &Stream) -> Stream {
+    const KEYS: &[(&[u8], &[u8])] = &[
+        (b"W", b"Width"),
+        (b"H", b"Height"),
+        (b"BPC", b"BitsPerComponent"),
+        (b"CS", b"ColorSpace"),
+        (b"F", b"Filter"),
+        (b"DP", b"DecodeParms"),
+        (b"D", b"Decode"),
+        (b"IM", b"ImageMask"),
+    ];
+    const NAMES: &[(&[u8], &[u8])] = &[
+        (b"G", b"DeviceGray"),
+        (b"RGB", b"DeviceRGB"),
+        (b"CMYK", b"DeviceCMYK"),
+        (b"Fl", b"FlateDecode"),
+        (b"DCT", b"DCTDecode"),
+    ];
+    let expand_name = |object: &Object| match object {
+        Object::Name(name) => NAMES
+            .iter()
+            .find(|(short, _)| short == name)
+            .map_or_else(|| object.clone(), |(_, long)| Object::Name(long.to_vec())),
+        object => object.clone(),
+    };
+
+    let mut dict = Dictionary::new();
+    for (key, value) in stream.dict.iter() {
+        let key = KEYS
+            .iter()
+            .find(|(short, _)| short == key)
+            .map_or(key.as_slice(), |(_, long)| long);
+        let value = match value {
+            Object::Array(array) => Object::Array(array.iter().map(expand_name).collect()),
+            value => expand_name(value),
+        };
+        dict.set(key.to_vec(), value);
+    }
+    Stream::new(dict, stream.content.clone()).with_compression(false)
+}
+
+/// Renders a 1-bit stencil mask (`ImageMask`) in `color`. Other encodings aren't supported.
+fn stencil_image(stream: &Stream, color: Color) -> Option<image::RgbaImage> {
+    let (width, height) = image_size(&stream.dict)?;
+    if !matches!(
+        stream.filters().unwrap_or_default().as_slice(),
+        [] | [b"FlateDecode"]
+    ) {
+        return None;
+    }
+    let data = stream_data(stream);
+    // The default Decode [0 1] paints where bits are 0.
+    let inverted = stream
+        .dict
+        .get(b"Decode")
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|d| d.first()?.as_i64().ok())
+        == Some(1);
+    let stride = width.div_ceil(8) as usize;
+    if data.len() < stride * height as usize {
+        return None;
+    }
+    let color = color.to_color_u8();
+    Some(image::RgbaImage::from_fn(width, height, |x, y| {
+        let byte = data[y as usize * stride + x as usize / 8];
+        let bit = (byte >> (7 - x % 8)) & 1;
+        if (bit == 0) != inverted {
+            image::Rgba([color.red(), color.green(), color.blue(), color.alpha()])
+        } else {
+            image::Rgba([0, 0, 0, 0])
+        }
+    }))
+}
+
+/// What's needed of a font to place its glyphs and, if it is embedded as TrueType or OpenType,
+/// draw them.
+struct Font {
+    /// Type 0 fonts, whose codes are taken as 2 bytes (`Identity-H` and the like).
+    composite: bool,
+    /// Glyph widths in thousandths of the font size, by code.
+    widths: HashMap<u32, f32>,
+    default_width: f32,
+    /// The embedded TrueType or OpenType font program.
+    program: Option<Vec<u8>>,
+    /// `CIDToGIDMap` of composite TrueType fonts, 2 bytes per CID; identity if absent.
+    cid_to_gid: Option<Vec<u8>>,
+    /// Symbolic simple fonts map codes through a (3,0) `cmap`, usually at `0xF000 + code`.
+    symbolic: bool,
+}
+
+impl Font {
+    fn load(doc: &Document, dict: &Dictionary) -> Self {
+        let composite = dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Type0");
+        let descendant = if composite {
+            dict.get_deref(b"DescendantFonts", doc)
+                .and_then(Object::as_array)
+                .ok()
+                .and_then(|fonts| deref(doc, fonts.first()?).as_dict().ok())
+        } else {
+            None
+        };
+        let described = descendant.unwrap_or(dict);
+        let descriptor = described
+            .get_deref(b"FontDescriptor", doc)
+            .and_then(Object::as_dict)
+            .ok();
+
+        let mut widths = HashMap::new();
+        let default_width;
+        if let Some(descendant) = descendant {
+            default_width = descendant
+                .get(b"DW")
+                .and_then(Object::as_float)
+                .unwrap_or(1000.0);
+            if let Ok(w) = descendant.get_deref(b"W", doc).and_then(Object::as_array) {
+                read_cid_widths(doc, w, &mut widths);
+            }
+        } else {
+            default_width = descriptor
+                .and_then(|d| d.get(b"MissingWidth").and_then(Object::as_float).ok())
+                .filter(|&w| w > 0.0)
+                .unwrap_or(500.0);
+            let first = dict.get(b"FirstChar").and_then(Object::as_i64).unwrap_or(0);
+            if let Ok(list) = dict.get_deref(b"Widths", doc).and_then(Object::as_array) {
+                for (i, width) in list.iter().enumerate() {
+                    if let Ok(width) = deref(doc, width).as_float() {
+                        widths.insert((first + i as i64) as u32, width);
+                    }
+                }
+            }
+        }
+
+        let program = descriptor.and_then(|d| {
+            let stream = d
+                .get_deref(b"FontFile2", doc)
+                .or_else(|_| d.get_deref(b"FontFile3", doc))
+                .and_then(Object::as_stream)
+                .ok()?;
+            let subtype = stream.dict.get(b"Subtype").and_then(Object::as_name).ok();
+            matches!(subtype, None | Some(b"OpenType")).then(|| stream_data(stream))
+        });
+        let cid_to_gid = descendant
+            .and_then(|d| {
+                d.get_deref(b"CIDToGIDMap", doc)
+                    .and_then(Object::as_stream)
+                    .ok()
+            })
+            .map(stream_data);
+        let symbolic = descriptor
+            .and_then(|d| d.get(b"Flags").and_then(Object::as_i64).ok())
+            .is_some_and(|flags| flags & 4 != 0);
+
+        Self {
+            composite,
+            widths,
+            default_width,
+            program,
+            cid_to_gid,
+            symbolic,
+        }
+    }
+
+    /// Splits a string into codes, each with whether it is a single-byte space, which word
+    /// spacing applies to.
+    fn codes<'b>(&self, bytes: &'b [u8]) -> Box<dyn Iterator<Item = (u32, bool)> + 'b> {
+        if self.composite {
+            Box::new(
+                bytes
+                    .chunks(2)
+                    .map(|pair| (pair.iter().fold(0, |code, &b| code << 8 | b as u32), false)),
+            )
+        } else {
+            Box::new(bytes.iter().map(|&b| (b as u32, b == b' ')))
+        }
+    }
+
+    fn width(&self, code: u32) -> f32 {
+        self.widths
+            .get(&code)
+            .copied()
+            .unwrap_or(self.default_width)
+    }
+
+    fn glyph_id(&self, face: &ttf_parser::Face, code: u32) -> Option<ttf_parser::GlyphId> {
+        if self.composite {
+            let gid = match &self.cid_to_gid {
+                Some(map) => {
+                    let at = code as usize * 2;
+                    u16::from_be_bytes([*map.get(at)?, *map.get(at + 1)?])
+                }
+                None => u16::try_from(code).ok()?,
+            };
+            return Some(ttf_parser::GlyphId(gid));
+        }
+
+        let subtables = || {
+            face.tables()
+                .cmap
+                .into_iter()
+                .flat_map(|cmap| cmap.subtables)
+        };
+        if !self.symbolic
+            && let Some(c) = char::from_u32(code)
+            && let Some(gid) = face.glyph_index(c)
+        {
+            return Some(gid);
+        }
+        subtables()
+            .find_map(|table| table.glyph_index(0xF000 + code))
+            .or_else(|| subtables().find_map(|table| table.glyph_index(code)))
+    }
+}
+
+/// Reads a CID font's `W` array: `c [w1 w2 …]` and `c_first c_last w` runs.
+fn read_cid_widths(doc: &Document, list: &[Object], widths: &mut HashMap<u32, f32>) {
+    let mut i = 0;
+    while let Some(Ok(first)) = list.get(i).map(|o| deref(doc, o).as_i64()) {
+        match list.get(i + 1).map(|o| deref(doc, o)) {
+            Some(Object::Array(run)) => {
+                for (j, width) in run.iter().enumerate() {
+                    if let Ok(width) = deref(doc, width).as_float() {
+                        widths.insert((first + j as i64) as u32, width);
+                    }
+                }
+                i += 2;
+            }
+            Some(last) => {
+                let (Ok(last), Some(Ok(width))) = (
+                    last.as_i64(),
+                    list.get(i + 2).map(|o| deref(doc, o).as_float()),
+                ) else {
+                    return;
+                };
+                // Bounded, as a malformed range could otherwise cover all of u32.
+                for code in first..=last.min(first + 0xFFFF) {
+                    widths.insert(code as u32, width);
+                }
+                i += 3;
+            }
+            None => return,
+        }
+    }
+}
+
+/// The outline of a glyph in font units.
+fn glyph_outline(
+    face: &ttf_parser::Face,
+    glyph: ttf_parser::GlyphId,
+) -> Option<resvg::tiny_skia::Path> {
+    struct Builder(PathBuilder);
+
+    impl ttf_parser::OutlineBuilder for Builder {
+        fn move_to(&mut self, x: f32, y: f32) {
+            self.0.move_to(x, y);
+        }
+        fn line_to(&mut self, x: f32, y: f32) {
+            self.0.line_to(x, y);
+        }
+        fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+            self.0.quad_to(x1, y1, x, y);
+        }
+        fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+            self.0.cubic_to(x1, y1, x2, y2, x, y);
+        }
+        fn close(&mut self) {
+            self.0.close();
+        }
+    }
+
+    let mut builder = Builder(PathBuilder::new());
+    face.outline_glyph(glyph, &mut builder)?;
+    builder.0.finish()
+}
@@ -1,6 +1,7 @@
 use super::*;
 use std::io::Write;
 use std::path::Path;
+use std::sync::Arc;
 use tempfile::tempdir;
 
 fn create_test_storage() -> (FileStorage, tempfile::TempDir) {
@@ -9,10 +10,78 @@ fn create_test_storage() -> (FileStorage, tempfile::TempDir) {
         content_path: temp_dir.path().join("content"),
         blobs_path: temp_dir.path().join("blobs"),
         thumbnails_path: temp_dir.path().join("thumbnails"),
+        text_path: temp_dir.path().join("text"),
+        cipher: None,
+        thumbnailers: Arc::new(ThumbnailRegistry::default()),
+        thumbnail_config: ThumbnailConfig::default(),
     };
     (storage, temp_dir)
 }
 
+impl FileStorage {
+    fn generate_thumbnail(&self, key_hash: &Path, filename: &str) -> Result<(), FileStorageError> {
+        let variants = self.render_thumbnails(key_hash, filename)?;
+        self.write_thumbnails(key_hash, filename, &variants)
+    }
+}
+
+/// A little-endian EXIF chunk with make, model and orientation in IFD0 and the capture date in
+/// the Exif IFD. Strings must be at least 4 bytes long, so they are stored out of line.
+fn exif_chunk(make: &str, model: &str, orientation: u16, taken_at: &str) -> Vec<u8> {
+    let strings = [make, model, taken_at].map(|s| format!("{s}\0").into_bytes());
+    let entry = |tag: u16, kind: u16, count: usize, value: usize| {
+        [
+            tag.to_le_bytes().as_slice(),
+            &kind.to_le_bytes(),
+            &(count as u32).to_le_bytes(),
+            &(value as u32).to_le_bytes(),
+        ]
+        .concat()
+    };
+    let (make_at, model_at) = (80, 80 + strings[0].len());
+    let taken_at_at = model_at + strings[1].len();
+
+    let mut chunk = b"II*\0".to_vec();
+    chunk.extend(8u32.to_le_bytes());
+    chunk.extend(4u16.to_le_bytes());
+    chunk.extend(entry(0x010f, 2, strings[0].len(), make_at));
+    chunk.extend(entry(0x0110, 2, strings[1].len(), model_at));
+    chunk.extend(entry(0x0112, 3, 1, orientation.into()));
+    chunk.extend(entry(0x8769, 4, 1, 62));
+    chunk.extend(0u32.to_le_bytes());
+    chunk.extend(1u16.to_le_bytes());
+    chunk.extend(entry(0x9003, 2, strings[2].len(), taken_at_at));
+    chunk.extend(0u32.to_le_bytes());
+    assert_eq!(chunk.len(), 80);
+    chunk.extend(strings.concat());
+    chunk
+}
+
+fn jpeg_with_exif(width: u32, height: u32, exif: Vec<u8>) -> Vec<u8> {
+    use image::ImageEncoder;
+
+    let img = image::RgbImage::from_fn(width, height, |x, _| {
+        image::Rgb([if x < width / 2 { 250 } else { 5 }, 10, 10])
+    });
+    let mut bytes = Vec::new();
+    let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut bytes);
+    encoder.set_exif_metadata(exif).unwrap();
+    encoder
+        .write_image(img.as_raw(), width, height, image::ExtendedColorType::Rgb8)
+        .unwrap();
+    bytes
+}
+
+/// Stores the contents of `source` as an attachment.
+fn add_attachment(storage: &FileStorage, key_hash: &Path, source: &Path, filename: &str) {
+    let file = std::fs::File::open(source).unwrap();
+    storage
+        .write_attachment(key_hash, filename, file, None)
+        .unwrap()
+        .finish()
+        .unwrap();
+}
+
 fn create_test_file(dir: &tempfile::TempDir, name: &str, content: &[u8]) -> std::path::PathBuf {
     let path = dir.path().join(name);
     let mut file = std::fs::File::create(&path).unwrap();
@@ -80,45 +149,69 @@ mod remove_content {
     }
 }
 
-mod add_attachment {
+mod write_attachment {
     use super::*;
 
     #[test]
-    fn test_add_attachment_copies_file() {
-        let (storage, temp) = create_test_storage();
+    fn test_write_attachment_streams_and_fingerprints() {
+        let (storage, _temp) = create_test_storage();
         let key_hash = Path::new("abc123");
-        let source = create_test_file(&temp, "source.txt", b"file content");
 
-        let size = storage
-            .add_attachment(key_hash, &source, "dest.txt")
+        let staged = storage
+            .write_attachment(key_hash, "dest.txt", &b"file content"[..], None)
             .unwrap();
+        assert_eq!(staged.fingerprint.size, 12);
+        assert_eq!(staged.fingerprint.hash, blake3::hash(b"file content"));
+        assert_eq!(staged.fingerprint.mime, "text/plain; charset=utf-8");
+        staged.finish().unwrap();
 
-        assert_eq!(size, 12);
         let dest_path = storage.attachment_path(key_hash, "dest.txt");
-        assert!(dest_path.exists());
         assert_eq!(std::fs::read_to_string(&dest_path).unwrap(), "file content");
     }
 
     #[test]
-    fn test_add_attachment_creates_directory() {
-        let (storage, temp) = create_test_storage();
+    fn test_write_attachment_creates_directory() {
+        let (storage, _temp) = create_test_storage();
         let key_hash = Path::new("abc123");
-        let source = create_test_file(&temp, "source.txt", b"content");
 
         assert!(!storage.blobs_path.join(key_hash).exists());
         storage
-            .add_attachment(key_hash, &source, "file.txt")
+            .write_attachment(key_hash, "file.txt", &b"content"[..], None)
+            .unwrap()
+            .finish()
             .unwrap();
         assert!(storage.blobs_path.join(key_hash).exists());
     }
 
     #[test]
-    fn test_add_directory_fails() {
-        let (storage, temp) = create_test_storage();
+    fn test_staged_attachment_replaces_only_when_finished() {
+        let (storage, _temp) = create_test_storage();
         let key_hash = Path::new("abc123");
+        let dest_path = storage.attachment_path(key_hash, "file.txt");
+        storage
+            .write_attachment(key_hash, "file.txt", &b"old"[..], None)
+            .unwrap()
+            .finish()
+            .unwrap();
+
+        let dropped = storage
+            .write_attachment(key_hash, "file.txt", &b"discarded"[..], None)
+            .unwrap();
+        assert_eq!(std::fs::read(&dest_path).unwrap(), b"old");
+        drop(dropped);
+        assert_eq!(
+            std::fs::read_dir(storage.blobs_path.join(key_hash))
+                .unwrap()
+                .count(),
+            1
+        );
 
-        let result = storage.add_attachment(key_hash, temp.path(), "dir");
-        assert!(matches!(result, Err(FileStorageError::IsDirectory)));
+        let staged = storage
+            .write_attachment(key_hash, "file.txt", &b"new"[..], None)
+            .unwrap();
+        assert_eq!(std::fs::read(&dest_path).unwrap(), b"old");
+        staged.finish().unwrap();
+        assert_eq!(std::fs::read(&dest_path).unwrap(), b"new");
     }
 }
 
@@ -146,9 +239,7 @@ mod remove_attachment {
         let key_hash = Path::new("abc123");
         let source = create_test_file(&temp, "source.txt", b"content");
 
-        storage
-            .add_attachment(key_hash, &source, "file.txt")
-            .unwrap();
+        add_attachment(&storage, key_hash, &source, "file.txt");
         let path = storage.attachment_path(key_hash, "file.txt");
         assert!(path.exists());
 
@@ -162,9 +253,7 @@ mod remove_attachment {
         let key_hash = Path::new("abc123");
         let source = create_test_file(&temp, "source.txt", b"content");
 
-        storage
-            .add_attachment(key_hash, &source, "file.txt")
-            .unwrap();
+        add_attachment(&storage, key_hash, &source, "file.txt");
         let key_dir = storage.blobs_path.join(key_hash);
         assert!(key_dir.exists());
 
@@ -192,9 +281,7 @@ mod rename_attachment {
         let key_hash = Path::new("abc123");
         let source = create_test_file(&temp, "source.txt", b"content");
 
-        storage
-            .add_attachment(key_hash, &source, "old.txt")
-            .unwrap();
+        add_attachment(&storage, key_hash, &source, "old.txt");
 
         storage
             .rename_attachment(key_hash, "old.txt", "new.txt")
@@ -228,12 +315,8 @@ mod remove_all_attachments {
         let source1 = create_test_file(&temp, "file1.txt", b"content1");
         let source2 = create_test_file(&temp, "file2.txt", b"content2");
 
-        storage
-            .add_attachment(key_hash, &source1, "file1.txt")
-            .unwrap();
-        storage
-            .add_attachment(key_hash, &source2, "file2.txt")
-            .unwrap();
+        add_attachment(&storage, key_hash, &source1, "file1.txt");
+        add_attachment(&storage, key_hash, &source2, "file2.txt");
 
         let key_dir = storage.blobs_path.join(key_hash);
         assert!(key_dir.exists());
@@ -255,19 +338,131 @@ mod thumbnail {
     use super::*;
 
     #[test]
-    fn test_thumbnail_path_format() {
+    fn test_thumbnail_dir_format() {
         let (storage, _temp) = create_test_storage();
         let key_hash = Path::new("abc123");
 
-        let path = storage.thumbnail_path(key_hash, "image.png");
-        assert!(
-            path.ends_with("thumbnails/abc123/image.png.thumb")
-                || path.ends_with("thumbnails\\abc123\\image.png.thumb")
+        let path = storage.thumbnail_dir(key_hash, "image.png");
+        assert_eq!(
+            path,
+            storage
+                .thumbnails_path
+                .join("abc123")
+                .join("image.png.thumbs")
+        );
+    }
+
+    #[test]
+    fn test_variant_names() {
+        assert_eq!(
+            variant_name(ThumbnailPreset::Grid, 1, ThumbnailFormat::Png),
+            "grid.png"
+        );
+        assert_eq!(
+            variant_name(ThumbnailPreset::ListIcon, 2, ThumbnailFormat::Jpeg),
+            "list_icon@2x.jpg"
+        );
+        assert_eq!(variant_pixels("grid.png"), Some(200));
+        assert_eq!(variant_pixels("preview@2x.webp"), Some(1600));
+        assert_eq!(variant_pixels("list_icon@2x.jpg"), Some(64));
+        assert_eq!(variant_pixels("poster.png"), None);
+        assert_eq!(variant_pixels("grid@x.png"), None);
+    }
+
+    fn write_variants(storage: &FileStorage, key_hash: &Path, filename: &str, names: &[&str]) {
+        let dir = storage.thumbnail_dir(key_hash, filename);
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in names {
+            std::fs::write(dir.join(name), b"data").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_best_thumbnail_picks_smallest_large_enough() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("abc123");
+        write_variants(
+            &storage,
+            key_hash,
+            "image.png",
+            &[
+                "list_icon.png",
+                "list_icon@2x.png",
+                "grid.png",
+                "preview.png",
+            ],
+        );
+
+        let best = |preset, scale| {
+            let path = storage.best_thumbnail(key_hash, "image.png", preset, scale);
+            path.unwrap()
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(best(ThumbnailPreset::ListIcon, 1), "list_icon.png");
+        assert_eq!(best(ThumbnailPreset::ListIcon, 2), "list_icon@2x.png");
+        // No grid@2x: the preview is the smallest that is large enough
+        assert_eq!(best(ThumbnailPreset::Grid, 2), "preview.png");
+        // Nothing large enough: the largest stored
+        assert_eq!(best(ThumbnailPreset::Preview, 2), "preview.png");
+    }
+
+    #[test]
+    fn test_best_thumbnail_without_thumbnails() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("abc123");
+
+        let best = storage.best_thumbnail(key_hash, "image.png", ThumbnailPreset::Grid, 1);
+        assert_eq!(best, None);
+    }
+
+    #[test]
+    fn test_legacy_thumbnail_serves_as_grid() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("abc123");
+        let thumb_dir = storage.thumbnails_path.join(key_hash);
+        std::fs::create_dir_all(&thumb_dir).unwrap();
+        std::fs::write(thumb_dir.join("image.png.thumb"), b"data").unwrap();
+
+        let best = storage.best_thumbnail(key_hash, "image.png", ThumbnailPreset::ListIcon, 1);
+        assert_eq!(best, Some(key_hash.join("image.png.thumb")));
+    }
+
+    #[test]
+    fn test_rename_thumbnail_moves_variants() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("abc123");
+        write_variants(&storage, key_hash, "old.png", &["grid.png", "grid@2x.png"]);
+
+        storage
+            .rename_thumbnail(key_hash, "old.png", "new.png")
+            .unwrap();
+        assert!(storage.thumbnail_files(key_hash, "old.png").is_empty());
+        assert_eq!(storage.thumbnail_files(key_hash, "new.png").len(), 2);
+    }
+
+    #[test]
+    fn test_remove_thumbnail_removes_variants() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("abc123");
+        write_variants(
+            &storage,
+            key_hash,
+            "image.png",
+            &["grid.png", "grid@2x.png"],
         );
+        write_variants(&storage, key_hash, "other.png", &["grid.png"]);
+
+        storage.remove_thumbnail(key_hash, "image.png").unwrap();
+        assert!(!storage.thumbnail_dir(key_hash, "image.png").exists());
+        assert!(storage.thumbnail_dir(key_hash, "other.png").exists());
     }
 
     #[test]
-    fn test_remove_thumbnail_removes_file() {
+    fn test_remove_thumbnail_removes_legacy_file() {
         let (storage, _temp) = create_test_storage();
         let key_hash = Path::new("abc123");
 
@@ -321,9 +516,7 @@ mod remove_all {
 
         // Create attachment
         let source = create_test_file(&temp, "file.txt", b"content");
-        storage
-            .add_attachment(key_hash, &source, "file.txt")
-            .unwrap();
+        add_attachment(&storage, key_hash, &source, "file.txt");
 
         // Create thumbnail
         let thumb_dir = storage.thumbnails_path.join(key_hash);
@@ -343,6 +536,61 @@ mod remove_all {
     }
 }
 
+mod holding {
+    use super::*;
+
+    #[test]
+    fn test_swap_key_files_moves_and_restores() {
+        let (storage, temp) = create_test_storage();
+        let key_hash = Path::new("abc123");
+        let holding = temp.path().join("holding/1");
+
+        storage.create_content(key_hash).unwrap();
+        let source = create_test_file(&temp, "file.txt", b"content");
+        add_attachment(&storage, key_hash, &source, "file.txt");
+
+        storage.swap_key_files(key_hash, &holding).unwrap();
+        assert!(!storage.content_file_path(key_hash).exists());
+        assert!(!storage.blobs_path.join(key_hash).exists());
+        assert!(holding.join("content.md").exists());
+        assert!(holding.join("blobs/file.txt").exists());
+
+        storage.swap_key_files(key_hash, &holding).unwrap();
+        assert!(storage.content_file_path(key_hash).exists());
+        assert_eq!(
+            std::fs::read(storage.attachment_path(key_hash, "file.txt")).unwrap(),
+            b"content"
+        );
+        assert!(!holding.join("blobs").exists());
+    }
+
+    #[test]
+    fn test_swap_attachment_files_exchanges_versions() {
+        let (storage, temp) = create_test_storage();
+        let key_hash = Path::new("abc123");
+        let holding = temp.path().join("holding/1");
+
+        let old = create_test_file(&temp, "old.txt", b"old");
+        add_attachment(&storage, key_hash, &old, "file.txt");
+        storage
+            .swap_attachment_files(key_hash, "file.txt", &holding)
+            .unwrap();
+        assert!(!storage.blobs_path.join(key_hash).exists());
+
+        let new = create_test_file(&temp, "new.txt", b"new");
+        add_attachment(&storage, key_hash, &new, "file.txt");
+        storage
+            .swap_attachment_files(key_hash, "file.txt", &holding)
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read(storage.attachment_path(key_hash, "file.txt")).unwrap(),
+            b"old"
+        );
+        assert_eq!(std::fs::read(holding.join("blob")).unwrap(), b"new");
+    }
+}
+
 mod rename_all {
     use super::*;
 
@@ -358,9 +606,7 @@ mod rename_all {
 
         // Create attachment
         let source = create_test_file(&temp, "file.txt", b"content");
-        storage
-            .add_attachment(old_hash, &source, "file.txt")
-            .unwrap();
+        add_attachment(&storage, old_hash, &source, "file.txt");
 
         // Create thumbnail
         let thumb_dir = storage.thumbnails_path.join(old_hash);
@@ -401,9 +647,7 @@ mod rename_all {
 
         // Create old attachment
         let source1 = create_test_file(&temp, "old.txt", b"old attachment");
-        storage
-            .add_attachment(old_hash, &source1, "file.txt")
-            .unwrap();
+        add_attachment(&storage, old_hash, &source1, "file.txt");
 
         // Create new content (to be overwritten)
         storage.create_content(new_hash).unwrap();
@@ -411,9 +655,7 @@ mod rename_all {
 
         // Create new attachment (to be overwritten)
         let source2 = create_test_file(&temp, "new.txt", b"new attachment");
-        storage
-            .add_attachment(new_hash, &source2, "other.txt")
-            .unwrap();
+        add_attachment(&storage, new_hash, &source2, "other.txt");
 
         storage.rename_all(old_hash, new_hash).unwrap();
 
@@ -438,12 +680,8 @@ mod list_key_hashes {
         let (storage, temp) = create_test_storage();
         let source = create_test_file(&temp, "file.txt", b"content");
 
-        storage
-            .add_attachment(Path::new("hash1"), &source, "file.txt")
-            .unwrap();
-        storage
-            .add_attachment(Path::new("hash2"), &source, "file.txt")
-            .unwrap();
+        add_attachment(&storage, Path::new("hash1"), &source, "file.txt");
+        add_attachment(&storage, Path::new("hash2"), &source, "file.txt");
 
         let hashes = storage.list_blob_key_hashes().unwrap();
         assert_eq!(hashes.len(), 2);
@@ -485,30 +723,43 @@ mod thumbnail_generation {
     use super::*;
 
     #[test]
-    fn test_is_supported_image_png() {
-        assert!(FileStorage::is_supported_image("image.png"));
-        assert!(FileStorage::is_supported_image("image.PNG"));
+    fn test_supports_thumbnail_png() {
+        let (storage, _temp) = create_test_storage();
+        assert!(storage.supports_thumbnail("image.png"));
+        assert!(storage.supports_thumbnail("image.PNG"));
     }
 
     #[test]
-    fn test_is_supported_image_jpeg() {
-        assert!(FileStorage::is_supported_image("photo.jpg"));
-        assert!(FileStorage::is_supported_image("photo.jpeg"));
-        assert!(FileStorage::is_supported_image("photo.JPEG"));
+    fn test_supports_thumbnail_jpeg() {
+        let (storage, _temp) = create_test_storage();
+        assert!(storage.supports_thumbnail("photo.jpg"));
+        assert!(storage.supports_thumbnail("photo.jpeg"));
+        assert!(storage.supports_thumbnail("photo.JPEG"));
     }
 
     #[test]
-    fn test_is_supported_image_other_formats() {
-        assert!(FileStorage::is_supported_image("image.gif"));
-        assert!(FileStorage::is_supported_image("image.webp"));
+    fn test_supports_thumbnail_other_formats() {
+        let (storage, _temp) = create_test_storage();
+        for name in [
+            "image.gif",
+            "image.webp",
+            "shot.bmp",
+            "scan.tif",
+            "scan.TIFF",
+            "diagram.svg",
+            "document.pdf",
+        ] {
+            assert!(storage.supports_thumbnail(name), "{name}");
+        }
     }
 
     #[test]
-    fn test_is_supported_image_unsupported() {
-        assert!(!FileStorage::is_supported_image("document.pdf"));
-        assert!(!FileStorage::is_supported_image("video.mp4"));
-        assert!(!FileStorage::is_supported_image("file.txt"));
-        assert!(!FileStorage::is_supported_image("noext"));
+    fn test_supports_thumbnail_unsupported() {
+        let (storage, _temp) = create_test_storage();
+        assert!(!storage.supports_thumbnail("video.mp4"));
+        assert!(!storage.supports_thumbnail("file.txt"));
+        assert!(!storage.supports_thumbnail("noext"));
+        assert!(!storage.supports_thumbnail("png"));
     }
 
     #[test]
@@ -516,7 +767,7 @@ mod thumbnail_generation {
         let (storage, _temp) = create_test_storage();
         let key_hash = Path::new("test_hash");
 
-        let result = storage.generate_thumbnail(key_hash, "document.pdf");
+        let result = storage.generate_thumbnail(key_hash, "video.mp4");
         assert!(matches!(result, Err(FileStorageError::UnsupportedFormat)));
     }
 
@@ -535,7 +786,8 @@ mod thumbnail_generation {
         img.save(blob_dir.join(filename)).unwrap();
     }
 
-    fn open_thumbnail(path: &Path) -> image::DynamicImage {
+    fn open_thumbnail(storage: &FileStorage, key_hash: &Path, name: &str) -> image::DynamicImage {
+        let path = storage.thumbnail_dir(key_hash, name).join("grid.png");
         let bytes = std::fs::read(path).unwrap();
         image::load_from_memory(&bytes).unwrap()
     }
@@ -550,10 +802,7 @@ mod thumbnail_generation {
             .generate_thumbnail(key_hash, "landscape.png")
             .unwrap();
 
-        let thumb_path = storage.thumbnail_path(key_hash, "landscape.png");
-        assert!(thumb_path.exists());
-
-        let thumb = open_thumbnail(&thumb_path);
+        let thumb = open_thumbnail(&storage, key_hash, "landscape.png");
         // 400x300 scaled to fit 200px max -> 200x150
         assert_eq!(thumb.width(), 200);
         assert_eq!(thumb.height(), 150);
@@ -569,7 +818,7 @@ mod thumbnail_generation {
             .generate_thumbnail(key_hash, "portrait.png")
             .unwrap();
 
-        let thumb = open_thumbnail(&storage.thumbnail_path(key_hash, "portrait.png"));
+        let thumb = open_thumbnail(&storage, key_hash, "portrait.png");
         // 300x600 scaled to fit 200px max -> 100x200
         assert_eq!(thumb.width(), 100);
         assert_eq!(thumb.height(), 200);
@@ -583,7 +832,7 @@ mod thumbnail_generation {
         create_test_image(&storage, key_hash, "square.png", 400, 400);
         storage.generate_thumbnail(key_hash, "square.png").unwrap();
 
-        let thumb = open_thumbnail(&storage.thumbnail_path(key_hash, "square.png"));
+        let thumb = open_thumbnail(&storage, key_hash, "square.png");
         assert_eq!(thumb.width(), 200);
         assert_eq!(thumb.height(), 200);
     }
@@ -596,7 +845,7 @@ mod thumbnail_generation {
         create_test_image(&storage, key_hash, "small.png", 100, 80);
         storage.generate_thumbnail(key_hash, "small.png").unwrap();
 
-        let thumb = open_thumbnail(&storage.thumbnail_path(key_hash, "small.png"));
+        let thumb = open_thumbnail(&storage, key_hash, "small.png");
         // Small images should not be upscaled
         assert_eq!(thumb.width(), 100);
         assert_eq!(thumb.height(), 80);
@@ -610,8 +859,693 @@ mod thumbnail_generation {
         create_test_image(&storage, key_hash, "exact.png", 200, 150);
         storage.generate_thumbnail(key_hash, "exact.png").unwrap();
 
-        let thumb = open_thumbnail(&storage.thumbnail_path(key_hash, "exact.png"));
+        let thumb = open_thumbnail(&storage, key_hash, "exact.png");
         assert_eq!(thumb.width(), 200);
         assert_eq!(thumb.height(), 150);
     }
+
+    fn variant_sizes(storage: &FileStorage, key_hash: &Path, name: &str) -> Vec<(String, u32)> {
+        let dir = storage.thumbnail_dir(key_hash, name);
+        let mut sizes: Vec<(String, u32)> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let thumb = image::load_from_memory(&std::fs::read(&path).unwrap()).unwrap();
+                let name = path.file_name().unwrap().to_str().unwrap().to_string();
+                (name, thumb.width().max(thumb.height()))
+            })
+            .collect();
+        sizes.sort();
+        sizes
+    }
+
+    #[test]
+    fn test_generate_thumbnail_all_presets_and_scales() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("test_hash");
+
+        create_test_image(&storage, key_hash, "photo.png", 2000, 1000);
+        storage.generate_thumbnail(key_hash, "photo.png").unwrap();
+
+        let expected = [
+            ("grid.png", 200),
+            ("grid@2x.png", 400),
+            ("list_icon.png", 32),
+            ("list_icon@2x.png", 64),
+            ("preview.png", 800),
+            ("preview@2x.png", 1600),
+        ];
+        let expected: Vec<_> = expected.map(|(n, s)| (n.to_string(), s)).into();
+        assert_eq!(variant_sizes(&storage, key_hash, "photo.png"), expected);
+    }
+
+    #[test]
+    fn test_generate_thumbnail_without_hidpi() {
+        let (mut storage, _temp) = create_test_storage();
+        let key_hash = Path::new("test_hash");
+        storage.thumbnail_config = ThumbnailConfig {
+            presets: vec![ThumbnailPreset::Grid],
+            hidpi: false,
+            ..ThumbnailConfig::default()
+        };
+
+        create_test_image(&storage, key_hash, "photo.png", 400, 300);
+        storage.generate_thumbnail(key_hash, "photo.png").unwrap();
+
+        assert_eq!(
+            variant_sizes(&storage, key_hash, "photo.png"),
+            vec![("grid.png".to_string(), 200)]
+        );
+    }
+
+    #[test]
+    fn test_generate_thumbnail_jpeg_and_webp() {
+        for (format, expected) in [
+            (ThumbnailFormat::Jpeg, image::ImageFormat::Jpeg),
+            (ThumbnailFormat::Webp, image::ImageFormat::WebP),
+        ] {
+            let (mut storage, _temp) = create_test_storage();
+            let key_hash = Path::new("test_hash");
+            storage.thumbnail_config = ThumbnailConfig {
+                presets: vec![ThumbnailPreset::Grid],
+                hidpi: false,
+                format,
+                jpeg_quality: 60,
+            };
+
+            create_test_image(&storage, key_hash, "photo.png", 400, 300);
+            storage.generate_thumbnail(key_hash, "photo.png").unwrap();
+
+            let name = format!("grid.{}", format.extension());
+            let path = storage.thumbnail_dir(key_hash, "photo.png").join(name);
+            let bytes = std::fs::read(path).unwrap();
+            assert_eq!(image::guess_format(&bytes).unwrap(), expected);
+            let thumb = image::load_from_memory(&bytes).unwrap();
+            assert_eq!((thumb.width(), thumb.height()), (200, 150));
+        }
+    }
+
+    #[test]
+    fn test_jpeg_flattens_transparency_onto_white() {
+        let (mut storage, _temp) = create_test_storage();
+        let key_hash = Path::new("test_hash");
+        storage.thumbnail_config = ThumbnailConfig {
+            presets: vec![ThumbnailPreset::ListIcon],
+            hidpi: false,
+            format: ThumbnailFormat::Jpeg,
+            jpeg_quality: 100,
+        };
+
+        let img = image::RgbaImage::from_pixel(16, 16, image::Rgba([0, 0, 0, 0]));
+        let blob_dir = storage.blobs_path.join(key_hash);
+        std::fs::create_dir_all(&blob_dir).unwrap();
+        img.save(blob_dir.join("clear.png")).unwrap();
+        storage.generate_thumbnail(key_hash, "clear.png").unwrap();
+
+        let path = storage
+            .thumbnail_dir(key_hash, "clear.png")
+            .join("list_icon.jpg");
+        let thumb = image::load_from_memory(&std::fs::read(path).unwrap()).unwrap();
+        let pixel = thumb.to_rgb8().get_pixel(8, 8).0;
+        assert!(pixel.iter().all(|&c| c > 250), "{pixel:?}");
+    }
+}
+
+mod thumbnail_renderers {
+    use super::*;
+    use lopdf::{Document, Object, Stream, dictionary};
+
+    fn write_blob(storage: &FileStorage, key_hash: &Path, filename: &str, bytes: &[u8]) {
+        let blob_dir = storage.blobs_path.join(key_hash);
+        std::fs::create_dir_all(&blob_dir).unwrap();
+        std::fs::write(blob_dir.join(filename), bytes).unwrap();
+    }
+
+    fn thumbnail_size(storage: &FileStorage, key_hash: &Path, filename: &str) -> (u32, u32) {
+        let path = storage.thumbnail_dir(key_hash, filename).join("grid.png");
+        let bytes = std::fs::read(path).unwrap();
+        let thumb = image::load_from_memory(&bytes).unwrap();
+        (thumb.width(), thumb.height())
+    }
+
+    fn encode(width: u32, height: u32, format: image::ImageFormat) -> Vec<u8> {
+        let img = image::RgbImage::from_pixel(width, height, image::Rgb([10, 20, 30]));
+        let mut bytes = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut bytes), format)
+            .unwrap();
+        bytes
+    }
+
+    /// A one-page PDF drawing `content`, whose page inherits a standard font `F1` and solid RGB
+    /// images `Im0`, `Im1`, ... of the given sizes and colours from its parent node.
+    fn pdf_page(
+        media_box: [i64; 4],
+        rotate: i64,
+        content: &str,
+        images: &[(i64, i64, [u8; 3])],
+    ) -> Vec<u8> {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+
+        let mut xobjects = lopdf::Dictionary::new();
+        for (i, &(width, height, rgb)) in images.iter().enumerate() {
+            let mut stream = Stream::new(
+                dictionary! {
+                    "Type" => "XObject",
+                    "Subtype" => "Image",
+                    "Width" => width,
+                    "Height" => height,
+                    "ColorSpace" => "DeviceRGB",
+                    "BitsPerComponent" => 8,
+                },
+                rgb.repeat((width * height) as usize),
+            );
+            stream.compress().unwrap();
+            xobjects.set(format!("Im{i}"), doc.add_object(stream));
+        }
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+
+        let mut content = Stream::new(dictionary! {}, content.as_bytes().to_vec());
+        content.compress().unwrap();
+        let content_id = doc.add_object(content);
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+            "Rotate" => rotate,
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+                "MediaBox" => media_box.iter().map(|&v| v.into()).collect::<Vec<Object>>(),
+                "Resources" => dictionary! {
+                    "XObject" => xobjects,
+                    "Font" => dictionary! { "F1" => font_id },
+                },
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn thumbnail_rgb(storage: &FileStorage, key_hash: &Path, filename: &str) -> image::RgbImage {
+        let path = storage.thumbnail_dir(key_hash, filename).join("grid.png");
+        image::open(path).unwrap().to_rgb8()
+    }
+
+    #[test]
+    fn test_bmp_and_tiff() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("test_hash");
+
+        write_blob(
+            &storage,
+            key_hash,
+            "shot.bmp",
+            &encode(400, 100, image::ImageFormat::Bmp),
+        );
+        write_blob(
+            &storage,
+            key_hash,
+            "scan.tiff",
+            &encode(100, 400, image::ImageFormat::Tiff),
+        );
+        storage.generate_thumbnail(key_hash, "shot.bmp").unwrap();
+        storage.generate_thumbnail(key_hash, "scan.tiff").unwrap();
+
+        assert_eq!(thumbnail_size(&storage, key_hash, "shot.bmp"), (200, 50));
+        assert_eq!(thumbnail_size(&storage, key_hash, "scan.tiff"), (50, 200));
+    }
+
+    #[test]
+    fn test_jpeg_exif_orientation_is_applied() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("test_hash");
+
+        // Rotate90: the left half (red) of the stored image ends up on top
+        let exif = exif_chunk("Apple", "iPhone 15 Pro", 6, "2024:05:01 13:45:12");
+        write_blob(
+            &storage,
+            key_hash,
+            "photo.jpg",
+            &jpeg_with_exif(40, 20, exif),
+        );
+        storage.generate_thumbnail(key_hash, "photo.jpg").unwrap();
+
+        let path = storage
+            .thumbnail_dir(key_hash, "photo.jpg")
+            .join("grid.png");
+        let thumb = image::open(path).unwrap().to_rgb8();
+        assert_eq!(thumb.dimensions(), (20, 40));
+        assert!(thumb.get_pixel(10, 5)[0] > 200);
+        assert!(thumb.get_pixel(10, 35)[0] < 50);
+    }
+
+    #[test]
+    fn test_svg_renders_to_thumbnail_size() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("test_hash");
+        let svg = br##"<svg xmlns="http://www.w3.org/2000/svg" width="40" height="20">
+            <rect width="40" height="20" fill="#0a0"/>
+        </svg>"##;
+
+        write_blob(&storage, key_hash, "diagram.svg", svg);
+        storage.generate_thumbnail(key_hash, "diagram.svg").unwrap();
+
+        // Vector images are rendered at the thumbnail size rather than scaled up
+        assert_eq!(
+            thumbnail_size(&storage, key_hash, "diagram.svg"),
+            (200, 100)
+        );
+    }
+
+    #[test]
+    fn test_pdf_renders_paths_and_images_of_first_page() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("test_hash");
+
+        // Red square on the left, a blue image scaled onto the right half
+        let content = "1 0 0 rg 0 0 100 100 re f q 100 0 0 100 100 0 cm /Im0 Do Q";
+        write_blob(
+            &storage,
+            key_hash,
+            "slide.pdf",
+            &pdf_page([0, 0, 200, 100], 0, content, &[(300, 300, [0, 0, 255])]),
+        );
+        storage.generate_thumbnail(key_hash, "slide.pdf").unwrap();
+
+        let thumb = thumbnail_rgb(&storage, key_hash, "slide.pdf");
+        assert_eq!(thumb.dimensions(), (200, 100));
+        assert_eq!(thumb.get_pixel(50, 50).0, [255, 0, 0]);
+        assert_eq!(thumb.get_pixel(150, 50).0, [0, 0, 255]);
+    }
+
+    #[test]
+    fn test_pdf_text_only_page_renders() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("test_hash");
+
+        let content = "BT /F1 40 Tf 10 50 Td (Budget report) Tj ET";
+        write_blob(
+            &storage,
+            key_hash,
+            "text.pdf",
+            &pdf_page([0, 0, 400, 200], 0, content, &[]),
+        );
+        storage.generate_thumbnail(key_hash, "text.pdf").unwrap();
+
+        // Glyphs of a font that isn't embedded are drawn as grey bars above the baseline
+        let thumb = thumbnail_rgb(&storage, key_hash, "text.pdf");
+        assert_eq!(thumb.dimensions(), (200, 100));
+        let dark = |y: u32| (0..200).filter(|&x| thumb.get_pixel(x, y)[0] < 200).count();
+        assert!(dark(70) > 50);
+        assert_eq!(dark(20), 0);
+        assert_eq!(dark(90), 0);
+    }
+
+    #[test]
+    fn test_pdf_page_rotation_and_empty_page() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("test_hash");
+
+        let content = "0 0 1 rg 0 0 100 100 re f";
+        write_blob(
+            &storage,
+            key_hash,
+            "rotated.pdf",
+            &pdf_page([0, 0, 200, 100], 90, content, &[]),
+        );
+        write_blob(
+            &storage,
+            key_hash,
+            "blank.pdf",
+            &pdf_page([0, 0, 100, 200], 0, "", &[]),
+        );
+        storage.generate_thumbnail(key_hash, "rotated.pdf").unwrap();
+        storage.generate_thumbnail(key_hash, "blank.pdf").unwrap();
+
+        // Turned clockwise, the left half of the page ends up on top
+        let rotated = thumbnail_rgb(&storage, key_hash, "rotated.pdf");
+        assert_eq!(rotated.dimensions(), (100, 200));
+        assert_eq!(rotated.get_pixel(50, 50).0, [0, 0, 255]);
+        assert_eq!(rotated.get_pixel(50, 150).0, [255, 255, 255]);
+
+        let blank = thumbnail_rgb(&storage, key_hash, "blank.pdf");
+        assert_eq!(blank.dimensions(), (100, 200));
+        assert!(blank.pixels().all(|p| p.0 == [255, 255, 255]));
+    }
+
+    #[test]
+    fn test_malformed_files_fail() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("test_hash");
+
+        write_blob(&storage, key_hash, "bad.svg", b"not svg");
+        write_blob(&storage, key_hash, "bad.pdf", b"not pdf");
+        assert!(matches!(
+            storage.generate_thumbnail(key_hash, "bad.svg"),
+            Err(FileStorageError::Svg(_))
+        ));
+        assert!(matches!(
+            storage.generate_thumbnail(key_hash, "bad.pdf"),
+            Err(FileStorageError::Pdf(_))
+        ));
+    }
+
+    struct Solid;
+
+    impl ThumbnailRenderer for Solid {
+        fn render(
+            &self,
+            _bytes: &[u8],
+            max_size: u32,
+        ) -> Result<image::DynamicImage, FileStorageError> {
+            Ok(image::DynamicImage::new_rgb8(max_size, max_size / 2))
+        }
+    }
+
+    #[test]
+    fn test_registered_renderer_handles_its_extensions() {
+        let (mut storage, _temp) = create_test_storage();
+        let key_hash = Path::new("test_hash");
+        let mut registry = ThumbnailRegistry::empty();
+        registry.register(&["TXT"], Arc::new(Solid));
+        storage.thumbnailers = Arc::new(registry);
+
+        assert!(storage.supports_thumbnail("notes.txt"));
+        assert!(!storage.supports_thumbnail("image.png"));
+
+        write_blob(&storage, key_hash, "notes.txt", b"hello");
+        storage.generate_thumbnail(key_hash, "notes.txt").unwrap();
+        assert_eq!(thumbnail_size(&storage, key_hash, "notes.txt"), (200, 100));
+    }
+}
+
+mod image_metadata {
+    use super::*;
+    use crate::types::value::versioned_value::latest_value::ImageMetadata;
+    use std::io::Cursor;
+
+    fn read(filename: &str, bytes: &[u8]) -> Option<ImageMetadata> {
+        read_image_metadata(filename, Cursor::new(bytes))
+    }
+
+    #[test]
+    fn test_reads_dimensions_date_and_camera() {
+        let exif = exif_chunk("Apple", "iPhone 15 Pro", 1, "2024:05:01 13:45:12");
+        let metadata = read("photo.jpg", &jpeg_with_exif(40, 20, exif)).unwrap();
+
+        assert_eq!(
+            metadata,
+            ImageMetadata {
+                width: 40,
+                height: 20,
+                captured_at: Some("2024-05-01T13:45:12".to_string()),
+                camera: Some("Apple iPhone 15 Pro".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_rotated_photo_reports_displayed_dimensions() {
+        let exif = exif_chunk("Canon", "Canon EOS R5", 6, "2023:12:24 08:00:00");
+        let metadata = read("photo.JPEG", &jpeg_with_exif(40, 20, exif)).unwrap();
+
+        assert_eq!((metadata.width, metadata.height), (20, 40));
+        // The make is not repeated when the model already starts with it
+        assert_eq!(metadata.camera.as_deref(), Some("Canon EOS R5"));
+    }
+
+    #[test]
+    fn test_unset_capture_date_is_none() {
+        let exif = exif_chunk("NIKON CORPORATION", "NIKON D750", 1, "0000:00:00 00:00:00");
+        let metadata = read("photo.jpg", &jpeg_with_exif(8, 8, exif)).unwrap();
+
+        assert_eq!(metadata.captured_at, None);
+        assert_eq!(metadata.camera.as_deref(), Some("NIKON D750"));
+    }
+
+    #[test]
+    fn test_image_without_exif() {
+        let img = image::RgbaImage::new(30, 10);
+        let mut bytes = Vec::new();
+        img.write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+
+        assert_eq!(
+            read("drawing.png", &bytes),
+            Some(ImageMetadata {
+                width: 30,
+                height: 10,
+                captured_at: None,
+                camera: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_malformed_exif_is_ignored() {
+        let mut exif = exif_chunk("Apple", "iPhone 15 Pro", 1, "2024:05:01 13:45:12");
+        // Point the Exif IFD past the end of the chunk
+        exif[54..58].copy_from_slice(&9999u32.to_le_bytes());
+        exif.truncate(84);
+        let metadata = read("photo.jpg", &jpeg_with_exif(8, 8, exif)).unwrap();
+
+        assert_eq!((metadata.width, metadata.height), (8, 8));
+        assert_eq!(metadata.captured_at, None);
+        assert_eq!(metadata.camera, None);
+    }
+
+    #[test]
+    fn test_non_images_are_skipped() {
+        assert_eq!(read("notes.txt", b"hello"), None);
+        assert_eq!(read("noext", b"hello"), None);
+        assert_eq!(read("broken.png", b"not an image"), None);
+    }
+}
+
+mod mime {
+    use super::*;
+    use crate::core::file_storage::mime::sniff_mime;
+
+    #[test]
+    fn test_binary_signatures() {
+        assert_eq!(sniff_mime(b"\x89PNG\r\n\x1a\n\0\0"), "image/png");
+        assert_eq!(sniff_mime(b"\xff\xd8\xff\xe0"), "image/jpeg");
+        assert_eq!(sniff_mime(b"%PDF-1.4"), "application/pdf");
+        assert_eq!(sniff_mime(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(sniff_mime(b"RIFF\0\0\0\0WAVEfmt "), "audio/wav");
+        assert_eq!(sniff_mime(b"\0\0\0\x18ftypisom"), "video/mp4");
+        assert_eq!(sniff_mime(b"\0\0\0\x18ftypheic"), "image/heic");
+        assert_eq!(sniff_mime(b"BM\x36\0\0\0\0\0\0\0"), "image/bmp");
+    }
+
+    #[test]
+    fn test_text_and_svg() {
+        assert_eq!(sniff_mime(b"BMW owners club"), "text/plain; charset=utf-8");
+        assert_eq!(sniff_mime(b""), "text/plain; charset=utf-8");
+        assert_eq!(
+            sniff_mime("caf\u{e9}".as_bytes()),
+            "text/plain; charset=utf-8"
+        );
+        // A multi-byte character cut off at the end of the sniffed bytes
+        assert_eq!(sniff_mime(&[b'a', 0xc3]), "text/plain; charset=utf-8");
+        assert_eq!(
+            sniff_mime(b"<?xml version=\"1.0\"?>\n<!-- logo -->\n<svg xmlns=\"\"/>"),
+            "image/svg+xml"
+        );
+        assert_eq!(sniff_mime(b"<html></html>"), "text/plain; charset=utf-8");
+    }
+
+    #[test]
+    fn test_binary_without_signature() {
+        assert_eq!(sniff_mime(b"abc\0def"), "application/octet-stream");
+        assert_eq!(sniff_mime(&[0xff, 0xfe, 0x00]), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_extension() {
+        let temp = tempdir().unwrap();
+        let path = create_test_file(&temp, "image.txt", b"GIF89a\x01\0\x01\0");
+
+        let fingerprint = FileStorage::fingerprint(&path).unwrap();
+        assert_eq!(fingerprint.mime, "image/gif");
+        assert_eq!(fingerprint.hash, blake3::hash(b"GIF89a\x01\0\x01\0"));
+        assert_eq!(fingerprint.size, 10);
+        assert!(matches!(
+            FileStorage::fingerprint(temp.path()),
+            Err(FileStorageError::IsDirectory)
+        ));
+    }
+}
+
+mod text {
+    use super::*;
+    use crate::core::file_storage::text::extract_text;
+    use crate::core::file_storage::{MAX_PDF_LEN, MAX_TEXT_LEN};
+    use lopdf::{Document, Object, Stream, dictionary};
+
+    /// A one-page PDF showing `line` in a standard font, or nothing if `line` is `None`.
+    fn pdf_with_text(line: Option<&str>) -> Vec<u8> {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+            "Encoding" => "WinAnsiEncoding",
+        });
+        let content = match line {
+            Some(line) => format!("BT /F1 12 Tf 72 720 Td ({line}) Tj ET").into_bytes(),
+            None => Vec::new(),
+        };
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn extract(filename: &str, bytes: &[u8]) -> Option<String> {
+        extract_text(filename, bytes).unwrap()
+    }
+
+    /// Fails every read, standing in for data that must not be read.
+    struct Unreadable;
+
+    impl Read for Unreadable {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("read past the limit"))
+        }
+    }
+
+    #[test]
+    fn test_text_formats() {
+        assert_eq!(
+            extract("notes.MD", b"# Budget").as_deref(),
+            Some("# Budget")
+        );
+        assert_eq!(
+            extract("main.rs", b"fn main() {}").as_deref(),
+            Some("fn main() {}")
+        );
+        // Invalid UTF-8 is replaced rather than rejected
+        assert_eq!(
+            extract("log.txt", b"ok \xff").as_deref(),
+            Some("ok \u{fffd}")
+        );
+        assert_eq!(extract("photo.png", b"budget"), None);
+        assert_eq!(extract("noext", b"budget"), None);
+    }
+
+    #[test]
+    fn test_pdf() {
+        let text = extract("report.pdf", &pdf_with_text(Some("Quarterly budget"))).unwrap();
+        assert!(text.contains("Quarterly budget"), "{text:?}");
+
+        // Scans without a text layer and broken files have no text
+        assert_eq!(extract("scan.pdf", &pdf_with_text(None)), None);
+        assert_eq!(extract("broken.pdf", b"%PDF-1.4 not really"), None);
+    }
+
+    #[test]
+    fn test_long_text_is_truncated_at_char_boundary() {
+        // 'é' is two bytes, so MAX_TEXT_LEN falls in the middle of the last one
+        let long = format!("a{}", "\u{e9}".repeat(MAX_TEXT_LEN / 2));
+        let text = extract("long.txt", long.as_bytes()).unwrap();
+        assert_eq!(text.len(), MAX_TEXT_LEN - 1);
+        assert!(text.ends_with('\u{e9}'));
+    }
+
+    #[test]
+    fn test_plain_text_read_stops_past_limit() {
+        let long = "a".repeat(MAX_TEXT_LEN + 4);
+        let text = extract_text("huge.log", long.as_bytes().chain(Unreadable))
+            .unwrap()
+            .unwrap();
+        assert_eq!(text.len(), MAX_TEXT_LEN);
+
+        let pdf = vec![b' '; MAX_PDF_LEN as usize + 1];
+        assert_eq!(
+            extract_text("huge.pdf", pdf.as_slice().chain(Unreadable)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_store_text_leaves_pdfs_for_later() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("test_hash");
+        let blob_dir = storage.blobs_path.join(key_hash);
+        std::fs::create_dir_all(&blob_dir).unwrap();
+        std::fs::write(blob_dir.join("a.txt"), b"hello").unwrap();
+        std::fs::write(
+            blob_dir.join("b.pdf"),
+            pdf_with_text(Some("Quarterly budget")),
+        )
+        .unwrap();
+
+        storage.store_text(key_hash, "a.txt").unwrap();
+        assert_eq!(
+            storage.read_text(key_hash, "a.txt").unwrap().as_deref(),
+            Some("hello")
+        );
+        storage.store_text(key_hash, "b.pdf").unwrap();
+        assert_eq!(storage.read_text(key_hash, "b.pdf").unwrap(), None);
+
+        storage.write_text(key_hash, "b.pdf").unwrap();
+        let text = storage.read_text(key_hash, "b.pdf").unwrap().unwrap();
+        assert!(text.contains("Quarterly budget"));
+    }
+
+    #[test]
+    fn test_write_and_read_text() {
+        let (storage, _temp) = create_test_storage();
+        let key_hash = Path::new("test_hash");
+        let blob_dir = storage.blobs_path.join(key_hash);
+        std::fs::create_dir_all(&blob_dir).unwrap();
+        std::fs::write(blob_dir.join("a.txt"), b"hello").unwrap();
+        std::fs::write(blob_dir.join("b.png"), b"hello").unwrap();
+
+        storage.write_text(key_hash, "a.txt").unwrap();
+        assert_eq!(
+            storage.read_text(key_hash, "a.txt").unwrap().as_deref(),
+            Some("hello")
+        );
+
+        storage.write_text(key_hash, "b.png").unwrap();
+        assert!(!storage.text_file_path(key_hash, "b.png").exists());
+        assert_eq!(storage.read_text(key_hash, "b.png").unwrap(), None);
+
+        storage.remove_text(key_hash, "a.txt").unwrap();
+        assert_eq!(storage.read_text(key_hash, "a.txt").unwrap(), None);
+    }
 }
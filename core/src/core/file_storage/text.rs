@@ -0,0 +1,100 @@
+//! Text extraction from attachments, for searching them by content.
+
+use lopdf::Document;
+use std::io::{self, Read};
+
+/// Longest text kept per attachment, in bytes; the rest isn't searchable.
+pub const MAX_TEXT_LEN: usize = 1024 * 1024;
+
+/// Largest PDF whose text is extracted, in bytes, since parsing needs the whole file in memory.
+pub const MAX_PDF_LEN: u64 = 64 * 1024 * 1024;
+
+/// Extensions of files read as UTF-8 text: plain text, markup, data and source code.
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "text", "log", "md", "markdown", "rst", "adoc", "org", "tex", "csv", "tsv", "json",
+    "jsonl", "toml", "yaml", "yml", "xml", "ini", "cfg", "conf", "env", "html", "htm", "css",
+    "scss", "less", "rs", "c", "h", "cc", "cpp", "cxx", "hpp", "hh", "cs", "java", "kt", "kts",
+    "scala", "go", "py", "rb", "php", "pl", "lua", "r", "jl", "swift", "m", "mm", "dart", "js",
+    "mjs", "cjs", "jsx", "ts", "tsx", "vue", "svelte", "hs", "ml", "ex", "exs", "erl", "clj",
+    "elm", "zig", "nim", "sql", "sh", "bash", "zsh", "fish", "ps1", "bat", "cmd", "gradle",
+    "cmake", "mk", "proto", "graphql", "tf",
+];
+
+enum Extractor {
+    Text,
+    Pdf,
+}
+
+fn extractor_for(filename: &str) -> Option<Extractor> {
+    let (_, extension) = filename.rsplit_once('.')?;
+    let extension = extension.to_ascii_lowercase();
+    if extension == "pdf" {
+        Some(Extractor::Pdf)
+    } else if TEXT_EXTENSIONS.contains(&extension.as_str()) {
+        Some(Extractor::Text)
+    } else {
+        None
+    }
+}
+
+/// Whether text can be extracted from files with `filename`'s extension.
+pub fn supports_text(filename: &str) -> bool {
+    extractor_for(filename).is_some()
+}
+
+/// Whether text is extracted from files with `filename`'s extension as soon as they are stored.
+/// Plain text only needs its first [`MAX_TEXT_LEN`] bytes read; other formats are read whole, so
+/// their text is extracted later.
+pub fn extracts_on_store(filename: &str) -> bool {
+    matches!(extractor_for(filename), Some(Extractor::Text))
+}
+
+/// Extracts the searchable text of the plaintext attachment read from `reader`.
+///
+/// Plain text is read up to just past [`MAX_TEXT_LEN`]; PDFs are read whole, up to
+/// [`MAX_PDF_LEN`]. Returns `None` for unsupported formats, and for PDFs that are too large,
+/// can't be parsed or have no text layer, such as scans.
+pub fn extract_text(filename: &str, reader: impl Read) -> io::Result<Option<String>> {
+    let text = match extractor_for(filename) {
+        None => return Ok(None),
+        Some(Extractor::Text) => {
+            // A few bytes more than kept, so a char cut off by the limit is dropped rather than
+            // replaced.
+            let mut bytes = Vec::new();
+            reader
+                .take(MAX_TEXT_LEN as u64 + 4)
+                .read_to_end(&mut bytes)?;
+            String::from_utf8_lossy(&bytes).into_owned()
+        }
+        Some(Extractor::Pdf) => {
+            let mut bytes = Vec::new();
+            reader.take(MAX_PDF_LEN + 1).read_to_end(&mut bytes)?;
+            if bytes.len() as u64 > MAX_PDF_LEN {
+                return Ok(None);
+            }
+            match extract_pdf_text(&bytes) {
+                Some(text) => text,
+                None => return Ok(None),
+            }
+        }
+    };
+    Ok(Some(truncate(text)))
+}
+
+fn truncate(mut text: String) -> String {
+    if text.len() > MAX_TEXT_LEN {
+        let mut end = MAX_TEXT_LEN;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+    text
+}
+
+fn extract_pdf_text(bytes: &[u8]) -> Option<String> {
+    let doc = Document::load_mem(bytes).ok()?;
+    let pages: Vec<u32> = doc.get_pages().into_keys().collect();
+    let text = doc.extract_text(&pages).ok()?;
+    (!text.trim().is_empty()).then_some(text)
+}
@@ -0,0 +1,144 @@
+//! Thumbnail renderers, registered per file extension.
+
+use super::error::FileStorageError;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use lopdf::Document;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Arc;
+
+/// Renders an attachment into an image, which [`FileStorage::render_thumbnails`] then scales
+/// down to each thumbnail size.
+///
+/// [`FileStorage::render_thumbnails`]: super::FileStorage::render_thumbnails
+pub trait ThumbnailRenderer: Send + Sync {
+    /// Renders the plaintext attachment `bytes`.
+    ///
+    /// `max_size` bounds the longer side of the largest thumbnail; vector formats can render at
+    /// that size directly, raster formats may return any size.
+    fn render(&self, bytes: &[u8], max_size: u32) -> Result<DynamicImage, FileStorageError>;
+}
+
+/// Maps lowercase file extensions to the renderer that thumbnails them.
+///
+/// The default registry renders PNG, JPEG, GIF, WebP, BMP and TIFF with `image`, SVG with
+/// `resvg` and the first page of PDFs with a built-in renderer.
+#[derive(Clone)]
+pub struct ThumbnailRegistry {
+    renderers: HashMap<String, Arc<dyn ThumbnailRenderer>>,
+}
+
+impl ThumbnailRegistry {
+    /// A registry without renderers, so no attachment gets a thumbnail.
+    pub fn empty() -> Self {
+        Self {
+            renderers: HashMap::new(),
+        }
+    }
+
+    /// Registers `renderer` for `extensions` (without the dot, any case), replacing the renderer
+    /// previously registered for them.
+    pub fn register(&mut self, extensions: &[&str], renderer: Arc<dyn ThumbnailRenderer>) {
+        for extension in extensions {
+            self.renderers
+                .insert(extension.to_ascii_lowercase(), renderer.clone());
+        }
+    }
+
+    /// Returns the renderer for `filename`'s extension.
+    pub fn renderer_for(&self, filename: &str) -> Option<&dyn ThumbnailRenderer> {
+        let (_, extension) = filename.rsplit_once('.')?;
+        self.renderers
+            .get(&extension.to_ascii_lowercase())
+            .map(Arc::as_ref)
+    }
+
+    pub fn supports(&self, filename: &str) -> bool {
+        self.renderer_for(filename).is_some()
+    }
+}
+
+impl Default for ThumbnailRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(
+            &["png", "jpg", "jpeg", "gif", "webp", "bmp", "tif", "tiff"],
+            Arc::new(RasterRenderer),
+        );
+        registry.register(&["svg"], Arc::new(SvgRenderer));
+        registry.register(&["pdf"], Arc::new(PdfRenderer));
+        registry
+    }
+}
+
+/// Decodes raster images, detecting the format from the bytes, and turns them upright according
+/// to their EXIF orientation.
+struct RasterRenderer;
+
+impl ThumbnailRenderer for RasterRenderer {
+    fn render(&self, bytes: &[u8], _max_size: u32) -> Result<DynamicImage, FileStorageError> {
+        let mut decoder = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()?
+            .into_decoder()?;
+        let orientation = decoder.orientation()?;
+        let mut image = DynamicImage::from_decoder(decoder)?;
+        image.apply_orientation(orientation);
+        // Thumbnails are PNG, which has no floating-point samples (HDR TIFFs).
+        Ok(match image {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                DynamicImage::ImageRgba8(image.to_rgba8())
+            }
+            image => image,
+        })
+    }
+}
+
+/// Rasterizes SVG to fit `max_size`. Text is not rendered, as no fonts are loaded.
+struct SvgRenderer;
+
+impl ThumbnailRenderer for SvgRenderer {
+    fn render(&self, bytes: &[u8], max_size: u32) -> Result<DynamicImage, FileStorageError> {
+        let tree = resvg::usvg::Tree::from_data(bytes, &resvg::usvg::Options::default())?;
+        let size = tree.size();
+        let scale = max_size as f32 / size.width().max(size.height());
+        let width = ((size.width() * scale).round() as u32).max(1);
+        let height = ((size.height() * scale).round() as u32).max(1);
+
+        let mut pixmap = resvg::tiny_skia::Pixmap::new(width, height)
+            .ok_or(FileStorageError::UnsupportedFormat)?;
+        resvg::render(
+            &tree,
+            resvg::tiny_skia::Transform::from_scale(scale, scale),
+            &mut pixmap.as_mut(),
+        );
+
+        Ok(pixmap_to_image(&pixmap))
+    }
+}
+
+/// Thumbnails a PDF by rendering its first page to fit `max_size` (see [`pdf_render`]).
+///
+/// [`pdf_render`]: super::pdf_render
+struct PdfRenderer;
+
+impl ThumbnailRenderer for PdfRenderer {
+    fn render(&self, bytes: &[u8], max_size: u32) -> Result<DynamicImage, FileStorageError> {
+        let doc = Document::load_mem(bytes)?;
+        super::pdf_render::render_first_page(&doc, max_size)
+    }
+}
+
+/// Converts a rendered pixmap, whose pixels are premultiplied, into an image.
+pub(super) fn pixmap_to_image(pixmap: &resvg::tiny_skia::Pixmap) -> DynamicImage {
+    let rgba: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+    let image = image::RgbaImage::from_raw(pixmap.width(), pixmap.height(), rgba)
+        .expect("pixmap holds width * height pixels");
+    DynamicImage::ImageRgba8(image)
+}
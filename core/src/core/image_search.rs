@@ -0,0 +1,107 @@
+//! Finding image attachments by the metadata read when they were added.
+
+use super::KevaCore;
+use crate::core::error::KevaError;
+use crate::types::{ImageMetadata, Key};
+
+/// Conditions an image attachment must meet in [`KevaCore::find_images`]. Unset fields match
+/// every image.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImageFilter {
+    /// Smallest displayed width in pixels.
+    pub min_width: Option<u32>,
+    /// Smallest displayed height in pixels.
+    pub min_height: Option<u32>,
+    /// Earliest capture time, inclusive: a prefix of `YYYY-MM-DDTHH:MM:SS` such as `2024` or
+    /// `2024-06-01`.
+    pub captured_from: Option<String>,
+    /// Latest capture time, inclusive, in the same format: `2024-06` matches all of June.
+    pub captured_to: Option<String>,
+    /// Text the camera make and model must contain, ignoring case.
+    pub camera: Option<String>,
+}
+
+impl ImageFilter {
+    /// Returns whether `image` meets every condition set. Images without a capture time or
+    /// camera don't meet conditions on them.
+    pub fn matches(&self, image: &ImageMetadata) -> bool {
+        if self.min_width.is_some_and(|min| image.width < min)
+            || self.min_height.is_some_and(|min| image.height < min)
+        {
+            return false;
+        }
+
+        if self.captured_from.is_some() || self.captured_to.is_some() {
+            let Some(captured_at) = &image.captured_at else {
+                return false;
+            };
+            if self
+                .captured_from
+                .as_ref()
+                .is_some_and(|from| captured_at.as_str() < from.as_str())
+            {
+                return false;
+            }
+            if let Some(to) = &self.captured_to {
+                // Compare only as much as given, so a date includes the whole day.
+                let end = to.len().min(captured_at.len());
+                if captured_at
+                    .get(..end)
+                    .is_none_or(|prefix| prefix > to.as_str())
+                {
+                    return false;
+                }
+            }
+        }
+
+        if let Some(camera) = &self.camera {
+            let wanted = camera.to_lowercase();
+            if !image
+                .camera
+                .as_ref()
+                .is_some_and(|c| c.to_lowercase().contains(&wanted))
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// An image attachment returned by [`KevaCore::find_images`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageMatch {
+    pub key: Key,
+    pub filename: String,
+    pub image: ImageMetadata,
+}
+
+/// Image search.
+impl KevaCore {
+    /// Returns the image attachments of Active keys that meet `filter`, in key and then filename
+    /// order. Locked sealed keys are skipped, since their attachments aren't listed.
+    pub fn find_images(&self, filter: &ImageFilter) -> Result<Vec<ImageMatch>, KevaError> {
+        let mut keys = self.db.active_keys()?;
+        keys.sort();
+
+        let mut matches = Vec::new();
+        for key in keys {
+            let Some(value) = self.get(&key)? else {
+                continue;
+            };
+            let mut images: Vec<(String, ImageMetadata)> = value
+                .attachments
+                .into_iter()
+                .filter_map(|a| Some((a.filename, a.image?)))
+                .filter(|(_, image)| filter.matches(image))
+                .collect();
+            images.sort_by(|a, b| a.0.cmp(&b.0));
+            matches.extend(images.into_iter().map(|(filename, image)| ImageMatch {
+                key: key.clone(),
+                filename,
+                image,
+            }));
+        }
+        Ok(matches)
+    }
+}
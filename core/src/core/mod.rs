@@ -1,20 +1,51 @@
 //! Core storage implementation combining database and file storage.
 
+use crate::core::crypto::Cipher;
+use crate::core::crypto::error::CryptoError;
 use crate::core::db::Database;
 use crate::core::db::error::DatabaseError;
 use crate::core::file_storage::FileStorage;
 use crate::core::file_storage::error::FileStorageError;
 use crate::types::value::PublicValue as Value;
 use crate::types::value::versioned_value::latest_value;
-use crate::types::value::versioned_value::latest_value::Attachment;
-use crate::types::{Config, GcConfig, Key};
+use crate::types::value::versioned_value::latest_value::{Attachment, LifecycleState};
+use crate::types::{
+    AccessStats, AttachmentConflictResolution, AttachmentName, Config, GcConfig, Key, KeyError,
+    QuotaConfig, ThumbnailConfig, ThumbnailPreset,
+};
 use error::KevaError;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
+mod batch;
+pub(crate) mod crypto;
 pub(crate) mod db;
 pub(crate) mod file_storage;
+mod image_search;
+mod sync;
+mod text_search;
+mod thumbnail_queue;
+mod token;
+mod undo;
+mod usage;
+
+pub use batch::{Batch, Change};
+pub use db::{OpLogEntry, Operation};
+pub use file_storage::{
+    FileReader, FileWriter, Fingerprint, MAX_PDF_LEN, MAX_TEXT_LEN, ThumbnailRegistry,
+    ThumbnailRenderer,
+};
+pub use image_search::{ImageFilter, ImageMatch};
+pub use sync::SyncOutcome;
+pub use text_search::{AttachmentMatch, SNIPPET_LEN};
+pub use thumbnail_queue::{ThumbnailCallback, ThumbnailStatus};
+pub use token::load_or_create_token;
+pub use undo::UndoAction;
+pub use usage::{QuotaError, StoreUsage, Usage};
 
 pub mod error {
     use super::*;
@@ -30,13 +61,56 @@ pub mod error {
 
         #[error("Destination key already exists")]
         DestinationExists,
+
+        #[error("Invalid key: {0}")]
+        InvalidKey(#[from] KeyError),
+
+        #[error("Crypto error: {0}")]
+        Crypto(#[from] CryptoError),
+
+        #[error("Store is encrypted; open it with a passphrase")]
+        PassphraseRequired,
+
+        #[error("Store is not encrypted")]
+        NotEncrypted,
+
+        #[error("Key is sealed; unlock it with its passphrase")]
+        Locked,
+
+        #[error("Key is not sealed")]
+        NotSealed,
+
+        #[error("Key is already sealed")]
+        AlreadySealed,
+
+        #[error("Quota exceeded: {0}")]
+        QuotaExceeded(#[from] QuotaError),
+
+        #[error(
+            "Changes were committed, but {} file operation(s) failed: {}",
+            .0.len(),
+            .0[0]
+        )]
+        FilesNotUpdated(Vec<FileStorageError>),
     }
 }
 
 pub struct KevaCore {
     base_path: PathBuf,
+    key_file_path: PathBuf,
+    sync_path: PathBuf,
     db: Database,
     file: FileStorage,
+    /// Data keys of unlocked sealed keys, with the time each unlock expires.
+    unlocked: HashMap<Key, (Cipher, SystemTime)>,
+    unlock_timeout: Duration,
+    history: undo::UndoHistory,
+    thumbnails: thumbnail_queue::ThumbnailQueue,
+    quotas: QuotaConfig,
+    /// Bytes the store used when last measured plus the growth of attachments added since, kept
+    /// while the store size is limited.
+    store_size: Option<u64>,
+    text_cache: text_search::TextCache,
 }
 
 #[derive(Debug, Default)]
@@ -44,25 +118,92 @@ pub struct MaintenanceOutcome {
     pub keys_trashed: Vec<Key>,
     pub keys_purged: Vec<Key>,
     pub orphaned_files_removed: usize,
+    pub oplog_entries_compacted: usize,
+    /// Attachments whose text was extracted, such as PDFs added since the last run.
+    pub text_extracted: usize,
+}
+
+/// An immediate child of a prefix in the `/`-separated key tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyTreeNode {
+    /// Full path of the node, e.g. `"project/notes"`.
+    pub path: String,
+    /// Last path segment, e.g. `"notes"`.
+    pub name: String,
+    /// Whether an Active key exists at exactly `path`.
+    pub is_key: bool,
+    /// Number of Active keys strictly below `path`.
+    pub descendant_count: usize,
 }
 
 impl KevaCore {
+    /// Opens an unencrypted store.
+    ///
+    /// Returns `Err(PassphraseRequired)` if the store is encrypted.
     pub fn open(config: Config) -> Result<Self, KevaError> {
+        if config.key_file_path().exists() {
+            return Err(KevaError::PassphraseRequired);
+        }
+        Self::open_with_cipher(config, None)
+    }
+
+    /// Opens an encrypted store, creating it if the data directory holds no store yet.
+    ///
+    /// Returns `Err(NotEncrypted)` if an unencrypted store already exists, and
+    /// `Err(Crypto(WrongPassphrase))` if the passphrase doesn't unlock the store.
+    pub fn open_encrypted(config: Config, passphrase: &str) -> Result<Self, KevaError> {
+        let key_file = config.key_file_path();
+        let cipher = if key_file.exists() {
+            Cipher::unlock(&key_file, passphrase)?
+        } else if config.db_path().exists() {
+            return Err(KevaError::NotEncrypted);
+        } else {
+            std::fs::create_dir_all(&config.base_path).map_err(DatabaseError::from)?;
+            Cipher::create(&key_file, passphrase)?
+        };
+        Self::open_with_cipher(config, Some(cipher))
+    }
+
+    fn open_with_cipher(config: Config, cipher: Option<Cipher>) -> Result<Self, KevaError> {
         let base_path = config.base_path.clone();
+        let key_file_path = config.key_file_path();
+        let sync_path = config.sync_path();
+        let history = undo::UndoHistory::new(config.holding_path());
+        // Undo history doesn't outlive a session, so nothing can refer to held files any more.
+        // Failures are ignored; maintenance removes whatever is left.
+        let _ = history.remove_orphans();
         let file = FileStorage {
             content_path: config.content_path(),
             blobs_path: config.blobs_path(),
             thumbnails_path: config.thumbnails_path(),
+            text_path: config.text_path(),
+            cipher: cipher.clone(),
+            thumbnailers: Arc::new(ThumbnailRegistry::default()),
+            thumbnail_config: ThumbnailConfig::default(),
         };
 
-        let db = Database::new(config)?;
+        let db = Database::new(config, cipher)?;
         Ok(Self {
             base_path,
+            key_file_path,
+            sync_path,
             db,
             file,
+            unlocked: HashMap::new(),
+            unlock_timeout: Self::DEFAULT_UNLOCK_TIMEOUT,
+            history,
+            thumbnails: thumbnail_queue::ThumbnailQueue::new(),
+            quotas: QuotaConfig::default(),
+            store_size: None,
+            text_cache: text_search::TextCache::default(),
         })
     }
 
+    /// Returns whether content, attachments, thumbnails and values are encrypted at rest.
+    pub fn is_encrypted(&self) -> bool {
+        self.file.cipher.is_some()
+    }
+
     /// Returns the base data directory path.
     pub fn data_dir(&self) -> &Path {
         &self.base_path
@@ -89,7 +230,7 @@ impl KevaCore {
 impl KevaCore {
     pub fn get(&self, key: &Key) -> Result<Option<Value>, KevaError> {
         let value = self.db.get(key)?;
-        Ok(value.map(Value::from_latest_value))
+        Ok(value.map(|value| self.present(key, value)))
     }
 
     pub fn active_keys(&self) -> Result<Vec<Key>, KevaError> {
@@ -99,6 +240,17 @@ impl KevaCore {
     pub fn trashed_keys(&self) -> Result<Vec<Key>, KevaError> {
         Ok(self.db.trashed_keys()?)
     }
+
+    /// Returns `last_accessed` and `access_count` for every Active key.
+    pub fn access_stats(&self) -> Result<Vec<(Key, AccessStats)>, KevaError> {
+        Ok(self.db.access_stats()?)
+    }
+
+    /// Returns the store generation, which changes whenever keys are created, renamed, trashed,
+    /// restored, purged or touched.
+    pub fn generation(&self) -> Result<u64, KevaError> {
+        Ok(self.db.generation()?)
+    }
 }
 
 /// Content operations.
@@ -112,80 +264,308 @@ impl KevaCore {
         Ok(Value::from_latest_value(value))
     }
 
+    /// Path of the content file. In an encrypted store the file holds ciphertext; use
+    /// [`KevaCore::open_content`] and [`KevaCore::write_content`] instead.
     pub fn content_path(&self, key: &Key) -> PathBuf {
         let key_hash = Self::key_to_path(key);
         self.file.content_file_path(&key_hash)
     }
 
-    /// Updates last_accessed timestamp.
+    /// Opens the content for reading, decrypting it if the store or key is encrypted.
+    pub fn open_content(&self, key: &Key) -> Result<FileReader, KevaError> {
+        Ok(self.key_files(key)?.open_file(&self.content_path(key))?)
+    }
+
+    /// Returns a writer that replaces the content once [`ContentWriter::finish`] is called.
+    pub fn write_content(&self, key: &Key) -> Result<ContentWriter<'_>, KevaError> {
+        Ok(ContentWriter {
+            writer: self.key_files(key)?.create_file(&self.content_path(key))?,
+            db: &self.db,
+            key: key.clone(),
+        })
+    }
+
+    /// Updates last_accessed timestamp and increments the access count.
     pub fn touch(&mut self, key: &Key, now: SystemTime) -> Result<Value, KevaError> {
-        Ok(Value::from_latest_value(self.db.touch(key, now)?))
+        let value = self.db.touch(key, now)?;
+        Ok(self.present(key, value))
+    }
+}
+
+/// Writes the content of a key, returned by [`KevaCore::write_content`].
+///
+/// Like [`FileWriter`], the content is only replaced by [`ContentWriter::finish`], which also logs
+/// [`Operation::ContentWritten`].
+pub struct ContentWriter<'a> {
+    writer: FileWriter,
+    db: &'a Database,
+    key: Key,
+}
+
+impl ContentWriter<'_> {
+    pub fn finish(self) -> Result<(), KevaError> {
+        self.writer.finish()?;
+        self.db.log_content_written(&self.key, SystemTime::now())?;
+        Ok(())
+    }
+}
+
+impl Write for ContentWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
     }
 }
 
 /// Attachment operations.
 impl KevaCore {
-    pub fn attachment_path(&self, key: &Key, filename: &str) -> PathBuf {
+    /// Path of the attachment blob. In an encrypted store the file holds ciphertext; use
+    /// [`KevaCore::open_attachment`] instead.
+    ///
+    /// Returns `Err(AttachmentNotFound)` if the key has no attachment named `filename`.
+    pub fn attachment_path(&self, key: &Key, filename: &str) -> Result<PathBuf, KevaError> {
+        self.find_attachment(key, filename)?
+            .ok_or_else(|| DatabaseError::AttachmentNotFound(filename.to_string()))?;
         let key_hash = Self::key_to_path(key);
-        self.file.attachment_path(&key_hash, filename)
+        Ok(self.file.attachment_path(&key_hash, filename))
     }
 
-    /// Add attachments with explicit target filenames.
-    /// If a file with the same name exists, it will be overwritten.
+    /// Opens an attachment for reading, decrypting it if the store or key is encrypted.
+    ///
+    /// Returns `Err(AttachmentNotFound)` if the key has no attachment named `filename`.
+    pub fn open_attachment(&self, key: &Key, filename: &str) -> Result<FileReader, KevaError> {
+        let path = self.attachment_path(key, filename)?;
+        Ok(self.key_files(key)?.open_file(&path)?)
+    }
+
+    /// Add attachments with explicit target filenames, resolving each name conflict as the
+    /// file's [`AttachmentConflictResolution`] says. Files earlier in `files` count as taken
+    /// for later ones.
+    ///
+    /// Returns the filename each file was added under, in input order, or `None` if it was
+    /// skipped. Names are chosen and quotas checked before anything is written, so a `Fail`
+    /// conflict, a directory source or `Err(QuotaExceeded)` adds nothing. Overwrites can be
+    /// undone, and re-attaching a file identical to the one already stored under its name
+    /// changes nothing.
     pub fn add_attachments(
         &mut self,
         key: &Key,
-        files: Vec<(PathBuf, String)>,
+        files: Vec<(PathBuf, AttachmentName, AttachmentConflictResolution)>,
         now: SystemTime,
-    ) -> Result<(), KevaError> {
-        let key_hash = Self::key_to_path(key);
+    ) -> Result<Vec<Option<AttachmentName>>, KevaError> {
+        let value = self.db.get(key)?.ok_or(DatabaseError::NotFound)?;
+        let stored: HashMap<String, u64> = value
+            .attachments
+            .into_iter()
+            .map(|a| (a.filename, a.size))
+            .collect();
+        let mut taken: HashSet<String> = stored.keys().cloned().collect();
+        let mut growth = 0;
 
-        for (source_path, target_filename) in files {
-            // Remove existing attachment if present (overwrite behavior)
-            let _ = self.remove_attachment(key, &target_filename, now);
+        let mut planned = Vec::with_capacity(files.len());
+        for (source_path, filename, resolution) in files {
+            let metadata = std::fs::metadata(&source_path).map_err(FileStorageError::from)?;
+            if metadata.is_dir() {
+                return Err(FileStorageError::IsDirectory.into());
+            }
+            let chosen = if !taken.contains(filename.as_str()) {
+                Some(filename)
+            } else {
+                match resolution {
+                    AttachmentConflictResolution::Overwrite => Some(filename),
+                    AttachmentConflictResolution::Rename => (1..)
+                        .map(|n| filename.numbered(n))
+                        .find(|name| !taken.contains(name.as_str())),
+                    AttachmentConflictResolution::Skip => None,
+                    AttachmentConflictResolution::Fail => {
+                        return Err(DatabaseError::AttachmentExists(filename.into_inner()).into());
+                    }
+                }
+            };
+            if let Some(name) = &chosen {
+                self.check_attachment_size(name, metadata.len())?;
+                let replaced = stored.get(name.as_str()).copied().unwrap_or(0);
+                growth += metadata.len().saturating_sub(replaced);
+                taken.insert(name.to_string());
+            }
+            planned.push((source_path, chosen));
+        }
+        let used = self.quota_usage()?;
+        self.check_store_growth(used, growth)?;
 
-            self.add_attachment_with_thumbnail(key, &key_hash, source_path, target_filename, now)?;
+        let mut added = Vec::with_capacity(planned.len());
+        for (source_path, chosen) in planned {
+            if let Some(name) = &chosen {
+                let file = File::open(&source_path).map_err(FileStorageError::from)?;
+                self.put_attachment(key, name, file, now, None)?;
+            }
+            added.push(chosen);
         }
-        Ok(())
+        Ok(added)
     }
 
-    fn add_attachment_with_thumbnail(
+    /// Adds an attachment named `filename` with the data read from `reader`, e.g. an upload.
+    ///
+    /// The data is streamed into a temporary file that replaces an existing attachment of the
+    /// same name only once complete, so a failed read or `Err(QuotaExceeded)` leaves the key as
+    /// it was. Reading stops as soon as the data goes over a quota. Overwriting can be undone, and
+    /// data identical to the existing attachment changes nothing.
+    pub fn add_attachment_from_reader(
         &mut self,
         key: &Key,
-        key_hash: &Path,
-        source_path: PathBuf,
-        filename: String,
+        filename: &AttachmentName,
+        reader: impl Read,
         now: SystemTime,
-    ) -> Result<u64, KevaError> {
-        let size = self
-            .file
-            .add_attachment(key_hash, &source_path, &filename)?;
+    ) -> Result<Fingerprint, KevaError> {
+        let used = self.quota_usage()?;
+        self.put_attachment(key, filename, reader, now, used)
+    }
+
+    /// Adds an attachment from `reader`, checking the store size limit against `used` bytes, or
+    /// not at all if `used` is `None` because the caller already did.
+    fn put_attachment(
+        &mut self,
+        key: &Key,
+        filename: &AttachmentName,
+        reader: impl Read,
+        now: SystemTime,
+        used: Option<u64>,
+    ) -> Result<Fingerprint, KevaError> {
+        let key_hash = Self::key_to_path(key);
+        let key_files = self.key_files(key)?;
+        let existing = self.find_attachment(key, filename)?;
+
+        let replaced = existing.as_ref().map_or(0, |old| old.size);
+        let max_size = self.attachment_room(used, replaced);
+        let staged = match key_files.write_attachment(&key_hash, filename, reader, max_size) {
+            Err(FileStorageError::TooLarge { size, limit }) => {
+                // Report which of the limits making up `max_size` was exceeded
+                self.check_attachment_size(filename, size)?;
+                self.check_store_growth(used, size.saturating_sub(replaced))?;
+                return Err(FileStorageError::TooLarge { size, limit }.into());
+            }
+            result => result?,
+        };
+        let fingerprint = staged.fingerprint.clone();
+        if existing
+            .as_ref()
+            .is_some_and(|old| old.hash == Some(fingerprint.hash))
+        {
+            return Ok(fingerprint);
+        }
+        let added_at = existing
+            .as_ref()
+            .and_then(|old| old.added_at)
+            .unwrap_or(now);
+
+        // Move existing attachment aside if present (overwrite behavior)
+        let held = match existing {
+            Some(old) => Some((self.hold_attachment(key, filename, now)?, old)),
+            None => None,
+        };
+        let result = staged.finish().map_err(KevaError::from).and_then(|()| {
+            self.insert_staged_attachment(key, &key_files, filename, &fingerprint, added_at, now)
+        });
 
-        if FileStorage::is_supported_image(&filename) {
-            self.file
-                .generate_thumbnail(key_hash, &filename)
-                .map_err(KevaError::from)?;
+        match (result, held) {
+            (Ok(new), Some((id, old))) => self.record_overwrite(id, key, old, new, now),
+            (Ok(_), None) => {}
+            (Err(e), held) => {
+                // Put the old attachment back so a failure leaves the key as it was
+                if let Some((id, old)) = held {
+                    self.unhold_attachment(id, key, old, now)?;
+                }
+                return Err(e);
+            }
         }
+        self.track_growth(replaced, fingerprint.size);
+        Ok(fingerprint)
+    }
+
+    /// Records an attachment whose staged file was just moved into place.
+    fn insert_staged_attachment(
+        &mut self,
+        key: &Key,
+        key_files: &FileStorage,
+        filename: &AttachmentName,
+        fingerprint: &Fingerprint,
+        added_at: SystemTime,
+        now: SystemTime,
+    ) -> Result<Attachment, KevaError> {
+        let image = fingerprint
+            .mime
+            .starts_with("image/")
+            .then(|| key_files.read_attachment_image_metadata(&Self::key_to_path(key), filename))
+            .flatten();
+        let new = Attachment {
+            filename: filename.to_string(),
+            size: fingerprint.size,
+            image,
+            mime: Some(fingerprint.mime.to_string()),
+            hash: Some(fingerprint.hash),
+            added_at: Some(added_at),
+            modified_at: Some(now),
+        };
+        self.insert_attachment(key, key_files, new.clone(), now)?;
+        Ok(new)
+    }
 
+    /// Records an attachment whose file is already in place, extracts its text if it is plain text
+    /// and queues its thumbnail.
+    fn insert_attachment(
+        &mut self,
+        key: &Key,
+        key_files: &FileStorage,
+        attachment: Attachment,
+        now: SystemTime,
+    ) -> Result<(), KevaError> {
+        let filename = attachment.filename.clone();
+        // Formats that can't be parsed get empty text; only failing to read or write files errs,
+        // before anything is recorded.
+        key_files.store_text(&Self::key_to_path(key), &filename)?;
         self.db
-            .add_attachment(key, Attachment { filename, size }, now)
+            .add_attachment(key, attachment, now)
             .map_err(KevaError::from)?;
 
-        Ok(size)
+        self.queue_thumbnail(key, key_files, &filename);
+        Ok(())
+    }
+
+    /// Returns the name of an attachment of `key` with the same contents as `source`, if any.
+    pub fn identical_attachment(
+        &self,
+        key: &Key,
+        source: &Path,
+    ) -> Result<Option<String>, KevaError> {
+        let hash = FileStorage::fingerprint(source)?.hash;
+        let Some(value) = self.get(key)? else {
+            return Ok(None);
+        };
+        Ok(value
+            .attachments
+            .into_iter()
+            .find(|a| a.hash == Some(hash))
+            .map(|a| a.filename))
     }
 
+    /// Removes an attachment. Its files are kept in the holding area until the removal can no
+    /// longer be undone.
     pub fn remove_attachment(
         &mut self,
         key: &Key,
         filename: &str,
         now: SystemTime,
     ) -> Result<(), KevaError> {
-        let key_hash = Self::key_to_path(key);
-
-        self.db.remove_attachment(key, filename, now)?;
+        let attachment = self
+            .find_attachment(key, filename)?
+            .ok_or_else(|| DatabaseError::AttachmentNotFound(filename.to_string()))?;
 
-        self.file.remove_attachment(&key_hash, filename)?;
-        self.file.remove_thumbnail(&key_hash, filename)?;
+        let id = self.hold_attachment(key, filename, now)?;
+        self.record_remove_attachment(id, key, attachment, now);
+        self.reset_store_size();
         Ok(())
     }
 
@@ -193,55 +573,131 @@ impl KevaCore {
         &mut self,
         key: &Key,
         old_filename: &str,
-        new_filename: &str,
+        new_filename: &AttachmentName,
+        now: SystemTime,
+    ) -> Result<(), KevaError> {
+        if old_filename == new_filename.as_str() {
+            return Ok(());
+        }
+
+        self.rename_attachment_files(key, old_filename, new_filename, now)?;
+        self.record_rename_attachment(key, old_filename, new_filename, now);
+        Ok(())
+    }
+
+    /// Renames an attachment, replacing any attachment already named `new_filename`.
+    ///
+    /// The rows change in one transaction, and the replaced attachment's files are kept in the
+    /// holding area until the rename can no longer be undone. A single undo renames the
+    /// attachment back and restores the replaced one.
+    pub fn rename_attachment_replacing(
+        &mut self,
+        key: &Key,
+        old_filename: &str,
+        new_filename: &AttachmentName,
         now: SystemTime,
     ) -> Result<(), KevaError> {
-        if old_filename == new_filename {
+        if old_filename == new_filename.as_str() {
             return Ok(());
         }
+        let Some(replaced) = self.find_attachment(key, new_filename.as_str())? else {
+            return self.rename_attachment(key, old_filename, new_filename, now);
+        };
+        self.find_attachment(key, old_filename)?
+            .ok_or_else(|| DatabaseError::AttachmentNotFound(old_filename.to_string()))?;
 
+        self.rename_attachment_replacing_undoable(key, old_filename, replaced, now)
+    }
+
+    fn find_attachment(&self, key: &Key, filename: &str) -> Result<Option<Attachment>, KevaError> {
         let value = self.db.get(key)?.ok_or(DatabaseError::NotFound)?;
-        if value.attachments.iter().any(|a| a.filename == new_filename) {
-            return Err(KevaError::DestinationExists);
+        Ok(value
+            .attachments
+            .into_iter()
+            .find(|a| a.filename == filename))
+    }
+
+    /// Returns `Err(DestinationExists)` if the key already has an attachment named `filename`.
+    fn ensure_attachment_free(&self, key: &Key, filename: &str) -> Result<(), KevaError> {
+        match self.find_attachment(key, filename)? {
+            Some(_) => Err(KevaError::DestinationExists),
+            None => Ok(()),
         }
+    }
 
-        let key_hash = Self::key_to_path(key);
+    fn rename_attachment_files(
+        &mut self,
+        key: &Key,
+        old_filename: &str,
+        new_filename: &str,
+        now: SystemTime,
+    ) -> Result<(), KevaError> {
+        self.ensure_attachment_free(key, new_filename)?;
 
         self.db
             .rename_attachment(key, old_filename, new_filename, now)?;
 
+        self.move_attachment_files(key, old_filename, new_filename)
+    }
+
+    /// Moves an attachment's files to its new name once its row was renamed.
+    fn move_attachment_files(
+        &mut self,
+        key: &Key,
+        old_filename: &str,
+        new_filename: &str,
+    ) -> Result<(), KevaError> {
+        let key_hash = Self::key_to_path(key);
+
+        let pending = self.thumbnails.cancel(key, old_filename);
+
         self.file
             .rename_attachment(&key_hash, old_filename, new_filename)?;
 
         self.file
             .rename_thumbnail(&key_hash, old_filename, new_filename)?;
 
+        self.file
+            .rename_text(&key_hash, old_filename, new_filename)?;
+
+        if pending {
+            self.requeue_thumbnails(key, vec![new_filename.to_string()]);
+        }
         Ok(())
     }
 }
 
 /// Thumbnail operations.
 impl KevaCore {
-    /// Returns filename -> thumbnail relative path map for attachments with thumbnails.
-    /// Paths are relative to the thumbnails directory (`data_dir()/thumbnails`).
-    pub fn thumbnail_paths(&mut self, key: &Key) -> Result<HashMap<String, PathBuf>, KevaError> {
+    /// Returns filename -> thumbnail relative path map for attachments with thumbnails, picking
+    /// for each the stored variant that best fits `preset` at `scale` (2 on high-DPI displays):
+    /// the smallest at least that large, else the largest.
+    /// Paths are relative to the thumbnails directory (`data_dir()/thumbnails`); attachments
+    /// whose thumbnail is still pending or whose rendering failed are left out.
+    ///
+    /// If the key's thumbnails predate `THUMB_VER`, they are queued for regeneration and the
+    /// outdated ones are returned until replaced.
+    pub fn thumbnail_paths(
+        &mut self,
+        key: &Key,
+        preset: ThumbnailPreset,
+        scale: u32,
+    ) -> Result<HashMap<String, PathBuf>, KevaError> {
         let key_hash = Self::key_to_path(key);
         let value = self.db.get(key)?.ok_or(DatabaseError::NotFound)?;
+        let key_files = self.files_for(key, &value)?;
         let mut result = HashMap::new();
 
-        // Regenerate all thumbnails if version is outdated
         for attachment in value.attachments {
-            if FileStorage::is_supported_image(&attachment.filename) {
+            if key_files.supports_thumbnail(&attachment.filename) {
                 if value.thumb_version < FileStorage::THUMB_VER {
-                    let _ = self
-                        .file
-                        .generate_thumbnail(&key_hash, &attachment.filename);
+                    self.queue_thumbnail(key, &key_files, &attachment.filename);
+                }
+                if let Some(path) =
+                    key_files.best_thumbnail(&key_hash, &attachment.filename, preset, scale)
+                {
+                    result.insert(attachment.filename, path);
                 }
-
-                result.insert(
-                    attachment.filename.clone(),
-                    FileStorage::thumbnail_rel_path(&key_hash, &attachment.filename),
-                );
             }
         }
 
@@ -250,6 +706,334 @@ impl KevaCore {
         }
         Ok(result)
     }
+
+    /// Registers `renderer` for attachments with the given extensions, replacing the built-in
+    /// renderer for them. Thumbnails that already exist are kept until the next `THUMB_VER` bump.
+    pub fn register_thumbnail_renderer(
+        &mut self,
+        extensions: &[&str],
+        renderer: Arc<dyn ThumbnailRenderer>,
+    ) {
+        Arc::make_mut(&mut self.file.thumbnailers).register(extensions, renderer);
+    }
+
+    /// Sets the sizes and encoding of thumbnails generated from now on. Existing thumbnails are
+    /// kept; [`KevaCore::thumbnail_paths`] falls back to the closest stored size.
+    pub fn set_thumbnail_config(&mut self, config: ThumbnailConfig) {
+        self.file.thumbnail_config = config;
+    }
+
+    /// Opens the attachment's thumbnail that best fits `preset` at `scale` for reading,
+    /// decrypting it if the store or key is encrypted.
+    pub fn open_thumbnail(
+        &self,
+        key: &Key,
+        filename: &str,
+        preset: ThumbnailPreset,
+        scale: u32,
+    ) -> Result<FileReader, KevaError> {
+        let key_hash = Self::key_to_path(key);
+        let rel_path = self
+            .file
+            .best_thumbnail(&key_hash, filename, preset, scale)
+            .ok_or_else(|| FileStorageError::Io(std::io::ErrorKind::NotFound.into()))?;
+        Ok(self
+            .key_files(key)?
+            .open_file(&self.file.thumbnails_path.join(rel_path))?)
+    }
+
+    /// Returns the thumbnail status of an attachment.
+    ///
+    /// Returns `None` for attachments without a thumbnail that aren't queued, such as formats
+    /// without a renderer. Failures are only remembered while the store is open.
+    pub fn thumbnail_status(&self, key: &Key, filename: &str) -> Option<ThumbnailStatus> {
+        self.thumbnails.status(key, filename).or_else(|| {
+            let key_hash = Self::key_to_path(key);
+            (!self.file.thumbnail_files(&key_hash, filename).is_empty())
+                .then_some(ThumbnailStatus::Ready)
+        })
+    }
+
+    /// Returns filename -> status for the attachments of `key` that have a status.
+    pub fn thumbnail_statuses(
+        &self,
+        key: &Key,
+    ) -> Result<HashMap<String, ThumbnailStatus>, KevaError> {
+        let value = self.db.get(key)?.ok_or(DatabaseError::NotFound)?;
+        Ok(value
+            .attachments
+            .into_iter()
+            .filter_map(|a| Some((a.filename.clone(), self.thumbnail_status(key, &a.filename)?)))
+            .collect())
+    }
+
+    /// Sets the callback run on a pool thread whenever a thumbnail becomes ready or fails,
+    /// replacing the previous one. The callback must not call back into the store.
+    pub fn set_thumbnail_callback(&mut self, callback: Option<ThumbnailCallback>) {
+        self.thumbnails.set_callback(callback);
+    }
+
+    /// Blocks until every queued thumbnail is rendered and its callback has returned.
+    pub fn wait_for_thumbnails(&self) {
+        self.thumbnails.wait();
+    }
+
+    /// Queues a thumbnail for an attachment if a renderer is registered for it.
+    ///
+    /// An attachment that can't be rendered is shown with an icon instead.
+    fn queue_thumbnail(&mut self, key: &Key, key_files: &FileStorage, filename: &str) {
+        if key_files.supports_thumbnail(filename) {
+            let key_hash = Self::key_to_path(key);
+            self.thumbnails
+                .enqueue(key_files.clone(), key, &key_hash, filename);
+        }
+    }
+
+    /// Queues thumbnails again after their attachments moved, e.g. with a key rename.
+    fn requeue_thumbnails(&mut self, key: &Key, filenames: Vec<String>) {
+        if filenames.is_empty() {
+            return;
+        }
+        // A locked sealed key can't be rendered; its thumbnails come with the next version bump.
+        let Ok(key_files) = self.key_files(key) else {
+            return;
+        };
+        for filename in filenames {
+            self.queue_thumbnail(key, &key_files, &filename);
+        }
+    }
+
+    /// Queues a thumbnail for an attachment whose files were swapped back in without one, which
+    /// happens when it was removed while still pending.
+    fn queue_missing_thumbnail(&mut self, key: &Key, filename: &str) {
+        let key_hash = Self::key_to_path(key);
+        if self.file.attachment_path(&key_hash, filename).exists()
+            && self.file.thumbnail_files(&key_hash, filename).is_empty()
+        {
+            self.requeue_thumbnails(key, vec![filename.to_string()]);
+        }
+    }
+}
+
+/// Sealed key operations.
+///
+/// A sealed key's content, attachments and thumbnails are encrypted with a data key of its own,
+/// wrapped under a per-key passphrase and stored in the key's metadata. While the key is locked,
+/// [`KevaCore::get`] reports [`SealState::Locked`](crate::types::SealState::Locked) without
+/// attachments and file access fails with `Err(Locked)`.
+impl KevaCore {
+    /// How long [`KevaCore::unlock`] grants access unless changed with
+    /// [`KevaCore::set_unlock_timeout`].
+    pub const DEFAULT_UNLOCK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+    /// Sets how long future unlocks last. Keys already unlocked keep their expiry.
+    pub fn set_unlock_timeout(&mut self, timeout: Duration) {
+        self.unlock_timeout = timeout;
+    }
+
+    /// Seals an Active key under `passphrase`, re-encrypting its files with a new data key.
+    ///
+    /// The key is locked afterwards. Returns `Err(AlreadySealed)` if the key is sealed.
+    pub fn seal(&mut self, key: &Key, passphrase: &str) -> Result<(), KevaError> {
+        let value = self.db.get(key)?.ok_or(DatabaseError::NotFound)?;
+        if value.metadata.seal.is_some() {
+            return Err(KevaError::AlreadySealed);
+        }
+        if matches!(value.metadata.lifecycle_state, LifecycleState::Trash { .. }) {
+            return Err(DatabaseError::Trashed.into());
+        }
+
+        let cipher = Cipher::generate();
+        let wrapped = cipher.wrap(passphrase)?;
+        let sealed_files = self.file.with_cipher(Some(cipher));
+        self.transcode_key_files(key, &value, &self.file, &sealed_files)?;
+
+        self.db.set_seal(key, Some(wrapped))?;
+        Ok(())
+    }
+
+    /// Removes the seal from a key, re-encrypting its files the way the rest of the store is.
+    ///
+    /// Returns `Err(NotSealed)` if the key isn't sealed.
+    pub fn unseal(&mut self, key: &Key, passphrase: &str) -> Result<(), KevaError> {
+        let value = self.db.get(key)?.ok_or(DatabaseError::NotFound)?;
+        let Some(wrapped) = &value.metadata.seal else {
+            return Err(KevaError::NotSealed);
+        };
+
+        let cipher = Cipher::unwrap(wrapped, passphrase)?;
+        let sealed_files = self.file.with_cipher(Some(cipher));
+        self.transcode_key_files(key, &value, &sealed_files, &self.file)?;
+
+        self.db.set_seal(key, None)?;
+        self.unlocked.remove(key);
+        Ok(())
+    }
+
+    /// Unlocks a sealed key until `now` plus the unlock timeout.
+    ///
+    /// Returns `Err(NotSealed)` if the key isn't sealed, and `Err(Crypto(WrongPassphrase))` if
+    /// `passphrase` doesn't match.
+    pub fn unlock(
+        &mut self,
+        key: &Key,
+        passphrase: &str,
+        now: SystemTime,
+    ) -> Result<(), KevaError> {
+        let value = self.db.get(key)?.ok_or(DatabaseError::NotFound)?;
+        let Some(wrapped) = &value.metadata.seal else {
+            return Err(KevaError::NotSealed);
+        };
+
+        let cipher = Cipher::unwrap(wrapped, passphrase)?;
+        self.unlocked
+            .insert(key.clone(), (cipher, now + self.unlock_timeout));
+        Ok(())
+    }
+
+    /// Locks a sealed key before its unlock expires.
+    pub fn lock(&mut self, key: &Key) {
+        self.unlocked.remove(key);
+    }
+
+    /// Locks every unlocked key.
+    pub fn lock_all(&mut self) {
+        self.unlocked.clear();
+    }
+
+    /// Drops the data keys of unlocks that expired by `now`, returning the keys locked.
+    ///
+    /// Expired unlocks are already refused; this only clears them from memory.
+    pub fn lock_expired(&mut self, now: SystemTime) -> Vec<Key> {
+        let expired: Vec<Key> = self
+            .unlocked
+            .iter()
+            .filter(|(_, (_, until))| *until <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            self.unlocked.remove(key);
+        }
+        expired
+    }
+
+    /// Returns the data key of an unlocked sealed key, if its unlock hasn't expired.
+    fn unlocked_cipher(&self, key: &Key) -> Option<&Cipher> {
+        self.unlocked
+            .get(key)
+            .filter(|(_, until)| SystemTime::now() < *until)
+            .map(|(cipher, _)| cipher)
+    }
+
+    fn present(&self, key: &Key, value: latest_value::Value) -> Value {
+        let unlocked = self.unlocked_cipher(key).is_some();
+        Value::from_latest_value_with_unlock(value, unlocked)
+    }
+
+    /// Returns the file storage holding `key`'s files.
+    ///
+    /// Returns `Err(Locked)` if the key is sealed and not unlocked.
+    fn key_files(&self, key: &Key) -> Result<FileStorage, KevaError> {
+        let value = self.db.get(key)?.ok_or(DatabaseError::NotFound)?;
+        self.files_for(key, &value)
+    }
+
+    fn files_for(&self, key: &Key, value: &latest_value::Value) -> Result<FileStorage, KevaError> {
+        if value.metadata.seal.is_none() {
+            return Ok(self.file.clone());
+        }
+        let cipher = self.unlocked_cipher(key).ok_or(KevaError::Locked)?;
+        Ok(self.file.with_cipher(Some(cipher.clone())))
+    }
+
+    /// Rewrites the content, attachments, thumbnails and extracted text of a key from one cipher
+    /// to another.
+    fn transcode_key_files(
+        &self,
+        key: &Key,
+        value: &latest_value::Value,
+        from: &FileStorage,
+        to: &FileStorage,
+    ) -> Result<(), KevaError> {
+        let key_hash = Self::key_to_path(key);
+        // Queued jobs write through the old cipher.
+        self.thumbnails.wait();
+
+        from.transcode_file(&self.file.content_file_path(&key_hash), to)?;
+        for attachment in &value.attachments {
+            from.transcode_file(
+                &self.file.attachment_path(&key_hash, &attachment.filename),
+                to,
+            )?;
+
+            for thumb_path in self.file.thumbnail_files(&key_hash, &attachment.filename) {
+                from.transcode_file(&thumb_path, to)?;
+            }
+
+            let text_path = self.file.text_file_path(&key_hash, &attachment.filename);
+            if text_path.exists() {
+                from.transcode_file(&text_path, to)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Carries an unlock over to a key's new name.
+    fn move_unlock(&mut self, from: &Key, to: &Key) {
+        if let Some(entry) = self.unlocked.remove(from) {
+            self.unlocked.insert(to.clone(), entry);
+        }
+    }
+}
+
+/// Encryption operations.
+impl KevaCore {
+    /// Re-wraps the data keys under a new passphrase. Stored data is not rewritten.
+    pub fn change_passphrase(&mut self, old: &str, new: &str) -> Result<(), KevaError> {
+        if !self.is_encrypted() {
+            return Err(KevaError::NotEncrypted);
+        }
+        let cipher = Cipher::unlock(&self.key_file_path, old)?;
+        cipher.save(&self.key_file_path, new)?;
+        Ok(())
+    }
+
+    /// Replaces the data key and re-encrypts all values, content, attachments, thumbnails and
+    /// sync bases, encrypting any plaintext files found along the way.
+    ///
+    /// The retired key stays in the key file until every file has been rewritten, so an
+    /// interrupted rotation leaves the store readable; calling this again finishes the job.
+    /// Clears the undo history.
+    pub fn rotate_key(&mut self, passphrase: &str) -> Result<(), KevaError> {
+        if !self.is_encrypted() {
+            return Err(KevaError::NotEncrypted);
+        }
+        // Held files can't be re-encrypted in place, so drop the undo history.
+        self.clear_undo();
+        // Queued jobs write through the old key, which is dropped below.
+        self.thumbnails.wait();
+        let rotating = Cipher::unlock(&self.key_file_path, passphrase)?.with_new_key();
+        rotating.save(&self.key_file_path, passphrase)?;
+
+        self.db.set_cipher(rotating.clone());
+        self.file.cipher = Some(rotating.clone());
+        self.db.reseal_all()?;
+        // Sealed keys' files use their own data keys.
+        let sealed: HashSet<PathBuf> = self
+            .db
+            .sealed_keys()?
+            .iter()
+            .map(Self::key_to_path)
+            .collect();
+        self.file.reencrypt_all(&sealed)?;
+        self.reseal_sync_bases()?;
+
+        let rotated = rotating.without_retired_keys();
+        rotated.save(&self.key_file_path, passphrase)?;
+        self.db.set_cipher(rotated.clone());
+        self.file.cipher = Some(rotated);
+        Ok(())
+    }
 }
 
 /// Key management operations.
@@ -264,6 +1048,38 @@ impl KevaCore {
             return Ok(());
         }
 
+        self.rename_key(old_key, new_key, now)?;
+        self.record_rename(old_key, new_key, now);
+        Ok(())
+    }
+
+    /// Renames a key, purging any key already at `new_key` so the renamed key takes its place.
+    ///
+    /// A single undo renames the key back and restores the replaced key with its files.
+    pub fn rename_replacing(
+        &mut self,
+        old_key: &Key,
+        new_key: &Key,
+        now: SystemTime,
+    ) -> Result<(), KevaError> {
+        if old_key == new_key {
+            return Ok(());
+        }
+        let Some(replaced) = self.db.get(new_key)? else {
+            return self.rename(old_key, new_key, now);
+        };
+        self.db.get(old_key)?.ok_or(DatabaseError::NotFound)?;
+
+        self.rename_replacing_undoable(old_key, new_key, replaced, now)
+    }
+
+    /// Renames a key without recording it for undo.
+    fn rename_key(
+        &mut self,
+        old_key: &Key,
+        new_key: &Key,
+        now: SystemTime,
+    ) -> Result<(), KevaError> {
         if self.db.get(new_key)?.is_some() {
             return Err(KevaError::DestinationExists);
         }
@@ -275,10 +1091,188 @@ impl KevaCore {
         self.db.rename(old_key, new_key, now)?;
 
         // Rename files
+        let pending = self.thumbnails.cancel_key(old_key);
         self.file.rename_all(&old_hash, &new_hash)?;
 
+        self.key_moved(old_key, new_key, pending);
         Ok(())
     }
+
+    /// Updates what's kept in memory about a key whose row and files were renamed, queueing
+    /// again the thumbnails that were `pending` when it moved.
+    pub(super) fn key_moved(&mut self, from: &Key, to: &Key, pending: Vec<String>) {
+        self.move_unlock(from, to);
+        self.text_cache.forget_key(from);
+        self.requeue_thumbnails(to, pending);
+    }
+}
+
+/// Key tree operations.
+///
+/// Keys are treated as a virtual tree split on `/`; the empty prefix is the root. Browsing only
+/// considers Active keys.
+impl KevaCore {
+    /// Lists the immediate children of `prefix`, sorted by name.
+    pub fn children(&self, prefix: &str) -> Result<Vec<KeyTreeNode>, KevaError> {
+        let mut nodes: BTreeMap<String, KeyTreeNode> = BTreeMap::new();
+
+        for key in self.active_subtree(prefix)? {
+            let Some(rest) = Self::strip_tree_prefix(&key, prefix) else {
+                continue;
+            };
+            let (name, is_key) = match rest.split_once('/') {
+                Some((name, _)) => (name, false),
+                None => (rest, true),
+            };
+
+            // Keys sharing a segment aren't contiguous ("a/b-x" sorts between "a/b" and
+            // "a/b/c"), so group through a map.
+            let node = nodes
+                .entry(name.to_string())
+                .or_insert_with(|| KeyTreeNode {
+                    path: key[..key.len() - rest.len() + name.len()].to_string(),
+                    name: name.to_string(),
+                    is_key: false,
+                    descendant_count: 0,
+                });
+            if is_key {
+                node.is_key = true;
+            } else {
+                node.descendant_count += 1;
+            }
+        }
+
+        Ok(nodes.into_values().collect())
+    }
+
+    /// Counts Active keys strictly below `prefix`.
+    pub fn descendant_count(&self, prefix: &str) -> Result<usize, KevaError> {
+        Ok(self
+            .active_subtree(prefix)?
+            .iter()
+            .filter(|key| Self::strip_tree_prefix(key, prefix).is_some())
+            .count())
+    }
+
+    /// Renames `old_prefix` and everything below it to `new_prefix`, in one transaction.
+    ///
+    /// Both Active and Trash keys are moved. Returns the `(old, new)` pairs in key order.
+    pub fn rename_subtree(
+        &mut self,
+        old_prefix: &Key,
+        new_prefix: &Key,
+        now: SystemTime,
+    ) -> Result<Vec<(Key, Key)>, KevaError> {
+        if old_prefix == new_prefix {
+            return Ok(Vec::new());
+        }
+
+        let mut moves = Vec::new();
+        for (key, _) in self.db.subtree(old_prefix)? {
+            let new_key = Key::try_from(format!("{new_prefix}{}", &key[old_prefix.len()..]))?;
+            moves.push((key, new_key));
+        }
+
+        let sources: HashSet<&Key> = moves.iter().map(|(old, _)| old).collect();
+        for (_, new_key) in &moves {
+            if !sources.contains(new_key) && self.db.get(new_key)?.is_some() {
+                return Err(KevaError::DestinationExists);
+            }
+        }
+
+        self.db.rename_many(&moves, now)?;
+        let pending: Vec<_> = moves
+            .iter()
+            .map(|(old_key, _)| self.thumbnails.cancel_key(old_key))
+            .collect();
+
+        // Moving a prefix into its own subtree makes some destinations also sources, so stage
+        // every file set under a temporary name before moving it into place.
+        let overlapping = moves.iter().any(|(_, new_key)| sources.contains(new_key));
+        if overlapping {
+            for (old_key, _) in &moves {
+                let old_hash = Self::key_to_path(old_key);
+                self.file
+                    .rename_all(&old_hash, &Self::staging_path(&old_hash))?;
+            }
+            for (old_key, new_key) in &moves {
+                let old_hash = Self::key_to_path(old_key);
+                self.file
+                    .rename_all(&Self::staging_path(&old_hash), &Self::key_to_path(new_key))?;
+            }
+        } else {
+            for (old_key, new_key) in &moves {
+                self.file
+                    .rename_all(&Self::key_to_path(old_key), &Self::key_to_path(new_key))?;
+            }
+        }
+
+        // Collect first: with overlapping moves a destination may still hold a source's unlock.
+        let unlocks: Vec<_> = moves
+            .iter()
+            .filter_map(|(old_key, new_key)| Some((new_key, self.unlocked.remove(old_key)?)))
+            .collect();
+        for (new_key, entry) in unlocks {
+            self.unlocked.insert(new_key.clone(), entry);
+        }
+        for ((_, new_key), filenames) in moves.iter().zip(pending) {
+            self.requeue_thumbnails(new_key, filenames);
+        }
+
+        Ok(moves)
+    }
+
+    /// Moves every Active key in the subtree of `prefix` to trash, in one transaction.
+    ///
+    /// Returns the trashed keys in key order.
+    pub fn trash_subtree(&mut self, prefix: &Key, now: SystemTime) -> Result<Vec<Key>, KevaError> {
+        let keys = self.active_subtree(prefix)?;
+        self.db.trash_many(&keys, now)?;
+        Ok(keys)
+    }
+
+    /// Restores every Trash key in the subtree of `prefix`, in one transaction.
+    ///
+    /// Returns the restored keys in key order.
+    pub fn restore_subtree(
+        &mut self,
+        prefix: &Key,
+        now: SystemTime,
+    ) -> Result<Vec<Key>, KevaError> {
+        let keys: Vec<Key> = self
+            .db
+            .subtree(prefix)?
+            .into_iter()
+            .filter(|(_, state)| matches!(state, LifecycleState::Trash { .. }))
+            .map(|(key, _)| key)
+            .collect();
+        self.db.restore_many(&keys, now)?;
+        Ok(keys)
+    }
+
+    fn active_subtree(&self, prefix: &str) -> Result<Vec<Key>, KevaError> {
+        Ok(self
+            .db
+            .subtree(prefix)?
+            .into_iter()
+            .filter(|(_, state)| matches!(state, LifecycleState::Active { .. }))
+            .map(|(key, _)| key)
+            .collect())
+    }
+
+    /// Returns the part of `key` below `prefix`, or `None` if `key` is `prefix` itself.
+    fn strip_tree_prefix<'k>(key: &'k Key, prefix: &str) -> Option<&'k str> {
+        if prefix.is_empty() {
+            return Some(key);
+        }
+        key.strip_prefix(prefix)?.strip_prefix('/')
+    }
+
+    fn staging_path(key_hash: &Path) -> PathBuf {
+        let mut name = key_hash.as_os_str().to_os_string();
+        name.push("-staging");
+        PathBuf::from(name)
+    }
 }
 
 /// Trash operations.
@@ -286,6 +1280,7 @@ impl KevaCore {
     /// Moves a key to trash.
     pub fn trash(&mut self, key: &Key, now: SystemTime) -> Result<(), KevaError> {
         self.db.trash(key, now)?;
+        self.record_trash(key, now);
         Ok(())
     }
 
@@ -296,12 +1291,47 @@ impl KevaCore {
     }
 
     /// Permanently deletes a key.
+    ///
+    /// The key's files are kept in the holding area until the purge can no longer be undone.
     pub fn purge(&mut self, key: &Key) -> Result<(), KevaError> {
+        self.purge_undoable(key, SystemTime::now())
+    }
+
+    /// Purges a key and deletes its files without recording it for undo.
+    fn purge_key(&mut self, key: &Key) -> Result<(), KevaError> {
         let key_hash = Self::key_to_path(key);
         self.db.purge(key)?;
+        self.thumbnails.cancel_key(key);
         self.file.remove_all(&key_hash)?;
+        self.key_purged(key);
         Ok(())
     }
+
+    /// Updates what's kept in memory about a key whose row and files were purged.
+    pub(super) fn key_purged(&mut self, key: &Key) {
+        self.unlocked.remove(key);
+        self.text_cache.forget_key(key);
+        self.reset_store_size();
+    }
+}
+
+/// Operation log operations.
+///
+/// External consumers follow the store by remembering the last sequence number they processed
+/// and asking for everything after it.
+impl KevaCore {
+    /// Returns the sequence number of the latest logged operation, or 0 if none.
+    pub fn last_seq(&self) -> Result<u64, KevaError> {
+        Ok(self.db.last_seq()?)
+    }
+
+    /// Returns up to `limit` operations logged after sequence number `after`, oldest first.
+    ///
+    /// Returns `Err(Database(OplogCompacted))` if maintenance already removed entries the
+    /// caller hasn't seen; the consumer must then resynchronize from scratch.
+    pub fn oplog_since(&self, after: u64, limit: usize) -> Result<Vec<OpLogEntry>, KevaError> {
+        Ok(self.db.oplog_since(after, limit)?)
+    }
 }
 
 /// Maintenance operations.
@@ -317,8 +1347,13 @@ impl KevaCore {
         // Clean up files for purged keys
         for key in &gc_result.purged {
             let key_hash = Self::key_to_path(key);
+            self.thumbnails.cancel_key(key);
             self.file.remove_all(&key_hash)?;
+            self.key_purged(key);
         }
+        self.lock_expired(now);
+        self.expire_undo(now);
+        self.reset_store_size();
 
         // Clean up orphan blobs (files without database entries)
         let valid_key_hashes: HashSet<_> = self
@@ -347,10 +1382,25 @@ impl KevaCore {
             }
         }
 
+        // Check the holding area for files of undo entries from earlier sessions
+        orphaned_files_removed += self
+            .history
+            .remove_orphans()
+            .map_err(FileStorageError::from)?;
+
+        let text_extracted = self.extract_missing_text()?;
+
+        let oplog_entries_compacted = match now.checked_sub(gc_config.oplog_retention) {
+            Some(cutoff) => self.db.compact_oplog(cutoff)?,
+            None => 0,
+        };
+
         Ok(MaintenanceOutcome {
             keys_trashed: gc_result.trashed,
             keys_purged: gc_result.purged,
             orphaned_files_removed,
+            oplog_entries_compacted,
+            text_extracted,
         })
     }
 
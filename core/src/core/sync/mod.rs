@@ -0,0 +1,604 @@
+//! Two-way synchronization between two stores.
+//!
+//! Each store remembers, per peer, the state of every key after their last sync (the base).
+//! Comparing both sides against the base tells which side changed a key: a key changed on one
+//! side is copied to the other, and a key changed on both sides to different states is a
+//! conflict, resolved by keeping both versions.
+
+use super::KevaCore;
+use crate::core::db::error::DatabaseError;
+use crate::core::db::{OpLogEntry, Operation};
+use crate::core::error::KevaError;
+use crate::core::file_storage::FileStorage;
+use crate::core::file_storage::error::FileStorageError;
+use crate::types::Key;
+use crate::types::value::versioned_value::latest_value::{self, LifecycleState};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::time::SystemTime;
+
+/// What a sync changed, from the point of view of the store [`KevaCore::sync`] was called on.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SyncOutcome {
+    /// Keys created, updated, trashed or restored in this store from the peer.
+    pub pulled: Vec<Key>,
+    /// Keys created, updated, trashed or restored in the peer from this store.
+    pub pushed: Vec<Key>,
+    /// Renames replayed on either store, as `(from, to)`.
+    pub renamed: Vec<(Key, Key)>,
+    /// Keys purged on either store because the other side purged them.
+    pub purged: Vec<Key>,
+    /// Keys changed on both sides, with the suffixed key now holding the peer's version.
+    pub conflicts: Vec<(Key, Key)>,
+}
+
+/// Synced state of a key. Access times and counts are local and not synced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct KeyState {
+    trashed: bool,
+    seal: Option<Vec<u8>>,
+    content: blake3::Hash,
+    attachments: BTreeMap<String, blake3::Hash>,
+}
+
+impl KeyState {
+    /// Whether the key holds anything beyond empty content, so that finding the same state under
+    /// a new name says something about where it came from.
+    fn has_data(&self) -> bool {
+        !self.attachments.is_empty() || self.content != blake3::hash(b"")
+    }
+}
+
+type Snapshot = BTreeMap<Key, KeyState>;
+
+/// State of every key after the last sync with a peer, and how far this store's operation log
+/// had got by then.
+#[derive(Debug, Serialize, Deserialize)]
+struct SyncBase {
+    seq: u64,
+    keys: Snapshot,
+}
+
+/// One side's state compared to the base.
+struct Changes {
+    snapshot: Snapshot,
+    /// Renames logged since the base, as `(name in the base, current name)`. `None` if the log
+    /// didn't reach back to the base, so renames have to be inferred from the snapshots.
+    renames: Option<Vec<(Key, Key)>>,
+    /// Sequence number of the latest logged operation `snapshot` reflects.
+    seq: u64,
+}
+
+/// Sync operations.
+impl KevaCore {
+    /// Synchronizes this store and `peer` in both directions.
+    ///
+    /// Changes made on one side since the previous sync of the two stores are applied to the
+    /// other: content, attachments, trash, restore, purge and renames. When both sides changed a
+    /// key differently, this store's version stays under the key and the peer's is kept under
+    /// `"{key} (conflict)"` on both sides. When one side purged a key the other changed, the
+    /// changed version is kept.
+    ///
+    /// Only keys named in the operation log since the previous sync are hashed. The first sync,
+    /// or one after maintenance compacted the log past the previous sync, hashes the content and
+    /// attachments of every key.
+    pub fn sync(&mut self, peer: &mut KevaCore, now: SystemTime) -> Result<SyncOutcome, KevaError> {
+        let local_id = self.db.store_id()?;
+        let peer_id = peer.db.store_id()?;
+
+        let stored = self.load_sync_base(&peer_id);
+        // The peer's log position only applies if it saved the same base.
+        let peer_stored = peer
+            .load_sync_base(&local_id)
+            .filter(|peer_base| stored.as_ref().is_some_and(|b| b.keys == peer_base.keys));
+        let mut local = self.changes_since(stored.as_ref())?;
+        let mut remote = peer.changes_since(peer_stored.as_ref())?;
+        let mut base = stored.map(|b| b.keys).unwrap_or_default();
+        let mut outcome = SyncOutcome::default();
+
+        // Replay renames first, so the moved keys compare as unchanged below.
+        for (from, to) in detect_renames(&base, &local, &remote.snapshot) {
+            peer.rename_key(&from, &to, now)?;
+            move_entry(&mut remote.snapshot, &from, &to);
+            move_entry(&mut base, &from, &to);
+            outcome.renamed.push((from, to));
+        }
+        for (from, to) in detect_renames(&base, &remote, &local.snapshot) {
+            self.rename_key(&from, &to, now)?;
+            move_entry(&mut local.snapshot, &from, &to);
+            move_entry(&mut base, &from, &to);
+            outcome.renamed.push((from, to));
+        }
+
+        let keys: BTreeSet<Key> = base
+            .keys()
+            .chain(local.snapshot.keys())
+            .chain(remote.snapshot.keys())
+            .cloned()
+            .collect();
+
+        for key in keys {
+            let old = base.get(&key);
+            let (mine, theirs) = (local.snapshot.get(&key), remote.snapshot.get(&key));
+            if mine == theirs {
+                continue;
+            }
+            let local_changed = mine != old;
+            let remote_changed = theirs != old;
+
+            match (mine, theirs) {
+                (Some(mine), Some(theirs)) if local_changed && remote_changed => {
+                    let conflict = conflict_key(&key, self, peer)?;
+                    peer.rename_key(&key, &conflict, now)?;
+                    copy_key(peer, self, &conflict, theirs, None, now)?;
+                    copy_key(self, peer, &key, mine, None, now)?;
+                    outcome.conflicts.push((key, conflict));
+                }
+                (Some(mine), Some(theirs)) if local_changed => {
+                    copy_key(self, peer, &key, mine, Some(theirs), now)?;
+                    outcome.pushed.push(key);
+                }
+                (Some(mine), Some(theirs)) => {
+                    copy_key(peer, self, &key, theirs, Some(mine), now)?;
+                    outcome.pulled.push(key);
+                }
+                (Some(_), None) if remote_changed && !local_changed => {
+                    self.purge_key(&key)?;
+                    outcome.purged.push(key);
+                }
+                (Some(mine), None) => {
+                    copy_key(self, peer, &key, mine, None, now)?;
+                    outcome.pushed.push(key);
+                }
+                (None, Some(_)) if local_changed && !remote_changed => {
+                    peer.purge_key(&key)?;
+                    outcome.purged.push(key);
+                }
+                (None, Some(theirs)) => {
+                    copy_key(peer, self, &key, theirs, None, now)?;
+                    outcome.pulled.push(key);
+                }
+                (None, None) => unreachable!("equal states are skipped"),
+            }
+        }
+
+        // Bring both sides up to date with what this sync changed. Not everything copy_key does
+        // is logged, so the keys it acted on are refreshed as well.
+        let acted: BTreeSet<Key> = outcome
+            .pulled
+            .iter()
+            .chain(&outcome.pushed)
+            .chain(&outcome.purged)
+            .chain(outcome.conflicts.iter().flat_map(|(a, b)| [a, b]))
+            .chain(outcome.renamed.iter().flat_map(|(a, b)| [a, b]))
+            .cloned()
+            .collect();
+        self.reset_store_size();
+        peer.reset_store_size();
+        let local_seq = self.refresh_since(&mut local.snapshot, local.seq, &acted)?;
+        let remote_seq = peer.refresh_since(&mut remote.snapshot, remote.seq, &acted)?;
+
+        // Only keys that ended up identical on both sides form the new base; anything else is
+        // treated as new on both sides next time, so it can't be mistaken for a one-sided change.
+        let keys: Snapshot = local
+            .snapshot
+            .into_iter()
+            .filter(|(key, state)| remote.snapshot.get(key) == Some(state))
+            .collect();
+        peer.save_sync_base(
+            &local_id,
+            &SyncBase {
+                seq: remote_seq,
+                keys: keys.clone(),
+            },
+        )?;
+        self.save_sync_base(
+            &peer_id,
+            &SyncBase {
+                seq: local_seq,
+                keys,
+            },
+        )?;
+
+        Ok(outcome)
+    }
+
+    /// Returns this side's state, reusing `base` for keys the log shows unchanged since.
+    fn changes_since(&self, base: Option<&SyncBase>) -> Result<Changes, KevaError> {
+        if let Some(base) = base
+            && let Some(entries) = self.oplog_entries_since(base.seq)?
+        {
+            let mut snapshot = base.keys.clone();
+            let changed: BTreeSet<Key> = entries
+                .iter()
+                .flat_map(|entry| logged_keys(&entry.op))
+                .collect();
+            self.refresh(&mut snapshot, &changed)?;
+            return Ok(Changes {
+                snapshot,
+                renames: Some(logged_renames(&entries)),
+                seq: entries.last().map_or(base.seq, |entry| entry.seq),
+            });
+        }
+
+        Ok(Changes {
+            seq: self.db.last_seq()?,
+            snapshot: self.snapshot()?,
+            renames: None,
+        })
+    }
+
+    /// Updates `snapshot`, the state as of log position `after`, for the keys logged since and
+    /// `extra`. Returns the new log position.
+    fn refresh_since(
+        &self,
+        snapshot: &mut Snapshot,
+        after: u64,
+        extra: &BTreeSet<Key>,
+    ) -> Result<u64, KevaError> {
+        let Some(entries) = self.oplog_entries_since(after)? else {
+            *snapshot = self.snapshot()?;
+            return Ok(self.db.last_seq()?);
+        };
+        let mut changed = extra.clone();
+        changed.extend(entries.iter().flat_map(|entry| logged_keys(&entry.op)));
+        self.refresh(snapshot, &changed)?;
+        Ok(entries.last().map_or(after, |entry| entry.seq))
+    }
+
+    /// Returns every operation logged after `after`, or `None` if some were compacted away.
+    fn oplog_entries_since(&self, after: u64) -> Result<Option<Vec<OpLogEntry>>, KevaError> {
+        const PAGE: usize = 1024;
+        let mut entries: Vec<OpLogEntry> = Vec::new();
+        loop {
+            let from = entries.last().map_or(after, |entry| entry.seq);
+            let page = match self.db.oplog_since(from, PAGE) {
+                Ok(page) => page,
+                Err(DatabaseError::OplogCompacted { .. }) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            let done = page.len() < PAGE;
+            entries.extend(page);
+            if done {
+                return Ok(Some(entries));
+            }
+        }
+    }
+
+    /// Recomputes the state of `keys` in `snapshot`, dropping the ones that no longer exist.
+    fn refresh(&self, snapshot: &mut Snapshot, keys: &BTreeSet<Key>) -> Result<(), KevaError> {
+        for key in keys {
+            match self.db.get(key)? {
+                Some(value) => {
+                    let state = self.key_state(key, &value)?;
+                    snapshot.insert(key.clone(), state);
+                }
+                None => {
+                    snapshot.remove(key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the synced state of every key.
+    fn snapshot(&self) -> Result<Snapshot, KevaError> {
+        let mut snapshot = Snapshot::new();
+        for key in self
+            .db
+            .active_keys()?
+            .into_iter()
+            .chain(self.db.trashed_keys()?)
+        {
+            if let Some(value) = self.db.get(&key)? {
+                let state = self.key_state(&key, &value)?;
+                snapshot.insert(key, state);
+            }
+        }
+        Ok(snapshot)
+    }
+
+    fn key_state(&self, key: &Key, value: &latest_value::Value) -> Result<KeyState, KevaError> {
+        let key_hash = Self::key_to_path(key);
+        let files = self.sync_files(value.metadata.seal.is_some());
+
+        let content = files.hash_file(&self.file.content_file_path(&key_hash))?;
+        let mut attachments = BTreeMap::new();
+        for attachment in &value.attachments {
+            let path = self.file.attachment_path(&key_hash, &attachment.filename);
+            attachments.insert(attachment.filename.clone(), files.hash_file(&path)?);
+        }
+
+        Ok(KeyState {
+            trashed: matches!(value.metadata.lifecycle_state, LifecycleState::Trash { .. }),
+            seal: value.metadata.seal.clone(),
+            content,
+            attachments,
+        })
+    }
+
+    /// Returns the storage to read or write a key's files through while syncing.
+    ///
+    /// Sealed keys' files are copied as ciphertext; their data key travels with the seal.
+    fn sync_files(&self, sealed: bool) -> FileStorage {
+        if sealed {
+            self.file.with_cipher(None)
+        } else {
+            self.file.clone()
+        }
+    }
+
+    /// Loads the base shared with `peer_id`. A missing or unreadable base means a first sync.
+    fn load_sync_base(&self, peer_id: &str) -> Option<SyncBase> {
+        std::fs::read(self.sync_path.join(peer_id))
+            .ok()
+            .and_then(|bytes| match &self.file.cipher {
+                Some(cipher) => cipher.open(&bytes).ok(),
+                None => Some(bytes),
+            })
+            .and_then(|bytes| postcard::from_bytes(&bytes).ok())
+    }
+
+    /// Saves the base shared with `peer_id`, sealed in encrypted stores since it lists key names
+    /// and content hashes.
+    fn save_sync_base(&self, peer_id: &str, base: &SyncBase) -> Result<(), KevaError> {
+        let mut bytes = postcard::to_allocvec(base).expect("sync base serialization cannot fail");
+        if let Some(cipher) = &self.file.cipher {
+            bytes = cipher.seal(&bytes);
+        }
+        let path = self.sync_path.join(peer_id);
+        let tmp_path = path.with_extension("tmp");
+
+        std::fs::create_dir_all(&self.sync_path).map_err(FileStorageError::from)?;
+        std::fs::write(&tmp_path, bytes).map_err(FileStorageError::from)?;
+        std::fs::rename(&tmp_path, &path).map_err(FileStorageError::from)?;
+        Ok(())
+    }
+
+    /// Re-seals every sync base with the cipher's current key, during key rotation.
+    pub(super) fn reseal_sync_bases(&self) -> Result<(), KevaError> {
+        let entries = match std::fs::read_dir(&self.sync_path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(FileStorageError::from(e).into()),
+        };
+        for entry in entries {
+            let path = entry.map_err(FileStorageError::from)?.path();
+            if path.extension().is_some() {
+                continue;
+            }
+            if let Some(peer_id) = path.file_name().and_then(|name| name.to_str())
+                && let Some(base) = self.load_sync_base(peer_id)
+            {
+                self.save_sync_base(peer_id, &base)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Finds keys that `side` renamed since `base` and that can be replayed on `other`, which must
+/// still have the old key and not the new one.
+///
+/// Renames come from `side`'s log when it reaches back to the base. Otherwise a rename shows up
+/// as a key missing from `side` plus a new key with the same state, and is only replayed if
+/// `other` still has the old key unchanged. Keys without attachments and with empty content
+/// can't be told apart that way, so they are never paired.
+fn detect_renames(base: &Snapshot, side: &Changes, other: &Snapshot) -> Vec<(Key, Key)> {
+    let is_new = |key: &Key| !base.contains_key(key) && !other.contains_key(key);
+
+    if let Some(renames) = &side.renames {
+        return renames
+            .iter()
+            .filter(|(from, to)| {
+                base.contains_key(from)
+                    && !side.snapshot.contains_key(from)
+                    && other.contains_key(from)
+                    && side.snapshot.contains_key(to)
+                    && is_new(to)
+            })
+            .cloned()
+            .collect();
+    }
+
+    let new_keys: Vec<(&Key, &KeyState)> = side
+        .snapshot
+        .iter()
+        .filter(|(key, state)| is_new(key) && state.has_data())
+        .collect();
+    let mut claimed = HashSet::new();
+    let mut renames = Vec::new();
+
+    for (from, state) in base {
+        if side.snapshot.contains_key(from) || other.get(from) != Some(state) {
+            continue;
+        }
+        let to = new_keys
+            .iter()
+            .find(|(to, new_state)| *new_state == state && !claimed.contains(*to));
+        if let Some((to, _)) = to {
+            claimed.insert(*to);
+            renames.push((from.clone(), (*to).clone()));
+        }
+    }
+    renames
+}
+
+/// Returns the renames in `entries` with chains collapsed, as `(first name, last name)`. Keys
+/// purged after being renamed are left out.
+fn logged_renames(entries: &[OpLogEntry]) -> Vec<(Key, Key)> {
+    // Current name -> name before the first logged rename
+    let mut origins: BTreeMap<Key, Key> = BTreeMap::new();
+    for entry in entries {
+        match &entry.op {
+            Operation::Renamed { from, to } => {
+                let origin = origins.remove(from).unwrap_or_else(|| from.clone());
+                origins.insert(to.clone(), origin);
+            }
+            Operation::Purged { key } => {
+                origins.remove(key);
+            }
+            _ => {}
+        }
+    }
+    origins
+        .into_iter()
+        .filter(|(to, from)| to != from)
+        .map(|(to, from)| (from, to))
+        .collect()
+}
+
+/// Returns the keys whose synced state `op` may have changed.
+///
+/// Touches are included since they follow edits made to content files directly, which aren't
+/// logged themselves.
+fn logged_keys(op: &Operation) -> Vec<Key> {
+    match op {
+        Operation::Renamed { from, to } => vec![from.clone(), to.clone()],
+        Operation::Created { key }
+        | Operation::Touched { key }
+        | Operation::Trashed { key }
+        | Operation::Restored { key }
+        | Operation::Purged { key }
+        | Operation::Reinserted { key }
+        | Operation::AttachmentAdded { key, .. }
+        | Operation::AttachmentRemoved { key, .. }
+        | Operation::AttachmentRenamed { key, .. }
+        | Operation::Sealed { key }
+        | Operation::Unsealed { key }
+        | Operation::ContentWritten { key } => vec![key.clone()],
+    }
+}
+
+fn move_entry(snapshot: &mut Snapshot, from: &Key, to: &Key) {
+    if let Some(state) = snapshot.remove(from) {
+        snapshot.insert(to.clone(), state);
+    }
+}
+
+/// Returns the first of `"{key} (conflict)"`, `"{key} (conflict 2)"`, ... free in both stores.
+fn conflict_key(key: &Key, a: &KevaCore, b: &KevaCore) -> Result<Key, KevaError> {
+    let mut n = 1;
+    loop {
+        let name = if n == 1 {
+            format!("{key} (conflict)")
+        } else {
+            format!("{key} (conflict {n})")
+        };
+        let candidate = Key::try_from(name)?;
+        if a.db.get(&candidate)?.is_none() && b.db.get(&candidate)?.is_none() {
+            return Ok(candidate);
+        }
+        n += 1;
+    }
+}
+
+/// Makes `key` in `to` match `state`, the state of `key` in `from`.
+///
+/// `existing` is the current state of `key` in `to`; files whose hashes already match are not
+/// copied.
+fn copy_key(
+    from: &KevaCore,
+    to: &mut KevaCore,
+    key: &Key,
+    state: &KeyState,
+    existing: Option<&KeyState>,
+    now: SystemTime,
+) -> Result<(), KevaError> {
+    let value = from.db.get(key)?.ok_or(DatabaseError::NotFound)?;
+    let key_hash = KevaCore::key_to_path(key);
+
+    match existing {
+        None => {
+            to.db.create(key, now)?;
+        }
+        Some(existing) if existing.trashed => to.db.restore(key, now)?,
+        Some(_) => {}
+    }
+
+    if existing.and_then(|e| e.seal.as_ref()) != state.seal.as_ref() {
+        to.db.set_seal(key, state.seal.clone())?;
+        to.lock(key);
+    }
+
+    let sealed = state.seal.is_some();
+    let (src, dst) = (from.sync_files(sealed), to.sync_files(sealed));
+
+    if existing.map(|e| e.content) != Some(state.content) {
+        src.copy_file(
+            &from.file.content_file_path(&key_hash),
+            &dst,
+            &to.file.content_file_path(&key_hash),
+        )?;
+        to.db.log_content_written(key, now)?;
+    }
+
+    let no_attachments = BTreeMap::new();
+    let existing_attachments = existing.map_or(&no_attachments, |e| &e.attachments);
+
+    for (filename, hash) in existing_attachments {
+        if state.attachments.get(filename) != Some(hash) {
+            to.db.remove_attachment(key, filename, now)?;
+            to.thumbnails.cancel(key, filename);
+            to.file.remove_attachment(&key_hash, filename)?;
+            to.file.remove_thumbnail(&key_hash, filename)?;
+            to.file.remove_text(&key_hash, filename)?;
+        }
+    }
+
+    for (filename, hash) in &state.attachments {
+        if existing_attachments.get(filename) == Some(hash) {
+            continue;
+        }
+        let attachment = value
+            .attachments
+            .iter()
+            .find(|a| &a.filename == filename)
+            .cloned()
+            .ok_or_else(|| DatabaseError::AttachmentNotFound(filename.clone()))?;
+
+        // A job still queued for the replaced version must not overwrite the new thumbnail.
+        to.thumbnails.cancel(key, filename);
+        src.copy_file(
+            &from.file.attachment_path(&key_hash, filename),
+            &dst,
+            &to.file.attachment_path(&key_hash, filename),
+        )?;
+
+        if sealed {
+            // Thumbnails and text of a sealed key can only be generated once unlocked; copy them
+            // instead.
+            to.file.remove_thumbnail(&key_hash, filename)?;
+            for (_, rel_path) in from.file.thumbnail_variants(&key_hash, filename) {
+                src.copy_file(
+                    &from.file.thumbnails_path.join(&rel_path),
+                    &dst,
+                    &to.file.thumbnails_path.join(&rel_path),
+                )?;
+            }
+            to.file.remove_text(&key_hash, filename)?;
+            let text_path = from.file.text_file_path(&key_hash, filename);
+            if text_path.exists() {
+                src.copy_file(
+                    &text_path,
+                    &dst,
+                    &to.file.text_file_path(&key_hash, filename),
+                )?;
+            }
+        } else {
+            let storage = to.file.clone();
+            storage.store_text(&key_hash, filename)?;
+            to.queue_thumbnail(key, &storage, filename);
+        }
+
+        to.db.add_attachment(key, attachment, now)?;
+    }
+
+    if state.trashed {
+        to.db.trash(key, now)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
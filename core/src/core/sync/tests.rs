@@ -0,0 +1,508 @@
+use super::*;
+use crate::types::{AttachmentConflictResolution, AttachmentName, Config, SealState};
+use common::{add_attachment, create_store, make_key, read_content, write_content};
+use std::time::Duration;
+use tempfile::TempDir;
+
+mod common {
+    use super::*;
+    use std::io::{Read, Write};
+
+    pub(super) fn create_store() -> (KevaCore, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let config = Config {
+            base_path: temp_dir.path().to_path_buf(),
+        };
+        (KevaCore::open(config).unwrap(), temp_dir)
+    }
+
+    pub(super) fn make_key(s: &str) -> Key {
+        Key::try_from(s).unwrap()
+    }
+
+    pub(super) fn write_content(store: &KevaCore, key: &Key, content: &str) {
+        let mut writer = store.write_content(key).unwrap();
+        writer.write_all(content.as_bytes()).unwrap();
+        writer.finish().unwrap();
+    }
+
+    pub(super) fn read_content(store: &KevaCore, key: &Key) -> String {
+        let mut content = String::new();
+        store
+            .open_content(key)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    pub(super) fn add_attachment(store: &mut KevaCore, key: &Key, name: &str, body: &[u8]) {
+        let source = TempDir::new().unwrap();
+        let path = source.path().join(name);
+        std::fs::write(&path, body).unwrap();
+        store
+            .add_attachments(
+                key,
+                vec![(
+                    path,
+                    AttachmentName::try_from(name).unwrap(),
+                    AttachmentConflictResolution::Overwrite,
+                )],
+                SystemTime::now(),
+            )
+            .unwrap();
+    }
+}
+
+mod transfer {
+    use super::*;
+
+    #[test]
+    fn test_new_keys_copied_both_ways() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let a = make_key("from/desktop");
+        let b = make_key("from/laptop");
+        let now = SystemTime::now();
+
+        desktop.create(&a, now).unwrap();
+        write_content(&desktop, &a, "desktop note");
+        add_attachment(&mut desktop, &a, "doc.txt", b"attached");
+        laptop.create(&b, now).unwrap();
+        write_content(&laptop, &b, "laptop note");
+
+        let outcome = desktop.sync(&mut laptop, now).unwrap();
+        assert_eq!(outcome.pushed, vec![a.clone()]);
+        assert_eq!(outcome.pulled, vec![b.clone()]);
+
+        assert_eq!(read_content(&laptop, &a), "desktop note");
+        assert_eq!(read_content(&desktop, &b), "laptop note");
+        let value = laptop.get(&a).unwrap().unwrap();
+        assert_eq!(value.attachments.len(), 1);
+        assert_eq!(
+            std::fs::read(laptop.attachment_path(&a, "doc.txt").unwrap()).unwrap(),
+            b"attached"
+        );
+    }
+
+    #[test]
+    fn test_second_sync_is_noop() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        desktop.create(&key, now).unwrap();
+        desktop.sync(&mut laptop, now).unwrap();
+
+        let outcome = laptop.sync(&mut desktop, now).unwrap();
+        assert_eq!(outcome, SyncOutcome::default());
+    }
+
+    #[test]
+    fn test_edits_propagate() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        desktop.create(&key, now).unwrap();
+        write_content(&desktop, &key, "v1");
+        add_attachment(&mut desktop, &key, "old.txt", b"old");
+        desktop.sync(&mut laptop, now).unwrap();
+
+        write_content(&laptop, &key, "v2");
+        laptop.remove_attachment(&key, "old.txt", now).unwrap();
+        add_attachment(&mut laptop, &key, "new.txt", b"new");
+
+        let outcome = desktop.sync(&mut laptop, now).unwrap();
+        assert_eq!(outcome.pulled, vec![key.clone()]);
+        assert_eq!(read_content(&desktop, &key), "v2");
+
+        let value = desktop.get(&key).unwrap().unwrap();
+        let names: Vec<_> = value
+            .attachments
+            .iter()
+            .map(|a| a.filename.as_str())
+            .collect();
+        assert_eq!(names, vec!["new.txt"]);
+        let key_hash = KevaCore::key_to_path(&key);
+        assert!(!desktop.file.attachment_path(&key_hash, "old.txt").exists());
+    }
+
+    #[test]
+    fn test_access_stats_are_not_synced() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        desktop.create(&key, now).unwrap();
+        desktop.sync(&mut laptop, now).unwrap();
+
+        desktop.touch(&key, now + Duration::from_secs(10)).unwrap();
+        let outcome = desktop.sync(&mut laptop, now).unwrap();
+        assert_eq!(outcome, SyncOutcome::default());
+        assert_eq!(laptop.get(&key).unwrap().unwrap().metadata.access_count, 0);
+    }
+}
+
+mod lifecycle {
+    use super::*;
+    use crate::types::LifecycleState;
+
+    #[test]
+    fn test_trash_and_restore_propagate() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        desktop.create(&key, now).unwrap();
+        desktop.sync(&mut laptop, now).unwrap();
+
+        desktop.trash(&key, now).unwrap();
+        desktop.sync(&mut laptop, now).unwrap();
+        let value = laptop.get(&key).unwrap().unwrap();
+        assert!(matches!(
+            value.metadata.lifecycle_state,
+            LifecycleState::Trash { .. }
+        ));
+
+        laptop.restore(&key, now).unwrap();
+        let outcome = desktop.sync(&mut laptop, now).unwrap();
+        assert_eq!(outcome.pulled, vec![key.clone()]);
+        let value = desktop.get(&key).unwrap().unwrap();
+        assert!(matches!(
+            value.metadata.lifecycle_state,
+            LifecycleState::Active { .. }
+        ));
+    }
+
+    #[test]
+    fn test_purge_propagates() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        desktop.create(&key, now).unwrap();
+        desktop.sync(&mut laptop, now).unwrap();
+
+        laptop.purge(&key).unwrap();
+        let outcome = desktop.sync(&mut laptop, now).unwrap();
+        assert_eq!(outcome.purged, vec![key.clone()]);
+        assert!(desktop.get(&key).unwrap().is_none());
+        assert!(!desktop.content_path(&key).exists());
+    }
+
+    #[test]
+    fn test_changed_key_survives_purge() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        desktop.create(&key, now).unwrap();
+        desktop.sync(&mut laptop, now).unwrap();
+
+        laptop.purge(&key).unwrap();
+        write_content(&desktop, &key, "still needed");
+
+        let outcome = desktop.sync(&mut laptop, now).unwrap();
+        assert_eq!(outcome.pushed, vec![key.clone()]);
+        assert_eq!(read_content(&laptop, &key), "still needed");
+    }
+
+    #[test]
+    fn test_rename_propagates() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let old = make_key("drafts/post");
+        let new = make_key("published/post");
+        let now = SystemTime::now();
+
+        desktop.create(&old, now).unwrap();
+        write_content(&desktop, &old, "body");
+        add_attachment(&mut desktop, &old, "img.txt", b"img");
+        desktop.sync(&mut laptop, now).unwrap();
+        laptop.touch(&old, now).unwrap();
+
+        desktop.rename(&old, &new, now).unwrap();
+        let outcome = laptop.sync(&mut desktop, now).unwrap();
+        assert_eq!(outcome.renamed, vec![(old.clone(), new.clone())]);
+        assert!(outcome.pulled.is_empty() && outcome.purged.is_empty());
+
+        assert!(laptop.get(&old).unwrap().is_none());
+        assert_eq!(read_content(&laptop, &new), "body");
+        // Renamed rather than recreated, so local access stats are kept
+        assert_eq!(laptop.get(&new).unwrap().unwrap().metadata.access_count, 1);
+    }
+}
+
+mod renames {
+    use super::*;
+    use crate::types::GcConfig;
+
+    #[test]
+    fn test_rename_with_edit_propagates() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let old = make_key("drafts/post");
+        let new = make_key("published/post");
+        let now = SystemTime::now();
+
+        desktop.create(&old, now).unwrap();
+        write_content(&desktop, &old, "draft");
+        desktop.sync(&mut laptop, now).unwrap();
+
+        desktop.rename(&old, &new, now).unwrap();
+        write_content(&desktop, &new, "final");
+        let outcome = desktop.sync(&mut laptop, now).unwrap();
+
+        assert_eq!(outcome.renamed, vec![(old.clone(), new.clone())]);
+        assert_eq!(outcome.pushed, vec![new.clone()]);
+        assert!(laptop.get(&old).unwrap().is_none());
+        assert_eq!(read_content(&laptop, &new), "final");
+    }
+
+    #[test]
+    fn test_empty_keys_are_not_paired() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let (a, b) = (make_key("a"), make_key("b"));
+        let now = SystemTime::now();
+
+        desktop.create(&a, now).unwrap();
+        desktop.sync(&mut laptop, now).unwrap();
+
+        desktop.purge(&a).unwrap();
+        desktop.create(&b, now).unwrap();
+        let outcome = desktop.sync(&mut laptop, now).unwrap();
+
+        assert!(outcome.renamed.is_empty());
+        assert_eq!(outcome.purged, vec![a]);
+        assert_eq!(outcome.pushed, vec![b]);
+    }
+
+    #[test]
+    fn test_empty_keys_are_not_paired_without_log() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let (a, b) = (make_key("a"), make_key("b"));
+        let now = SystemTime::now();
+
+        desktop.create(&a, now).unwrap();
+        desktop.sync(&mut laptop, now).unwrap();
+
+        desktop.purge(&a).unwrap();
+        desktop.create(&b, now).unwrap();
+        let gc_config = GcConfig {
+            trash_ttl: Duration::from_secs(3600),
+            purge_ttl: Duration::from_secs(3600),
+            oplog_retention: Duration::ZERO,
+        };
+        desktop
+            .maintenance(now + Duration::from_secs(60), gc_config)
+            .unwrap();
+        let outcome = desktop.sync(&mut laptop, now).unwrap();
+
+        assert!(outcome.renamed.is_empty());
+        assert_eq!(outcome.purged, vec![a]);
+        assert_eq!(outcome.pushed, vec![b]);
+    }
+}
+
+mod incremental {
+    use super::*;
+
+    #[test]
+    fn test_only_logged_keys_are_rehashed() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        desktop.create(&key, now).unwrap();
+        write_content(&desktop, &key, "v1");
+        desktop.sync(&mut laptop, now).unwrap();
+
+        // Edited on disk without going through the store: nothing is logged
+        std::fs::write(desktop.content_path(&key), "v2").unwrap();
+        assert_eq!(
+            desktop.sync(&mut laptop, now).unwrap(),
+            SyncOutcome::default()
+        );
+
+        // A touch, as frontends do after external edits, makes it count
+        desktop.touch(&key, now).unwrap();
+        let outcome = desktop.sync(&mut laptop, now).unwrap();
+        assert_eq!(outcome.pushed, vec![key.clone()]);
+        assert_eq!(read_content(&laptop, &key), "v2");
+    }
+
+    #[test]
+    fn test_missing_peer_base_falls_back_to_full_snapshot() {
+        let temp = TempDir::new().unwrap();
+        let config = Config {
+            base_path: temp.path().to_path_buf(),
+        };
+        let mut desktop = KevaCore::open(config.clone()).unwrap();
+        let (mut laptop, _l) = create_store();
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        laptop.create(&key, now).unwrap();
+        laptop.sync(&mut desktop, now).unwrap();
+        std::fs::remove_dir_all(config.sync_path()).unwrap();
+
+        write_content(&laptop, &key, "edited");
+        let outcome = laptop.sync(&mut desktop, now).unwrap();
+        assert_eq!(outcome.pushed, vec![key.clone()]);
+        assert_eq!(read_content(&desktop, &key), "edited");
+    }
+}
+
+mod conflict {
+    use super::*;
+
+    #[test]
+    fn test_concurrent_edits_keep_both() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let key = make_key("note");
+        let conflict = make_key("note (conflict)");
+        let now = SystemTime::now();
+
+        desktop.create(&key, now).unwrap();
+        desktop.sync(&mut laptop, now).unwrap();
+
+        write_content(&desktop, &key, "desktop edit");
+        write_content(&laptop, &key, "laptop edit");
+
+        let outcome = desktop.sync(&mut laptop, now).unwrap();
+        assert_eq!(outcome.conflicts, vec![(key.clone(), conflict.clone())]);
+
+        for store in [&desktop, &laptop] {
+            assert_eq!(read_content(store, &key), "desktop edit");
+            assert_eq!(read_content(store, &conflict), "laptop edit");
+        }
+        assert_eq!(
+            laptop.sync(&mut desktop, now).unwrap(),
+            SyncOutcome::default()
+        );
+    }
+
+    #[test]
+    fn test_conflict_key_suffix_increments() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        desktop.create(&key, now).unwrap();
+        desktop.create(&make_key("note (conflict)"), now).unwrap();
+        desktop.sync(&mut laptop, now).unwrap();
+
+        write_content(&desktop, &key, "a");
+        write_content(&laptop, &key, "b");
+
+        let outcome = desktop.sync(&mut laptop, now).unwrap();
+        assert_eq!(
+            outcome.conflicts,
+            vec![(key.clone(), make_key("note (conflict 2)"))]
+        );
+    }
+
+    #[test]
+    fn test_first_sync_with_different_versions() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        desktop.create(&key, now).unwrap();
+        write_content(&desktop, &key, "a");
+        laptop.create(&key, now).unwrap();
+        write_content(&laptop, &key, "b");
+
+        let outcome = desktop.sync(&mut laptop, now).unwrap();
+        assert_eq!(outcome.conflicts.len(), 1);
+    }
+}
+
+mod encryption {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_store_syncs_plaintext() {
+        let temp = TempDir::new().unwrap();
+        let config = Config {
+            base_path: temp.path().to_path_buf(),
+        };
+        let mut desktop = KevaCore::open_encrypted(config, "passphrase").unwrap();
+        let (mut laptop, _l) = create_store();
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        desktop.create(&key, now).unwrap();
+        write_content(&desktop, &key, "secret");
+        desktop.sync(&mut laptop, now).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(laptop.content_path(&key)).unwrap(),
+            "secret"
+        );
+    }
+
+    #[test]
+    fn test_encrypted_store_seals_sync_base_across_rotation() {
+        let temp = TempDir::new().unwrap();
+        let config = Config {
+            base_path: temp.path().to_path_buf(),
+        };
+        let mut desktop = KevaCore::open_encrypted(config.clone(), "passphrase").unwrap();
+        let (mut laptop, _l) = create_store();
+        let key = make_key("project/secret-plan");
+        let now = SystemTime::now();
+
+        desktop.create(&key, now).unwrap();
+        desktop.sync(&mut laptop, now).unwrap();
+        for entry in std::fs::read_dir(config.sync_path()).unwrap() {
+            let bytes = std::fs::read(entry.unwrap().path()).unwrap();
+            assert!(!bytes.windows(11).any(|w| w == b"secret-plan"));
+        }
+
+        desktop.rotate_key("passphrase").unwrap();
+        write_content(&laptop, &key, "edited");
+        let outcome = desktop.sync(&mut laptop, now).unwrap();
+
+        // The base is still readable, so the edit is a one-sided change and not a conflict
+        assert_eq!(outcome.pulled, vec![key.clone()]);
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(read_content(&desktop, &key), "edited");
+    }
+
+    #[test]
+    fn test_sealed_key_syncs_as_ciphertext() {
+        let (mut desktop, _d) = create_store();
+        let (mut laptop, _l) = create_store();
+        let key = make_key("diary");
+        let now = SystemTime::now();
+
+        desktop.create(&key, now).unwrap();
+        write_content(&desktop, &key, "dear diary");
+        add_attachment(&mut desktop, &key, "a.txt", b"attached");
+        desktop.seal(&key, "pin").unwrap();
+
+        desktop.sync(&mut laptop, now).unwrap();
+
+        let value = laptop.get(&key).unwrap().unwrap();
+        assert_eq!(value.metadata.seal, SealState::Locked);
+        assert!(matches!(laptop.open_content(&key), Err(KevaError::Locked)));
+
+        laptop.unlock(&key, "pin", SystemTime::now()).unwrap();
+        assert_eq!(read_content(&laptop, &key), "dear diary");
+        assert_eq!(laptop.get(&key).unwrap().unwrap().attachments.len(), 1);
+    }
+}
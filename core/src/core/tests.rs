@@ -24,6 +24,7 @@ mod common {
         GcConfig {
             trash_ttl: Duration::from_secs(trash_ttl_secs),
             purge_ttl: Duration::from_secs(purge_ttl_secs),
+            oplog_retention: Duration::from_secs(30 * 24 * 60 * 60),
         }
     }
 
@@ -31,6 +32,21 @@ mod common {
         Key::try_from(s).unwrap()
     }
 
+    pub(super) fn name(s: &str) -> AttachmentName {
+        AttachmentName::try_from(s).unwrap()
+    }
+
+    pub(super) fn overwrite(
+        source: PathBuf,
+        filename: &str,
+    ) -> (PathBuf, AttachmentName, AttachmentConflictResolution) {
+        (
+            source,
+            name(filename),
+            AttachmentConflictResolution::Overwrite,
+        )
+    }
+
     pub(super) fn create_test_file(dir: &TempDir, name: &str, content: &[u8]) -> PathBuf {
         let path = dir.path().join(name);
         let mut file = std::fs::File::create(&path).unwrap();
@@ -176,6 +192,29 @@ mod touch {
         );
     }
 
+    #[test]
+    fn test_touch_counts_accesses() {
+        let (mut storage, _temp) = create_test_storage();
+        let key = make_key("test/key");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage.touch(&key, now).unwrap();
+        let value = storage.touch(&key, now).unwrap();
+
+        assert_eq!(value.metadata.access_count, 2);
+        assert_eq!(
+            storage.access_stats().unwrap(),
+            vec![(
+                key,
+                AccessStats {
+                    last_accessed: now,
+                    access_count: 2,
+                }
+            )]
+        );
+    }
+
     #[test]
     fn test_touch_nonexistent_key_fails() {
         let (mut storage, _temp) = create_test_storage();
@@ -217,7 +256,7 @@ mod add_attachments {
 
         storage.create(&key, now).unwrap();
         storage
-            .add_attachments(&key, vec![(file_path, "test.txt".into())], now)
+            .add_attachments(&key, vec![overwrite(file_path, "test.txt")], now)
             .unwrap();
 
         let value = storage.get(&key).unwrap().unwrap();
@@ -238,7 +277,7 @@ mod add_attachments {
         storage
             .add_attachments(
                 &key,
-                vec![(file1, "file1.txt".into()), (file2, "file2.txt".into())],
+                vec![overwrite(file1, "file1.txt"), overwrite(file2, "file2.txt")],
                 now,
             )
             .unwrap();
@@ -254,7 +293,7 @@ mod add_attachments {
         let file_path = create_test_file(&temp, "test.txt", b"content");
         let now = SystemTime::now();
 
-        let result = storage.add_attachments(&key, vec![(file_path, "test.txt".into())], now);
+        let result = storage.add_attachments(&key, vec![overwrite(file_path, "test.txt")], now);
         assert!(matches!(
             result,
             Err(KevaError::Database(DatabaseError::NotFound))
@@ -270,7 +309,7 @@ mod add_attachments {
 
         storage.create(&key, now).unwrap();
         storage
-            .add_attachments(&key, vec![(file_path, "renamed.txt".into())], now)
+            .add_attachments(&key, vec![overwrite(file_path, "renamed.txt")], now)
             .unwrap();
 
         let value = storage.get(&key).unwrap().unwrap();
@@ -288,12 +327,12 @@ mod add_attachments {
 
         let file1 = create_test_file(&temp, "first.txt", b"first");
         storage
-            .add_attachments(&key, vec![(file1, "same.txt".into())], now)
+            .add_attachments(&key, vec![overwrite(file1, "same.txt")], now)
             .unwrap();
 
         let file2 = create_test_file(&temp, "second.txt", b"second content");
         storage
-            .add_attachments(&key, vec![(file2, "same.txt".into())], now)
+            .add_attachments(&key, vec![overwrite(file2, "same.txt")], now)
             .unwrap();
 
         let value = storage.get(&key).unwrap().unwrap();
@@ -311,16 +350,393 @@ mod add_attachments {
 
         storage.create(&key, now).unwrap();
         storage
-            .add_attachments(&key, vec![(file_path, "test.txt".into())], now)
+            .add_attachments(&key, vec![overwrite(file_path, "test.txt")], now)
             .unwrap();
 
-        let attachment_path = storage.attachment_path(&key, "test.txt");
+        let attachment_path = storage.attachment_path(&key, "test.txt").unwrap();
         assert!(attachment_path.exists());
         assert_eq!(
             std::fs::read_to_string(&attachment_path).unwrap(),
             "file content"
         );
     }
+
+    #[test]
+    fn test_image_metadata_is_stored() {
+        let temp = TempDir::new().unwrap();
+        let key = make_key("test/key");
+        let image_path = temp.path().join("photo.png");
+        image::RgbImage::new(40, 30).save(&image_path).unwrap();
+        let text_path = create_test_file(&temp, "notes.txt", b"hello");
+        let now = SystemTime::now();
+        let config = Config {
+            base_path: temp.path().join("store"),
+        };
+
+        let mut storage = KevaCore::open(config.clone()).unwrap();
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(
+                &key,
+                vec![
+                    overwrite(image_path, "photo.png"),
+                    overwrite(text_path, "notes.txt"),
+                ],
+                now,
+            )
+            .unwrap();
+        drop(storage);
+
+        let storage = KevaCore::open(config).unwrap();
+        let value = storage.get(&key).unwrap().unwrap();
+        let image = value.attachments[0].image.as_ref().unwrap();
+        assert_eq!((image.width, image.height), (40, 30));
+        assert_eq!(image.captured_at, None);
+        assert_eq!(value.attachments[1].image, None);
+    }
+
+    #[test]
+    fn test_fingerprint_and_timestamps_are_stored() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let pdf = create_test_file(&temp, "scan.dat", b"%PDF-1.7\n...");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(pdf, "scan.txt")], now)
+            .unwrap();
+
+        let value = storage.get(&key).unwrap().unwrap();
+        let attachment = &value.attachments[0];
+        assert_eq!(attachment.mime.as_deref(), Some("application/pdf"));
+        assert_eq!(attachment.hash, Some(blake3::hash(b"%PDF-1.7\n...")));
+        assert_eq!(attachment.added_at, Some(now));
+        assert_eq!(attachment.modified_at, Some(now));
+    }
+
+    #[test]
+    fn test_overwrite_keeps_added_at() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let first = create_test_file(&temp, "v1.txt", b"first");
+        let second = create_test_file(&temp, "v2.txt", b"second");
+        let t1 = SystemTime::now();
+        let t2 = t1 + Duration::from_secs(60);
+
+        storage.create(&key, t1).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(first, "notes.txt")], t1)
+            .unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(second, "notes.txt")], t2)
+            .unwrap();
+
+        let value = storage.get(&key).unwrap().unwrap();
+        assert_eq!(value.attachments[0].added_at, Some(t1));
+        assert_eq!(value.attachments[0].modified_at, Some(t2));
+        assert_eq!(value.attachments[0].hash, Some(blake3::hash(b"second")));
+    }
+
+    #[test]
+    fn test_identical_reattach_is_skipped() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let file = create_test_file(&temp, "a.txt", b"same");
+        let t1 = SystemTime::now();
+        let t2 = t1 + Duration::from_secs(60);
+
+        storage.create(&key, t1).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(file.clone(), "a.txt")], t1)
+            .unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(file, "a.txt")], t2)
+            .unwrap();
+
+        let value = storage.get(&key).unwrap().unwrap();
+        assert_eq!(value.attachments.len(), 1);
+        assert_eq!(value.attachments[0].modified_at, Some(t1));
+        // Nothing was overwritten, so there is nothing to undo
+        assert_eq!(storage.next_undo(), None);
+    }
+
+    #[test]
+    fn test_add_directory_fails() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        let result = storage.add_attachments(&key, vec![overwrite(temp.path().into(), "dir")], now);
+
+        assert!(matches!(
+            result,
+            Err(KevaError::FileStorage(FileStorageError::IsDirectory))
+        ));
+        assert!(storage.get(&key).unwrap().unwrap().attachments.is_empty());
+    }
+
+    #[test]
+    fn test_conflict_rename_keeps_both() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let first = create_test_file(&temp, "first.pdf", b"first");
+        let second = create_test_file(&temp, "second.pdf", b"second");
+        let third = create_test_file(&temp, "third.pdf", b"third");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(first, "report.pdf")], now)
+            .unwrap();
+        let rename = AttachmentConflictResolution::Rename;
+        let added = storage
+            .add_attachments(
+                &key,
+                vec![
+                    (second, name("report.pdf"), rename),
+                    (third, name("report.pdf"), rename),
+                ],
+                now,
+            )
+            .unwrap();
+
+        assert_eq!(
+            added,
+            vec![Some(name("report (1).pdf")), Some(name("report (2).pdf"))]
+        );
+        let value = storage.get(&key).unwrap().unwrap();
+        let mut names: Vec<_> = value
+            .attachments
+            .iter()
+            .map(|a| a.filename.as_str())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["report (1).pdf", "report (2).pdf", "report.pdf"]);
+    }
+
+    #[test]
+    fn test_conflict_skip_keeps_existing() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let first = create_test_file(&temp, "first.txt", b"first");
+        let second = create_test_file(&temp, "second.txt", b"second content");
+        let other = create_test_file(&temp, "other.txt", b"other");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(first, "a.txt")], now)
+            .unwrap();
+        let skip = AttachmentConflictResolution::Skip;
+        let added = storage
+            .add_attachments(
+                &key,
+                vec![(second, name("a.txt"), skip), (other, name("b.txt"), skip)],
+                now,
+            )
+            .unwrap();
+
+        assert_eq!(added, vec![None, Some(name("b.txt"))]);
+        let value = storage.get(&key).unwrap().unwrap();
+        let a = value.attachments.iter().find(|a| a.filename == "a.txt");
+        assert_eq!(a.unwrap().size, 5);
+    }
+
+    #[test]
+    fn test_conflict_fail_adds_nothing() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let first = create_test_file(&temp, "first.txt", b"first");
+        let other = create_test_file(&temp, "other.txt", b"other");
+        let second = create_test_file(&temp, "second.txt", b"second");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(first, "a.txt")], now)
+            .unwrap();
+        let fail = AttachmentConflictResolution::Fail;
+        let result = storage.add_attachments(
+            &key,
+            vec![(other, name("b.txt"), fail), (second, name("a.txt"), fail)],
+            now,
+        );
+
+        assert!(matches!(
+            result,
+            Err(KevaError::Database(DatabaseError::AttachmentExists(ref n))) if n == "a.txt"
+        ));
+        assert_eq!(storage.get(&key).unwrap().unwrap().attachments.len(), 1);
+    }
+
+    #[test]
+    fn test_conflict_within_one_call() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let first = create_test_file(&temp, "first.txt", b"first");
+        let second = create_test_file(&temp, "second.txt", b"second");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        let added = storage
+            .add_attachments(
+                &key,
+                vec![
+                    (first, name("a.txt"), AttachmentConflictResolution::Fail),
+                    (second, name("a.txt"), AttachmentConflictResolution::Rename),
+                ],
+                now,
+            )
+            .unwrap();
+
+        assert_eq!(added, vec![Some(name("a.txt")), Some(name("a (1).txt"))]);
+    }
+
+    #[test]
+    fn test_identical_attachment() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let file = create_test_file(&temp, "a.txt", b"same");
+        let copy = create_test_file(&temp, "copy.txt", b"same");
+        let other = create_test_file(&temp, "other.txt", b"different");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(file, "a.txt")], now)
+            .unwrap();
+
+        assert_eq!(
+            storage.identical_attachment(&key, &copy).unwrap(),
+            Some("a.txt".to_string())
+        );
+        assert_eq!(storage.identical_attachment(&key, &other).unwrap(), None);
+    }
+}
+
+mod add_attachment_from_reader {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    /// Yields `data`, then fails as a dropped upload would.
+    struct FailingReader {
+        data: Cursor<Vec<u8>>,
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.data.read(buf)? {
+                0 => Err(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionReset,
+                    "upload interrupted",
+                )),
+                n => Ok(n),
+            }
+        }
+    }
+
+    #[test]
+    fn test_streams_reader_into_attachment() {
+        let (mut storage, _temp) = create_test_storage();
+        let key = make_key("test/key");
+        let data = vec![b'x'; 200_000];
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        let fingerprint = storage
+            .add_attachment_from_reader(&key, &name("big.txt"), Cursor::new(data.clone()), now)
+            .unwrap();
+
+        assert_eq!(fingerprint.size, 200_000);
+        assert_eq!(fingerprint.hash, blake3::hash(&data));
+        assert_eq!(fingerprint.mime, "text/plain; charset=utf-8");
+        let value = storage.get(&key).unwrap().unwrap();
+        assert_eq!(value.attachments[0].size, 200_000);
+        assert_eq!(value.attachments[0].hash, Some(fingerprint.hash));
+        let mut stored = Vec::new();
+        storage
+            .open_attachment(&key, "big.txt")
+            .unwrap()
+            .read_to_end(&mut stored)
+            .unwrap();
+        assert_eq!(stored, data);
+    }
+
+    #[test]
+    fn test_failed_read_keeps_existing_attachment() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let file = create_test_file(&temp, "a.txt", b"original");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(file, "a.txt")], now)
+            .unwrap();
+
+        let reader = FailingReader {
+            data: Cursor::new(b"partial".to_vec()),
+        };
+        let result = storage.add_attachment_from_reader(&key, &name("a.txt"), reader, now);
+        assert!(matches!(result, Err(KevaError::FileStorage(_))));
+
+        let path = storage.attachment_path(&key, "a.txt").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"original");
+        let entries: Vec<_> = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, ["a.txt"]);
+        let value = storage.get(&key).unwrap().unwrap();
+        assert_eq!(value.attachments[0].hash, Some(blake3::hash(b"original")));
+        assert_eq!(storage.next_undo(), None);
+    }
+
+    #[test]
+    fn test_staging_does_not_touch_tmp_named_attachment() {
+        let (mut storage, _temp) = create_test_storage();
+        let key = make_key("test/key");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachment_from_reader(&key, &name("a.pdf.tmp"), &b"first"[..], now)
+            .unwrap();
+        storage
+            .add_attachment_from_reader(&key, &name("a.pdf"), &b"second"[..], now)
+            .unwrap();
+
+        for (filename, expected) in [("a.pdf.tmp", b"first".as_slice()), ("a.pdf", b"second")] {
+            let mut stored = Vec::new();
+            storage
+                .open_attachment(&key, filename)
+                .unwrap()
+                .read_to_end(&mut stored)
+                .unwrap();
+            assert_eq!(stored, expected);
+        }
+    }
+
+    #[test]
+    fn test_missing_key_writes_nothing() {
+        let (mut storage, _temp) = create_test_storage();
+        let key = make_key("missing");
+
+        let result = storage.add_attachment_from_reader(
+            &key,
+            &name("a.txt"),
+            &b"data"[..],
+            SystemTime::now(),
+        );
+        assert!(matches!(
+            result,
+            Err(KevaError::Database(DatabaseError::NotFound))
+        ));
+        let key_hash = KevaCore::key_to_path(&key);
+        assert!(!storage.file.attachment_path(&key_hash, "a.txt").exists());
+    }
 }
 
 mod remove_attachment {
@@ -335,7 +751,7 @@ mod remove_attachment {
 
         storage.create(&key, now).unwrap();
         storage
-            .add_attachments(&key, vec![(file_path, "test.txt".into())], now)
+            .add_attachments(&key, vec![overwrite(file_path, "test.txt")], now)
             .unwrap();
 
         let later = now + Duration::from_secs(1);
@@ -354,10 +770,10 @@ mod remove_attachment {
 
         storage.create(&key, now).unwrap();
         storage
-            .add_attachments(&key, vec![(file_path, "test.txt".into())], now)
+            .add_attachments(&key, vec![overwrite(file_path, "test.txt")], now)
             .unwrap();
 
-        let attachment_path = storage.attachment_path(&key, "test.txt");
+        let attachment_path = storage.attachment_path(&key, "test.txt").unwrap();
         assert!(attachment_path.exists());
 
         storage.remove_attachment(&key, "test.txt", now).unwrap();
@@ -392,11 +808,11 @@ mod rename_attachment {
 
         storage.create(&key, now).unwrap();
         storage
-            .add_attachments(&key, vec![(file_path, "old.txt".into())], now)
+            .add_attachments(&key, vec![overwrite(file_path, "old.txt")], now)
             .unwrap();
 
         storage
-            .rename_attachment(&key, "old.txt", "new.txt", now)
+            .rename_attachment(&key, "old.txt", &name("new.txt"), now)
             .unwrap();
 
         let value = storage.get(&key).unwrap().unwrap();
@@ -404,12 +820,45 @@ mod rename_attachment {
         assert_eq!(value.attachments[0].filename, "new.txt");
 
         // Old path should not exist, new path should
-        let old_path = storage.attachment_path(&key, "old.txt");
-        let new_path = storage.attachment_path(&key, "new.txt");
+        let old_path = storage
+            .file
+            .attachment_path(&KevaCore::key_to_path(&key), "old.txt");
+        let new_path = storage.attachment_path(&key, "new.txt").unwrap();
         assert!(!old_path.exists());
         assert!(new_path.exists());
     }
 
+    #[test]
+    fn test_unknown_attachment_is_not_opened() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let file_path = create_test_file(&temp, "a.txt", b"data");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(file_path, "a.txt")], now)
+            .unwrap();
+        // A file beside the attachments that isn't one of them
+        let key_hash = KevaCore::key_to_path(&key);
+        std::fs::write(storage.file.attachment_path(&key_hash, "stray.txt"), b"x").unwrap();
+
+        for filename in ["stray.txt", "../a.txt", ""] {
+            assert!(matches!(
+                storage.open_attachment(&key, filename),
+                Err(KevaError::Database(DatabaseError::AttachmentNotFound(_)))
+            ));
+            assert!(matches!(
+                storage.attachment_path(&key, filename),
+                Err(KevaError::Database(DatabaseError::AttachmentNotFound(_)))
+            ));
+        }
+        assert!(matches!(
+            storage.open_attachment(&make_key("missing"), "a.txt"),
+            Err(KevaError::Database(DatabaseError::NotFound))
+        ));
+    }
+
     #[test]
     fn test_rename_nonexistent_attachment_fails() {
         let (mut storage, _temp) = create_test_storage();
@@ -418,7 +867,7 @@ mod rename_attachment {
 
         storage.create(&key, now).unwrap();
 
-        let result = storage.rename_attachment(&key, "nonexistent.txt", "new.txt", now);
+        let result = storage.rename_attachment(&key, "nonexistent.txt", &name("new.txt"), now);
         assert!(matches!(
             result,
             Err(KevaError::Database(DatabaseError::AttachmentNotFound(_)))
@@ -437,13 +886,13 @@ mod rename_attachment {
         storage
             .add_attachments(
                 &key,
-                vec![(file_a, "a.txt".into()), (file_b, "b.txt".into())],
+                vec![overwrite(file_a, "a.txt"), overwrite(file_b, "b.txt")],
                 now,
             )
             .unwrap();
 
         // Rename a.txt -> b.txt should fail (destination exists)
-        let result = storage.rename_attachment(&key, "a.txt", "b.txt", now);
+        let result = storage.rename_attachment(&key, "a.txt", &name("b.txt"), now);
         assert!(matches!(result, Err(KevaError::DestinationExists)));
 
         // Both files should still exist unchanged
@@ -460,11 +909,11 @@ mod rename_attachment {
 
         storage.create(&key, now).unwrap();
         storage
-            .add_attachments(&key, vec![(file_path, "a.txt".into())], now)
+            .add_attachments(&key, vec![overwrite(file_path, "a.txt")], now)
             .unwrap();
 
         storage
-            .rename_attachment(&key, "a.txt", "a.txt", now)
+            .rename_attachment(&key, "a.txt", &name("a.txt"), now)
             .unwrap();
 
         let value = storage.get(&key).unwrap().unwrap();
@@ -575,16 +1024,16 @@ mod rename {
 
         storage.create(&old_key, now).unwrap();
         storage
-            .add_attachments(&old_key, vec![(file_path, "test.txt".into())], now)
+            .add_attachments(&old_key, vec![overwrite(file_path, "test.txt")], now)
             .unwrap();
 
-        let old_attachment_path = storage.attachment_path(&old_key, "test.txt");
+        let old_attachment_path = storage.attachment_path(&old_key, "test.txt").unwrap();
         assert!(old_attachment_path.exists());
 
         storage.rename(&old_key, &new_key, now).unwrap();
 
         assert!(!old_attachment_path.exists());
-        let new_attachment_path = storage.attachment_path(&new_key, "test.txt");
+        let new_attachment_path = storage.attachment_path(&new_key, "test.txt").unwrap();
         assert!(new_attachment_path.exists());
     }
 
@@ -751,10 +1200,10 @@ mod purge {
 
         storage.create(&key, now).unwrap();
         storage
-            .add_attachments(&key, vec![(file_path, "test.txt".into())], now)
+            .add_attachments(&key, vec![overwrite(file_path, "test.txt")], now)
             .unwrap();
 
-        let attachment_path = storage.attachment_path(&key, "test.txt");
+        let attachment_path = storage.attachment_path(&key, "test.txt").unwrap();
         assert!(attachment_path.exists());
 
         storage.purge(&key).unwrap();
@@ -778,30 +1227,80 @@ mod maintenance {
     use super::*;
 
     #[test]
-    fn test_maintenance_purges_expired_trash_keys() {
+    fn test_maintenance_compacts_oplog() {
         let (mut storage, _temp) = create_test_storage();
-        let gc_config = make_gc_config(10, 5);
-        let key = make_key("key");
+        let mut gc_config = make_gc_config(1000, 1000);
+        gc_config.oplog_retention = Duration::from_secs(10);
         let now = SystemTime::now();
 
-        storage.create(&key, now).unwrap();
-        storage.trash(&key, now).unwrap();
+        storage.create(&make_key("old"), now).unwrap();
+        storage
+            .create(&make_key("new"), now + Duration::from_secs(15))
+            .unwrap();
 
-        let after_ttl = now + Duration::from_secs(6);
-        let result = storage.maintenance(after_ttl, gc_config).unwrap();
+        let result = storage
+            .maintenance(now + Duration::from_secs(20), gc_config)
+            .unwrap();
+        assert_eq!(result.oplog_entries_compacted, 1);
 
-        assert!(result.keys_purged.contains(&key));
-        assert!(result.keys_trashed.is_empty());
+        let entries = storage.oplog_since(1, 10).unwrap();
+        assert_eq!(
+            entries[0].op,
+            Operation::Created {
+                key: make_key("new")
+            }
+        );
+        assert!(matches!(
+            storage.oplog_since(0, 10),
+            Err(KevaError::Database(DatabaseError::OplogCompacted { .. }))
+        ));
     }
 
     #[test]
-    fn test_maintenance_trashes_expired_active_keys() {
+    fn test_content_writes_are_logged() {
         let (mut storage, _temp) = create_test_storage();
-        let gc_config = make_gc_config(10, 5);
         let key = make_key("key");
-        let now = SystemTime::now();
+        storage.create(&key, SystemTime::now()).unwrap();
 
-        storage.create(&key, now).unwrap();
+        let mut writer = storage.write_content(&key).unwrap();
+        writer.write_all(b"draft").unwrap();
+        assert_eq!(storage.last_seq().unwrap(), 1);
+        writer.finish().unwrap();
+
+        let entries = storage.oplog_since(1, 10).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].op, Operation::ContentWritten { key });
+
+        // An abandoned write changes nothing and isn't logged
+        drop(storage.write_content(&make_key("key")).unwrap());
+        assert_eq!(storage.last_seq().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_maintenance_purges_expired_trash_keys() {
+        let (mut storage, _temp) = create_test_storage();
+        let gc_config = make_gc_config(10, 5);
+        let key = make_key("key");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage.trash(&key, now).unwrap();
+
+        let after_ttl = now + Duration::from_secs(6);
+        let result = storage.maintenance(after_ttl, gc_config).unwrap();
+
+        assert!(result.keys_purged.contains(&key));
+        assert!(result.keys_trashed.is_empty());
+    }
+
+    #[test]
+    fn test_maintenance_trashes_expired_active_keys() {
+        let (mut storage, _temp) = create_test_storage();
+        let gc_config = make_gc_config(10, 5);
+        let key = make_key("key");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
 
         let after_ttl = now + Duration::from_secs(11);
         let result = storage.maintenance(after_ttl, gc_config).unwrap();
@@ -823,11 +1322,11 @@ mod maintenance {
 
         storage.create(&key, now).unwrap();
         storage
-            .add_attachments(&key, vec![(file_path, "test.txt".into())], now)
+            .add_attachments(&key, vec![overwrite(file_path, "test.txt")], now)
             .unwrap();
 
         let content_path = storage.content_path(&key);
-        let attachment_path = storage.attachment_path(&key, "test.txt");
+        let attachment_path = storage.attachment_path(&key, "test.txt").unwrap();
         assert!(content_path.exists());
         assert!(attachment_path.exists());
 
@@ -870,7 +1369,7 @@ mod maintenance {
 
         storage.create(&key, now).unwrap();
         storage
-            .add_attachments(&key, vec![(file_path, "test.txt".into())], now)
+            .add_attachments(&key, vec![overwrite(file_path, "test.txt")], now)
             .unwrap();
 
         let key_hash = KevaCore::key_to_path(&key);
@@ -918,48 +1417,2367 @@ mod maintenance {
     }
 }
 
-mod thumbnail {
+mod key_tree {
     use super::*;
 
+    fn create_keys(storage: &mut KevaCore, keys: &[&str], now: SystemTime) {
+        for k in keys {
+            storage.create(&make_key(k), now).unwrap();
+        }
+    }
+
     #[test]
-    fn test_thumbnail_paths_excludes_unsupported_formats() {
+    fn test_children_of_root() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        create_keys(&mut storage, &["a", "a/b", "a/c/d", "b-x", "c"], now);
+
+        let children = storage.children("").unwrap();
+        let summary: Vec<_> = children
+            .iter()
+            .map(|n| (n.name.as_str(), n.is_key, n.descendant_count))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("a", true, 2), ("b-x", true, 0), ("c", true, 0)]
+        );
+    }
+
+    #[test]
+    fn test_children_groups_non_contiguous_keys() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        // "p/b-x" sorts between "p/b" and "p/b/c"
+        create_keys(&mut storage, &["p/b", "p/b-x", "p/b/c"], now);
+
+        let children = storage.children("p").unwrap();
+        assert_eq!(
+            children,
+            vec![
+                KeyTreeNode {
+                    path: "p/b".to_string(),
+                    name: "b".to_string(),
+                    is_key: true,
+                    descendant_count: 1,
+                },
+                KeyTreeNode {
+                    path: "p/b-x".to_string(),
+                    name: "b-x".to_string(),
+                    is_key: true,
+                    descendant_count: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_children_intermediate_node() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        create_keys(&mut storage, &["x/y/z"], now);
+
+        let children = storage.children("x").unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].path, "x/y");
+        assert!(!children[0].is_key);
+        assert_eq!(children[0].descendant_count, 1);
+    }
+
+    #[test]
+    fn test_children_ignores_trashed() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        create_keys(&mut storage, &["a/1", "a/2"], now);
+        storage.trash(&make_key("a/2"), now).unwrap();
+
+        let children = storage.children("a").unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].name, "1");
+    }
+
+    #[test]
+    fn test_descendant_count() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        create_keys(&mut storage, &["a", "a/b", "a/b/c", "ab"], now);
+
+        assert_eq!(storage.descendant_count("a").unwrap(), 2);
+        assert_eq!(storage.descendant_count("a/b").unwrap(), 1);
+        assert_eq!(storage.descendant_count("").unwrap(), 4);
+        assert_eq!(storage.descendant_count("missing").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_rename_subtree_moves_keys_and_files() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        create_keys(&mut storage, &["a", "a/b", "ab"], now);
+        std::fs::write(storage.content_path(&make_key("a/b")), "child").unwrap();
+
+        let moved = storage
+            .rename_subtree(&make_key("a"), &make_key("z"), now)
+            .unwrap();
+
+        assert_eq!(
+            moved,
+            vec![
+                (make_key("a"), make_key("z")),
+                (make_key("a/b"), make_key("z/b")),
+            ]
+        );
+        assert!(storage.get(&make_key("a/b")).unwrap().is_none());
+        assert!(storage.get(&make_key("ab")).unwrap().is_some());
+        assert_eq!(
+            std::fs::read_to_string(storage.content_path(&make_key("z/b"))).unwrap(),
+            "child"
+        );
+    }
+
+    #[test]
+    fn test_rename_subtree_into_itself() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        create_keys(&mut storage, &["a", "a/a"], now);
+        std::fs::write(storage.content_path(&make_key("a")), "parent").unwrap();
+        std::fs::write(storage.content_path(&make_key("a/a")), "child").unwrap();
+
+        storage
+            .rename_subtree(&make_key("a"), &make_key("a/a"), now)
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(storage.content_path(&make_key("a/a"))).unwrap(),
+            "parent"
+        );
+        assert_eq!(
+            std::fs::read_to_string(storage.content_path(&make_key("a/a/a"))).unwrap(),
+            "child"
+        );
+    }
+
+    #[test]
+    fn test_rename_subtree_destination_exists() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        create_keys(&mut storage, &["a/b", "z/b"], now);
+
+        let result = storage.rename_subtree(&make_key("a"), &make_key("z"), now);
+        assert!(matches!(result, Err(KevaError::DestinationExists)));
+        assert!(storage.get(&make_key("a/b")).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_rename_subtree_too_long() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        create_keys(&mut storage, &["a/b"], now);
+
+        let long = make_key(&"x".repeat(crate::types::MAX_KEY_LENGTH));
+        let result = storage.rename_subtree(&make_key("a"), &long, now);
+        assert!(matches!(result, Err(KevaError::InvalidKey(_))));
+    }
+
+    #[test]
+    fn test_trash_and_restore_subtree() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        create_keys(&mut storage, &["a", "a/b", "ab"], now);
+
+        let trashed = storage.trash_subtree(&make_key("a"), now).unwrap();
+        assert_eq!(trashed, vec![make_key("a"), make_key("a/b")]);
+        assert_eq!(storage.active_keys().unwrap(), vec![make_key("ab")]);
+
+        let restored = storage.restore_subtree(&make_key("a"), now).unwrap();
+        assert_eq!(restored, trashed);
+        assert_eq!(storage.active_keys().unwrap().len(), 3);
+    }
+}
+
+mod batch {
+    use super::*;
+
+    #[test]
+    fn test_batch_returns_changes_in_order() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        let a = make_key("a");
+        let b = make_key("b");
+
+        let ((), changes) = storage
+            .batch(|tx| {
+                tx.create(&a, now)?;
+                tx.touch(&a, now)?;
+                tx.rename(&a, &b, now)?;
+                tx.trash(&b, now)?;
+                tx.restore(&b, now)?;
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(
+            changes,
+            vec![
+                Change::Created(a.clone()),
+                Change::Touched(a.clone()),
+                Change::Renamed {
+                    from: a.clone(),
+                    to: b.clone()
+                },
+                Change::Trashed(b.clone()),
+                Change::Restored(b.clone()),
+            ]
+        );
+        assert!(storage.get(&a).unwrap().is_none());
+        assert!(storage.content_path(&b).exists());
+        assert_eq!(storage.active_keys().unwrap(), vec![b]);
+    }
+
+    #[test]
+    fn test_batch_error_rolls_back() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        let a = make_key("a");
+
+        let result = storage.batch(|tx| {
+            tx.create(&a, now)?;
+            tx.trash(&make_key("missing"), now)
+        });
+
+        assert!(matches!(
+            result,
+            Err(KevaError::Database(DatabaseError::NotFound))
+        ));
+        assert!(storage.get(&a).unwrap().is_none());
+        assert!(!storage.content_path(&a).exists());
+    }
+
+    #[test]
+    fn test_batch_defers_file_operations() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        let a = make_key("a");
+        let content_path = storage.content_path(&a);
+
+        storage
+            .batch(|tx| {
+                tx.create(&a, now)?;
+                assert!(!content_path.exists());
+                Ok(())
+            })
+            .unwrap();
+
+        assert!(content_path.exists());
+    }
+
+    #[test]
+    fn test_batch_reads_own_changes() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        let a = make_key("a");
+
+        let (found, _) = storage
+            .batch(|tx| {
+                tx.create(&a, now)?;
+                Ok(tx.get(&a)?.is_some())
+            })
+            .unwrap();
+
+        assert!(found);
+    }
+
+    #[test]
+    fn test_batch_purge_removes_files() {
         let (mut storage, temp) = create_test_storage();
-        let key = make_key("test/key");
-        let pdf_path = create_test_file(&temp, "document.pdf", b"pdf content");
         let now = SystemTime::now();
+        let a = make_key("a");
+        let file_path = create_test_file(&temp, "test.txt", b"data");
 
-        storage.create(&key, now).unwrap();
+        storage.create(&a, now).unwrap();
         storage
-            .add_attachments(&key, vec![(pdf_path, "document.pdf".into())], now)
+            .add_attachments(&a, vec![overwrite(file_path, "test.txt")], now)
             .unwrap();
+        let blob_path = storage.attachment_path(&a, "test.txt").unwrap();
+        assert!(blob_path.exists());
 
-        let paths = storage.thumbnail_paths(&key).unwrap();
-        // PDF is not a supported image format, so no thumbnail
-        assert!(paths.is_empty());
+        let (_, changes) = storage.batch(|tx| tx.purge(&a)).unwrap();
+
+        assert_eq!(changes, vec![Change::Purged(a.clone())]);
+        assert!(!blob_path.exists());
+        assert!(!storage.content_path(&a).exists());
     }
 
     #[test]
-    fn test_thumbnail_paths_empty_for_no_attachments() {
+    fn test_batch_purge_frees_store_size() {
         let (mut storage, _temp) = create_test_storage();
-        let key = make_key("test/key");
         let now = SystemTime::now();
+        let a = make_key("a");
+        let b = make_key("b");
+        storage.create(&a, now).unwrap();
+        storage.create(&b, now).unwrap();
+        storage.set_quota_config(QuotaConfig {
+            max_attachment_size: None,
+            max_store_size: Some(100),
+        });
+        storage
+            .add_attachment_from_reader(&a, &name("a.bin"), std::io::Cursor::new([1; 80]), now)
+            .unwrap();
 
-        storage.create(&key, now).unwrap();
+        storage.batch(|tx| tx.purge(&a)).unwrap();
 
-        let paths = storage.thumbnail_paths(&key).unwrap();
-        assert!(paths.is_empty());
+        storage
+            .add_attachment_from_reader(&b, &name("b.bin"), std::io::Cursor::new([2; 80]), now)
+            .unwrap();
     }
 
     #[test]
-    fn test_new_key_starts_with_current_thumb_version() {
+    fn test_batch_applies_every_file_operation() {
         let (mut storage, _temp) = create_test_storage();
-        let key = make_key("test/key");
+        let now = SystemTime::now();
+        let a = make_key("a");
+        let b = make_key("b");
+        // A directory in the way of a's content file
+        std::fs::create_dir_all(storage.content_path(&a)).unwrap();
+
+        let result = storage.batch(|tx| {
+            tx.create(&a, now)?;
+            tx.create(&b, now)?;
+            Ok(())
+        });
+
+        match result {
+            Err(KevaError::FilesNotUpdated(errors)) => assert_eq!(errors.len(), 1),
+            other => panic!("expected FilesNotUpdated, got {other:?}"),
+        }
+        assert_eq!(storage.active_keys().unwrap().len(), 2);
+        assert!(storage.content_path(&b).is_file());
+    }
+
+    #[test]
+    fn test_batch_rename_destination_exists() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+
+        let result = storage.batch(|tx| {
+            tx.create(&make_key("a"), now)?;
+            tx.create(&make_key("b"), now)?;
+            tx.rename(&make_key("a"), &make_key("b"), now)
+        });
+
+        assert!(matches!(result, Err(KevaError::DestinationExists)));
+        assert!(storage.active_keys().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_batch_bumps_generation_once() {
+        let (mut storage, _temp) = create_test_storage();
+        let now = SystemTime::now();
+        let before = storage.generation().unwrap();
+
+        storage
+            .batch(|tx| {
+                for i in 0..10 {
+                    tx.create(&make_key(&format!("k{i}")), now)?;
+                }
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(storage.generation().unwrap(), before + 1);
+        assert_eq!(storage.active_keys().unwrap().len(), 10);
+    }
+}
+
+mod encryption {
+    use super::*;
+    use crate::core::crypto::error::CryptoError;
+    use std::io::{Cursor, Read};
+
+    fn create_encrypted_storage(temp: &TempDir) -> KevaCore {
+        let config = Config {
+            base_path: temp.path().join("store"),
+        };
+        KevaCore::open_encrypted(config, "passphrase").unwrap()
+    }
+
+    fn reopen(temp: &TempDir, passphrase: &str) -> Result<KevaCore, KevaError> {
+        let config = Config {
+            base_path: temp.path().join("store"),
+        };
+        KevaCore::open_encrypted(config, passphrase)
+    }
+
+    fn read_all(mut reader: impl Read) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    fn test_content_roundtrip_is_encrypted_at_rest() {
+        let temp = TempDir::new().unwrap();
+        let mut storage = create_encrypted_storage(&temp);
+        let key = make_key("secret/note");
         let now = SystemTime::now();
 
         storage.create(&key, now).unwrap();
+        assert!(storage.is_encrypted());
+        assert!(read_all(storage.open_content(&key).unwrap()).is_empty());
+
+        let mut writer = storage.write_content(&key).unwrap();
+        writer.write_all(b"my password is hunter2").unwrap();
+        writer.finish().unwrap();
+
+        let raw = std::fs::read(storage.content_path(&key)).unwrap();
+        assert!(!contains(&raw, b"hunter2"));
+        assert_eq!(
+            read_all(storage.open_content(&key).unwrap()),
+            b"my password is hunter2"
+        );
+    }
+
+    #[test]
+    fn test_attachments_and_values_encrypted_at_rest() {
+        let temp = TempDir::new().unwrap();
+        let mut storage = create_encrypted_storage(&temp);
+        let key = make_key("secret/note");
+        let file_path = create_test_file(&temp, "plain.txt", b"attachment body");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(file_path, "recovery-codes.txt")], now)
+            .unwrap();
+
+        let raw =
+            std::fs::read(storage.attachment_path(&key, "recovery-codes.txt").unwrap()).unwrap();
+        assert!(!contains(&raw, b"attachment body"));
+        assert_eq!(
+            read_all(storage.open_attachment(&key, "recovery-codes.txt").unwrap()),
+            b"attachment body"
+        );
 
-        // New keys start with current THUMB_VER (no regeneration needed)
         let value = storage.get(&key).unwrap().unwrap();
-        assert_eq!(value.thumb_version, FileStorage::THUMB_VER);
+        assert_eq!(value.attachments[0].filename, "recovery-codes.txt");
+        drop(storage);
+
+        let db = std::fs::read(temp.path().join("store/keva.redb")).unwrap();
+        assert!(!contains(&db, b"recovery-codes.txt"));
+    }
+
+    #[test]
+    fn test_image_metadata_read_through_cipher() {
+        let temp = TempDir::new().unwrap();
+        let mut storage = create_encrypted_storage(&temp);
+        let key = make_key("secret/photos");
+        let mut png = Cursor::new(Vec::new());
+        image::RgbImage::new(24, 16)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        let fingerprint = storage
+            .add_attachment_from_reader(&key, &name("pic.png"), png.get_ref().as_slice(), now)
+            .unwrap();
+
+        assert_eq!(fingerprint.mime, "image/png");
+        let value = storage.get(&key).unwrap().unwrap();
+        let image = value.attachments[0].image.as_ref().unwrap();
+        assert_eq!((image.width, image.height), (24, 16));
+    }
+
+    #[test]
+    fn test_thumbnail_encrypted_at_rest() {
+        let temp = TempDir::new().unwrap();
+        let mut storage = create_encrypted_storage(&temp);
+        let key = make_key("images");
+        let image_path = temp.path().join("photo.png");
+        image::RgbImage::from_pixel(400, 300, image::Rgb([10, 20, 30]))
+            .save(&image_path)
+            .unwrap();
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(image_path, "photo.png")], now)
+            .unwrap();
+        storage.wait_for_thumbnails();
+
+        let bytes = read_all(
+            storage
+                .open_thumbnail(&key, "photo.png", ThumbnailPreset::Grid, 1)
+                .unwrap(),
+        );
+        let thumb = image::load_from_memory(&bytes).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (200, 150));
+    }
+
+    #[test]
+    fn test_reopen_requires_passphrase() {
+        let temp = TempDir::new().unwrap();
+        let mut storage = create_encrypted_storage(&temp);
+        let key = make_key("k");
+        storage.create(&key, SystemTime::now()).unwrap();
+        drop(storage);
+
+        let config = Config {
+            base_path: temp.path().join("store"),
+        };
+        assert!(matches!(
+            KevaCore::open(config),
+            Err(KevaError::PassphraseRequired)
+        ));
+        assert!(matches!(
+            reopen(&temp, "wrong"),
+            Err(KevaError::Crypto(CryptoError::WrongPassphrase))
+        ));
+
+        let storage = reopen(&temp, "passphrase").unwrap();
+        assert!(storage.get(&key).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_open_encrypted_rejects_plain_store() {
+        let (storage, temp) = create_test_storage();
+        drop(storage);
+
+        let config = Config {
+            base_path: temp.path().to_path_buf(),
+        };
+        let result = KevaCore::open_encrypted(config, "passphrase");
+        assert!(matches!(result, Err(KevaError::NotEncrypted)));
+    }
+
+    #[test]
+    fn test_plain_store_streams_plaintext() {
+        let (mut storage, _temp) = create_test_storage();
+        let key = make_key("k");
+        storage.create(&key, SystemTime::now()).unwrap();
+
+        let mut writer = storage.write_content(&key).unwrap();
+        writer.write_all(b"plain").unwrap();
+        writer.finish().unwrap();
+
+        assert!(!storage.is_encrypted());
+        assert_eq!(std::fs::read(storage.content_path(&key)).unwrap(), b"plain");
+        assert_eq!(read_all(storage.open_content(&key).unwrap()), b"plain");
+    }
+
+    #[test]
+    fn test_unfinished_writer_keeps_old_content() {
+        let temp = TempDir::new().unwrap();
+        let mut storage = create_encrypted_storage(&temp);
+        let key = make_key("k");
+        storage.create(&key, SystemTime::now()).unwrap();
+
+        let mut writer = storage.write_content(&key).unwrap();
+        writer.write_all(b"abandoned").unwrap();
+        drop(writer);
+
+        assert!(read_all(storage.open_content(&key).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_change_passphrase() {
+        let temp = TempDir::new().unwrap();
+        let mut storage = create_encrypted_storage(&temp);
+        let key = make_key("k");
+        storage.create(&key, SystemTime::now()).unwrap();
+
+        assert!(matches!(
+            storage.change_passphrase("wrong", "new"),
+            Err(KevaError::Crypto(CryptoError::WrongPassphrase))
+        ));
+        storage.change_passphrase("passphrase", "new").unwrap();
+        drop(storage);
+
+        assert!(reopen(&temp, "passphrase").is_err());
+        let storage = reopen(&temp, "new").unwrap();
+        assert!(storage.get(&key).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_change_passphrase_requires_encryption() {
+        let (mut storage, _temp) = create_test_storage();
+        assert!(matches!(
+            storage.change_passphrase("a", "b"),
+            Err(KevaError::NotEncrypted)
+        ));
+    }
+
+    #[test]
+    fn test_rotate_key_reencrypts_everything() {
+        let temp = TempDir::new().unwrap();
+        let mut storage = create_encrypted_storage(&temp);
+        let key = make_key("k");
+        let file_path = create_test_file(&temp, "a.txt", b"attachment");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        let mut writer = storage.write_content(&key).unwrap();
+        writer.write_all(b"content").unwrap();
+        writer.finish().unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(file_path, "a.txt")], now)
+            .unwrap();
+
+        let content_before = std::fs::read(storage.content_path(&key)).unwrap();
+        let key_file_before = std::fs::read(temp.path().join("store/keyfile")).unwrap();
+
+        storage.rotate_key("passphrase").unwrap();
+
+        let content_after = std::fs::read(storage.content_path(&key)).unwrap();
+        // The key id following the magic changed
+        assert_ne!(content_before[4..12], content_after[4..12]);
+        assert_ne!(
+            key_file_before,
+            std::fs::read(temp.path().join("store/keyfile")).unwrap()
+        );
+        drop(storage);
+
+        let storage = reopen(&temp, "passphrase").unwrap();
+        assert_eq!(read_all(storage.open_content(&key).unwrap()), b"content");
+        assert_eq!(
+            read_all(storage.open_attachment(&key, "a.txt").unwrap()),
+            b"attachment"
+        );
+        assert_eq!(storage.get(&key).unwrap().unwrap().attachments.len(), 1);
+        assert_eq!(storage.oplog_since(0, 100).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_rotate_key_encrypts_plaintext_files() {
+        let temp = TempDir::new().unwrap();
+        let mut storage = create_encrypted_storage(&temp);
+        let key = make_key("k");
+        storage.create(&key, SystemTime::now()).unwrap();
+        // As left by a version that didn't encrypt this file
+        std::fs::write(storage.content_path(&key), b"plain").unwrap();
+
+        storage.rotate_key("passphrase").unwrap();
+
+        assert!(!contains(
+            &std::fs::read(storage.content_path(&key)).unwrap(),
+            b"plain"
+        ));
+        assert_eq!(read_all(storage.open_content(&key).unwrap()), b"plain");
+    }
+}
+
+mod sealed {
+    use super::*;
+    use crate::core::crypto::error::CryptoError;
+    use crate::types::SealState;
+    use std::io::Read;
+
+    fn read_all(mut reader: impl Read) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// Creates a key with content and an attachment, then seals it under "pin".
+    fn create_sealed_key(storage: &mut KevaCore, temp: &TempDir, key: &Key) {
+        let file_path = create_test_file(temp, "a.txt", b"attachment");
+        let now = SystemTime::now();
+
+        storage.create(key, now).unwrap();
+        let mut writer = storage.write_content(key).unwrap();
+        writer.write_all(b"secret content").unwrap();
+        writer.finish().unwrap();
+        storage
+            .add_attachments(key, vec![overwrite(file_path, "a.txt")], now)
+            .unwrap();
+
+        storage.seal(key, "pin").unwrap();
+    }
+
+    #[test]
+    fn test_sealed_key_is_locked() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("diary");
+        create_sealed_key(&mut storage, &temp, &key);
+
+        let value = storage.get(&key).unwrap().unwrap();
+        assert_eq!(value.metadata.seal, SealState::Locked);
+        assert!(value.attachments.is_empty());
+
+        assert!(matches!(storage.open_content(&key), Err(KevaError::Locked)));
+        assert!(matches!(
+            storage.open_attachment(&key, "a.txt"),
+            Err(KevaError::Locked)
+        ));
+        assert!(matches!(
+            storage.write_content(&key),
+            Err(KevaError::Locked)
+        ));
+
+        let raw = std::fs::read(storage.content_path(&key)).unwrap();
+        assert!(!raw.windows(6).any(|w| w == b"secret"));
+    }
+
+    #[test]
+    fn test_unlock_grants_access() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("diary");
+        create_sealed_key(&mut storage, &temp, &key);
+
+        storage.unlock(&key, "pin", SystemTime::now()).unwrap();
+
+        let value = storage.get(&key).unwrap().unwrap();
+        assert_eq!(value.metadata.seal, SealState::Unlocked);
+        assert_eq!(value.attachments.len(), 1);
+        assert_eq!(
+            read_all(storage.open_content(&key).unwrap()),
+            b"secret content"
+        );
+        assert_eq!(
+            read_all(storage.open_attachment(&key, "a.txt").unwrap()),
+            b"attachment"
+        );
+
+        storage.lock(&key);
+        assert!(matches!(storage.open_content(&key), Err(KevaError::Locked)));
+    }
+
+    #[test]
+    fn test_unlock_wrong_passphrase() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("diary");
+        create_sealed_key(&mut storage, &temp, &key);
+
+        let result = storage.unlock(&key, "wrong", SystemTime::now());
+        assert!(matches!(
+            result,
+            Err(KevaError::Crypto(CryptoError::WrongPassphrase))
+        ));
+    }
+
+    #[test]
+    fn test_unlock_expires() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("diary");
+        create_sealed_key(&mut storage, &temp, &key);
+
+        storage.set_unlock_timeout(Duration::ZERO);
+        storage.unlock(&key, "pin", SystemTime::now()).unwrap();
+        assert!(matches!(storage.open_content(&key), Err(KevaError::Locked)));
+        assert_eq!(
+            storage.get(&key).unwrap().unwrap().metadata.seal,
+            SealState::Locked
+        );
+    }
+
+    #[test]
+    fn test_lock_expired() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("diary");
+        create_sealed_key(&mut storage, &temp, &key);
+        let now = SystemTime::now();
+
+        storage.unlock(&key, "pin", now).unwrap();
+        assert!(storage.lock_expired(now).is_empty());
+
+        let expired = storage.lock_expired(now + KevaCore::DEFAULT_UNLOCK_TIMEOUT);
+        assert_eq!(expired, vec![key.clone()]);
+        assert!(matches!(storage.open_content(&key), Err(KevaError::Locked)));
+    }
+
+    #[test]
+    fn test_unseal_restores_plain_files() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("diary");
+        create_sealed_key(&mut storage, &temp, &key);
+
+        assert!(storage.unseal(&key, "wrong").is_err());
+        storage.unseal(&key, "pin").unwrap();
+
+        let value = storage.get(&key).unwrap().unwrap();
+        assert_eq!(value.metadata.seal, SealState::Unsealed);
+        assert_eq!(
+            std::fs::read(storage.content_path(&key)).unwrap(),
+            b"secret content"
+        );
+        assert_eq!(
+            std::fs::read(storage.attachment_path(&key, "a.txt").unwrap()).unwrap(),
+            b"attachment"
+        );
+    }
+
+    #[test]
+    fn test_seal_errors() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("diary");
+        create_sealed_key(&mut storage, &temp, &key);
+
+        assert!(matches!(
+            storage.seal(&key, "pin"),
+            Err(KevaError::AlreadySealed)
+        ));
+
+        let plain = make_key("plain");
+        storage.create(&plain, SystemTime::now()).unwrap();
+        assert!(matches!(
+            storage.unlock(&plain, "pin", SystemTime::now()),
+            Err(KevaError::NotSealed)
+        ));
+        assert!(matches!(
+            storage.unseal(&plain, "pin"),
+            Err(KevaError::NotSealed)
+        ));
+    }
+
+    #[test]
+    fn test_unlock_follows_rename() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("diary");
+        let renamed = make_key("journal");
+        create_sealed_key(&mut storage, &temp, &key);
+        let now = SystemTime::now();
+
+        storage.unlock(&key, "pin", now).unwrap();
+        storage.rename(&key, &renamed, now).unwrap();
+
+        assert_eq!(
+            read_all(storage.open_content(&renamed).unwrap()),
+            b"secret content"
+        );
+    }
+
+    #[test]
+    fn test_sealed_key_in_encrypted_store_survives_rotation() {
+        let temp = TempDir::new().unwrap();
+        let config = Config {
+            base_path: temp.path().join("store"),
+        };
+        let mut storage = KevaCore::open_encrypted(config, "passphrase").unwrap();
+        let key = make_key("diary");
+        create_sealed_key(&mut storage, &temp, &key);
+
+        storage.rotate_key("passphrase").unwrap();
+
+        storage.unlock(&key, "pin", SystemTime::now()).unwrap();
+        assert_eq!(
+            read_all(storage.open_content(&key).unwrap()),
+            b"secret content"
+        );
+        storage.unseal(&key, "pin").unwrap();
+        assert_eq!(
+            read_all(storage.open_attachment(&key, "a.txt").unwrap()),
+            b"attachment"
+        );
+    }
+}
+
+mod thumbnail {
+    use super::*;
+    use crate::types::ThumbnailFormat;
+    use std::io::Read;
+    use std::sync::{Mutex, mpsc};
+
+    #[test]
+    fn test_thumbnail_paths_excludes_unsupported_formats() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let pdf_path = create_test_file(&temp, "document.pdf", b"pdf content");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(pdf_path, "document.pdf")], now)
+            .unwrap();
+        storage.wait_for_thumbnails();
+
+        let paths = storage
+            .thumbnail_paths(&key, ThumbnailPreset::Grid, 1)
+            .unwrap();
+        // The PDF can't be rendered, so it has no thumbnail
+        assert!(paths.is_empty());
+        assert!(matches!(
+            storage.thumbnail_status(&key, "document.pdf"),
+            Some(ThumbnailStatus::Failed(_))
+        ));
+    }
+
+    #[test]
+    fn test_thumbnail_paths_excludes_formats_without_renderer() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let video_path = create_test_file(&temp, "clip.mp4", b"video");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(video_path, "clip.mp4")], now)
+            .unwrap();
+        storage.wait_for_thumbnails();
+
+        assert!(
+            storage
+                .thumbnail_paths(&key, ThumbnailPreset::Grid, 1)
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(storage.thumbnail_status(&key, "clip.mp4"), None);
+    }
+
+    #[test]
+    fn test_thumbnail_paths_regenerates_after_version_bump() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let image_path = temp.path().join("photo.bmp");
+        image::RgbImage::from_pixel(400, 300, image::Rgb([10, 20, 30]))
+            .save(&image_path)
+            .unwrap();
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(image_path, "photo.bmp")], now)
+            .unwrap();
+        storage.wait_for_thumbnails();
+
+        // Simulate a thumbnail from an older version that didn't render BMP
+        let key_hash = KevaCore::key_to_path(&key);
+        storage
+            .file
+            .remove_thumbnail(&key_hash, "photo.bmp")
+            .unwrap();
+        storage.db.update_thumb_version(&key, 1).unwrap();
+
+        storage
+            .thumbnail_paths(&key, ThumbnailPreset::Grid, 1)
+            .unwrap();
+        storage.wait_for_thumbnails();
+        let paths = storage
+            .thumbnail_paths(&key, ThumbnailPreset::Grid, 1)
+            .unwrap();
+        assert_eq!(
+            paths.get("photo.bmp"),
+            Some(&FileStorage::thumbnail_rel_dir(&key_hash, "photo.bmp").join("grid.png"))
+        );
+        let value = storage.get(&key).unwrap().unwrap();
+        assert_eq!(value.thumb_version, FileStorage::THUMB_VER);
+    }
+
+    struct Solid;
+
+    impl ThumbnailRenderer for Solid {
+        fn render(
+            &self,
+            _bytes: &[u8],
+            max_size: u32,
+        ) -> Result<image::DynamicImage, FileStorageError> {
+            Ok(image::DynamicImage::new_rgb8(max_size, max_size))
+        }
+    }
+
+    #[test]
+    fn test_registered_renderer_is_used() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let text_path = create_test_file(&temp, "notes.txt", b"hello");
+        let now = SystemTime::now();
+
+        storage.register_thumbnail_renderer(&["txt"], Arc::new(Solid));
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(text_path, "notes.txt")], now)
+            .unwrap();
+        storage.wait_for_thumbnails();
+
+        let paths = storage
+            .thumbnail_paths(&key, ThumbnailPreset::Grid, 1)
+            .unwrap();
+        assert!(paths.contains_key("notes.txt"));
+    }
+
+    /// Renders a solid square once released through its channel.
+    struct Gated(Mutex<mpsc::Receiver<()>>);
+
+    impl Gated {
+        fn new() -> (Arc<Self>, mpsc::Sender<()>) {
+            let (tx, rx) = mpsc::channel();
+            (Arc::new(Self(Mutex::new(rx))), tx)
+        }
+    }
+
+    impl ThumbnailRenderer for Gated {
+        fn render(
+            &self,
+            bytes: &[u8],
+            max_size: u32,
+        ) -> Result<image::DynamicImage, FileStorageError> {
+            self.0.lock().unwrap().recv().unwrap();
+            Solid.render(bytes, max_size)
+        }
+    }
+
+    #[test]
+    fn test_add_attachments_returns_before_rendering() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let text_path = create_test_file(&temp, "notes.txt", b"hello");
+        let now = SystemTime::now();
+        let (gated, release) = Gated::new();
+
+        storage.register_thumbnail_renderer(&["txt"], gated);
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(text_path, "notes.txt")], now)
+            .unwrap();
+
+        assert_eq!(
+            storage.thumbnail_status(&key, "notes.txt"),
+            Some(ThumbnailStatus::Pending)
+        );
+        assert!(
+            storage
+                .thumbnail_paths(&key, ThumbnailPreset::Grid, 1)
+                .unwrap()
+                .is_empty()
+        );
+
+        release.send(()).unwrap();
+        storage.wait_for_thumbnails();
+
+        assert_eq!(
+            storage.thumbnail_status(&key, "notes.txt"),
+            Some(ThumbnailStatus::Ready)
+        );
+        assert!(
+            storage
+                .thumbnail_paths(&key, ThumbnailPreset::Grid, 1)
+                .unwrap()
+                .contains_key("notes.txt")
+        );
+    }
+
+    #[test]
+    fn test_thumbnail_statuses() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let image_path = temp.path().join("photo.png");
+        image::RgbImage::from_pixel(40, 30, image::Rgb([10, 20, 30]))
+            .save(&image_path)
+            .unwrap();
+        let broken_path = create_test_file(&temp, "broken.png", b"not an image");
+        let video_path = create_test_file(&temp, "clip.mp4", b"video");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(
+                &key,
+                vec![
+                    overwrite(image_path, "photo.png"),
+                    overwrite(broken_path, "broken.png"),
+                    overwrite(video_path, "clip.mp4"),
+                ],
+                now,
+            )
+            .unwrap();
+        storage.wait_for_thumbnails();
+
+        let statuses = storage.thumbnail_statuses(&key).unwrap();
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses["photo.png"], ThumbnailStatus::Ready);
+        assert!(matches!(statuses["broken.png"], ThumbnailStatus::Failed(_)));
+    }
+
+    #[test]
+    fn test_thumbnail_callback_reports_completion() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let text_path = create_test_file(&temp, "notes.txt", b"hello");
+        let broken_path = create_test_file(&temp, "broken.png", b"not an image");
+        let now = SystemTime::now();
+        let (tx, rx) = mpsc::channel();
+
+        storage.register_thumbnail_renderer(&["txt"], Arc::new(Solid));
+        storage.set_thumbnail_callback(Some(Arc::new(move |key, filename, status| {
+            tx.send((key.clone(), filename.to_string(), status.clone()))
+                .unwrap();
+        })));
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(
+                &key,
+                vec![
+                    overwrite(text_path, "notes.txt"),
+                    overwrite(broken_path, "broken.png"),
+                ],
+                now,
+            )
+            .unwrap();
+        storage.wait_for_thumbnails();
+
+        let mut events: Vec<_> = rx.try_iter().collect();
+        events.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].0, key);
+        assert_eq!(events[0].1, "broken.png");
+        assert!(matches!(events[0].2, ThumbnailStatus::Failed(_)));
+        assert_eq!(
+            (events[1].1.as_str(), &events[1].2),
+            ("notes.txt", &ThumbnailStatus::Ready)
+        );
+    }
+
+    #[test]
+    fn test_remove_attachment_drops_pending_thumbnail() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let text_path = create_test_file(&temp, "notes.txt", b"hello");
+        let now = SystemTime::now();
+        let (gated, release) = Gated::new();
+
+        storage.register_thumbnail_renderer(&["txt"], gated);
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(text_path, "notes.txt")], now)
+            .unwrap();
+        storage.remove_attachment(&key, "notes.txt", now).unwrap();
+        release.send(()).unwrap();
+        storage.wait_for_thumbnails();
+
+        let key_hash = KevaCore::key_to_path(&key);
+        assert!(
+            storage
+                .file
+                .thumbnail_files(&key_hash, "notes.txt")
+                .is_empty()
+        );
+        assert_eq!(storage.thumbnail_status(&key, "notes.txt"), None);
+    }
+
+    #[test]
+    fn test_rename_requeues_pending_thumbnails() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let new_key = make_key("test/renamed");
+        let text_path = create_test_file(&temp, "notes.txt", b"hello");
+        let now = SystemTime::now();
+        let (gated, release) = Gated::new();
+
+        storage.register_thumbnail_renderer(&["txt"], gated);
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(text_path, "notes.txt")], now)
+            .unwrap();
+        storage.rename(&key, &new_key, now).unwrap();
+        storage
+            .rename_attachment(&new_key, "notes.txt", &name("renamed.txt"), now)
+            .unwrap();
+        assert_eq!(
+            storage.thumbnail_status(&new_key, "renamed.txt"),
+            Some(ThumbnailStatus::Pending)
+        );
+
+        // One release for each job a worker may have taken before it was cancelled.
+        for _ in 0..3 {
+            release.send(()).unwrap();
+        }
+        storage.wait_for_thumbnails();
+
+        let old_hash = KevaCore::key_to_path(&key);
+        let new_hash = KevaCore::key_to_path(&new_key);
+        assert!(
+            storage
+                .file
+                .thumbnail_files(&old_hash, "notes.txt")
+                .is_empty()
+        );
+        assert!(
+            !storage
+                .file
+                .thumbnail_files(&new_hash, "renamed.txt")
+                .is_empty()
+        );
+        assert_eq!(storage.thumbnail_status(&key, "notes.txt"), None);
+    }
+
+    #[test]
+    fn test_batch_rename_requeues_pending_thumbnails() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let middle = make_key("test/middle");
+        let new_key = make_key("test/renamed");
+        let text_path = create_test_file(&temp, "notes.txt", b"hello");
+        let now = SystemTime::now();
+        let (gated, release) = Gated::new();
+
+        storage.register_thumbnail_renderer(&["txt"], gated);
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(text_path, "notes.txt")], now)
+            .unwrap();
+        storage
+            .batch(|tx| {
+                tx.rename(&key, &middle, now)?;
+                tx.rename(&middle, &new_key, now)
+            })
+            .unwrap();
+        assert_eq!(
+            storage.thumbnail_status(&new_key, "notes.txt"),
+            Some(ThumbnailStatus::Pending)
+        );
+
+        for _ in 0..2 {
+            release.send(()).unwrap();
+        }
+        storage.wait_for_thumbnails();
+
+        let new_hash = KevaCore::key_to_path(&new_key);
+        assert!(
+            !storage
+                .file
+                .thumbnail_files(&new_hash, "notes.txt")
+                .is_empty()
+        );
+        assert_eq!(storage.thumbnail_status(&key, "notes.txt"), None);
+        assert_eq!(storage.thumbnail_status(&middle, "notes.txt"), None);
+    }
+
+    #[test]
+    fn test_closing_store_finishes_queued_thumbnails() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let image_path = temp.path().join("photo.png");
+        image::RgbImage::from_pixel(400, 300, image::Rgb([10, 20, 30]))
+            .save(&image_path)
+            .unwrap();
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(image_path, "photo.png")], now)
+            .unwrap();
+        let thumb_dir = storage
+            .file
+            .thumbnail_dir(&KevaCore::key_to_path(&key), "photo.png");
+        drop(storage);
+
+        assert!(thumb_dir.join("grid.png").exists());
+    }
+
+    #[test]
+    fn test_thumbnail_paths_picks_best_match() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let image_path = temp.path().join("photo.png");
+        image::RgbImage::from_pixel(2000, 1500, image::Rgb([10, 20, 30]))
+            .save(&image_path)
+            .unwrap();
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(image_path, "photo.png")], now)
+            .unwrap();
+        storage.wait_for_thumbnails();
+
+        let file_name = |storage: &mut KevaCore, preset, scale| {
+            let paths = storage.thumbnail_paths(&key, preset, scale).unwrap();
+            let name = paths["photo.png"].file_name().unwrap().to_owned();
+            name.into_string().unwrap()
+        };
+        assert_eq!(
+            file_name(&mut storage, ThumbnailPreset::ListIcon, 1),
+            "list_icon.png"
+        );
+        assert_eq!(
+            file_name(&mut storage, ThumbnailPreset::ListIcon, 2),
+            "list_icon@2x.png"
+        );
+        assert_eq!(
+            file_name(&mut storage, ThumbnailPreset::Preview, 2),
+            "preview@2x.png"
+        );
+    }
+
+    #[test]
+    fn test_thumbnail_config_applies_to_new_thumbnails() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let image_path = temp.path().join("photo.png");
+        image::RgbImage::from_pixel(400, 300, image::Rgb([10, 20, 30]))
+            .save(&image_path)
+            .unwrap();
+        let now = SystemTime::now();
+
+        storage.set_thumbnail_config(ThumbnailConfig {
+            presets: vec![ThumbnailPreset::Grid],
+            hidpi: false,
+            format: ThumbnailFormat::Jpeg,
+            jpeg_quality: 70,
+        });
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(image_path, "photo.png")], now)
+            .unwrap();
+        storage.wait_for_thumbnails();
+
+        // Only the 1x grid thumbnail exists, so it is the best match for any request
+        let key_hash = KevaCore::key_to_path(&key);
+        let expected = FileStorage::thumbnail_rel_dir(&key_hash, "photo.png").join("grid.jpg");
+        for (preset, scale) in [
+            (ThumbnailPreset::ListIcon, 1),
+            (ThumbnailPreset::Preview, 2),
+        ] {
+            let paths = storage.thumbnail_paths(&key, preset, scale).unwrap();
+            assert_eq!(paths.get("photo.png"), Some(&expected));
+        }
+        let mut bytes = Vec::new();
+        storage
+            .open_thumbnail(&key, "photo.png", ThumbnailPreset::Grid, 2)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(
+            image::guess_format(&bytes).unwrap(),
+            image::ImageFormat::Jpeg
+        );
+    }
+
+    #[test]
+    fn test_legacy_thumbnail_served_until_regenerated() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("test/key");
+        let text_path = create_test_file(&temp, "notes.txt", b"hello");
+        let now = SystemTime::now();
+        let (gated, release) = Gated::new();
+
+        storage.register_thumbnail_renderer(&["txt"], gated);
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(text_path, "notes.txt")], now)
+            .unwrap();
+        release.send(()).unwrap();
+        storage.wait_for_thumbnails();
+
+        // Replace the thumbnails with a single one in the pre-preset layout
+        let key_hash = KevaCore::key_to_path(&key);
+        storage
+            .file
+            .remove_thumbnail(&key_hash, "notes.txt")
+            .unwrap();
+        let legacy_path = key_hash.join("notes.txt.thumb");
+        std::fs::create_dir_all(storage.file.thumbnails_path.join(&key_hash)).unwrap();
+        std::fs::write(storage.file.thumbnails_path.join(&legacy_path), b"old").unwrap();
+        storage.db.update_thumb_version(&key, 2).unwrap();
+
+        let paths = storage
+            .thumbnail_paths(&key, ThumbnailPreset::ListIcon, 2)
+            .unwrap();
+        assert_eq!(paths.get("notes.txt"), Some(&legacy_path));
+
+        release.send(()).unwrap();
+        storage.wait_for_thumbnails();
+        let paths = storage
+            .thumbnail_paths(&key, ThumbnailPreset::ListIcon, 2)
+            .unwrap();
+        assert_eq!(
+            paths.get("notes.txt"),
+            Some(&FileStorage::thumbnail_rel_dir(&key_hash, "notes.txt").join("list_icon@2x.png"))
+        );
+        assert!(!storage.file.thumbnails_path.join(&legacy_path).exists());
+    }
+
+    #[test]
+    fn test_thumbnail_paths_empty_for_no_attachments() {
+        let (mut storage, _temp) = create_test_storage();
+        let key = make_key("test/key");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+
+        let paths = storage
+            .thumbnail_paths(&key, ThumbnailPreset::Grid, 1)
+            .unwrap();
+        assert!(paths.is_empty());
+    }
+
+    #[test]
+    fn test_new_key_starts_with_current_thumb_version() {
+        let (mut storage, _temp) = create_test_storage();
+        let key = make_key("test/key");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+
+        // New keys start with current THUMB_VER (no regeneration needed)
+        let value = storage.get(&key).unwrap().unwrap();
+        assert_eq!(value.thumb_version, FileStorage::THUMB_VER);
+    }
+}
+
+mod undo {
+    use super::*;
+    use std::io::Read;
+
+    fn read_content(storage: &KevaCore, key: &Key) -> String {
+        let mut content = String::new();
+        storage
+            .open_content(key)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    fn holding_dirs(temp: &TempDir) -> usize {
+        std::fs::read_dir(temp.path().join("holding"))
+            .map(|dirs| dirs.count())
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_undo_purge_restores_row_and_files() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        let mut writer = storage.write_content(&key).unwrap();
+        writer.write_all(b"body").unwrap();
+        writer.finish().unwrap();
+        let file_path = create_test_file(&temp, "a.txt", b"attached");
+        storage
+            .add_attachments(&key, vec![overwrite(file_path, "a.txt")], now)
+            .unwrap();
+        let before = storage.get(&key).unwrap().unwrap();
+
+        storage.purge(&key).unwrap();
+        assert!(!storage.content_path(&key).exists());
+        assert_eq!(holding_dirs(&temp), 1);
+
+        let undone = storage.undo(SystemTime::now()).unwrap();
+        assert_eq!(undone, Some(UndoAction::Purged(key.clone())));
+        assert_eq!(storage.get(&key).unwrap(), Some(before));
+        assert_eq!(read_content(&storage, &key), "body");
+        assert_eq!(
+            std::fs::read(storage.attachment_path(&key, "a.txt").unwrap()).unwrap(),
+            b"attached"
+        );
+
+        storage.redo(SystemTime::now()).unwrap();
+        assert!(storage.get(&key).unwrap().is_none());
+        assert!(!storage.content_path(&key).exists());
+    }
+
+    #[test]
+    fn test_undo_rename_and_trash() {
+        let (mut storage, _temp) = create_test_storage();
+        let old = make_key("old");
+        let new = make_key("new");
+        let now = SystemTime::now();
+
+        storage.create(&old, now).unwrap();
+        storage.rename(&old, &new, now).unwrap();
+        storage.trash(&new, now).unwrap();
+
+        assert_eq!(
+            storage.undo(now).unwrap(),
+            Some(UndoAction::Trashed(new.clone()))
+        );
+        assert_eq!(storage.trashed_keys().unwrap(), vec![]);
+
+        storage.undo(now).unwrap();
+        assert_eq!(storage.active_keys().unwrap(), vec![old.clone()]);
+        assert!(storage.content_path(&old).exists());
+        assert_eq!(storage.undo(now).unwrap(), None);
+
+        storage.redo(now).unwrap();
+        assert_eq!(storage.active_keys().unwrap(), vec![new.clone()]);
+        assert_eq!(storage.next_redo(), Some(UndoAction::Trashed(new.clone())));
+    }
+
+    #[test]
+    fn test_undo_attachment_remove_and_rename() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("note");
+        let now = SystemTime::now();
+        let file_path = create_test_file(&temp, "a.txt", b"attached");
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(file_path, "a.txt")], now)
+            .unwrap();
+        storage
+            .rename_attachment(&key, "a.txt", &name("b.txt"), now)
+            .unwrap();
+        storage.remove_attachment(&key, "b.txt", now).unwrap();
+        assert!(storage.get(&key).unwrap().unwrap().attachments.is_empty());
+
+        storage.undo(now).unwrap();
+        assert_eq!(
+            std::fs::read(storage.attachment_path(&key, "b.txt").unwrap()).unwrap(),
+            b"attached"
+        );
+
+        assert_eq!(
+            storage.undo(now).unwrap(),
+            Some(UndoAction::AttachmentRenamed {
+                key: key.clone(),
+                from: "a.txt".into(),
+                to: "b.txt".into(),
+            })
+        );
+        let value = storage.get(&key).unwrap().unwrap();
+        assert_eq!(value.attachments.len(), 1);
+        assert_eq!(value.attachments[0].filename, "a.txt");
+        assert!(storage.attachment_path(&key, "a.txt").unwrap().exists());
+    }
+
+    #[test]
+    fn test_undo_rename_attachment_replacing_restores_both() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("note");
+        let now = SystemTime::now();
+        let moved = create_test_file(&temp, "a.txt", b"moved");
+        let older = create_test_file(&temp, "b.txt", b"older");
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(
+                &key,
+                vec![overwrite(moved, "a.txt"), overwrite(older, "b.txt")],
+                now,
+            )
+            .unwrap();
+        storage
+            .rename_attachment_replacing(&key, "a.txt", &name("b.txt"), now)
+            .unwrap();
+        let read = |storage: &KevaCore, filename: &str| {
+            std::fs::read(storage.attachment_path(&key, filename).unwrap()).unwrap()
+        };
+        let filenames = |storage: &KevaCore| {
+            let mut filenames: Vec<_> = storage
+                .get(&key)
+                .unwrap()
+                .unwrap()
+                .attachments
+                .into_iter()
+                .map(|a| a.filename)
+                .collect();
+            filenames.sort();
+            filenames
+        };
+        assert_eq!(filenames(&storage), vec!["b.txt"]);
+        assert_eq!(read(&storage, "b.txt"), b"moved");
+        assert_eq!(
+            storage.next_undo(),
+            Some(UndoAction::AttachmentReplaced {
+                key: key.clone(),
+                from: "a.txt".into(),
+                to: "b.txt".into(),
+            })
+        );
+
+        storage.undo(now).unwrap();
+        assert_eq!(filenames(&storage), vec!["a.txt", "b.txt"]);
+        assert_eq!(read(&storage, "a.txt"), b"moved");
+        assert_eq!(read(&storage, "b.txt"), b"older");
+        assert_eq!(storage.next_undo(), None);
+
+        storage.redo(now).unwrap();
+        assert_eq!(filenames(&storage), vec!["b.txt"]);
+        assert_eq!(read(&storage, "b.txt"), b"moved");
+    }
+
+    #[test]
+    fn test_rename_attachment_replacing_missing_source_keeps_destination() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("note");
+        let now = SystemTime::now();
+        let file_path = create_test_file(&temp, "b.txt", b"kept");
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(file_path, "b.txt")], now)
+            .unwrap();
+        storage.clear_undo();
+
+        let result = storage.rename_attachment_replacing(&key, "missing.txt", &name("b.txt"), now);
+        assert!(matches!(
+            result,
+            Err(KevaError::Database(DatabaseError::AttachmentNotFound(_)))
+        ));
+        assert_eq!(
+            std::fs::read(storage.attachment_path(&key, "b.txt").unwrap()).unwrap(),
+            b"kept"
+        );
+        assert_eq!(storage.next_undo(), None);
+    }
+
+    #[test]
+    fn test_undo_overwrite_restores_previous_file() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("note");
+        let now = SystemTime::now();
+        let old = create_test_file(&temp, "old.txt", b"old");
+        let new = create_test_file(&temp, "new.txt", b"newer");
+
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(old, "a.txt")], now)
+            .unwrap();
+        assert_eq!(storage.next_undo(), None);
+        storage
+            .add_attachments(&key, vec![overwrite(new, "a.txt")], now)
+            .unwrap();
+
+        storage.undo(now).unwrap();
+        let value = storage.get(&key).unwrap().unwrap();
+        assert_eq!(value.attachments.len(), 1);
+        assert_eq!(value.attachments[0].size, 3);
+        assert_eq!(
+            std::fs::read(storage.attachment_path(&key, "a.txt").unwrap()).unwrap(),
+            b"old"
+        );
+
+        storage.redo(now).unwrap();
+        assert_eq!(
+            std::fs::read(storage.attachment_path(&key, "a.txt").unwrap()).unwrap(),
+            b"newer"
+        );
+    }
+
+    #[test]
+    fn test_new_operation_clears_redo() {
+        let (mut storage, temp) = create_test_storage();
+        let a = make_key("a");
+        let b = make_key("b");
+        let now = SystemTime::now();
+
+        storage.create(&a, now).unwrap();
+        storage.create(&b, now).unwrap();
+        storage.purge(&a).unwrap();
+        storage.undo(SystemTime::now()).unwrap();
+        assert!(storage.next_redo().is_some());
+
+        storage.trash(&b, now).unwrap();
+        assert_eq!(storage.next_redo(), None);
+        assert_eq!(holding_dirs(&temp), 0);
+    }
+
+    #[test]
+    fn test_undo_purge_keeps_files_of_recreated_key() {
+        let (mut storage, _temp) = create_test_storage();
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        storage.create(&key, now).unwrap();
+        storage.purge(&key).unwrap();
+        storage.create(&key, now).unwrap();
+        let mut writer = storage.write_content(&key).unwrap();
+        writer.write_all(b"new").unwrap();
+        writer.finish().unwrap();
+
+        assert!(storage.undo(now).is_err());
+        assert_eq!(read_content(&storage, &key), "new");
+        assert_eq!(storage.next_undo(), Some(UndoAction::Purged(key.clone())));
+    }
+
+    #[test]
+    fn test_undo_rename_replacing_restores_both_keys() {
+        let (mut storage, _temp) = create_test_storage();
+        let from = make_key("from");
+        let to = make_key("to");
+        let now = SystemTime::now();
+
+        for (key, body) in [(&from, b"moved"), (&to, b"older")] {
+            storage.create(key, now).unwrap();
+            let mut writer = storage.write_content(key).unwrap();
+            writer.write_all(body).unwrap();
+            writer.finish().unwrap();
+        }
+
+        storage.rename_replacing(&from, &to, now).unwrap();
+        assert_eq!(storage.active_keys().unwrap(), vec![to.clone()]);
+        assert_eq!(read_content(&storage, &to), "moved");
+        assert_eq!(
+            storage.next_undo(),
+            Some(UndoAction::Replaced {
+                from: from.clone(),
+                to: to.clone(),
+            })
+        );
+
+        storage.undo(now).unwrap();
+        assert_eq!(
+            storage.active_keys().unwrap(),
+            vec![from.clone(), to.clone()]
+        );
+        assert_eq!(read_content(&storage, &from), "moved");
+        assert_eq!(read_content(&storage, &to), "older");
+        assert_eq!(storage.next_undo(), None);
+
+        storage.redo(now).unwrap();
+        assert_eq!(storage.active_keys().unwrap(), vec![to.clone()]);
+        assert_eq!(read_content(&storage, &to), "moved");
+    }
+
+    #[test]
+    fn test_rename_replacing_missing_source_keeps_destination() {
+        let (mut storage, _temp) = create_test_storage();
+        let to = make_key("to");
+        let now = SystemTime::now();
+
+        storage.create(&to, now).unwrap();
+        assert!(
+            storage
+                .rename_replacing(&make_key("missing"), &to, now)
+                .is_err()
+        );
+        assert_eq!(storage.active_keys().unwrap(), vec![to]);
+        assert_eq!(storage.next_undo(), None);
+    }
+
+    #[test]
+    fn test_conflicting_undo_keeps_entry() {
+        let (mut storage, _temp) = create_test_storage();
+        let old = make_key("old");
+        let new = make_key("new");
+        let now = SystemTime::now();
+
+        storage.create(&old, now).unwrap();
+        storage.rename(&old, &new, now).unwrap();
+        storage.create(&old, now).unwrap();
+
+        let result = storage.undo(now);
+        assert!(matches!(result, Err(KevaError::DestinationExists)));
+        assert!(storage.next_undo().is_some());
+    }
+
+    #[test]
+    fn test_entries_expire_after_window() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        storage.set_undo_window(Duration::from_secs(60));
+        storage.create(&key, now).unwrap();
+        storage.purge(&key).unwrap();
+        assert_eq!(holding_dirs(&temp), 1);
+
+        let outcome = storage
+            .maintenance(now + Duration::from_secs(120), make_gc_config(1000, 1000))
+            .unwrap();
+        assert!(outcome.keys_purged.is_empty());
+        assert_eq!(holding_dirs(&temp), 0);
+        assert_eq!(storage.undo(now + Duration::from_secs(120)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_open_clears_holding_of_earlier_session() {
+        let temp = TempDir::new().unwrap();
+        let config = Config {
+            base_path: temp.path().to_path_buf(),
+        };
+        let key = make_key("note");
+        let now = SystemTime::now();
+
+        {
+            let mut storage = KevaCore::open(config.clone()).unwrap();
+            storage.create(&key, now).unwrap();
+            storage.purge(&key).unwrap();
+        }
+        assert_eq!(holding_dirs(&temp), 1);
+
+        let storage = KevaCore::open(config).unwrap();
+        assert_eq!(storage.next_undo(), None);
+        assert_eq!(holding_dirs(&temp), 0);
+    }
+}
+
+mod usage {
+    use super::*;
+    use std::io::Cursor;
+
+    fn limit_store(
+        storage: &mut KevaCore,
+        max_attachment_size: Option<u64>,
+        max_store_size: Option<u64>,
+    ) {
+        storage.set_quota_config(QuotaConfig {
+            max_attachment_size,
+            max_store_size,
+        });
+    }
+
+    #[test]
+    fn test_usage_per_key_and_total() {
+        let (mut storage, temp) = create_test_storage();
+        let small = make_key("small");
+        let large = make_key("large");
+        let now = SystemTime::now();
+        storage.create(&small, now).unwrap();
+        storage.create(&large, now).unwrap();
+
+        let mut writer = storage.write_content(&small).unwrap();
+        writer.write_all(b"hello").unwrap();
+        writer.finish().unwrap();
+        let file = create_test_file(&temp, "data.bin", &[0; 100]);
+        storage
+            .add_attachments(&large, vec![overwrite(file, "data.bin")], now)
+            .unwrap();
+        storage.trash(&large, now).unwrap();
+
+        let usage = storage.usage().unwrap();
+        assert_eq!(
+            usage.keys,
+            vec![
+                (
+                    large.clone(),
+                    Usage {
+                        content: 0,
+                        blobs: 100,
+                        thumbnails: 0,
+                    }
+                ),
+                (
+                    small.clone(),
+                    Usage {
+                        content: 5,
+                        blobs: 0,
+                        thumbnails: 0,
+                    }
+                ),
+            ]
+        );
+        assert_eq!(usage.total.total(), 105);
+        assert_eq!(storage.key_usage(&small).unwrap().total(), 5);
+
+        let largest = usage.largest_keys(1);
+        assert_eq!(largest.len(), 1);
+        assert_eq!(largest[0].0, large);
+    }
+
+    #[test]
+    fn test_usage_counts_thumbnails() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("key");
+        let now = SystemTime::now();
+        storage.create(&key, now).unwrap();
+
+        let image = image::RgbImage::from_pixel(64, 64, image::Rgb([255, 0, 0]));
+        let image_path = temp.path().join("photo.png");
+        image.save(&image_path).unwrap();
+        storage
+            .add_attachments(&key, vec![overwrite(image_path, "photo.png")], now)
+            .unwrap();
+        storage.wait_for_thumbnails();
+
+        let usage = storage.key_usage(&key).unwrap();
+        assert!(usage.thumbnails > 0);
+        assert_eq!(storage.usage().unwrap().total.thumbnails, usage.thumbnails);
+    }
+
+    #[test]
+    fn test_usage_of_missing_key() {
+        let (storage, _temp) = create_test_storage();
+        let result = storage.key_usage(&make_key("missing"));
+        assert!(matches!(
+            result,
+            Err(KevaError::Database(DatabaseError::NotFound))
+        ));
+    }
+
+    #[test]
+    fn test_attachment_size_quota() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("key");
+        let small = create_test_file(&temp, "small.bin", &[0; 10]);
+        let large = create_test_file(&temp, "large.bin", &[0; 11]);
+        let now = SystemTime::now();
+        storage.create(&key, now).unwrap();
+        limit_store(&mut storage, Some(10), None);
+
+        let result = storage.add_attachments(
+            &key,
+            vec![overwrite(small, "small.bin"), overwrite(large, "large.bin")],
+            now,
+        );
+        assert!(matches!(
+            result,
+            Err(KevaError::QuotaExceeded(QuotaError::AttachmentTooLarge { ref filename, size: 11, limit: 10 }))
+                if filename == "large.bin"
+        ));
+        assert!(storage.get(&key).unwrap().unwrap().attachments.is_empty());
+
+        let result = storage.add_attachment_from_reader(
+            &key,
+            &name("upload.bin"),
+            Cursor::new([0; 11]),
+            now,
+        );
+        assert!(matches!(result, Err(KevaError::QuotaExceeded(_))));
+        assert!(storage.get(&key).unwrap().unwrap().attachments.is_empty());
+        assert_eq!(storage.key_usage(&key).unwrap().blobs, 0);
+    }
+
+    #[test]
+    fn test_store_size_quota() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("key");
+        let first = create_test_file(&temp, "first.bin", &[1; 60]);
+        let second = create_test_file(&temp, "second.bin", &[2; 60]);
+        let now = SystemTime::now();
+        storage.create(&key, now).unwrap();
+        limit_store(&mut storage, None, Some(100));
+
+        storage
+            .add_attachments(&key, vec![overwrite(first, "a.bin")], now)
+            .unwrap();
+        let result = storage.add_attachments(&key, vec![overwrite(second.clone(), "b.bin")], now);
+        assert!(matches!(
+            result,
+            Err(KevaError::QuotaExceeded(QuotaError::StoreFull {
+                size: 120,
+                limit: 100
+            }))
+        ));
+
+        // Replacing an attachment only counts the growth.
+        storage
+            .add_attachments(&key, vec![overwrite(second, "a.bin")], now)
+            .unwrap();
+        let result =
+            storage.add_attachment_from_reader(&key, &name("b.bin"), Cursor::new([3; 41]), now);
+        assert!(matches!(result, Err(KevaError::QuotaExceeded(_))));
+        assert_eq!(storage.usage().unwrap().total.total(), 60);
+    }
+
+    #[test]
+    fn test_oversized_stream_stops_at_limit() {
+        let (mut storage, _temp) = create_test_storage();
+        let key = make_key("key");
+        let now = SystemTime::now();
+        storage.create(&key, now).unwrap();
+        limit_store(&mut storage, None, Some(1_000_000));
+
+        // Would never end if the limit were only checked once the stream is stored
+        let result =
+            storage.add_attachment_from_reader(&key, &name("endless.bin"), std::io::repeat(0), now);
+        assert!(matches!(
+            result,
+            Err(KevaError::QuotaExceeded(QuotaError::StoreFull {
+                limit: 1_000_000,
+                ..
+            }))
+        ));
+        assert_eq!(storage.usage().unwrap().total.total(), 0);
+    }
+
+    #[test]
+    fn test_store_size_tracks_added_attachments() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("key");
+        let now = SystemTime::now();
+        storage.create(&key, now).unwrap();
+        limit_store(&mut storage, None, Some(100));
+
+        for i in 0..2 {
+            let file = create_test_file(&temp, &format!("{i}.bin"), &[i; 40]);
+            storage
+                .add_attachments(&key, vec![overwrite(file, &format!("{i}.bin"))], now)
+                .unwrap();
+        }
+        let result =
+            storage.add_attachment_from_reader(&key, &name("2.bin"), Cursor::new([2; 21]), now);
+        assert!(matches!(
+            result,
+            Err(KevaError::QuotaExceeded(QuotaError::StoreFull { .. }))
+        ));
+
+        // Removed files count as freed right away
+        storage.remove_attachment(&key, "0.bin", now).unwrap();
+        storage
+            .add_attachment_from_reader(&key, &name("2.bin"), Cursor::new([2; 21]), now)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_store_size_follows_purge_and_undo() {
+        let (mut storage, _temp) = create_test_storage();
+        let full = make_key("full");
+        let other = make_key("other");
+        let now = SystemTime::now();
+        storage.create(&full, now).unwrap();
+        storage.create(&other, now).unwrap();
+        limit_store(&mut storage, None, Some(100));
+        storage
+            .add_attachment_from_reader(&full, &name("a.bin"), Cursor::new([1; 80]), now)
+            .unwrap();
+
+        let add = |storage: &mut KevaCore| {
+            storage.add_attachment_from_reader(&other, &name("b.bin"), Cursor::new([2; 40]), now)
+        };
+        assert!(matches!(
+            add(&mut storage),
+            Err(KevaError::QuotaExceeded(QuotaError::StoreFull { .. }))
+        ));
+
+        storage.purge(&full).unwrap();
+        storage.undo(now).unwrap();
+        assert!(matches!(
+            add(&mut storage),
+            Err(KevaError::QuotaExceeded(QuotaError::StoreFull { .. }))
+        ));
+
+        storage.redo(now).unwrap();
+        add(&mut storage).unwrap();
+    }
+}
+
+mod text_search {
+    use super::*;
+
+    fn text_file(storage: &KevaCore, key: &Key, filename: &str) -> PathBuf {
+        storage
+            .data_dir()
+            .join("text")
+            .join(KevaCore::key_to_path(key))
+            .join(format!("{filename}.txt"))
+    }
+
+    /// Returns the matches as `key:filename`.
+    fn found(storage: &KevaCore, query: &str) -> Vec<String> {
+        storage
+            .search_attachment_text(query)
+            .unwrap()
+            .into_iter()
+            .map(|m| format!("{}:{}", m.key, m.filename))
+            .collect()
+    }
+
+    #[test]
+    fn test_finds_keys_by_attachment_text() {
+        let (mut storage, temp) = create_test_storage();
+        let notes = make_key("notes");
+        let code = make_key("code");
+        let now = SystemTime::now();
+        storage.create(&notes, now).unwrap();
+        storage.create(&code, now).unwrap();
+
+        let txt = create_test_file(&temp, "a.txt", b"intro\nMeeting about the Budget\n");
+        let csv = create_test_file(&temp, "b.csv", b"item,budget\nrent,100\n");
+        let rs = create_test_file(&temp, "c.rs", b"fn budget() {}\n");
+        let bin = create_test_file(&temp, "d.bin", b"budget");
+        storage
+            .add_attachments(
+                &notes,
+                vec![overwrite(txt, "minutes.txt"), overwrite(csv, "costs.csv")],
+                now,
+            )
+            .unwrap();
+        storage
+            .add_attachments(
+                &code,
+                vec![overwrite(rs, "lib.rs"), overwrite(bin, "blob.bin")],
+                now,
+            )
+            .unwrap();
+
+        let matches = storage.search_attachment_text("BUDGET").unwrap();
+        assert_eq!(
+            matches
+                .iter()
+                .map(|m| (m.key.as_str(), m.filename.as_str(), m.snippet.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("code", "lib.rs", "fn budget() {}"),
+                ("notes", "costs.csv", "item,budget"),
+                ("notes", "minutes.txt", "Meeting about the Budget"),
+            ]
+        );
+        assert!(found(&storage, "nowhere").is_empty());
+        assert!(found(&storage, "  ").is_empty());
+        assert!(!text_file(&storage, &code, "blob.bin").exists());
+    }
+
+    #[test]
+    fn test_snippet_around_match_of_long_line() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("key");
+        let now = SystemTime::now();
+        storage.create(&key, now).unwrap();
+        let line = format!("{}needle{}", "a".repeat(300), "b".repeat(300));
+        let file = create_test_file(&temp, "long.txt", line.as_bytes());
+        storage
+            .add_attachments(&key, vec![overwrite(file, "long.txt")], now)
+            .unwrap();
+
+        let matches = storage.search_attachment_text("needle").unwrap();
+        assert_eq!(matches[0].snippet.chars().count(), SNIPPET_LEN);
+        assert!(matches[0].snippet.contains("needle"));
+    }
+
+    #[test]
+    fn test_text_follows_attachment() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("key");
+        let now = SystemTime::now();
+        storage.create(&key, now).unwrap();
+        let file = create_test_file(&temp, "a.txt", b"searchable");
+        storage
+            .add_attachments(&key, vec![overwrite(file, "a.txt")], now)
+            .unwrap();
+
+        storage
+            .rename_attachment(&key, "a.txt", &name("b.md"), now)
+            .unwrap();
+        assert_eq!(found(&storage, "searchable"), ["key:b.md"]);
+
+        storage.remove_attachment(&key, "b.md", now).unwrap();
+        assert!(found(&storage, "searchable").is_empty());
+        assert!(!text_file(&storage, &key, "b.md").exists());
+
+        storage.undo(now).unwrap();
+        assert_eq!(found(&storage, "searchable"), ["key:b.md"]);
+
+        let renamed = make_key("renamed");
+        storage.rename(&key, &renamed, now).unwrap();
+        assert_eq!(found(&storage, "searchable"), ["renamed:b.md"]);
+
+        storage.trash(&renamed, now).unwrap();
+        assert!(found(&storage, "searchable").is_empty());
+    }
+
+    #[test]
+    fn test_search_sees_replaced_attachments_in_key_order() {
+        let (mut storage, _temp) = create_test_storage();
+        let (a, b) = (make_key("a"), make_key("b"));
+        let now = SystemTime::now();
+        // Created later, "a" expires later and comes after "b" in the database.
+        storage.create(&b, now).unwrap();
+        storage.create(&a, now + Duration::from_secs(1)).unwrap();
+        for key in [&a, &b] {
+            storage
+                .add_attachment_from_reader(key, &name("a.txt"), &b"first draft"[..], now)
+                .unwrap();
+        }
+        assert_eq!(found(&storage, "first"), ["a:a.txt", "b:a.txt"]);
+
+        storage
+            .add_attachment_from_reader(&b, &name("a.txt"), &b"second draft"[..], now)
+            .unwrap();
+        assert_eq!(found(&storage, "first"), ["a:a.txt"]);
+        assert_eq!(found(&storage, "second"), ["b:a.txt"]);
+    }
+
+    #[test]
+    fn test_missing_text_is_extracted_by_maintenance() {
+        let (mut storage, temp) = create_test_storage();
+        let key = make_key("key");
+        let now = SystemTime::now();
+        storage.create(&key, now).unwrap();
+        let file = create_test_file(&temp, "a.txt", b"older attachment");
+        storage
+            .add_attachments(&key, vec![overwrite(file, "a.txt")], now)
+            .unwrap();
+        std::fs::remove_dir_all(storage.data_dir().join("text")).unwrap();
+
+        // Searching doesn't write; the attachment is unsearchable until maintenance runs.
+        assert!(found(&storage, "older").is_empty());
+        assert!(!text_file(&storage, &key, "a.txt").exists());
+
+        let result = storage
+            .maintenance(now, make_gc_config(1000, 1000))
+            .unwrap();
+        assert_eq!(result.text_extracted, 1);
+        assert_eq!(found(&storage, "older"), ["key:a.txt"]);
+    }
+
+    #[test]
+    fn test_pdf_text_is_left_for_maintenance() {
+        let (mut storage, _temp) = create_test_storage();
+        let key = make_key("key");
+        let now = SystemTime::now();
+        storage.create(&key, now).unwrap();
+        storage
+            .add_attachment_from_reader(&key, &name("scan.pdf"), &b"%PDF-1.4 no text"[..], now)
+            .unwrap();
+        assert!(!text_file(&storage, &key, "scan.pdf").exists());
+
+        let result = storage
+            .maintenance(now, make_gc_config(1000, 1000))
+            .unwrap();
+        assert_eq!(result.text_extracted, 1);
+        assert!(text_file(&storage, &key, "scan.pdf").exists());
+    }
+
+    #[test]
+    fn test_encrypted_text_and_sealed_keys() {
+        let temp = TempDir::new().unwrap();
+        let config = Config {
+            base_path: temp.path().join("store"),
+        };
+        let mut storage = KevaCore::open_encrypted(config, "passphrase").unwrap();
+        let open = make_key("open");
+        let sealed = make_key("sealed");
+        let now = SystemTime::now();
+        for key in [&open, &sealed] {
+            storage.create(key, now).unwrap();
+            let file = create_test_file(&temp, "a.txt", b"secret phrase");
+            storage
+                .add_attachments(key, vec![overwrite(file, "a.txt")], now)
+                .unwrap();
+        }
+        storage.seal(&sealed, "pin").unwrap();
+
+        let on_disk = std::fs::read(text_file(&storage, &open, "a.txt")).unwrap();
+        assert!(!on_disk.windows(6).any(|w| w == b"secret"));
+        assert_eq!(found(&storage, "secret"), ["open:a.txt"]);
+
+        storage.unlock(&sealed, "pin", now).unwrap();
+        assert_eq!(found(&storage, "secret"), ["open:a.txt", "sealed:a.txt"]);
+    }
+}
+
+mod image_search {
+    use super::*;
+    use crate::types::ImageMetadata;
+
+    fn photo(captured_at: Option<&str>, camera: Option<&str>) -> ImageMetadata {
+        ImageMetadata {
+            width: 4000,
+            height: 3000,
+            captured_at: captured_at.map(String::from),
+            camera: camera.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_filter_conditions() {
+        let image = photo(Some("2024-06-15T10:30:00"), Some("Apple iPhone 15 Pro"));
+        let filter = |f: ImageFilter| f.matches(&image);
+
+        assert!(filter(ImageFilter::default()));
+        assert!(filter(ImageFilter {
+            min_width: Some(4000),
+            min_height: Some(3000),
+            ..Default::default()
+        }));
+        assert!(!filter(ImageFilter {
+            min_width: Some(4001),
+            ..Default::default()
+        }));
+        assert!(filter(ImageFilter {
+            captured_from: Some("2024-06-15".to_string()),
+            captured_to: Some("2024-06".to_string()),
+            ..Default::default()
+        }));
+        assert!(!filter(ImageFilter {
+            captured_from: Some("2024-07".to_string()),
+            ..Default::default()
+        }));
+        assert!(!filter(ImageFilter {
+            captured_to: Some("2024-06-14".to_string()),
+            ..Default::default()
+        }));
+        assert!(filter(ImageFilter {
+            camera: Some("iphone".to_string()),
+            ..Default::default()
+        }));
+        assert!(!filter(ImageFilter {
+            camera: Some("canon".to_string()),
+            ..Default::default()
+        }));
+
+        // Conditions on missing metadata fail
+        let bare = photo(None, None);
+        assert!(
+            !ImageFilter {
+                captured_from: Some("2000".to_string()),
+                ..Default::default()
+            }
+            .matches(&bare)
+        );
+        assert!(
+            !ImageFilter {
+                camera: Some(String::new()),
+                ..Default::default()
+            }
+            .matches(&bare)
+        );
+    }
+
+    #[test]
+    fn test_find_images_by_size() {
+        let (mut storage, temp) = create_test_storage();
+        let (trashed, kept) = (make_key("b/photos"), make_key("a/photos"));
+        let now = SystemTime::now();
+        let large = temp.path().join("large.png");
+        image::RgbImage::new(64, 48).save(&large).unwrap();
+        let small = temp.path().join("small.png");
+        image::RgbImage::new(8, 8).save(&small).unwrap();
+        let text = create_test_file(&temp, "notes.txt", b"not an image");
+
+        for key in [&trashed, &kept] {
+            storage.create(key, now).unwrap();
+            storage
+                .add_attachments(
+                    key,
+                    vec![
+                        overwrite(large.clone(), "wide.png"),
+                        overwrite(small.clone(), "icon.png"),
+                        overwrite(text.clone(), "notes.txt"),
+                    ],
+                    now,
+                )
+                .unwrap();
+        }
+        storage.trash(&trashed, now).unwrap();
+        let c = make_key("c");
+        storage.create(&c, now).unwrap();
+        storage
+            .add_attachments(&c, vec![overwrite(large.clone(), "z.png")], now)
+            .unwrap();
+
+        let found = |filter: &ImageFilter| -> Vec<String> {
+            storage
+                .find_images(filter)
+                .unwrap()
+                .into_iter()
+                .map(|m| format!("{}:{}", m.key, m.filename))
+                .collect()
+        };
+        assert_eq!(
+            found(&ImageFilter::default()),
+            vec!["a/photos:icon.png", "a/photos:wide.png", "c:z.png"]
+        );
+        assert_eq!(
+            found(&ImageFilter {
+                min_width: Some(32),
+                ..Default::default()
+            }),
+            vec!["a/photos:wide.png", "c:z.png"]
+        );
+    }
+}
+
+mod token {
+    use super::*;
+
+    #[test]
+    fn test_token_is_created_once_and_reused() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("nested/server.token");
+
+        let token = load_or_create_token(&path).unwrap();
+        assert_eq!(token.len(), 64);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), token);
+        assert_eq!(load_or_create_token(&path).unwrap(), token);
+
+        std::fs::write(&path, "  \n").unwrap();
+        assert_ne!(load_or_create_token(&path).unwrap(), token);
     }
 }
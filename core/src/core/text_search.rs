@@ -0,0 +1,180 @@
+//! Finding keys by the text of their attachments.
+//!
+//! Plain text is extracted when an attachment is added and other formats by maintenance (see
+//! [`FileStorage::store_text`]), into `text/{key_hash}/`, encrypted like the attachment itself. Searches keep the text they read in
+//! memory, so only attachments added or replaced since the last search are read from disk.
+
+use super::KevaCore;
+use crate::core::error::KevaError;
+use crate::core::file_storage::FileStorage;
+use crate::types::Key;
+use crate::types::value::versioned_value::latest_value::Attachment;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+
+/// Longest [`AttachmentMatch::snippet`], in characters.
+pub const SNIPPET_LEN: usize = 120;
+
+/// An attachment whose text contains the query, returned by
+/// [`KevaCore::search_attachment_text`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachmentMatch {
+    pub key: Key,
+    pub filename: String,
+    /// Part of the first line containing the query, trimmed to at most [`SNIPPET_LEN`]
+    /// characters around the match.
+    pub snippet: String,
+}
+
+/// Extracted text of the attachments seen by the last search, by key and filename.
+///
+/// An entry is reused while the attachment's hash and modification time are unchanged, which is
+/// the case exactly while its text file is. Entries of attachments the last search didn't see,
+/// such as removed ones or those of keys locked since, are dropped.
+#[derive(Default)]
+pub(crate) struct TextCache(Mutex<HashMap<(Key, String), CachedText>>);
+
+struct CachedText {
+    version: (Option<blake3::Hash>, Option<SystemTime>),
+    text: String,
+    /// `text` lowercased, to rule out attachments without reading them line by line.
+    lowered: String,
+}
+
+impl TextCache {
+    fn lock(&self) -> MutexGuard<'_, HashMap<(Key, String), CachedText>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Drops the text of a key's attachments, e.g. once the key is purged or renamed.
+    pub(crate) fn forget_key(&self, key: &Key) {
+        self.lock().retain(|(cached_key, _), _| cached_key != key);
+    }
+}
+
+/// Attachment text search.
+impl KevaCore {
+    /// Returns the attachments of Active keys whose text contains `query`, ignoring case, in key
+    /// and then filename order. An empty query matches nothing.
+    ///
+    /// Locked sealed keys are skipped, as are attachments without text yet (see
+    /// [`KevaCore::extract_missing_text`]) and those whose text can't be read.
+    pub fn search_attachment_text(&self, query: &str) -> Result<Vec<AttachmentMatch>, KevaError> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut keys = self.db.active_keys()?;
+        keys.sort();
+
+        let mut cache = self.text_cache.lock();
+        let mut previous = std::mem::take(&mut *cache);
+        let mut matches = Vec::new();
+        for key in keys {
+            let Some(value) = self.db.get(&key)? else {
+                continue;
+            };
+            let Ok(key_files) = self.files_for(&key, &value) else {
+                continue;
+            };
+
+            let mut attachments = value.attachments;
+            attachments.sort_by(|a, b| a.filename.cmp(&b.filename));
+            for attachment in attachments {
+                let id = (key.clone(), attachment.filename.clone());
+                let version = (attachment.hash, attachment.modified_at);
+                let cached = match previous.remove(&id) {
+                    Some(cached) if cached.version == version => cached,
+                    _ => match read_text(&key_files, &key, &attachment) {
+                        Some(text) => CachedText {
+                            version,
+                            lowered: text.to_lowercase(),
+                            text,
+                        },
+                        None => continue,
+                    },
+                };
+                if cached.lowered.contains(&query)
+                    && let Some(snippet) = find_snippet(&cached.text, &query)
+                {
+                    matches.push(AttachmentMatch {
+                        key: key.clone(),
+                        filename: attachment.filename,
+                        snippet,
+                    });
+                }
+                cache.insert(id, cached);
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Extracts the text of attachments that have none yet, such as PDFs, which aren't extracted
+    /// when added since they are read whole, and attachments that predate extraction. Returns how
+    /// many were extracted; attachments of locked sealed keys are left for later.
+    ///
+    /// Runs as part of [`KevaCore::maintenance`].
+    pub fn extract_missing_text(&self) -> Result<usize, KevaError> {
+        let mut extracted = 0;
+        for key in self.db.active_keys()? {
+            let Some(value) = self.db.get(&key)? else {
+                continue;
+            };
+            let Ok(key_files) = self.files_for(&key, &value) else {
+                continue;
+            };
+            let key_hash = Self::key_to_path(&key);
+            for attachment in &value.attachments {
+                if FileStorage::supports_text(&attachment.filename)
+                    && !key_files
+                        .text_file_path(&key_hash, &attachment.filename)
+                        .exists()
+                {
+                    key_files.write_text(&key_hash, &attachment.filename)?;
+                    extracted += 1;
+                }
+            }
+        }
+        Ok(extracted)
+    }
+}
+
+/// Reads the extracted text of an attachment, or `None` if it has none or it can't be read.
+fn read_text(files: &FileStorage, key: &Key, attachment: &Attachment) -> Option<String> {
+    if !FileStorage::supports_text(&attachment.filename) {
+        return None;
+    }
+    files
+        .read_text(&KevaCore::key_to_path(key), &attachment.filename)
+        .ok()
+        .flatten()
+}
+
+/// Returns the part of the first line of `text` containing `query`, which must be lowercase.
+fn find_snippet(text: &str, query: &str) -> Option<String> {
+    for line in text.lines() {
+        // Lowercase char by char, remembering where each lowercase char came from, since
+        // lowercasing can change the number of chars.
+        let chars: Vec<char> = line.chars().collect();
+        let mut lowered = String::with_capacity(line.len());
+        let mut origins = Vec::with_capacity(chars.len());
+        for (i, c) in chars.iter().enumerate() {
+            for lower in c.to_lowercase() {
+                lowered.push(lower);
+                origins.push(i);
+            }
+        }
+        let Some(pos) = lowered.find(query) else {
+            continue;
+        };
+
+        let matched = origins[lowered[..pos].chars().count()];
+        let start = matched.saturating_sub(SNIPPET_LEN / 4);
+        let end = (start + SNIPPET_LEN).min(chars.len());
+        let start = end.saturating_sub(SNIPPET_LEN);
+        let snippet: String = chars[start..end].iter().collect();
+        return Some(snippet.trim().to_string());
+    }
+    None
+}
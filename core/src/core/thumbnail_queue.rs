@@ -0,0 +1,275 @@
+//! Background thumbnail generation.
+//!
+//! Attachments are rendered on a small pool of threads, so adding them doesn't wait for image
+//! decoding. Each job carries a ticket; cancelling or re-queuing an attachment invalidates its
+//! ticket, and a job whose ticket is no longer current is dropped without writing anything.
+//! Thumbnails are written while holding the queue lock, so once [`ThumbnailQueue::cancel`]
+//! returns no stale thumbnail can appear for the attachment.
+
+use crate::core::file_storage::FileStorage;
+use crate::types::Key;
+use std::collections::{HashMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+
+/// Progress of an attachment's thumbnail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThumbnailStatus {
+    /// Queued or being rendered.
+    Pending,
+    /// The thumbnails are stored.
+    Ready,
+    /// Rendering failed; the attachment is shown with an icon.
+    Failed(String),
+}
+
+/// Called on a pool thread once an attachment's thumbnail is `Ready` or `Failed`, with the key
+/// and attachment filename.
+pub type ThumbnailCallback = Arc<dyn Fn(&Key, &str, &ThumbnailStatus) + Send + Sync>;
+
+/// Upper bound on pool threads; rendering is memory-hungry for large photos.
+const MAX_WORKERS: usize = 4;
+
+struct Job {
+    storage: FileStorage,
+    key: Key,
+    key_hash: PathBuf,
+    filename: String,
+    ticket: u64,
+}
+
+struct Entry {
+    status: ThumbnailStatus,
+    ticket: u64,
+}
+
+#[derive(Default)]
+struct State {
+    jobs: VecDeque<Job>,
+    /// Pending and failed attachments. Ready entries are dropped; the stored thumbnail tells.
+    entries: HashMap<Key, HashMap<String, Entry>>,
+    next_ticket: u64,
+    /// Jobs taken by a worker whose callback hasn't returned yet.
+    running: usize,
+    shutdown: bool,
+    callback: Option<ThumbnailCallback>,
+}
+
+impl State {
+    fn entry_mut(&mut self, job: &Job) -> Option<&mut Entry> {
+        self.entries
+            .get_mut(&job.key)?
+            .get_mut(&job.filename)
+            .filter(|entry| entry.ticket == job.ticket)
+    }
+
+    fn is_current(&mut self, job: &Job) -> bool {
+        self.entry_mut(job).is_some()
+    }
+
+    fn remove(&mut self, key: &Key, filename: &str) -> Option<Entry> {
+        let files = self.entries.get_mut(key)?;
+        let entry = files.remove(filename);
+        if files.is_empty() {
+            self.entries.remove(key);
+        }
+        entry
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Signalled when a job is queued or shutdown begins.
+    work: Condvar,
+    /// Signalled when a worker finds the queue empty and nothing running.
+    idle: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Queue of thumbnail jobs with per-attachment status. Threads are started on demand.
+///
+/// Dropping the queue finishes the queued jobs before joining the threads, so closing the store
+/// doesn't lose thumbnails whose version is already recorded.
+pub(crate) struct ThumbnailQueue {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+    max_workers: usize,
+}
+
+impl ThumbnailQueue {
+    pub(crate) fn new() -> Self {
+        let max_workers = thread::available_parallelism()
+            .map_or(1, usize::from)
+            .min(MAX_WORKERS);
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State::default()),
+                work: Condvar::new(),
+                idle: Condvar::new(),
+            }),
+            workers: Vec::new(),
+            max_workers,
+        }
+    }
+
+    /// Queues rendering of an attachment through `storage`, replacing any job already queued
+    /// for it.
+    pub(crate) fn enqueue(
+        &mut self,
+        storage: FileStorage,
+        key: &Key,
+        key_hash: &Path,
+        filename: &str,
+    ) {
+        {
+            let mut state = self.shared.lock();
+            state.next_ticket += 1;
+            let ticket = state.next_ticket;
+            state.entries.entry(key.clone()).or_default().insert(
+                filename.to_string(),
+                Entry {
+                    status: ThumbnailStatus::Pending,
+                    ticket,
+                },
+            );
+            state.jobs.push_back(Job {
+                storage,
+                key: key.clone(),
+                key_hash: key_hash.to_path_buf(),
+                filename: filename.to_string(),
+                ticket,
+            });
+        }
+        self.shared.work.notify_one();
+
+        if self.workers.len() < self.max_workers {
+            let shared = self.shared.clone();
+            self.workers.push(thread::spawn(move || run(&shared)));
+        }
+    }
+
+    /// Forgets an attachment's status and drops its queued job.
+    ///
+    /// Returns whether the thumbnail was still pending.
+    pub(crate) fn cancel(&self, key: &Key, filename: &str) -> bool {
+        let entry = self.shared.lock().remove(key, filename);
+        entry.is_some_and(|e| e.status == ThumbnailStatus::Pending)
+    }
+
+    /// Forgets the status of every attachment of `key` and drops their queued jobs.
+    ///
+    /// Returns the filenames whose thumbnails were still pending.
+    pub(crate) fn cancel_key(&self, key: &Key) -> Vec<String> {
+        let files = self.shared.lock().entries.remove(key).unwrap_or_default();
+        files
+            .into_iter()
+            .filter(|(_, entry)| entry.status == ThumbnailStatus::Pending)
+            .map(|(filename, _)| filename)
+            .collect()
+    }
+
+    /// Status of a pending or failed attachment; `None` if the queue doesn't track it.
+    pub(crate) fn status(&self, key: &Key, filename: &str) -> Option<ThumbnailStatus> {
+        let state = self.shared.lock();
+        Some(state.entries.get(key)?.get(filename)?.status.clone())
+    }
+
+    pub(crate) fn set_callback(&self, callback: Option<ThumbnailCallback>) {
+        self.shared.lock().callback = callback;
+    }
+
+    /// Blocks until every queued job is finished and its callback has returned.
+    pub(crate) fn wait(&self) {
+        let mut state = self.shared.lock();
+        while !state.jobs.is_empty() || state.running > 0 {
+            state = self
+                .shared
+                .idle
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+impl Drop for ThumbnailQueue {
+    fn drop(&mut self) {
+        self.shared.lock().shutdown = true;
+        self.shared.work.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run(shared: &Shared) {
+    let mut state = shared.lock();
+    loop {
+        let Some(job) = state.jobs.pop_front() else {
+            if state.running == 0 {
+                shared.idle.notify_all();
+            }
+            if state.shutdown {
+                return;
+            }
+            state = shared
+                .work
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+            continue;
+        };
+        if !state.is_current(&job) {
+            continue;
+        }
+        state.running += 1;
+        drop(state);
+
+        // Panics fail the attachment instead of taking the thread down, which would stall `wait`.
+        let rendered = panic::catch_unwind(AssertUnwindSafe(|| {
+            job.storage.render_thumbnails(&job.key_hash, &job.filename)
+        }));
+
+        state = shared.lock();
+        let finished = if state.is_current(&job) {
+            let result = match rendered {
+                Ok(Ok(variants)) => job
+                    .storage
+                    .write_thumbnails(&job.key_hash, &job.filename, &variants)
+                    .map_err(|e| e.to_string()),
+                Ok(Err(e)) => Err(e.to_string()),
+                Err(_) => Err("Renderer panicked".to_string()),
+            };
+            let status = match result {
+                Ok(()) => {
+                    state.remove(&job.key, &job.filename);
+                    ThumbnailStatus::Ready
+                }
+                Err(message) => {
+                    let status = ThumbnailStatus::Failed(message);
+                    if let Some(entry) = state.entry_mut(&job) {
+                        entry.status = status.clone();
+                    }
+                    status
+                }
+            };
+            state.callback.clone().map(|callback| (callback, status))
+        } else {
+            None
+        };
+
+        if let Some((callback, status)) = finished {
+            drop(state);
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+                callback(&job.key, &job.filename, &status)
+            }));
+            state = shared.lock();
+        }
+        state.running -= 1;
+    }
+}
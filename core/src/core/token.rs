@@ -0,0 +1,37 @@
+//! Secrets that local servers (keva_server, keva_webdav) store in the data directory.
+
+use crate::core::crypto::random_bytes;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Token length in random bytes, hex-encoded on disk.
+const TOKEN_BYTES: usize = 32;
+
+/// Reads the token at `path`, or generates one and writes it there, readable only by the
+/// current user on Unix.
+pub fn load_or_create_token(path: &Path) -> io::Result<String> {
+    if path.exists() {
+        let token = std::fs::read_to_string(path)?.trim().to_string();
+        if !token.is_empty() {
+            return Ok(token);
+        }
+    }
+
+    let token: String = random_bytes::<TOKEN_BYTES>()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(token.as_bytes())?;
+    Ok(token)
+}
@@ -0,0 +1,633 @@
+//! Undoing and redoing destructive key operations.
+//!
+//! Undoable operations push an entry on an in-memory undo stack. Files they would delete are
+//! moved into `holding/{id}` instead, so undo can put them back. Undo applies the inverse
+//! operation and moves the entry to the redo stack; any new undoable operation clears the redo
+//! stack. Entries older than the undo window are dropped along with their held files.
+
+use super::KevaCore;
+use crate::core::db::error::DatabaseError;
+use crate::core::error::KevaError;
+use crate::types::Key;
+use crate::types::value::versioned_value::latest_value;
+use crate::types::value::versioned_value::latest_value::Attachment;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// An operation that [`KevaCore::undo`] and [`KevaCore::redo`] can revert and reapply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UndoAction {
+    Renamed {
+        from: Key,
+        to: Key,
+    },
+    /// A rename that purged the key already at `to`.
+    Replaced {
+        from: Key,
+        to: Key,
+    },
+    Trashed(Key),
+    Purged(Key),
+    AttachmentRemoved {
+        key: Key,
+        filename: String,
+    },
+    AttachmentRenamed {
+        key: Key,
+        from: String,
+        to: String,
+    },
+    AttachmentOverwritten {
+        key: Key,
+        filename: String,
+    },
+    /// An attachment rename that replaced the attachment already named `to`.
+    AttachmentReplaced {
+        key: Key,
+        from: String,
+        to: String,
+    },
+}
+
+/// An undoable operation with the rows needed to apply it in either direction.
+enum Step {
+    Rename {
+        from: Key,
+        to: Key,
+    },
+    Replace {
+        from: Key,
+        to: Key,
+        replaced: latest_value::Value,
+    },
+    Trash(Key),
+    Purge {
+        key: Key,
+        value: latest_value::Value,
+    },
+    RemoveAttachment {
+        key: Key,
+        attachment: Attachment,
+    },
+    RenameAttachment {
+        key: Key,
+        from: String,
+        to: String,
+    },
+    OverwriteAttachment {
+        key: Key,
+        old: Attachment,
+        new: Attachment,
+    },
+    ReplaceAttachment {
+        key: Key,
+        from: String,
+        replaced: Attachment,
+    },
+}
+
+impl Step {
+    fn action(&self) -> UndoAction {
+        match self {
+            Step::Rename { from, to } => UndoAction::Renamed {
+                from: from.clone(),
+                to: to.clone(),
+            },
+            Step::Replace { from, to, .. } => UndoAction::Replaced {
+                from: from.clone(),
+                to: to.clone(),
+            },
+            Step::Trash(key) => UndoAction::Trashed(key.clone()),
+            Step::Purge { key, .. } => UndoAction::Purged(key.clone()),
+            Step::RemoveAttachment { key, attachment } => UndoAction::AttachmentRemoved {
+                key: key.clone(),
+                filename: attachment.filename.clone(),
+            },
+            Step::RenameAttachment { key, from, to } => UndoAction::AttachmentRenamed {
+                key: key.clone(),
+                from: from.clone(),
+                to: to.clone(),
+            },
+            Step::OverwriteAttachment { key, new, .. } => UndoAction::AttachmentOverwritten {
+                key: key.clone(),
+                filename: new.filename.clone(),
+            },
+            Step::ReplaceAttachment {
+                key,
+                from,
+                replaced,
+            } => UndoAction::AttachmentReplaced {
+                key: key.clone(),
+                from: from.clone(),
+                to: replaced.filename.clone(),
+            },
+        }
+    }
+}
+
+pub(super) struct UndoEntry {
+    /// Names the entry's directory in the holding area.
+    id: u64,
+    at: SystemTime,
+    step: Step,
+}
+
+/// Undo history of a [`KevaCore`].
+pub(super) struct UndoHistory {
+    holding_path: PathBuf,
+    window: Duration,
+    next_id: u64,
+    undo: Vec<UndoEntry>,
+    redo: Vec<UndoEntry>,
+}
+
+impl UndoHistory {
+    pub(super) fn new(holding_path: PathBuf) -> Self {
+        Self {
+            holding_path,
+            window: KevaCore::DEFAULT_UNDO_WINDOW,
+            next_id: 0,
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Reserves an entry id and returns it with the entry's holding directory.
+    ///
+    /// Ids start from the current time so they don't collide with directories left behind by
+    /// an earlier session that maintenance hasn't removed yet.
+    fn reserve(&mut self, now: SystemTime) -> (u64, PathBuf) {
+        let since_epoch = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        self.next_id = self.next_id.max(since_epoch.as_micros() as u64) + 1;
+        (
+            self.next_id,
+            self.holding_path.join(self.next_id.to_string()),
+        )
+    }
+
+    fn push(&mut self, id: u64, now: SystemTime, step: Step) {
+        for entry in std::mem::take(&mut self.redo) {
+            self.discard(&entry);
+        }
+        self.undo.push(UndoEntry { id, at: now, step });
+    }
+
+    fn holding(&self, id: u64) -> PathBuf {
+        self.holding_path.join(id.to_string())
+    }
+
+    /// Deletes the entry's held files.
+    ///
+    /// Failures are ignored; maintenance removes whatever is left.
+    fn discard(&self, entry: &UndoEntry) {
+        let _ = std::fs::remove_dir_all(self.holding(entry.id));
+    }
+
+    fn expire(&mut self, now: SystemTime) {
+        let (holding_path, window) = (&self.holding_path, self.window);
+        let keep = |entry: &UndoEntry| {
+            let expired = now.duration_since(entry.at).is_ok_and(|age| age > window);
+            if expired {
+                let _ = std::fs::remove_dir_all(holding_path.join(entry.id.to_string()));
+            }
+            !expired
+        };
+        self.undo.retain(keep);
+        self.redo.retain(keep);
+    }
+
+    fn clear(&mut self) {
+        for entry in std::mem::take(&mut self.undo)
+            .into_iter()
+            .chain(std::mem::take(&mut self.redo))
+        {
+            self.discard(&entry);
+        }
+    }
+
+    /// Removes holding directories that belong to no entry, returning how many were removed.
+    ///
+    /// On open the history is empty, so this clears what earlier sessions left behind.
+    pub(super) fn remove_orphans(&self) -> Result<usize, std::io::Error> {
+        if !self.holding_path.exists() {
+            return Ok(0);
+        }
+
+        let live: HashSet<String> = self
+            .undo
+            .iter()
+            .chain(&self.redo)
+            .map(|entry| entry.id.to_string())
+            .collect();
+
+        let mut removed = 0;
+        for dir in std::fs::read_dir(&self.holding_path)? {
+            let dir = dir?;
+            if !live.contains(dir.file_name().to_string_lossy().as_ref()) {
+                std::fs::remove_dir_all(dir.path())?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Undo operations.
+///
+/// Covers [`rename`](KevaCore::rename), [`rename_replacing`](KevaCore::rename_replacing),
+/// [`trash`](KevaCore::trash), [`purge`](KevaCore::purge),
+/// [`remove_attachment`](KevaCore::remove_attachment),
+/// [`rename_attachment`](KevaCore::rename_attachment) and attachments overwritten by
+/// [`add_attachments`](KevaCore::add_attachments). Batches, subtree operations, sync and garbage
+/// collection are not recorded. The history lives in memory and is lost when the store is
+/// closed.
+impl KevaCore {
+    pub const DEFAULT_UNDO_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+    /// Sets how long operations stay undoable and their removed files are kept.
+    pub fn set_undo_window(&mut self, window: Duration) {
+        self.history.window = window;
+    }
+
+    /// Returns the operation the next [`KevaCore::undo`] would revert, if any.
+    pub fn next_undo(&self) -> Option<UndoAction> {
+        self.history.undo.last().map(|entry| entry.step.action())
+    }
+
+    /// Returns the operation the next [`KevaCore::redo`] would reapply, if any.
+    pub fn next_redo(&self) -> Option<UndoAction> {
+        self.history.redo.last().map(|entry| entry.step.action())
+    }
+
+    /// Reverts the most recent undoable operation, returning it, or `None` if there is nothing
+    /// left to undo within the undo window.
+    ///
+    /// Fails if the store changed in a way that conflicts with the revert, e.g. a renamed key's
+    /// old name was taken again. The operation then stays on the undo stack.
+    pub fn undo(&mut self, now: SystemTime) -> Result<Option<UndoAction>, KevaError> {
+        self.history.expire(now);
+        let Some(entry) = self.history.undo.pop() else {
+            return Ok(None);
+        };
+
+        if let Err(e) = self.apply_step(&entry, true, now) {
+            self.history.undo.push(entry);
+            return Err(e);
+        }
+        let action = entry.step.action();
+        self.history.redo.push(entry);
+        self.reset_store_size();
+        Ok(Some(action))
+    }
+
+    /// Reapplies the most recently undone operation, returning it, or `None` if there is
+    /// nothing to redo.
+    pub fn redo(&mut self, now: SystemTime) -> Result<Option<UndoAction>, KevaError> {
+        self.history.expire(now);
+        let Some(entry) = self.history.redo.pop() else {
+            return Ok(None);
+        };
+
+        if let Err(e) = self.apply_step(&entry, false, now) {
+            self.history.redo.push(entry);
+            return Err(e);
+        }
+        let action = entry.step.action();
+        self.history.undo.push(entry);
+        self.reset_store_size();
+        Ok(Some(action))
+    }
+
+    /// Drops the undo and redo history and deletes all held files.
+    pub fn clear_undo(&mut self) {
+        self.history.clear();
+    }
+
+    pub(super) fn expire_undo(&mut self, now: SystemTime) {
+        self.history.expire(now);
+    }
+
+    pub(super) fn record_rename(&mut self, from: &Key, to: &Key, now: SystemTime) {
+        let (id, _) = self.history.reserve(now);
+        let step = Step::Rename {
+            from: from.clone(),
+            to: to.clone(),
+        };
+        self.history.push(id, now, step);
+    }
+
+    pub(super) fn record_trash(&mut self, key: &Key, now: SystemTime) {
+        let (id, _) = self.history.reserve(now);
+        self.history.push(id, now, Step::Trash(key.clone()));
+    }
+
+    pub(super) fn record_rename_attachment(
+        &mut self,
+        key: &Key,
+        from: &str,
+        to: &str,
+        now: SystemTime,
+    ) {
+        let (id, _) = self.history.reserve(now);
+        let step = Step::RenameAttachment {
+            key: key.clone(),
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+        self.history.push(id, now, step);
+    }
+
+    /// Purges a key, moving its files into the holding area.
+    pub(super) fn purge_undoable(&mut self, key: &Key, now: SystemTime) -> Result<(), KevaError> {
+        let value = self.db.get(key)?.ok_or(DatabaseError::NotFound)?;
+        let (id, holding) = self.history.reserve(now);
+
+        self.purge_to_holding(key, &value, &holding, now)?;
+
+        let step = Step::Purge {
+            key: key.clone(),
+            value,
+        };
+        self.history.push(id, now, step);
+        Ok(())
+    }
+
+    /// Renames `from` over `to`, purging `to` (whose row is `replaced`) into the holding area,
+    /// and records both as one entry.
+    pub(super) fn rename_replacing_undoable(
+        &mut self,
+        from: &Key,
+        to: &Key,
+        replaced: latest_value::Value,
+        now: SystemTime,
+    ) -> Result<(), KevaError> {
+        let (id, holding) = self.history.reserve(now);
+
+        self.purge_to_holding(to, &replaced, &holding, now)?;
+        if let Err(e) = self.rename_key(from, to, now) {
+            self.restore_from_holding(to, &replaced, &holding, now)?;
+            return Err(e);
+        }
+
+        let step = Step::Replace {
+            from: from.clone(),
+            to: to.clone(),
+            replaced,
+        };
+        self.history.push(id, now, step);
+        Ok(())
+    }
+
+    /// Purges a key's row, then swaps its files with those in `holding`. The row is put back if
+    /// the files can't be moved.
+    fn purge_to_holding(
+        &mut self,
+        key: &Key,
+        value: &latest_value::Value,
+        holding: &Path,
+        now: SystemTime,
+    ) -> Result<(), KevaError> {
+        self.db.purge(key)?;
+        self.thumbnails.cancel_key(key);
+        if let Err(e) = self.file.swap_key_files(&Self::key_to_path(key), holding) {
+            self.db.reinsert(key, value.clone(), now)?;
+            return Err(e.into());
+        }
+        self.key_purged(key);
+        Ok(())
+    }
+
+    /// Reverts [`purge_to_holding`](Self::purge_to_holding): swaps the files back, then puts the
+    /// row back. The key must still be free, so a key created since keeps its files; the files
+    /// are swapped out again if the row can't be put back.
+    fn restore_from_holding(
+        &mut self,
+        key: &Key,
+        value: &latest_value::Value,
+        holding: &Path,
+        now: SystemTime,
+    ) -> Result<(), KevaError> {
+        if self.db.get(key)?.is_some() {
+            return Err(DatabaseError::AlreadyExists.into());
+        }
+        let key_hash = Self::key_to_path(key);
+        self.file.swap_key_files(&key_hash, holding)?;
+        if let Err(e) = self.db.reinsert(key, value.clone(), now) {
+            let _ = self.file.swap_key_files(&key_hash, holding);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Removes an attachment, moving its files into the holding area.
+    ///
+    /// Returns the reserved entry id; the caller records the removal or overwrite with it.
+    pub(super) fn hold_attachment(
+        &mut self,
+        key: &Key,
+        filename: &str,
+        now: SystemTime,
+    ) -> Result<u64, KevaError> {
+        let (id, holding) = self.history.reserve(now);
+
+        self.db.remove_attachment(key, filename, now)?;
+        self.thumbnails.cancel(key, filename);
+        self.file
+            .swap_attachment_files(&Self::key_to_path(key), filename, &holding)?;
+        Ok(id)
+    }
+
+    /// Puts back an attachment moved aside by [`hold_attachment`](Self::hold_attachment) when the
+    /// operation holding it fails, discarding any file that took its place.
+    pub(super) fn unhold_attachment(
+        &mut self,
+        id: u64,
+        key: &Key,
+        attachment: Attachment,
+        now: SystemTime,
+    ) -> Result<(), KevaError> {
+        let holding = self.history.holding(id);
+        self.file
+            .swap_attachment_files(&Self::key_to_path(key), &attachment.filename, &holding)?;
+        let filename = attachment.filename.clone();
+        self.db.add_attachment(key, attachment, now)?;
+        let _ = std::fs::remove_dir_all(holding);
+        self.queue_missing_thumbnail(key, &filename);
+        Ok(())
+    }
+
+    /// Renames attachment `from` over `replaced`, moving the replaced files into the holding
+    /// area, and records it as one entry.
+    pub(super) fn rename_attachment_replacing_undoable(
+        &mut self,
+        key: &Key,
+        from: &str,
+        replaced: Attachment,
+        now: SystemTime,
+    ) -> Result<(), KevaError> {
+        let (id, holding) = self.history.reserve(now);
+
+        self.rename_attachment_over(key, from, &replaced.filename, &holding, now)?;
+
+        let step = Step::ReplaceAttachment {
+            key: key.clone(),
+            from: from.to_string(),
+            replaced,
+        };
+        self.history.push(id, now, step);
+        self.reset_store_size();
+        Ok(())
+    }
+
+    /// Swaps the files of attachment `to` with those in `holding`, then renames `from` to `to`,
+    /// which drops the row of `to` in the same transaction. The files are swapped back if the
+    /// rename fails.
+    fn rename_attachment_over(
+        &mut self,
+        key: &Key,
+        from: &str,
+        to: &str,
+        holding: &Path,
+        now: SystemTime,
+    ) -> Result<(), KevaError> {
+        let key_hash = Self::key_to_path(key);
+        self.thumbnails.cancel(key, to);
+        self.file.swap_attachment_files(&key_hash, to, holding)?;
+        if let Err(e) = self.db.rename_attachment(key, from, to, now) {
+            self.file.swap_attachment_files(&key_hash, to, holding)?;
+            self.queue_missing_thumbnail(key, to);
+            return Err(e.into());
+        }
+        self.move_attachment_files(key, from, to)
+    }
+
+    pub(super) fn record_remove_attachment(
+        &mut self,
+        id: u64,
+        key: &Key,
+        attachment: Attachment,
+        now: SystemTime,
+    ) {
+        let step = Step::RemoveAttachment {
+            key: key.clone(),
+            attachment,
+        };
+        self.history.push(id, now, step);
+    }
+
+    pub(super) fn record_overwrite(
+        &mut self,
+        id: u64,
+        key: &Key,
+        old: Attachment,
+        new: Attachment,
+        now: SystemTime,
+    ) {
+        let step = Step::OverwriteAttachment {
+            key: key.clone(),
+            old,
+            new,
+        };
+        self.history.push(id, now, step);
+    }
+
+    /// Reverts (`undo`) or reapplies an entry's operation.
+    fn apply_step(
+        &mut self,
+        entry: &UndoEntry,
+        undo: bool,
+        now: SystemTime,
+    ) -> Result<(), KevaError> {
+        let holding = self.history.holding(entry.id);
+
+        match &entry.step {
+            Step::Rename { from, to } => {
+                let (src, dst) = if undo { (to, from) } else { (from, to) };
+                self.rename_key(src, dst, now)?;
+            }
+            Step::Replace { from, to, replaced } => {
+                if undo {
+                    self.rename_key(to, from, now)?;
+                    if let Err(e) = self.restore_from_holding(to, replaced, &holding, now) {
+                        let _ = self.rename_key(from, to, now);
+                        return Err(e);
+                    }
+                } else {
+                    self.purge_to_holding(to, replaced, &holding, now)?;
+                    if let Err(e) = self.rename_key(from, to, now) {
+                        self.restore_from_holding(to, replaced, &holding, now)?;
+                        return Err(e);
+                    }
+                }
+            }
+            Step::Trash(key) => {
+                if undo {
+                    self.db.restore(key, now)?;
+                } else {
+                    self.db.trash(key, now)?;
+                }
+            }
+            Step::Purge { key, value } => {
+                if undo {
+                    self.restore_from_holding(key, value, &holding, now)?;
+                } else {
+                    self.purge_to_holding(key, value, &holding, now)?;
+                }
+            }
+            Step::RemoveAttachment { key, attachment } => {
+                if undo {
+                    self.ensure_attachment_free(key, &attachment.filename)?;
+                    self.db.add_attachment(key, attachment.clone(), now)?;
+                } else {
+                    self.db.remove_attachment(key, &attachment.filename, now)?;
+                }
+                self.thumbnails.cancel(key, &attachment.filename);
+                self.file.swap_attachment_files(
+                    &Self::key_to_path(key),
+                    &attachment.filename,
+                    &holding,
+                )?;
+                self.queue_missing_thumbnail(key, &attachment.filename);
+            }
+            Step::RenameAttachment { key, from, to } => {
+                let (src, dst) = if undo { (to, from) } else { (from, to) };
+                self.rename_attachment_files(key, src, dst, now)?;
+            }
+            Step::OverwriteAttachment { key, old, new } => {
+                let (current, restored) = if undo { (new, old) } else { (old, new) };
+                self.db.remove_attachment(key, &current.filename, now)?;
+                self.db.add_attachment(key, restored.clone(), now)?;
+                self.thumbnails.cancel(key, &current.filename);
+                self.file.swap_attachment_files(
+                    &Self::key_to_path(key),
+                    &current.filename,
+                    &holding,
+                )?;
+                self.queue_missing_thumbnail(key, &current.filename);
+            }
+            Step::ReplaceAttachment {
+                key,
+                from,
+                replaced,
+            } => {
+                let to = &replaced.filename;
+                if undo {
+                    self.rename_attachment_files(key, to, from, now)?;
+                    if let Err(e) = self.db.add_attachment(key, replaced.clone(), now) {
+                        let _ = self.rename_attachment_files(key, from, to, now);
+                        return Err(e.into());
+                    }
+                    self.file
+                        .swap_attachment_files(&Self::key_to_path(key), to, &holding)?;
+                    self.queue_missing_thumbnail(key, to);
+                } else {
+                    self.rename_attachment_over(key, from, to, &holding, now)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+//! Disk usage reporting and storage quotas.
+//!
+//! Usage counts the files under `content/`, `blobs/` and `thumbnails/` as stored, so encrypted
+//! files include their overhead. Extracted attachment text, the database, the holding area of undo
+//! and sync state are not counted.
+//!
+//! To check the store size limit without walking the store on every add, the size is measured
+//! once and then grown by each attachment added. Removals, undo, redo and sync drop the measurement
+//! so the next add measures again; thumbnails and content edits are only picked up when something
+//! else does.
+
+use super::KevaCore;
+use crate::core::db::error::DatabaseError;
+use crate::core::error::KevaError;
+use crate::types::{Key, QuotaConfig};
+use thiserror::Error;
+
+/// Bytes used by content, attachment and thumbnail files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub content: u64,
+    pub blobs: u64,
+    pub thumbnails: u64,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.content + self.blobs + self.thumbnails
+    }
+}
+
+/// Disk usage of a store, returned by [`KevaCore::usage`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoreUsage {
+    /// Every file of the store, including those of no key that maintenance hasn't removed yet.
+    pub total: Usage,
+    /// Each Active and Trash key, in key order.
+    pub keys: Vec<(Key, Usage)>,
+}
+
+impl StoreUsage {
+    /// Returns up to `limit` keys using the most space, largest first.
+    pub fn largest_keys(&self, limit: usize) -> Vec<(Key, Usage)> {
+        let mut keys = self.keys.clone();
+        keys.sort_by(|(a_key, a), (b_key, b)| {
+            b.total().cmp(&a.total()).then_with(|| a_key.cmp(b_key))
+        });
+        keys.truncate(limit);
+        keys
+    }
+}
+
+/// A limit of [`QuotaConfig`] that an operation would break.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum QuotaError {
+    #[error("'{filename}' is {size} bytes; attachments may be at most {limit} bytes")]
+    AttachmentTooLarge {
+        filename: String,
+        size: u64,
+        limit: u64,
+    },
+
+    #[error("the store would grow to {size} bytes; it may use at most {limit} bytes")]
+    StoreFull { size: u64, limit: u64 },
+}
+
+/// Usage and quota operations.
+impl KevaCore {
+    /// Returns the disk usage of the store and of each key.
+    pub fn usage(&self) -> Result<StoreUsage, KevaError> {
+        let mut keys = self.db.active_keys()?;
+        keys.extend(self.db.trashed_keys()?);
+        keys.sort();
+
+        let mut usage = StoreUsage {
+            total: self.file.usage()?,
+            keys: Vec::with_capacity(keys.len()),
+        };
+        for key in keys {
+            let key_usage = self.file.key_usage(&Self::key_to_path(&key))?;
+            usage.keys.push((key, key_usage));
+        }
+        Ok(usage)
+    }
+
+    /// Returns the disk usage of a single key.
+    pub fn key_usage(&self, key: &Key) -> Result<Usage, KevaError> {
+        self.db.get(key)?.ok_or(DatabaseError::NotFound)?;
+        Ok(self.file.key_usage(&Self::key_to_path(key))?)
+    }
+
+    /// Sets the limits enforced when attachments are added from now on. Data already stored is
+    /// kept even if it exceeds them.
+    pub fn set_quota_config(&mut self, config: QuotaConfig) {
+        self.quotas = config;
+        self.store_size = None;
+    }
+
+    /// Returns the bytes the store uses if its size is limited, to check growth against.
+    pub(super) fn quota_usage(&mut self) -> Result<Option<u64>, KevaError> {
+        if self.quotas.max_store_size.is_none() {
+            return Ok(None);
+        }
+        if self.store_size.is_none() {
+            self.store_size = Some(self.file.usage()?.total());
+        }
+        Ok(self.store_size)
+    }
+
+    /// Accounts for an attachment of `replaced` bytes being replaced by one of `size` bytes.
+    pub(super) fn track_growth(&mut self, replaced: u64, size: u64) {
+        if let Some(store_size) = &mut self.store_size {
+            *store_size = (*store_size + size).saturating_sub(replaced);
+        }
+    }
+
+    /// Forgets the measured store size so the next check measures it again.
+    pub(super) fn reset_store_size(&mut self) {
+        self.store_size = None;
+    }
+
+    /// Returns the largest attachment that may replace one of `replaced` bytes, given that the
+    /// store uses `used` bytes, or `None` if any size may.
+    pub(super) fn attachment_room(&self, used: Option<u64>, replaced: u64) -> Option<u64> {
+        let store_room = match (used, self.quotas.max_store_size) {
+            (Some(used), Some(limit)) => Some(limit.saturating_sub(used) + replaced),
+            _ => None,
+        };
+        match (self.quotas.max_attachment_size, store_room) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Returns `Err(QuotaExceeded)` if an attachment of `size` bytes is over the size limit.
+    pub(super) fn check_attachment_size(&self, filename: &str, size: u64) -> Result<(), KevaError> {
+        match self.quotas.max_attachment_size {
+            Some(limit) if size > limit => Err(QuotaError::AttachmentTooLarge {
+                filename: filename.to_string(),
+                size,
+                limit,
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns `Err(QuotaExceeded)` if growing the store from `used` bytes by `growth` bytes goes
+    /// over the store size limit. `used` is `None` when there's nothing to check.
+    pub(super) fn check_store_growth(
+        &self,
+        used: Option<u64>,
+        growth: u64,
+    ) -> Result<(), KevaError> {
+        match (used, self.quotas.max_store_size) {
+            (Some(used), Some(limit)) if growth > 0 && used + growth > limit => {
+                Err(QuotaError::StoreFull {
+                    size: used + growth,
+                    limit,
+                }
+                .into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -3,6 +3,7 @@ pub mod types;
 pub mod error {
     pub use crate::core::error::KevaError;
 
+    pub use crate::core::crypto::error::CryptoError;
     pub use crate::core::db::error::DatabaseError;
     pub use crate::core::file_storage::error::FileStorageError;
 }
@@ -0,0 +1,206 @@
+use nutype::nutype;
+use serde::{Deserialize, Serialize};
+
+/// Longest attachment name in bytes. Filesystems allow 255, and the store appends suffixes such
+/// as `.thumbs` and `.txt` to attachment names.
+pub const MAX_ATTACHMENT_NAME_LENGTH: usize = 240;
+
+/// Device names Windows reserves, with or without an extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters Windows doesn't allow in filenames, besides separators and control characters.
+const FORBIDDEN_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*'];
+
+/// Used by [`AttachmentName::sanitize`] when nothing of the input is left.
+const FALLBACK_NAME: &str = "attachment";
+
+/// What to do when an added file's name is already taken by an attachment of the key, or by a
+/// file added earlier in the same call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachmentConflictResolution {
+    /// Replace the existing attachment; undoable.
+    Overwrite,
+    /// Keep both, adding the file as `name (1).ext`, `name (2).ext`, ...
+    Rename,
+    /// Leave the existing attachment and don't add the file.
+    Skip,
+    /// Fail the whole call before anything is added.
+    Fail,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AttachmentNameError {
+    #[error("name is empty")]
+    Empty,
+
+    #[error("name is longer than {MAX_ATTACHMENT_NAME_LENGTH} bytes")]
+    TooLong,
+
+    #[error("'.' and '..' are not file names")]
+    DotName,
+
+    #[error("name contains a path separator")]
+    Separator,
+
+    #[error("name contains '{0}'")]
+    ForbiddenChar(char),
+
+    #[error("'{0}' is reserved on Windows")]
+    Reserved(String),
+
+    #[error("name ends with a dot or space")]
+    TrailingDotOrSpace,
+
+    #[error("name starts with a dot")]
+    Hidden,
+}
+
+/// The filename of an attachment, valid on every platform the store is opened on.
+///
+/// Attachments stored before names were validated may not pass; lookups by name take `&str` so
+/// they stay reachable.
+#[nutype(
+    validate(with = validate_attachment_name, error = AttachmentNameError),
+    derive(
+        Debug,
+        Clone,
+        PartialEq,
+        Eq,
+        PartialOrd,
+        Ord,
+        AsRef,
+        Deref,
+        TryFrom,
+        Into,
+        Hash,
+        Borrow,
+        Display,
+        Serialize,
+        Deserialize,
+    )
+)]
+pub struct AttachmentName(String);
+
+fn validate_attachment_name(name: &str) -> Result<(), AttachmentNameError> {
+    if name.is_empty() {
+        return Err(AttachmentNameError::Empty);
+    }
+    if name.len() > MAX_ATTACHMENT_NAME_LENGTH {
+        return Err(AttachmentNameError::TooLong);
+    }
+    if name == "." || name == ".." {
+        return Err(AttachmentNameError::DotName);
+    }
+    if name.contains(['/', '\\']) {
+        return Err(AttachmentNameError::Separator);
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| c.is_control() || FORBIDDEN_CHARS.contains(c))
+    {
+        return Err(AttachmentNameError::ForbiddenChar(c));
+    }
+    if let Some(stem) = reserved_stem(name) {
+        return Err(AttachmentNameError::Reserved(stem.to_string()));
+    }
+    if name.ends_with(['.', ' ']) {
+        return Err(AttachmentNameError::TrailingDotOrSpace);
+    }
+    Ok(())
+}
+
+/// The part before the first dot if it is a reserved device name, e.g. `nul` in `nul.tar.gz`.
+fn reserved_stem(name: &str) -> Option<&str> {
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+        .then_some(stem)
+}
+
+impl AttachmentName {
+    /// Validates a name sent by a remote client, which additionally must not start with `.`:
+    /// such clients upload hidden files (e.g. the `._name` and `.DS_Store` files macOS writes next
+    /// to everything) without the user asking.
+    pub fn try_new_visible(name: &str) -> Result<Self, AttachmentNameError> {
+        if name.starts_with('.') {
+            return Err(AttachmentNameError::Hidden);
+        }
+        Self::try_new(name)
+    }
+
+    /// Turns any string, such as a name from a browser upload or another OS, into a valid
+    /// attachment name: separators and forbidden characters become `_`, control characters are
+    /// dropped, reserved names get a `_` prefix and trailing dots and spaces are trimmed. Long
+    /// names are shortened, keeping the extension.
+    pub fn sanitize(raw: &str) -> Self {
+        let mut name: String = raw
+            .chars()
+            .filter(|c| !c.is_control())
+            .map(|c| match c {
+                '/' | '\\' => '_',
+                c if FORBIDDEN_CHARS.contains(&c) => '_',
+                c => c,
+            })
+            .collect();
+        if reserved_stem(&name).is_some() {
+            name.insert(0, '_');
+        }
+        truncate_keeping_extension(&mut name);
+        let name = name.trim_end_matches(['.', ' ']);
+
+        let name = match name {
+            "" | "." | ".." => FALLBACK_NAME,
+            name => name,
+        };
+        Self::try_new(name).expect("sanitized name is valid")
+    }
+
+    /// Returns `stem (n).ext`, the `n`th name tried when keeping both files on a conflict. The
+    /// stem is shortened if the result would be too long.
+    pub fn numbered(&self, n: u32) -> Self {
+        let suffix = format!(" ({n})");
+        let (mut stem, extension) = match self.rfind('.') {
+            Some(dot)
+                if dot > 0 && self.len() - dot + suffix.len() < MAX_ATTACHMENT_NAME_LENGTH =>
+            {
+                self.split_at(dot)
+            }
+            _ => (self.as_str(), ""),
+        };
+        let max_stem = MAX_ATTACHMENT_NAME_LENGTH - suffix.len() - extension.len();
+        if stem.len() > max_stem {
+            let mut end = max_stem;
+            while !stem.is_char_boundary(end) {
+                end -= 1;
+            }
+            stem = &stem[..end];
+        }
+        Self::try_new(format!("{stem}{suffix}{extension}")).expect("numbered name is valid")
+    }
+}
+
+/// Shortens `name` to [`MAX_ATTACHMENT_NAME_LENGTH`] bytes at a character boundary, cutting the
+/// stem rather than a short extension.
+fn truncate_keeping_extension(name: &mut String) {
+    if name.len() <= MAX_ATTACHMENT_NAME_LENGTH {
+        return;
+    }
+    let extension = match name.rfind('.') {
+        Some(dot) if dot > 0 && name.len() - dot <= 16 => name.split_off(dot),
+        _ => String::new(),
+    };
+    let mut end = MAX_ATTACHMENT_NAME_LENGTH - extension.len();
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    name.truncate(end);
+    name.push_str(&extension);
+}
+
+#[cfg(test)]
+mod tests;
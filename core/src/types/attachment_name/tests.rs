@@ -0,0 +1,142 @@
+use super::*;
+
+#[test]
+fn attachment_name_normal_usage() {
+    for name in [
+        "report.pdf",
+        "a..b.txt",
+        ".env",
+        "photo (1).JPG",
+        "日本語.md",
+        "no extension",
+    ] {
+        let attachment_name = AttachmentName::try_from(name).unwrap();
+        assert_eq!(attachment_name.as_str(), name);
+    }
+}
+
+#[test]
+fn attachment_name_rejects_dot_names_and_separators() {
+    assert_eq!(
+        AttachmentName::try_from(""),
+        Err(AttachmentNameError::Empty)
+    );
+    assert_eq!(
+        AttachmentName::try_from("."),
+        Err(AttachmentNameError::DotName)
+    );
+    assert_eq!(
+        AttachmentName::try_from(".."),
+        Err(AttachmentNameError::DotName)
+    );
+    for name in ["../escape", "dir/file.txt", "dir\\file.txt"] {
+        assert_eq!(
+            AttachmentName::try_from(name),
+            Err(AttachmentNameError::Separator)
+        );
+    }
+}
+
+#[test]
+fn attachment_name_try_new_visible_rejects_hidden_names() {
+    for name in [".env", "._report.pdf", ".DS_Store"] {
+        assert_eq!(
+            AttachmentName::try_new_visible(name),
+            Err(AttachmentNameError::Hidden)
+        );
+    }
+    assert_eq!(
+        AttachmentName::try_new_visible("report.pdf")
+            .unwrap()
+            .as_str(),
+        "report.pdf"
+    );
+    assert_eq!(
+        AttachmentName::try_new_visible("a/b"),
+        Err(AttachmentNameError::Separator)
+    );
+}
+
+#[test]
+fn attachment_name_rejects_windows_reserved_names() {
+    for name in ["CON", "nul.txt", "Com1.tar.gz", "LPT9"] {
+        assert!(matches!(
+            AttachmentName::try_from(name),
+            Err(AttachmentNameError::Reserved(_))
+        ));
+    }
+    for name in ["CONSOLE.txt", "nullable", "COM10"] {
+        AttachmentName::try_from(name).unwrap();
+    }
+}
+
+#[test]
+fn attachment_name_rejects_forbidden_chars_and_trailing_dots() {
+    assert_eq!(
+        AttachmentName::try_from("a:b.txt"),
+        Err(AttachmentNameError::ForbiddenChar(':'))
+    );
+    assert_eq!(
+        AttachmentName::try_from("tab\there"),
+        Err(AttachmentNameError::ForbiddenChar('\t'))
+    );
+    for name in ["file.", "file ", "file. ."] {
+        assert_eq!(
+            AttachmentName::try_from(name),
+            Err(AttachmentNameError::TrailingDotOrSpace)
+        );
+    }
+}
+
+#[test]
+fn attachment_name_rejects_too_long_names() {
+    let name = "a".repeat(MAX_ATTACHMENT_NAME_LENGTH);
+    AttachmentName::try_from(name.as_str()).unwrap();
+    let name = "a".repeat(MAX_ATTACHMENT_NAME_LENGTH + 1);
+    assert_eq!(
+        AttachmentName::try_from(name.as_str()),
+        Err(AttachmentNameError::TooLong)
+    );
+}
+
+#[test]
+fn sanitize_produces_valid_names() {
+    let cases = [
+        ("report.pdf", "report.pdf"),
+        ("../../etc/passwd", ".._.._etc_passwd"),
+        ("a:b*c?.txt", "a_b_c_.txt"),
+        ("line\nbreak.txt", "linebreak.txt"),
+        ("CON.txt", "_CON.txt"),
+        ("trailing... ", "trailing"),
+        ("..", "attachment"),
+        ("", "attachment"),
+        ("\u{7}", "attachment"),
+    ];
+    for (raw, expected) in cases {
+        assert_eq!(AttachmentName::sanitize(raw).as_str(), expected, "{raw:?}");
+    }
+}
+
+#[test]
+fn sanitize_shortens_keeping_extension() {
+    let raw = format!("{}.tar.gz", "é".repeat(200));
+    let name = AttachmentName::sanitize(&raw);
+
+    assert!(name.len() <= MAX_ATTACHMENT_NAME_LENGTH);
+    assert!(name.ends_with("é.gz"));
+}
+
+#[test]
+fn attachment_name_numbered() {
+    let numbered = |name: &str, n| AttachmentName::try_from(name).unwrap().numbered(n);
+
+    assert_eq!(numbered("report.pdf", 1).as_str(), "report (1).pdf");
+    assert_eq!(numbered("archive.tar.gz", 2).as_str(), "archive.tar (2).gz");
+    assert_eq!(numbered("no extension", 3).as_str(), "no extension (3)");
+    assert_eq!(numbered(".env", 1).as_str(), ".env (1)");
+
+    let long = format!("{}.txt", "a".repeat(MAX_ATTACHMENT_NAME_LENGTH - 4));
+    let renamed = numbered(&long, 12);
+    assert_eq!(renamed.len(), MAX_ATTACHMENT_NAME_LENGTH);
+    assert!(renamed.ends_with("a (12).txt"));
+}
@@ -1,3 +1,4 @@
+use super::{QuotaConfig, ThumbnailConfig};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::path::Path;
@@ -13,6 +14,10 @@ pub struct AppConfig {
     pub shortcuts: ShortcutsConfig,
     #[serde(default)]
     pub lifecycle: LifecycleConfig,
+    #[serde(default)]
+    pub thumbnails: ThumbnailConfig,
+    #[serde(default)]
+    pub quotas: QuotaConfig,
 }
 
 impl AppConfig {
@@ -31,6 +36,25 @@ impl AppConfig {
         Ok(config)
     }
 
+    /// Loads config for a frontend that must start regardless: an unreadable file yields the
+    /// defaults and invalid values are replaced by theirs. Returns the config together with the
+    /// problems found, for the frontend to report.
+    ///
+    /// The file is never written; save the returned config to persist the fixes.
+    pub fn load_or_default(path: &Path) -> (Self, Vec<String>) {
+        let config = match Self::load(path) {
+            Ok(config) => config,
+            Err(e) => return (Self::default(), vec![format!("{}: {e}", path.display())]),
+        };
+
+        let errors = config.validate();
+        if errors.is_empty() {
+            (config, errors)
+        } else {
+            (config.with_defaults_for_invalid(), errors)
+        }
+    }
+
     /// Saves config to a TOML file.
     pub fn save(&self, path: &Path) -> Result<(), AppConfigError> {
         let content = toml::to_string_pretty(self)?;
@@ -51,6 +75,26 @@ impl AppConfig {
             errors.push("purge_ttl_days must be at least 1".to_string());
         }
 
+        if self.lifecycle.oplog_retention_days == 0 {
+            errors.push("oplog_retention_days must be at least 1".to_string());
+        }
+
+        if self.thumbnails.presets.is_empty() {
+            errors.push("thumbnails.presets must not be empty".to_string());
+        }
+
+        if !(1..=100).contains(&self.thumbnails.jpeg_quality) {
+            errors.push("thumbnails.jpeg_quality must be between 1 and 100".to_string());
+        }
+
+        if self.quotas.max_attachment_size == Some(0) {
+            errors.push("quotas.max_attachment_size must be at least 1".to_string());
+        }
+
+        if self.quotas.max_store_size == Some(0) {
+            errors.push("quotas.max_store_size must be at least 1".to_string());
+        }
+
         errors
     }
 
@@ -71,6 +115,28 @@ impl AppConfig {
                 } else {
                     self.lifecycle.purge_ttl_days
                 },
+                oplog_retention_days: if self.lifecycle.oplog_retention_days == 0 {
+                    defaults.lifecycle.oplog_retention_days
+                } else {
+                    self.lifecycle.oplog_retention_days
+                },
+            },
+            thumbnails: ThumbnailConfig {
+                presets: if self.thumbnails.presets.is_empty() {
+                    defaults.thumbnails.presets
+                } else {
+                    self.thumbnails.presets.clone()
+                },
+                jpeg_quality: if (1..=100).contains(&self.thumbnails.jpeg_quality) {
+                    self.thumbnails.jpeg_quality
+                } else {
+                    defaults.thumbnails.jpeg_quality
+                },
+                ..self.thumbnails.clone()
+            },
+            quotas: QuotaConfig {
+                max_attachment_size: self.quotas.max_attachment_size.filter(|&size| size > 0),
+                max_store_size: self.quotas.max_store_size.filter(|&size| size > 0),
             },
         }
     }
@@ -171,6 +237,8 @@ pub struct LifecycleConfig {
     pub trash_ttl_days: u32,
     #[serde(default = "default_purge_ttl_days")]
     pub purge_ttl_days: u32,
+    #[serde(default = "default_oplog_retention_days")]
+    pub oplog_retention_days: u32,
 }
 
 impl Default for LifecycleConfig {
@@ -178,6 +246,7 @@ impl Default for LifecycleConfig {
         Self {
             trash_ttl_days: default_trash_ttl_days(),
             purge_ttl_days: default_purge_ttl_days(),
+            oplog_retention_days: default_oplog_retention_days(),
         }
     }
 }
@@ -190,6 +259,10 @@ fn default_purge_ttl_days() -> u32 {
     7
 }
 
+fn default_oplog_retention_days() -> u32 {
+    30
+}
+
 fn default_true() -> bool {
     true
 }
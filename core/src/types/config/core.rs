@@ -1,5 +1,9 @@
+use std::ffi::OsString;
 use std::path::PathBuf;
 
+/// Environment variable that overrides the platform data directory.
+pub const DATA_DIR_ENV: &str = "KEVA_DATA_DIR";
+
 /// Core configuration for KevaCore initialization.
 #[derive(Clone)]
 pub struct Config {
@@ -7,6 +11,15 @@ pub struct Config {
 }
 
 impl Config {
+    /// Resolves the data directory: `KEVA_DATA_DIR` if set, otherwise `%LOCALAPPDATA%\keva` on
+    /// Windows, `~/Library/Application Support/keva` on macOS and `$XDG_DATA_HOME/keva` (default
+    /// `~/.local/share/keva`) elsewhere.
+    ///
+    /// Returns `None` if the variables the platform needs are unset.
+    pub fn default_data_dir() -> Option<PathBuf> {
+        resolve_data_dir(std::env::consts::OS, |name| std::env::var_os(name))
+    }
+
     pub fn db_path(&self) -> PathBuf {
         self.base_path.join("keva.redb")
     }
@@ -22,4 +35,60 @@ impl Config {
     pub fn thumbnails_path(&self) -> PathBuf {
         self.base_path.join("thumbnails")
     }
+
+    /// Text extracted from attachments for search.
+    pub fn text_path(&self) -> PathBuf {
+        self.base_path.join("text")
+    }
+
+    /// Wrapped data keys of an encrypted store.
+    pub fn key_file_path(&self) -> PathBuf {
+        self.base_path.join("keyfile")
+    }
+
+    /// Per-peer sync state, one file per peer store id.
+    pub fn sync_path(&self) -> PathBuf {
+        self.base_path.join("sync")
+    }
+
+    /// Files removed by undoable operations, one directory per undo entry.
+    pub fn holding_path(&self) -> PathBuf {
+        self.base_path.join("holding")
+    }
+
+    /// API token of the local HTTP server (keva_server).
+    pub fn server_token_path(&self) -> PathBuf {
+        self.base_path.join("server.token")
+    }
+
+    /// Basic-auth password of the WebDAV server (keva_webdav).
+    pub fn webdav_token_path(&self) -> PathBuf {
+        self.base_path.join("webdav.token")
+    }
+
+    /// Persisted search index, validated against `KevaCore::generation`.
+    pub fn search_index_path(&self) -> PathBuf {
+        self.base_path.join("search.idx")
+    }
+}
+
+pub(super) fn resolve_data_dir(
+    os: &str,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> Option<PathBuf> {
+    let var = |name| {
+        var(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    if let Some(dir) = var(DATA_DIR_ENV) {
+        return Some(dir);
+    }
+
+    let base = match os {
+        "windows" => var("LOCALAPPDATA")?,
+        "macos" => var("HOME")?.join("Library").join("Application Support"),
+        _ => var("XDG_DATA_HOME").or_else(|| Some(var("HOME")?.join(".local").join("share")))?,
+    };
+    Some(base.join("keva"))
 }
@@ -6,6 +6,8 @@ use std::time::Duration;
 pub struct GcConfig {
     pub trash_ttl: Duration,
     pub purge_ttl: Duration,
+    /// How long operation log entries are kept before maintenance compacts them.
+    pub oplog_retention: Duration,
 }
 
 impl From<&LifecycleConfig> for GcConfig {
@@ -13,6 +15,7 @@ impl From<&LifecycleConfig> for GcConfig {
         Self {
             trash_ttl: Duration::from_secs(config.trash_ttl_days as u64 * 24 * 60 * 60),
             purge_ttl: Duration::from_secs(config.purge_ttl_days as u64 * 24 * 60 * 60),
+            oplog_retention: Duration::from_secs(config.oplog_retention_days as u64 * 24 * 60 * 60),
         }
     }
 }
@@ -1,7 +1,14 @@
 mod app;
 mod core;
 mod gc;
+mod quota;
+mod thumbnail;
 
 pub use app::{AppConfig, AppConfigError, GeneralConfig, LifecycleConfig, ShortcutsConfig, Theme};
-pub use core::Config;
+pub use core::{Config, DATA_DIR_ENV};
 pub use gc::GcConfig;
+pub use quota::QuotaConfig;
+pub use thumbnail::{ThumbnailConfig, ThumbnailFormat, ThumbnailPreset};
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+/// Storage limits, the `[quotas]` section of config.toml. Unset limits don't apply.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaConfig {
+    /// Largest attachment accepted, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_attachment_size: Option<u64>,
+    /// Bytes the content, attachment and thumbnail files of the store may take up together.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_store_size: Option<u64>,
+}
@@ -0,0 +1,160 @@
+use super::core::resolve_data_dir;
+use super::*;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+    move |name| {
+        vars.iter()
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| OsString::from(v))
+    }
+}
+
+#[test]
+fn data_dir_env_override_wins() {
+    let vars = [(DATA_DIR_ENV, "/custom"), ("HOME", "/home/u")];
+    for os in ["windows", "macos", "linux"] {
+        assert_eq!(
+            resolve_data_dir(os, env(&vars)),
+            Some(PathBuf::from("/custom"))
+        );
+    }
+}
+
+#[test]
+fn data_dir_per_platform() {
+    let vars = [
+        ("LOCALAPPDATA", "C:\\Users\\u\\AppData\\Local"),
+        ("HOME", "/home/u"),
+    ];
+    assert_eq!(
+        resolve_data_dir("windows", env(&vars)),
+        Some(Path::new("C:\\Users\\u\\AppData\\Local").join("keva"))
+    );
+    assert_eq!(
+        resolve_data_dir("macos", env(&vars)),
+        Some(PathBuf::from("/home/u/Library/Application Support/keva"))
+    );
+    assert_eq!(
+        resolve_data_dir("linux", env(&vars)),
+        Some(PathBuf::from("/home/u/.local/share/keva"))
+    );
+}
+
+#[test]
+fn data_dir_prefers_xdg_data_home() {
+    let vars = [("XDG_DATA_HOME", "/data"), ("HOME", "/home/u")];
+    assert_eq!(
+        resolve_data_dir("linux", env(&vars)),
+        Some(PathBuf::from("/data/keva"))
+    );
+}
+
+#[test]
+fn data_dir_empty_or_missing_vars() {
+    let vars = [(DATA_DIR_ENV, ""), ("XDG_DATA_HOME", "")];
+    assert_eq!(resolve_data_dir("linux", env(&vars)), None);
+    assert_eq!(resolve_data_dir("windows", env(&[])), None);
+}
+
+#[test]
+fn load_or_default_missing_file() {
+    let temp = TempDir::new().unwrap();
+    let (config, problems) = AppConfig::load_or_default(&AppConfig::path(temp.path()));
+    assert!(problems.is_empty());
+    assert_eq!(config.lifecycle.trash_ttl_days, 30);
+}
+
+#[test]
+fn load_or_default_replaces_invalid_values() {
+    let temp = TempDir::new().unwrap();
+    let path = AppConfig::path(temp.path());
+    std::fs::write(
+        &path,
+        "[lifecycle]\ntrash_ttl_days = 0\npurge_ttl_days = 3\n",
+    )
+    .unwrap();
+
+    let (config, problems) = AppConfig::load_or_default(&path);
+    assert_eq!(problems, vec!["trash_ttl_days must be at least 1"]);
+    assert_eq!(config.lifecycle.trash_ttl_days, 30);
+    assert_eq!(config.lifecycle.purge_ttl_days, 3);
+
+    // The file is left for the frontend to fix.
+    assert!(std::fs::read_to_string(&path).unwrap().contains("= 0"));
+}
+
+#[test]
+fn load_or_default_unparseable_file() {
+    let temp = TempDir::new().unwrap();
+    let path = AppConfig::path(temp.path());
+    std::fs::write(&path, "not toml [").unwrap();
+
+    let (config, problems) = AppConfig::load_or_default(&path);
+    assert_eq!(problems.len(), 1);
+    assert!(config.validate().is_empty());
+}
+
+#[test]
+fn thumbnails_section() {
+    let temp = TempDir::new().unwrap();
+    let path = AppConfig::path(temp.path());
+    std::fs::write(
+        &path,
+        "[thumbnails]\npresets = [\"grid\", \"preview\"]\nhidpi = false\nformat = \"jpeg\"\n",
+    )
+    .unwrap();
+
+    let config = AppConfig::load(&path).unwrap();
+    assert_eq!(
+        config.thumbnails,
+        ThumbnailConfig {
+            presets: vec![ThumbnailPreset::Grid, ThumbnailPreset::Preview],
+            hidpi: false,
+            format: ThumbnailFormat::Jpeg,
+            jpeg_quality: 80,
+        }
+    );
+    assert_eq!(config.thumbnails.scales(), &[1]);
+}
+
+#[test]
+fn load_or_default_replaces_invalid_thumbnail_values() {
+    let temp = TempDir::new().unwrap();
+    let path = AppConfig::path(temp.path());
+    std::fs::write(
+        &path,
+        "[thumbnails]\npresets = []\nformat = \"webp\"\njpeg_quality = 0\n",
+    )
+    .unwrap();
+
+    let (config, problems) = AppConfig::load_or_default(&path);
+    assert_eq!(problems.len(), 2);
+    assert_eq!(config.thumbnails.presets, ThumbnailPreset::ALL);
+    assert_eq!(config.thumbnails.jpeg_quality, 80);
+    assert_eq!(config.thumbnails.format, ThumbnailFormat::Webp);
+}
+
+#[test]
+fn quotas_section() {
+    let temp = TempDir::new().unwrap();
+    let path = AppConfig::path(temp.path());
+    assert_eq!(AppConfig::default().quotas, QuotaConfig::default());
+
+    std::fs::write(
+        &path,
+        "[quotas]\nmax_attachment_size = 1048576\nmax_store_size = 0\n",
+    )
+    .unwrap();
+    let (config, problems) = AppConfig::load_or_default(&path);
+    assert_eq!(problems.len(), 1);
+    assert_eq!(
+        config.quotas,
+        QuotaConfig {
+            max_attachment_size: Some(1048576),
+            max_store_size: None,
+        }
+    );
+}
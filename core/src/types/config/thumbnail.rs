@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+/// Thumbnail generation settings, the `[thumbnails]` section of config.toml.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThumbnailConfig {
+    /// Presets rendered for each attachment.
+    #[serde(default = "default_presets")]
+    pub presets: Vec<ThumbnailPreset>,
+    /// Also render each preset at twice its size, for high-DPI displays.
+    #[serde(default = "default_hidpi")]
+    pub hidpi: bool,
+    #[serde(default)]
+    pub format: ThumbnailFormat,
+    /// JPEG quality from 1 to 100; ignored for other formats.
+    #[serde(default = "default_jpeg_quality")]
+    pub jpeg_quality: u8,
+}
+
+impl Default for ThumbnailConfig {
+    fn default() -> Self {
+        Self {
+            presets: default_presets(),
+            hidpi: default_hidpi(),
+            format: ThumbnailFormat::default(),
+            jpeg_quality: default_jpeg_quality(),
+        }
+    }
+}
+
+impl ThumbnailConfig {
+    /// Scales rendered for each preset.
+    pub fn scales(&self) -> &'static [u32] {
+        if self.hidpi { &[1, 2] } else { &[1] }
+    }
+}
+
+/// Thumbnail size, by where it is shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThumbnailPreset {
+    /// Next to the attachment name in a list.
+    ListIcon,
+    /// Tiles of an attachment grid.
+    Grid,
+    /// A large preview of a single attachment.
+    Preview,
+}
+
+impl ThumbnailPreset {
+    pub const ALL: [Self; 3] = [Self::ListIcon, Self::Grid, Self::Preview];
+
+    /// Bound on the longer side in pixels, at 1x.
+    pub const fn size(self) -> u32 {
+        match self {
+            Self::ListIcon => 32,
+            Self::Grid => 200,
+            Self::Preview => 800,
+        }
+    }
+
+    /// Name used in thumbnail file names and config.toml.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::ListIcon => "list_icon",
+            Self::Grid => "grid",
+            Self::Preview => "preview",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|preset| preset.name() == name)
+    }
+}
+
+/// Encoding of stored thumbnails.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailFormat {
+    /// Lossless; keeps transparency.
+    #[default]
+    Png,
+    /// Lossless, usually smaller than PNG; keeps transparency.
+    Webp,
+    /// Lossy and by far the smallest for photos; transparency is flattened onto white.
+    Jpeg,
+}
+
+impl ThumbnailFormat {
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Webp => "webp",
+            Self::Jpeg => "jpg",
+        }
+    }
+}
+
+fn default_presets() -> Vec<ThumbnailPreset> {
+    ThumbnailPreset::ALL.to_vec()
+}
+
+fn default_hidpi() -> bool {
+    true
+}
+
+fn default_jpeg_quality() -> u8 {
+    80
+}
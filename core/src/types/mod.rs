@@ -1,17 +1,22 @@
 pub mod config;
 pub use config::{
-    AppConfig, AppConfigError, Config, GcConfig, GeneralConfig, LifecycleConfig, ShortcutsConfig,
-    Theme,
+    AppConfig, AppConfigError, Config, DATA_DIR_ENV, GcConfig, GeneralConfig, LifecycleConfig,
+    QuotaConfig, ShortcutsConfig, Theme, ThumbnailConfig, ThumbnailFormat, ThumbnailPreset,
 };
 
 pub(crate) mod key;
 pub use key::{Key, KeyError, MAX_KEY_LENGTH};
 
+pub(crate) mod attachment_name;
+pub use attachment_name::{
+    AttachmentConflictResolution, AttachmentName, AttachmentNameError, MAX_ATTACHMENT_NAME_LENGTH,
+};
+
 pub(crate) mod metadata;
 
 pub(crate) mod value;
 pub use value::PublicValue as Value;
-pub use value::{Attachment, LifecycleState, Metadata};
+pub use value::{AccessStats, Attachment, ImageMetadata, LifecycleState, Metadata, SealState};
 
 pub(crate) mod ttl_key;
 pub use ttl_key::TtlKey;
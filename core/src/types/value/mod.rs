@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
 
 pub(crate) mod versioned_value;
@@ -14,6 +15,20 @@ pub struct PublicValue {
 
 impl PublicValue {
     pub(crate) fn from_latest_value(value: latest_value::Value) -> Self {
+        Self::from_latest_value_with_unlock(value, false)
+    }
+
+    /// Converts a stored value; attachments of a sealed key are only listed when `unlocked`.
+    pub(crate) fn from_latest_value_with_unlock(
+        value: latest_value::Value,
+        unlocked: bool,
+    ) -> Self {
+        let seal = match (&value.metadata.seal, unlocked) {
+            (None, _) => SealState::Unsealed,
+            (Some(_), false) => SealState::Locked,
+            (Some(_), true) => SealState::Unlocked,
+        };
+
         let metadata = Metadata {
             lifecycle_state: match value.metadata.lifecycle_state {
                 latest_value::LifecycleState::Active { last_accessed } => {
@@ -23,14 +38,27 @@ impl PublicValue {
                     LifecycleState::Trash { trashed_at }
                 }
             },
+            access_count: value.metadata.access_count,
+            seal,
         };
 
         let attachments = value
             .attachments
             .into_iter()
+            .filter(|_| seal != SealState::Locked)
             .map(|a| Attachment {
                 filename: a.filename,
                 size: a.size,
+                image: a.image.map(|image| ImageMetadata {
+                    width: image.width,
+                    height: image.height,
+                    captured_at: image.captured_at,
+                    camera: image.camera,
+                }),
+                mime: a.mime,
+                hash: a.hash,
+                added_at: a.added_at,
+                modified_at: a.modified_at,
             })
             .collect();
 
@@ -46,6 +74,32 @@ impl PublicValue {
 #[derive(Debug, Clone)]
 pub struct Metadata {
     pub lifecycle_state: LifecycleState,
+    pub access_count: u64,
+    pub seal: SealState,
+}
+
+impl Metadata {
+    /// Returns the access stats of an Active key, e.g. to pass a `touch` result on to frecency
+    /// ranking.
+    pub fn access_stats(&self) -> Option<AccessStats> {
+        match self.lifecycle_state {
+            LifecycleState::Active { last_accessed } => Some(AccessStats {
+                last_accessed,
+                access_count: self.access_count,
+            }),
+            LifecycleState::Trash { .. } => None,
+        }
+    }
+}
+
+/// Whether a key is sealed under its own passphrase, and if so whether it is unlocked.
+///
+/// A Locked key hides its attachments and refuses access to its files.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum SealState {
+    Unsealed,
+    Locked,
+    Unlocked,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
@@ -54,9 +108,42 @@ pub enum LifecycleState {
     Trash { trashed_at: SystemTime },
 }
 
+/// `mime`, `hash`, `added_at` and `modified_at` are `None` for attachments added by versions
+/// that didn't record them.
 #[cfg_attr(test, derive(PartialEq))]
 #[derive(Debug, Clone)]
 pub struct Attachment {
     pub filename: String,
     pub size: u64,
+    /// Set for images whose header could be read when they were attached.
+    pub image: Option<ImageMetadata>,
+    /// MIME type sniffed from the contents, e.g. `image/png` or `text/plain; charset=utf-8`.
+    pub mime: Option<String>,
+    /// blake3 hash of the contents.
+    pub hash: Option<blake3::Hash>,
+    /// When a file was first attached under this name.
+    pub added_at: Option<SystemTime>,
+    /// When the contents were last replaced.
+    pub modified_at: Option<SystemTime>,
+}
+
+/// Properties of an image attachment, read when it is added.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ImageMetadata {
+    /// Displayed width in pixels, after applying the EXIF orientation.
+    pub width: u32,
+    /// Displayed height in pixels, after applying the EXIF orientation.
+    pub height: u32,
+    /// When the photo was taken (EXIF `DateTimeOriginal`), as `YYYY-MM-DDTHH:MM:SS` in the
+    /// camera's local time; EXIF usually records no time zone.
+    pub captured_at: Option<String>,
+    /// Camera make and model, e.g. `Apple iPhone 15 Pro`.
+    pub camera: Option<String>,
+}
+
+/// Access history of an Active key, used for frecency ranking.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AccessStats {
+    pub last_accessed: SystemTime,
+    pub access_count: u64,
 }
@@ -1,15 +1,28 @@
 use redb::TypeName;
-pub use v1 as latest_value;
+pub use v5 as latest_value;
 
 pub mod v1;
+pub mod v2;
+pub mod v3;
+pub mod v4;
+pub mod v5;
 
 pub trait ValueVariant {
     const VERSION: u8;
 }
 
+/// Leading byte of values encrypted at rest. Kept well clear of plain value versions.
+pub const SEALED_TAG: u8 = 0xE0;
+
 #[derive(Debug, Clone)]
 pub enum VersionedValue {
     V1(v1::Value),
+    V2(v2::Value),
+    V3(v3::Value),
+    V4(v4::Value),
+    V5(v5::Value),
+    /// An encrypted plain variant, including its version byte.
+    Sealed(Vec<u8>),
 }
 
 impl redb::Value for VersionedValue {
@@ -30,6 +43,23 @@ impl redb::Value for VersionedValue {
                 let v1 = postcard::from_bytes::<v1::Value>(data).expect("invalid value");
                 VersionedValue::V1(v1)
             }
+            v2::Value::VERSION => {
+                let v2 = postcard::from_bytes::<v2::Value>(data).expect("invalid value");
+                VersionedValue::V2(v2)
+            }
+            v3::Value::VERSION => {
+                let v3 = postcard::from_bytes::<v3::Value>(data).expect("invalid value");
+                VersionedValue::V3(v3)
+            }
+            v4::Value::VERSION => {
+                let v4 = postcard::from_bytes::<v4::Value>(data).expect("invalid value");
+                VersionedValue::V4(v4)
+            }
+            v5::Value::VERSION => {
+                let v5 = postcard::from_bytes::<v5::Value>(data).expect("invalid value");
+                VersionedValue::V5(v5)
+            }
+            SEALED_TAG => VersionedValue::Sealed(data.to_vec()),
             version => panic!("unsupported version: {}", version),
         }
     }
@@ -40,6 +70,11 @@ impl redb::Value for VersionedValue {
     {
         match value {
             VersionedValue::V1(v1) => postcard::to_extend(v1, vec![v1::Value::VERSION]).unwrap(),
+            VersionedValue::V2(v2) => postcard::to_extend(v2, vec![v2::Value::VERSION]).unwrap(),
+            VersionedValue::V3(v3) => postcard::to_extend(v3, vec![v3::Value::VERSION]).unwrap(),
+            VersionedValue::V4(v4) => postcard::to_extend(v4, vec![v4::Value::VERSION]).unwrap(),
+            VersionedValue::V5(v5) => postcard::to_extend(v5, vec![v5::Value::VERSION]).unwrap(),
+            VersionedValue::Sealed(sealed) => [&[SEALED_TAG], sealed.as_slice()].concat(),
         }
     }
 
@@ -1,5 +1,5 @@
 use super::*;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 #[test]
 fn value_v1_empty_attachments_serialization() {
@@ -16,7 +16,6 @@ fn value_v1_empty_attachments_serialization() {
     let bytes = <VersionedValue as redb::Value>::as_bytes(&versioned_value);
     let deserialized_value = <VersionedValue as redb::Value>::from_bytes(&bytes);
 
-    #[expect(unreachable_patterns)]
     match deserialized_value {
         VersionedValue::V1(v1_value) => {
             assert_eq!(v1_value, original_value);
@@ -49,7 +48,6 @@ fn value_v1_with_attachments_serialization() {
     let bytes = <VersionedValue as redb::Value>::as_bytes(&versioned_value);
     let deserialized_value = <VersionedValue as redb::Value>::from_bytes(&bytes);
 
-    #[expect(unreachable_patterns)]
     match deserialized_value {
         VersionedValue::V1(v1_value) => {
             assert_eq!(v1_value, original_value);
@@ -73,7 +71,6 @@ fn value_v1_trashed_serialization() {
     let bytes = <VersionedValue as redb::Value>::as_bytes(&versioned_value);
     let deserialized_value = <VersionedValue as redb::Value>::from_bytes(&bytes);
 
-    #[expect(unreachable_patterns)]
     match deserialized_value {
         VersionedValue::V1(v1_value) => {
             assert_eq!(v1_value, original_value);
@@ -81,3 +78,266 @@ fn value_v1_trashed_serialization() {
         _ => panic!("Deserialized to incorrect version"),
     }
 }
+
+#[test]
+fn value_v2_serialization() {
+    let now = SystemTime::now();
+    let original_value = v2::Value {
+        metadata: v2::Metadata {
+            lifecycle_state: v2::LifecycleState::Active { last_accessed: now },
+            access_count: 42,
+        },
+        attachments: vec![v2::Attachment {
+            filename: "test.txt".to_string(),
+            size: 1024,
+        }],
+        thumb_version: 1,
+    };
+
+    let versioned_value = VersionedValue::V2(original_value.clone());
+    let bytes = <VersionedValue as redb::Value>::as_bytes(&versioned_value);
+    let deserialized_value = <VersionedValue as redb::Value>::from_bytes(&bytes);
+
+    match deserialized_value {
+        VersionedValue::V2(v2_value) => {
+            assert_eq!(v2_value, original_value);
+        }
+        _ => panic!("Deserialized to incorrect version"),
+    }
+}
+
+#[test]
+fn value_v1_migrates_to_v2() {
+    let now = SystemTime::now();
+    let v1_value = v1::Value {
+        metadata: v1::Metadata {
+            lifecycle_state: v1::LifecycleState::Trash { trashed_at: now },
+        },
+        attachments: vec![v1::Attachment {
+            filename: "image.png".to_string(),
+            size: 2048,
+        }],
+        thumb_version: 1,
+    };
+
+    let migrated = v2::Value::from(v1_value.clone());
+
+    assert_eq!(
+        migrated.metadata,
+        v2::Metadata {
+            lifecycle_state: v1_value.metadata.lifecycle_state,
+            access_count: 0,
+        }
+    );
+    assert_eq!(migrated.attachments, v1_value.attachments);
+    assert_eq!(migrated.thumb_version, v1_value.thumb_version);
+}
+
+#[test]
+fn value_v3_serialization() {
+    let now = SystemTime::now();
+    let original_value = v3::Value {
+        metadata: v3::Metadata {
+            lifecycle_state: v3::LifecycleState::Active { last_accessed: now },
+            access_count: 3,
+            seal: Some(vec![9, 8, 7]),
+        },
+        attachments: vec![],
+        thumb_version: 1,
+    };
+
+    let versioned_value = VersionedValue::V3(original_value.clone());
+    let bytes = <VersionedValue as redb::Value>::as_bytes(&versioned_value);
+    let deserialized_value = <VersionedValue as redb::Value>::from_bytes(&bytes);
+
+    match deserialized_value {
+        VersionedValue::V3(v3_value) => {
+            assert_eq!(v3_value, original_value);
+        }
+        _ => panic!("Deserialized to incorrect version"),
+    }
+}
+
+#[test]
+fn value_v2_migrates_to_v3() {
+    let now = SystemTime::now();
+    let v2_value = v2::Value {
+        metadata: v2::Metadata {
+            lifecycle_state: v2::LifecycleState::Active { last_accessed: now },
+            access_count: 5,
+        },
+        attachments: vec![],
+        thumb_version: 1,
+    };
+
+    let migrated = v3::Value::from(v2_value.clone());
+
+    assert_eq!(
+        migrated.metadata,
+        v3::Metadata {
+            lifecycle_state: v2_value.metadata.lifecycle_state,
+            access_count: 5,
+            seal: None,
+        }
+    );
+    assert_eq!(migrated.thumb_version, v2_value.thumb_version);
+}
+
+#[test]
+fn value_v4_serialization() {
+    let now = SystemTime::now();
+    let original_value = v4::Value {
+        metadata: v4::Metadata {
+            lifecycle_state: v4::LifecycleState::Active { last_accessed: now },
+            access_count: 3,
+            seal: None,
+        },
+        attachments: vec![
+            v4::Attachment {
+                filename: "photo.jpg".to_string(),
+                size: 2048,
+                image: Some(v4::ImageMetadata {
+                    width: 3024,
+                    height: 4032,
+                    captured_at: Some("2024-05-01T13:45:12".to_string()),
+                    camera: Some("Apple iPhone 15 Pro".to_string()),
+                }),
+            },
+            v4::Attachment {
+                filename: "notes.txt".to_string(),
+                size: 5,
+                image: None,
+            },
+        ],
+        thumb_version: 4,
+    };
+
+    let versioned_value = VersionedValue::V4(original_value.clone());
+    let bytes = <VersionedValue as redb::Value>::as_bytes(&versioned_value);
+    let deserialized_value = <VersionedValue as redb::Value>::from_bytes(&bytes);
+
+    match deserialized_value {
+        VersionedValue::V4(v4_value) => {
+            assert_eq!(v4_value, original_value);
+        }
+        _ => panic!("Deserialized to incorrect version"),
+    }
+}
+
+#[test]
+fn value_v3_migrates_to_v4() {
+    let now = SystemTime::now();
+    let v3_value = v3::Value {
+        metadata: v3::Metadata {
+            lifecycle_state: v3::LifecycleState::Active { last_accessed: now },
+            access_count: 5,
+            seal: Some(vec![1, 2]),
+        },
+        attachments: vec![v3::Attachment {
+            filename: "photo.jpg".to_string(),
+            size: 2048,
+        }],
+        thumb_version: 3,
+    };
+
+    let migrated = v4::Value::from(v3_value.clone());
+
+    assert_eq!(migrated.metadata, v3_value.metadata);
+    assert_eq!(
+        migrated.attachments,
+        vec![v4::Attachment {
+            filename: "photo.jpg".to_string(),
+            size: 2048,
+            image: None,
+        }]
+    );
+    assert_eq!(migrated.thumb_version, v3_value.thumb_version);
+}
+
+#[test]
+fn value_v5_serialization() {
+    let now = SystemTime::now();
+    let original_value = v5::Value {
+        metadata: v5::Metadata {
+            lifecycle_state: v5::LifecycleState::Active { last_accessed: now },
+            access_count: 1,
+            seal: None,
+        },
+        attachments: vec![v5::Attachment {
+            filename: "report.pdf".to_string(),
+            size: 4096,
+            image: None,
+            mime: Some("application/pdf".to_string()),
+            hash: Some(blake3::hash(b"report")),
+            added_at: Some(now),
+            modified_at: Some(now + Duration::from_secs(60)),
+        }],
+        thumb_version: 4,
+    };
+
+    let versioned_value = VersionedValue::V5(original_value.clone());
+    let bytes = <VersionedValue as redb::Value>::as_bytes(&versioned_value);
+    let deserialized_value = <VersionedValue as redb::Value>::from_bytes(&bytes);
+
+    match deserialized_value {
+        VersionedValue::V5(v5_value) => {
+            assert_eq!(v5_value, original_value);
+        }
+        _ => panic!("Deserialized to incorrect version"),
+    }
+}
+
+#[test]
+fn value_v4_migrates_to_v5() {
+    let now = SystemTime::now();
+    let image = v4::ImageMetadata {
+        width: 640,
+        height: 480,
+        captured_at: None,
+        camera: None,
+    };
+    let v4_value = v4::Value {
+        metadata: v4::Metadata {
+            lifecycle_state: v4::LifecycleState::Active { last_accessed: now },
+            access_count: 2,
+            seal: None,
+        },
+        attachments: vec![v4::Attachment {
+            filename: "photo.png".to_string(),
+            size: 2048,
+            image: Some(image.clone()),
+        }],
+        thumb_version: 4,
+    };
+
+    let migrated = v5::Value::from(v4_value.clone());
+
+    assert_eq!(migrated.metadata, v4_value.metadata);
+    assert_eq!(
+        migrated.attachments,
+        vec![v5::Attachment {
+            filename: "photo.png".to_string(),
+            size: 2048,
+            image: Some(image),
+            mime: None,
+            hash: None,
+            added_at: None,
+            modified_at: None,
+        }]
+    );
+    assert_eq!(migrated.thumb_version, v4_value.thumb_version);
+}
+
+#[test]
+fn value_sealed_serialization() {
+    let versioned_value = VersionedValue::Sealed(vec![1, 2, 3, 4]);
+    let bytes = <VersionedValue as redb::Value>::as_bytes(&versioned_value);
+    assert_eq!(bytes[0], SEALED_TAG);
+
+    let deserialized_value = <VersionedValue as redb::Value>::from_bytes(&bytes);
+
+    match deserialized_value {
+        VersionedValue::Sealed(sealed) => assert_eq!(sealed, vec![1, 2, 3, 4]),
+        _ => panic!("Deserialized to incorrect version"),
+    }
+}
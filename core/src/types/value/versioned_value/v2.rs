@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+use super::ValueVariant;
+use super::v1;
+
+pub use v1::{Attachment, LifecycleState};
+
+#[cfg_attr(test, derive(Eq, PartialEq))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Value {
+    pub metadata: Metadata,
+    pub attachments: Vec<Attachment>,
+    pub thumb_version: u32,
+}
+
+impl ValueVariant for Value {
+    const VERSION: u8 = 2;
+}
+
+#[cfg_attr(test, derive(Eq, PartialEq))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Metadata {
+    pub lifecycle_state: LifecycleState,
+    /// Number of `touch` calls, used for frecency ranking.
+    pub access_count: u64,
+}
+
+impl From<v1::Value> for Value {
+    fn from(value: v1::Value) -> Self {
+        Self {
+            metadata: Metadata {
+                lifecycle_state: value.metadata.lifecycle_state,
+                access_count: 0,
+            },
+            attachments: value.attachments,
+            thumb_version: value.thumb_version,
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+use super::ValueVariant;
+use super::v2;
+
+pub use v2::{Attachment, LifecycleState};
+
+#[cfg_attr(test, derive(Eq, PartialEq))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Value {
+    pub metadata: Metadata,
+    pub attachments: Vec<Attachment>,
+    pub thumb_version: u32,
+}
+
+impl ValueVariant for Value {
+    const VERSION: u8 = 3;
+}
+
+#[cfg_attr(test, derive(Eq, PartialEq))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Metadata {
+    pub lifecycle_state: LifecycleState,
+    /// Number of `touch` calls, used for frecency ranking.
+    pub access_count: u64,
+    /// Key protecting a sealed key's content and attachments, wrapped under its own passphrase.
+    /// `None` if the key is not sealed.
+    pub seal: Option<Vec<u8>>,
+}
+
+impl From<v2::Value> for Value {
+    fn from(value: v2::Value) -> Self {
+        Self {
+            metadata: Metadata {
+                lifecycle_state: value.metadata.lifecycle_state,
+                access_count: value.metadata.access_count,
+                seal: None,
+            },
+            attachments: value.attachments,
+            thumb_version: value.thumb_version,
+        }
+    }
+}
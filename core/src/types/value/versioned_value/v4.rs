@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+use super::ValueVariant;
+use super::v3;
+
+pub use v3::{LifecycleState, Metadata};
+
+#[cfg_attr(test, derive(Eq, PartialEq))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Value {
+    pub metadata: Metadata,
+    pub attachments: Vec<Attachment>,
+    pub thumb_version: u32,
+}
+
+impl ValueVariant for Value {
+    const VERSION: u8 = 4;
+}
+
+#[cfg_attr(test, derive(Eq, PartialEq))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub filename: String,
+    pub size: u64,
+    /// Read when the attachment is added; `None` for non-images and attachments added before
+    /// version 4.
+    pub image: Option<ImageMetadata>,
+}
+
+#[cfg_attr(test, derive(Eq, PartialEq))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageMetadata {
+    /// Displayed width, after applying the EXIF orientation.
+    pub width: u32,
+    /// Displayed height, after applying the EXIF orientation.
+    pub height: u32,
+    /// EXIF `DateTimeOriginal` as `YYYY-MM-DDTHH:MM:SS`, in the camera's local time.
+    pub captured_at: Option<String>,
+    /// EXIF make and model, e.g. `Apple iPhone 15 Pro`.
+    pub camera: Option<String>,
+}
+
+impl From<v3::Attachment> for Attachment {
+    fn from(attachment: v3::Attachment) -> Self {
+        Self {
+            filename: attachment.filename,
+            size: attachment.size,
+            image: None,
+        }
+    }
+}
+
+impl From<v3::Value> for Value {
+    fn from(value: v3::Value) -> Self {
+        Self {
+            metadata: value.metadata,
+            attachments: value.attachments.into_iter().map(Into::into).collect(),
+            thumb_version: value.thumb_version,
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+use super::ValueVariant;
+use super::v4;
+
+pub use v4::{ImageMetadata, LifecycleState, Metadata};
+
+#[cfg_attr(test, derive(Eq, PartialEq))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Value {
+    pub metadata: Metadata,
+    pub attachments: Vec<Attachment>,
+    pub thumb_version: u32,
+}
+
+impl ValueVariant for Value {
+    const VERSION: u8 = 5;
+}
+
+/// Fields other than `filename` and `size` are `None` for attachments added before the version
+/// that introduced them.
+#[cfg_attr(test, derive(Eq, PartialEq))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub filename: String,
+    pub size: u64,
+    pub image: Option<ImageMetadata>,
+    /// Sniffed from the first bytes of the contents, not the filename.
+    pub mime: Option<String>,
+    /// blake3 hash of the plaintext contents.
+    pub hash: Option<blake3::Hash>,
+    /// When a file was first attached under this name. Overwriting keeps it.
+    pub added_at: Option<SystemTime>,
+    /// When the contents were last replaced; `added_at` until overwritten.
+    pub modified_at: Option<SystemTime>,
+}
+
+impl From<v4::Attachment> for Attachment {
+    fn from(attachment: v4::Attachment) -> Self {
+        Self {
+            filename: attachment.filename,
+            size: attachment.size,
+            image: attachment.image,
+            mime: None,
+            hash: None,
+            added_at: None,
+            modified_at: None,
+        }
+    }
+}
+
+impl From<v4::Value> for Value {
+    fn from(value: v4::Value) -> Self {
+        Self {
+            metadata: value.metadata,
+            attachments: value.attachments.into_iter().map(Into::into).collect(),
+            thumb_version: value.thumb_version,
+        }
+    }
+}
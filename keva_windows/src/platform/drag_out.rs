@@ -7,7 +7,7 @@
 //! Uses CompositeDataObject to wrap shell formats with custom MIME data so
 //! internal drops can be detected via dataTransfer.getData().
 
-use crate::keva_worker::get_data_path;
+use crate::worker::get_data_path;
 use keva_core::core::KevaCore;
 use keva_core::types::Key;
 use std::cell::Cell;
@@ -1,6 +1,5 @@
 //! Window message handlers.
 
-use crate::keva_worker::Request;
 use crate::platform::drop_target::revoke_drop_target;
 use crate::platform::file_picker::open_file_picker;
 use crate::platform::hotkey::{unregister_global_hotkey, update_global_hotkey};
@@ -12,16 +11,12 @@ use crate::platform::tray::{
 use crate::render::theme::{MIN_WINDOW_HEIGHT, MIN_WINDOW_WIDTH, Theme};
 use crate::webview::bridge::post_message;
 use crate::webview::{FilePickerRequest, OutgoingMessage, WEBVIEW};
+use crate::worker::Request;
 use keva_core::types::AppConfig;
 use std::sync::RwLock;
 use std::sync::atomic::{AtomicIsize, AtomicU8, Ordering};
 use std::sync::mpsc::Sender;
-use webview2_com::Microsoft::Web::WebView2::Win32::{
-    COREWEBVIEW2_FILE_SYSTEM_HANDLE_PERMISSION_READ_ONLY,
-    COREWEBVIEW2_FILE_SYSTEM_HANDLE_PERMISSION_READ_WRITE,
-    COREWEBVIEW2_MOVE_FOCUS_REASON_PROGRAMMATIC, ICoreWebView2_23, ICoreWebView2Environment14,
-    ICoreWebView2ObjectCollection,
-};
+use webview2_com::Microsoft::Web::WebView2::Win32::COREWEBVIEW2_MOVE_FOCUS_REASON_PROGRAMMATIC;
 use webview2_com::pwstr_from_str;
 use windows::Win32::{
     Foundation::{COLORREF, HWND, LPARAM, LRESULT, RECT, WPARAM},
@@ -41,7 +36,6 @@ use windows::Win32::{
         },
     },
 };
-use windows::core::Interface;
 use windows::core::PCWSTR;
 use windows_strings::w;
 
@@ -399,7 +393,6 @@ pub fn on_settingchange(hwnd: HWND, lparam: LPARAM) -> LRESULT {
 }
 
 /// WM_WEBVIEW_MESSAGE: Forward OutgoingMessage to WebView.
-/// Value variant uses PostWebMessageAsJsonWithAdditionalObjects for FileSystemHandle.
 pub fn on_webview_message(lparam: LPARAM) -> LRESULT {
     let ptr = lparam.0 as *mut OutgoingMessage;
     if ptr.is_null() {
@@ -412,85 +405,18 @@ pub fn on_webview_message(lparam: LPARAM) -> LRESULT {
         return LRESULT(0);
     };
 
-    // Value messages need FileSystemHandle creation
-    if let OutgoingMessage::Value {
-        ref content_path,
-        read_only,
-        ..
-    } = *msg
-    {
-        unsafe {
-            let Ok(env14) = wv.env.cast::<ICoreWebView2Environment14>() else {
-                eprintln!("[FileHandle] ICoreWebView2Environment14 not available");
-                return LRESULT(0);
-            };
-            let Ok(webview23) = wv.webview.cast::<ICoreWebView2_23>() else {
-                eprintln!("[FileHandle] ICoreWebView2_23 not available");
-                return LRESULT(0);
-            };
-
-            let path_str = content_path.to_string_lossy();
-            let path_pwstr = pwstr_from_str(&path_str);
-            let permission = if read_only {
-                COREWEBVIEW2_FILE_SYSTEM_HANDLE_PERMISSION_READ_ONLY
-            } else {
-                COREWEBVIEW2_FILE_SYSTEM_HANDLE_PERMISSION_READ_WRITE
-            };
-
-            let handle = match env14.CreateWebFileSystemFileHandle(path_pwstr, permission) {
-                Ok(h) => h,
-                Err(e) => {
-                    eprintln!("[FileHandle] CreateWebFileSystemFileHandle failed: {:?}", e);
-                    return LRESULT(0);
-                }
-            };
-
-            let handle_iunknown: windows::core::IUnknown = match handle.cast() {
-                Ok(u) => u,
-                Err(e) => {
-                    eprintln!("[FileHandle] Failed to cast handle to IUnknown: {:?}", e);
-                    return LRESULT(0);
-                }
-            };
-            let mut items = [Some(handle_iunknown)];
-            let mut collection: Option<ICoreWebView2ObjectCollection> = None;
-
-            if let Err(e) = env14.CreateObjectCollection(1, items.as_mut_ptr(), &mut collection) {
-                eprintln!("[FileHandle] CreateObjectCollection failed: {:?}", e);
-                return LRESULT(0);
-            }
-
-            let Some(objects) = collection else {
-                eprintln!("[FileHandle] CreateObjectCollection returned None");
-                return LRESULT(0);
-            };
-
-            let json = serde_json::to_string(&*msg).expect("Failed to serialize message");
-            let json_pwstr = pwstr_from_str(&json);
-
-            if let Err(e) =
-                webview23.PostWebMessageAsJsonWithAdditionalObjects(json_pwstr, &*objects)
-            {
-                eprintln!(
-                    "[FileHandle] PostWebMessageAsJsonWithAdditionalObjects failed: {:?}",
-                    e
-                );
-            }
-        }
-    } else {
-        // Focus WebView on CoreReady (first launch) so accelerator keys work
-        if matches!(*msg, OutgoingMessage::CoreReady) {
-            let _ = unsafe {
-                wv.controller
-                    .MoveFocus(COREWEBVIEW2_MOVE_FOCUS_REASON_PROGRAMMATIC)
-            };
-        }
-
-        let json = serde_json::to_string(&*msg).expect("Failed to serialize message");
-        let msg_pwstr = pwstr_from_str(&json);
-        let _ = unsafe { wv.webview.PostWebMessageAsJson(msg_pwstr) };
+    // Focus WebView on CoreReady (first launch) so accelerator keys work
+    if matches!(*msg, OutgoingMessage::CoreReady) {
+        let _ = unsafe {
+            wv.controller
+                .MoveFocus(COREWEBVIEW2_MOVE_FOCUS_REASON_PROGRAMMATIC)
+        };
     }
 
+    let json = serde_json::to_string(&*msg).expect("Failed to serialize message");
+    let msg_pwstr = pwstr_from_str(&json);
+    let _ = unsafe { wv.webview.PostWebMessageAsJson(msg_pwstr) };
+
     LRESULT(0)
 }
 
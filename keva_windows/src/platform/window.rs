@@ -1,6 +1,5 @@
 //! Window creation and message handling.
 
-use crate::keva_worker;
 use crate::platform::wm;
 use crate::platform::{
     drop_target::register_drop_target,
@@ -18,6 +17,7 @@ use crate::platform::{
 };
 use crate::render::theme::{Theme, WINDOW_HEIGHT, WINDOW_WIDTH};
 use crate::webview::{OutgoingMessage, WEBVIEW, bridge::post_message, init_webview};
+use crate::worker;
 use windows::{
     Win32::{
         Foundation::{HWND, LPARAM, LRESULT, TRUE, WPARAM},
@@ -65,7 +65,7 @@ pub fn run(start_minimized: bool) -> Result<()> {
             }
         };
 
-        let config_path = keva_core::types::AppConfig::path(&keva_worker::get_data_path());
+        let config_path = keva_core::types::AppConfig::path(&worker::get_data_path());
         let config = keva_core::types::AppConfig::load(&config_path).unwrap_or_default();
         set_app_config(config.clone());
         let initial_theme = match config.general.theme {
@@ -138,7 +138,7 @@ pub fn run(start_minimized: bool) -> Result<()> {
         }
 
         // Start worker thread (owns KevaCore + SearchEngine, posts directly to UI thread)
-        let request_tx = keva_worker::start(hwnd);
+        let request_tx = worker::start(hwnd);
 
         // Create WebView filling entire client area
         init_webview(
@@ -2,11 +2,11 @@
 
 use super::messages::IncomingMessage;
 use super::{FilePickerRequest, OutgoingMessage, WEBVIEW};
-use crate::keva_worker::{Request, get_data_path};
 use crate::platform::clipboard::{take_pending_file_paths, write_files};
 use crate::platform::handlers::PREV_FOREGROUND;
 use crate::platform::wm;
 use crate::render::theme::Theme;
+use crate::worker::{Request, get_data_path};
 use std::sync::atomic::Ordering;
 use std::sync::mpsc::Sender;
 use webview2_com::Microsoft::Web::WebView2::Win32::ICoreWebView2;
@@ -150,10 +150,12 @@ pub fn handle_webview_message(msg: &str, parent_hwnd: HWND, request_tx: &Sender<
         IncomingMessage::AddFiles { key, files } => {
             // Get cached paths (from drop or clipboard) and match by index
             let cached_paths = take_pending_file_paths();
-            let resolved_files: Vec<(std::path::PathBuf, String)> = files
+            let resolved_files: Vec<_> = files
                 .into_iter()
-                .filter_map(|(index, filename)| {
-                    cached_paths.get(index).map(|path| (path.clone(), filename))
+                .filter_map(|(index, filename, resolution)| {
+                    cached_paths
+                        .get(index)
+                        .map(|path| (path.clone(), filename, resolution))
                 })
                 .collect();
 
@@ -2,7 +2,6 @@
 
 use super::bridge::{handle_webview_message, post_message};
 use super::{CopyAction, OutgoingMessage, WEBVIEW, WebView};
-use crate::keva_worker::{Request, get_data_path};
 use crate::platform::clipboard::{read_clipboard, set_pending_file_paths};
 use crate::platform::composition::CompositionHost;
 use crate::platform::drag_out::handle_drag_starting;
@@ -10,6 +9,7 @@ use crate::platform::handlers::get_app_config;
 use crate::platform::hotkey::ShortcutBinding;
 use crate::platform::tray::IDM_SETTINGS;
 use crate::render::theme::Theme;
+use crate::worker::{Request, get_data_path};
 use std::ffi::c_void;
 use std::sync::mpsc::Sender;
 #[cfg(debug_assertions)]
@@ -1,5 +1,6 @@
 //! WebView message types.
 
+use keva_core::types::AttachmentConflictResolution;
 use serde::Deserialize;
 
 /// Messages from WebView to native.
@@ -50,8 +51,8 @@ pub enum IncomingMessage {
     /// Add attachments with target filenames.
     AddAttachments {
         key: String,
-        /// Each file: [source_path, target_filename]
-        files: Vec<(String, String)>,
+        /// Each file: [source_path, target_filename, conflict_resolution]
+        files: Vec<(String, String, AttachmentConflictResolution)>,
     },
     /// Remove an attachment from a key.
     RemoveAttachment {
@@ -70,8 +71,8 @@ pub enum IncomingMessage {
     /// Files are referenced by index (matching order in JS event).
     AddFiles {
         key: String,
-        /// Each file: [index, resolved_filename]
-        files: Vec<(usize, String)>,
+        /// Each file: [index, target_filename, conflict_resolution]
+        files: Vec<(usize, String, AttachmentConflictResolution)>,
     },
     /// Copy files to clipboard.
     CopyFiles {
@@ -5,6 +5,7 @@ mod init;
 pub mod messages;
 
 pub use init::init_webview;
+pub use keva_worker::{ExactMatch, RenameResultType};
 
 use crate::platform::composition::CompositionHost;
 use serde::Serialize;
@@ -23,12 +24,13 @@ pub struct AttachmentInfo {
     pub filename: String,
     pub size: u64,
     pub thumbnail_url: Option<String>,
+    /// `[width, height]` of image attachments.
+    pub dimensions: Option<(u32, u32)>,
 }
 
 /// Messages from native to WebView.
 ///
 /// All messages are posted via PostMessageW from worker thread to UI thread.
-/// The Value variant requires `PostWebMessageAsJsonWithAdditionalObjects` for FileSystemHandle.
 #[derive(Serialize)]
 #[serde(
     tag = "type",
@@ -71,15 +73,25 @@ pub enum OutgoingMessage {
         /// Selected file paths
         files: Vec<String>,
     },
-    /// Key value with FileSystemHandle for content file.
+    /// Key value with its content. Edits are sent back as `save` messages.
     Value {
         key: String,
         key_hash: String,
-        #[serde(skip)]
-        content_path: std::path::PathBuf,
+        content: String,
         read_only: bool,
         attachments: Vec<AttachmentInfo>,
     },
+    /// Files were added under these names, after conflict resolution.
+    AttachmentsAdded {
+        key: String,
+        filenames: Vec<String>,
+    },
+    /// An attachment's thumbnail finished rendering after the Value was sent.
+    ThumbnailReady {
+        key: String,
+        filename: String,
+        thumbnail_url: String,
+    },
     /// Files pasted from clipboard (paths cached in native).
     FilesPasted {
         files: Vec<String>,
@@ -124,28 +136,10 @@ pub enum CopyAction {
     Files,
 }
 
-/// Exact match status for current search query.
-#[derive(Debug, Clone, Copy, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub enum ExactMatch {
-    None,
-    Active,
-    Trashed,
-}
-
-#[derive(Debug, Clone, Copy, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub enum RenameResultType {
-    Success,
-    DestinationExists,
-    InvalidKey,
-    NotFound,
-}
-
 /// Request data for opening a file picker.
 pub struct FilePickerRequest {
     pub key: String,
-    pub request_tx: std::sync::mpsc::Sender<crate::keva_worker::Request>,
+    pub request_tx: std::sync::mpsc::Sender<crate::worker::Request>,
 }
 
 pub static WEBVIEW: OnceLock<WebView> = OnceLock::new();
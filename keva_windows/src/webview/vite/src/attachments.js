@@ -180,7 +180,7 @@ export const Attachments = {
         return '<div class="attachment-item" tabindex="-1" draggable="true" data-index="' + index + '" data-filename="' + Utils.escapeHtml(att.filename) + '">' +
             icon +
             '<span class="attachment-name">' + Utils.escapeHtml(att.filename) + '</span>' +
-            '<span class="attachment-size">' + this.formatSize(att.size) + '</span>' +
+            '<span class="attachment-size">' + this.formatDetails(att) + '</span>' +
             '<span class="attachment-actions">' +
             '<button class="attachment-action-btn" data-action="rename" title="Rename">\u270F\uFE0F</button>' +
             '<button class="attachment-action-btn" data-action="delete" title="Delete">\u2716</button>' +
@@ -188,6 +188,11 @@ export const Attachments = {
             '</div>';
     },
 
+    formatDetails: function (att) {
+        const size = this.formatSize(att.size);
+        return att.dimensions ? att.dimensions[0] + '\u00D7' + att.dimensions[1] + ' \u00B7 ' + size : size;
+    },
+
     formatSize: function (bytes) {
         if (bytes < 1024) return bytes + ' B';
         if (bytes < 1024 * 1024) return (bytes / 1024).toFixed(1) + ' KB';
@@ -6,7 +6,8 @@ import { State } from './state.js';
 export const ConflictDialog = {
     /// Partitions files into conflicts/nonConflicts based on existing attachments.
     /// fileList: array of {id, filename} where id is index or path
-    /// Returns: {conflicts: [[id, filename], ...], nonConflicts: [[id, filename], ...]}
+    /// Returns: {conflicts: [[id, filename], ...], nonConflicts: [[id, filename, 'rename'], ...]}
+    /// Non-conflicts clashing with each other are renamed by core.
     checkConflicts: function (fileList) {
         const existingNames = new Set();
         for (let i = 0; i < State.data.attachments.length; i++) {
@@ -20,7 +21,7 @@ export const ConflictDialog = {
             if (existingNames.has(file.filename)) {
                 conflicts.push([file.id, file.filename]);
             } else {
-                nonConflicts.push([file.id, file.filename]);
+                nonConflicts.push([file.id, file.filename, 'rename']);
             }
         }
         return { conflicts: conflicts, nonConflicts: nonConflicts };
@@ -30,7 +31,6 @@ export const ConflictDialog = {
     key: null,
     conflicts: [],
     pending: [],
-    takenNames: null,
     applyToAll: false,
     resolutions: [],
     insertLinks: false,
@@ -47,12 +47,6 @@ export const ConflictDialog = {
         this.editorPosition = editorPosition;
         this.onFinish = onFinish;
 
-        // Build initial set of taken filenames
-        this.takenNames = new Set();
-        for (let i = 0; i < State.data.attachments.length; i++) {
-            this.takenNames.add(State.data.attachments[i].filename);
-        }
-
         this.createOverlay();
         this.showCurrentConflict();
     },
@@ -158,59 +152,15 @@ export const ConflictDialog = {
             this.resolveFile(conflict, action);
         }
 
-        this.recheckPending();
         this.showCurrentConflict();
     },
 
+    /// Core picks the final name for 'rename' ("name (1).ext") and reports it back.
     resolveFile: function (file, action) {
-        const index = file[0];
-        const filename = file[1];
-
         if (action === 'skip') {
             return;
         }
-
-        if (action === 'rename') {
-            const newName = this.findNextAvailableName(filename);
-            this.resolutions.push([index, newName]);
-            this.takenNames.add(newName);
-        } else if (action === 'overwrite') {
-            this.resolutions.push([index, filename]);
-        }
-    },
-
-    findNextAvailableName: function (filename) {
-        const dotIndex = filename.lastIndexOf('.');
-        let stem, ext;
-        if (dotIndex > 0) {
-            stem = filename.substring(0, dotIndex);
-            ext = filename.substring(dotIndex);
-        } else {
-            stem = filename;
-            ext = '';
-        }
-
-        let counter = 1;
-        let newName;
-        do {
-            newName = stem + ' (' + counter + ')' + ext;
-            counter++;
-        } while (this.takenNames.has(newName));
-
-        return newName;
-    },
-
-    recheckPending: function () {
-        const stillPending = [];
-        for (let i = 0; i < this.pending.length; i++) {
-            const file = this.pending[i];
-            if (this.takenNames.has(file[1])) {
-                this.conflicts.push(file);
-            } else {
-                stillPending.push(file);
-            }
-        }
-        this.pending = stillPending;
+        this.resolutions.push([file[0], file[1], action]);
     },
 
     cancel: function () {
@@ -228,9 +178,7 @@ export const ConflictDialog = {
 
         // Add remaining pending files
         for (let i = 0; i < this.pending.length; i++) {
-            const index = this.pending[i][0];
-            const filename = this.pending[i][1];
-            this.resolutions.push([index, filename]);
+            this.resolutions.push(this.pending[i]);
         }
 
         if (this.resolutions.length === 0) {
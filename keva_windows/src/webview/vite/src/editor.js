@@ -20,11 +20,9 @@ export const Editor = {
     instance: null,
     saveTimer: null,
     dom: null,
-    currentHandle: null,
     currentKey: null,
     keyHash: null,
     isReadOnly: false,
-    isSaving: false,
     isLargeFile: false,
     placeholderElement: null,
     previewCache: {html: null},
@@ -95,69 +93,42 @@ export const Editor = {
         }
     },
 
-    showWithHandle: async function (handle, key, readOnly) {
-        this.currentHandle = handle;
+    showValue: function (content, key, readOnly) {
         this.currentKey = key;
         this.isReadOnly = readOnly;
         SaveBanner.hide();
 
         if (!this.instance) return;
 
-        try {
-            const file = await handle.getFile();
-            const content = await file.text();
-            this.instance.setValue(content);
-            this.applyFileSizeOptions(content.length);
-            this.instance.updateOptions({readOnly: readOnly});
-            State.data.isDirty = false;
-            this.updatePlaceholder();
+        this.instance.setValue(content);
+        this.applyFileSizeOptions(content.length);
+        this.instance.updateOptions({readOnly: readOnly});
+        State.data.isDirty = false;
+        this.updatePlaceholder();
 
-            if (State.data.focusEditorOnLoad) {
-                this.instance.focus();
-                State.data.focusEditorOnLoad = false;
-            }
-        } catch (error) {
-            console.error('[Editor] Failed to read file:', error);
-            this.showError('Failed to read content');
+        if (State.data.focusEditorOnLoad) {
+            this.instance.focus();
+            State.data.focusEditorOnLoad = false;
         }
     },
 
-    writeToHandle: async function () {
-        if (!this.currentHandle || !this.instance || this.isReadOnly) {
-            return false;
-        }
-
-        if (this.isSaving) {
+    writeContent: function () {
+        if (!this.currentKey || !this.instance || this.isReadOnly) {
             return false;
         }
 
-        this.isSaving = true;
-        try {
-            const content = this.instance.getValue();
-            const writable = await this.currentHandle.createWritable();
-            await writable.write(content);
-            await writable.close();
-
-            // Update timestamp
-            if (this.currentKey) {
-                Api.send({type: 'touch', key: this.currentKey});
-            }
-            return true;
-        } catch (error) {
-            console.error('[Editor] Failed to write file:', error);
-            SaveBanner.show(null, this.retrySave.bind(this));
-            return false;
-        } finally {
-            this.isSaving = false;
-        }
+        // Written through the store, which also updates the timestamp; failures come back as
+        // saveFailed messages.
+        Api.send({type: 'save', key: this.currentKey, content: this.instance.getValue()});
+        return true;
     },
 
     scheduleSave: function () {
         const self = this;
         if (this.saveTimer) clearTimeout(this.saveTimer);
         this.saveTimer = setTimeout(async function () {
-            if (State.data.isDirty && State.data.selectedKey && self.currentHandle) {
-                const success = await self.writeToHandle();
+            if (State.data.isDirty && State.data.selectedKey && self.currentKey) {
+                const success = self.writeContent();
                 if (success) {
                     State.data.isDirty = false;
                 }
@@ -167,8 +138,8 @@ export const Editor = {
 
     forceSave: async function () {
         if (this.saveTimer) clearTimeout(this.saveTimer);
-        if (State.data.isDirty && State.data.selectedKey && this.currentHandle) {
-            const success = await this.writeToHandle();
+        if (State.data.isDirty && State.data.selectedKey && this.currentKey) {
+            const success = this.writeContent();
             if (success) {
                 State.data.isDirty = false;
             }
@@ -187,7 +158,6 @@ export const Editor = {
     },
 
     resetState: function () {
-        this.currentHandle = null;
         this.currentKey = null;
         this.keyHash = null;
         this.invalidatePreviewCache();
@@ -203,7 +173,7 @@ export const Editor = {
 
     retrySave: async function () {
         State.data.isDirty = true;
-        const success = await this.writeToHandle();
+        const success = this.writeContent();
         if (success) {
             State.data.isDirty = false;
         }
@@ -561,22 +561,24 @@ export const Main = {
                 // Reset to edit mode when switching keys
                 self.resetToEditMode();
 
-                // Check for FileSystemHandle in additionalObjects
-                if (event.additionalObjects && event.additionalObjects.length > 0) {
-                    const handle = event.additionalObjects[0];
-                    self.showEditorUI();
-                    await Editor.showWithHandle(handle, msg.key, msg.readOnly);
-                    // Handle pending copy after editor is loaded
-                    if (pendingCopyAction) {
-                        self.performCopy(pendingCopyAction);
-                    }
-                } else {
-                    // No handle received - show error
-                    console.error('[Main] No FileSystemHandle in message');
-                    Editor.showError('Failed to access content');
+                self.showEditorUI();
+                Editor.showValue(msg.content, msg.key, msg.readOnly);
+                // Handle pending copy after editor is loaded
+                if (pendingCopyAction) {
+                    self.performCopy(pendingCopyAction);
                 }
             },
 
+            thumbnailReady: function (msg) {
+                if (msg.key !== State.data.selectedKey) return;
+                const att = State.data.attachments.find(function (a) {
+                    return a.filename === msg.filename;
+                });
+                if (!att) return;
+                att.thumbnailUrl = msg.thumbnailUrl;
+                Attachments.render();
+            },
+
             keyCreated: function (msg) {
                 if (!msg.success) {
                     alert('Key already exists: ' + msg.key);
@@ -646,6 +648,13 @@ export const Main = {
                 }
             },
 
+            attachmentsAdded: function (msg) {
+                // Names after core's conflict resolution, used for links once the Value arrives
+                if (State.data.pendingLinkInsert && msg.key === State.data.selectedKey) {
+                    State.data.pendingLinkInsert.files = msg.filenames;
+                }
+            },
+
             filesPasted: function (msg) {
                 // Reject if search bar focused or no key selected
                 if (State.data.activePane === 'search') return;
@@ -772,12 +781,10 @@ export const Main = {
         State.data.isCopying = true;
         this.showAddingOverlay();
 
-        // Store pending link insertion info
+        // Store pending link insertion info; filenames arrive with attachmentsAdded
         if (insertLinks && editorPosition) {
             State.data.pendingLinkInsert = {
-                files: files.map(function (f) {
-                    return f[1];
-                }), // filenames
+                files: [],
                 selection: editorPosition // insertAttachmentLinks handles both position and selection
             };
         }
@@ -785,7 +792,7 @@ export const Main = {
         Api.send({
             type: 'addFiles',
             key: key,
-            files: files // [[index, filename], ...]
+            files: files // [[index, filename, resolution], ...]
         });
     },
 
@@ -0,0 +1,315 @@
+//! Background worker thread for KevaCore and SearchEngine operations.
+//!
+//! Request handling lives in `keva_worker`; this module opens the store with Win32 error
+//! dialogs and delivers responses to the UI thread via PostMessageW.
+
+use crate::platform::wm;
+use crate::webview::{AttachmentInfo, OutgoingMessage};
+use base64::prelude::{BASE64_STANDARD, Engine};
+use keva_core::core::KevaCore;
+use keva_core::types::{AppConfig, Config, GcConfig};
+use keva_worker::{Response, ResponseSink, Thumbnail, Worker, load_search_engine};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use windows::Win32::Foundation::{HWND, LPARAM, WPARAM};
+use windows::Win32::UI::WindowsAndMessaging::{
+    IDYES, IsWindowVisible, MB_ICONERROR, MB_OK, MB_YESNO, MessageBoxW, PostMessageW,
+};
+use windows_strings::w;
+
+pub use keva_worker::Request;
+
+/// Starts the worker thread.
+///
+/// The worker owns KevaCore and SearchEngine. It handles all requests and posts
+/// responses directly to the UI thread via PostMessageW.
+pub fn start(hwnd: HWND) -> Sender<Request> {
+    let (request_tx, request_rx) = mpsc::channel::<Request>();
+    let notify_tx = request_tx.clone();
+    let thumbnail_tx = request_tx.clone();
+    let hwnd_raw = hwnd.0 as isize;
+
+    thread::spawn(move || {
+        let hwnd = HWND(hwnd_raw as *mut _);
+
+        let app_config = load_app_config();
+        let gc_config = gc_config_from_app(&app_config);
+
+        let mut keva = match open_keva() {
+            Ok(keva) => keva,
+            Err(e) => {
+                let data_path = get_data_path();
+                show_database_error(&e, &data_path);
+
+                // Do not panic in a background thread. Inform the UI and exit the worker loop cleanly.
+                post_response(
+                    hwnd,
+                    OutgoingMessage::CoreInitFailed {
+                        message: format!("Failed to open database: {e}"),
+                        data_dir: data_path.to_string_lossy().into_owned(),
+                    },
+                );
+                return;
+            }
+        };
+
+        let notify: Arc<dyn Fn() + Send + Sync> = Arc::new(move || {
+            let _ = notify_tx.send(Request::SearchTick);
+        });
+        let index_path = search_index_path();
+        let search = load_search_engine(&keva, &index_path, notify);
+        keva.set_thumbnail_config(app_config.thumbnails.clone());
+        keva.set_quota_config(app_config.quotas);
+        keva.set_thumbnail_callback(Some(Arc::new(move |key, filename, _| {
+            let _ = thumbnail_tx.send(Request::ThumbnailReady {
+                key: key.to_string(),
+                filename: filename.to_string(),
+            });
+        })));
+        let welcome_shown = app_config.general.welcome_shown;
+
+        Worker::new(
+            keva,
+            search,
+            gc_config,
+            welcome_shown,
+            index_path,
+            WindowSink { hwnd },
+        )
+        .run(request_rx);
+    });
+
+    request_tx
+}
+
+/// Posts worker responses to the window.
+struct WindowSink {
+    hwnd: HWND,
+}
+
+impl ResponseSink for WindowSink {
+    fn send(&self, response: Response) {
+        let msg = match response {
+            Response::ShutdownComplete => {
+                unsafe {
+                    let _ =
+                        PostMessageW(Some(self.hwnd), wm::SHUTDOWN_COMPLETE, WPARAM(0), LPARAM(0));
+                }
+                return;
+            }
+            Response::CoreReady => OutgoingMessage::CoreReady,
+            Response::ShowWelcome => OutgoingMessage::ShowWelcome,
+            Response::KeyCreated { key, success } => OutgoingMessage::KeyCreated { key, success },
+            Response::SearchResults {
+                active_keys,
+                trashed_keys,
+                exact_match,
+            } => OutgoingMessage::SearchResults {
+                active_keys,
+                trashed_keys,
+                exact_match,
+            },
+            Response::RenameResult {
+                old_key,
+                new_key,
+                result,
+            } => OutgoingMessage::RenameResult {
+                old_key,
+                new_key,
+                result,
+            },
+            Response::FilesSelected { key, files } => OutgoingMessage::FilesSelected { key, files },
+            Response::Value {
+                key,
+                key_hash,
+                content,
+                read_only,
+                attachments,
+            } => OutgoingMessage::Value {
+                key,
+                key_hash,
+                content,
+                read_only,
+                attachments: attachments
+                    .into_iter()
+                    .map(|att| AttachmentInfo {
+                        filename: att.filename,
+                        size: att.size,
+                        thumbnail_url: att.thumbnail.as_ref().map(thumbnail_url),
+                        dimensions: att.dimensions,
+                    })
+                    .collect(),
+            },
+            Response::AttachmentsAdded { key, filenames } => {
+                OutgoingMessage::AttachmentsAdded { key, filenames }
+            }
+            Response::ThumbnailReady {
+                key,
+                filename,
+                thumbnail,
+            } => OutgoingMessage::ThumbnailReady {
+                key,
+                filename,
+                thumbnail_url: thumbnail_url(&thumbnail),
+            },
+            Response::Toast { message } => OutgoingMessage::Toast { message },
+            Response::SaveFailed { key, message } => OutgoingMessage::SaveFailed { key, message },
+        };
+        post_response(self.hwnd, msg);
+    }
+
+    fn is_visible(&self) -> bool {
+        unsafe { IsWindowVisible(self.hwnd).as_bool() }
+    }
+}
+
+/// Thumbnails are read through the store and inlined, so encrypted files never reach the
+/// WebView.
+fn thumbnail_url(thumbnail: &Thumbnail) -> String {
+    let mime = match thumbnail.extension.as_str() {
+        "jpg" => "jpeg",
+        other => other,
+    };
+    format!(
+        "data:image/{mime};base64,{}",
+        BASE64_STANDARD.encode(&thumbnail.bytes)
+    )
+}
+
+/// Posts an OutgoingMessage to the UI thread for WebView delivery.
+fn post_response(hwnd: HWND, msg: OutgoingMessage) {
+    let ptr = Box::into_raw(Box::new(msg));
+    unsafe {
+        if PostMessageW(
+            Some(hwnd),
+            wm::WEBVIEW_MESSAGE,
+            WPARAM(0),
+            LPARAM(ptr as isize),
+        )
+        .is_err()
+        {
+            drop(Box::from_raw(ptr));
+        }
+    }
+}
+
+fn search_index_path() -> PathBuf {
+    Config {
+        base_path: get_data_path(),
+    }
+    .search_index_path()
+}
+
+fn open_keva() -> Result<KevaCore, keva_core::core::error::KevaError> {
+    let base_path = get_data_path();
+    ensure_data_dir_exists_or_exit(&base_path);
+
+    let config = Config { base_path };
+    KevaCore::open(config)
+}
+
+fn load_app_config() -> AppConfig {
+    let config_path = AppConfig::path(&get_data_path());
+    let (config, errors) = AppConfig::load_or_default(&config_path);
+    if errors.is_empty() {
+        return config;
+    }
+
+    // Show validation error dialog
+    let error_list = errors.join("\n");
+    let backup_path = config_path.with_extension("toml.bak");
+    let msg = format!(
+        "Configuration file contains invalid values:\n\n{}\n\n\
+         Click Yes to launch with defaults in their place, or No to quit. \
+         Your file is kept, with a copy at {}.",
+        error_list,
+        backup_path.display()
+    );
+    let msg_wide: Vec<u16> = msg.encode_utf16().chain(std::iter::once(0)).collect();
+
+    let result = unsafe {
+        MessageBoxW(
+            None,
+            windows::core::PCWSTR(msg_wide.as_ptr()),
+            w!("Keva - Invalid Configuration"),
+            MB_ICONERROR | MB_YESNO,
+        )
+    };
+
+    if result == IDYES {
+        // Settings saved later replace the file, so keep the user's version beside it
+        if config_path.exists() {
+            let _ = std::fs::copy(&config_path, &backup_path);
+        }
+        config
+    } else {
+        std::process::exit(1);
+    }
+}
+
+fn gc_config_from_app(app_config: &AppConfig) -> GcConfig {
+    GcConfig::from(&app_config.lifecycle)
+}
+
+fn show_database_error(error: &keva_core::core::error::KevaError, data_path: &std::path::Path) {
+    let msg = format!(
+        "Failed to open database.\n\n\
+         Error: {error}\n\n\
+         Data directory: {}",
+        data_path.display()
+    );
+    let msg_wide: Vec<u16> = msg.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        MessageBoxW(
+            None,
+            windows::core::PCWSTR(msg_wide.as_ptr()),
+            w!("Keva - Database Error"),
+            MB_ICONERROR | MB_OK,
+        );
+    }
+}
+
+fn ensure_data_dir_exists_or_exit(data_path: &std::path::Path) {
+    if let Err(e) = std::fs::create_dir_all(data_path) {
+        let msg = format!(
+            "Failed to create data directory.\n\n\
+             Data directory: {}\n\n\
+             Error: {e}",
+            data_path.display()
+        );
+        let msg_wide: Vec<u16> = msg.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe {
+            MessageBoxW(
+                None,
+                windows::core::PCWSTR(msg_wide.as_ptr()),
+                w!("Keva - Data Directory Error"),
+                MB_ICONERROR | MB_OK,
+            );
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Returns the data directory, resolved once. Without one there's nowhere to keep the store, so
+/// Keva reports it and exits.
+pub fn get_data_path() -> PathBuf {
+    static DATA_PATH: OnceLock<PathBuf> = OnceLock::new();
+    DATA_PATH
+        .get_or_init(|| {
+            Config::default_data_dir().unwrap_or_else(|| {
+                unsafe {
+                    MessageBoxW(
+                        None,
+                        w!("Failed to locate the data directory.\n\n\
+                            The LOCALAPPDATA environment variable is not set."),
+                        w!("Keva - Data Directory Error"),
+                        MB_ICONERROR | MB_OK,
+                    );
+                }
+                std::process::exit(1);
+            })
+        })
+        .clone()
+}
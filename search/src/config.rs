@@ -7,6 +7,19 @@ pub enum CaseMatching {
     Smart,
 }
 
+/// Ordering of active search results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Ranking {
+    /// Fuzzy match score only.
+    #[default]
+    Score,
+    /// Fuzzy match score plus a bonus for recently and frequently accessed keys.
+    ///
+    /// Access stats are supplied via `SearchEngine::set_access_stats`. Trashed results are
+    /// always ordered by score.
+    Frecency,
+}
+
 #[derive(Debug, Clone)]
 pub struct SearchConfig {
     pub case_matching: CaseMatching,
@@ -14,6 +27,7 @@ pub struct SearchConfig {
     pub rebuild_threshold: usize,
     pub active_result_limit: usize,
     pub trashed_result_limit: usize,
+    pub ranking: Ranking,
 }
 
 impl Default for SearchConfig {
@@ -24,6 +38,7 @@ impl Default for SearchConfig {
             rebuild_threshold: 100,
             active_result_limit: 100,
             trashed_result_limit: 20,
+            ranking: Ranking::default(),
         }
     }
 }
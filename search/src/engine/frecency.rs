@@ -0,0 +1,46 @@
+//! Frecency bonus blending access recency and frequency into fuzzy match scores.
+
+use keva_core::types::{AccessStats, Key};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// Bonus for one unit of frecency, roughly what Nucleo awards per matched character.
+///
+/// Short queries produce small fuzzy scores, so frecency dominates them; long queries produce
+/// large scores and the best textual match still wins.
+const BONUS_UNIT: f64 = 16.0;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Borrowed access stats plus the reference time used to compute recency.
+pub(crate) struct Frecency<'a> {
+    pub(crate) stats: &'a HashMap<Key, AccessStats>,
+    pub(crate) now: SystemTime,
+}
+
+impl Frecency<'_> {
+    /// Returns the score bonus for a key. Keys without stats get no bonus.
+    pub(crate) fn bonus(&self, key: &Key) -> u32 {
+        let Some(stats) = self.stats.get(key) else {
+            return 0;
+        };
+
+        let age_days = self
+            .now
+            .duration_since(stats.last_accessed)
+            .unwrap_or_default()
+            .as_secs()
+            / DAY.as_secs();
+
+        let recency = match age_days {
+            0 => 1.0,
+            1..7 => 0.7,
+            7..30 => 0.5,
+            30..90 => 0.3,
+            _ => 0.1,
+        };
+        let frequency = (1.0 + stats.access_count as f64).log2();
+
+        (BONUS_UNIT * recency * frequency).round() as u32
+    }
+}
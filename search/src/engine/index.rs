@@ -1,6 +1,7 @@
+use super::frecency::Frecency;
 use keva_core::types::Key;
 use nucleo::pattern::{CaseMatching, Normalization};
-use nucleo::{Config as NucleoConfig, Nucleo, Snapshot, Utf32String};
+use nucleo::{Config as NucleoConfig, Item, Matcher, Nucleo, Snapshot, Utf32String};
 use std::collections::HashSet;
 use std::sync::Arc;
 
@@ -16,6 +17,12 @@ pub(crate) struct Index {
     current_pattern: String,
     /// True when current query uses append optimization (count may be stale until done).
     is_appending: bool,
+    /// When true, ticking continues until Nucleo is done instead of stopping at the result limit,
+    /// so re-ranking sees every match.
+    rank_all: bool,
+    /// Positions in the snapshot's matches, best first, as computed by [`rerank`](Self::rerank).
+    /// `None` until the current snapshot has been ranked; results are then in Nucleo's order.
+    ranking: Option<Vec<u32>>,
 }
 
 impl Index {
@@ -23,6 +30,7 @@ impl Index {
         initial: Vec<Key>,
         rebuild_threshold: usize,
         result_limit: usize,
+        rank_all: bool,
         notify: Arc<dyn Fn() + Send + Sync>,
     ) -> Self {
         let nucleo = Nucleo::new(NucleoConfig::DEFAULT, notify, None, 1);
@@ -37,6 +45,8 @@ impl Index {
             at_threshold: false,
             current_pattern: String::new(),
             is_appending: false,
+            rank_all,
+            ranking: None,
         };
 
         for key in initial {
@@ -46,6 +56,14 @@ impl Index {
         index
     }
 
+    /// Returns keys that are injected and not tombstoned.
+    pub(crate) fn live_keys(&self) -> Vec<Key> {
+        self.injected_keys
+            .difference(&self.tombstones)
+            .cloned()
+            .collect()
+    }
+
     pub(crate) fn is_present(&self, key: &Key) -> bool {
         self.injected_keys.contains(key) && !self.tombstones.contains(key)
     }
@@ -86,20 +104,23 @@ impl Index {
         self.current_pattern = pattern.to_string();
         self.at_threshold = false;
         self.is_appending = append;
+        self.ranking = None;
     }
 
     /// Returns true if results may have changed and we should send updates.
-    pub(crate) fn tick(&mut self) -> bool {
+    ///
+    /// Waits up to `timeout_ms` for Nucleo to finish matching; 0 doesn't block.
+    pub(crate) fn tick(&mut self, timeout_ms: u64) -> bool {
         if self.at_threshold {
             return false;
         }
 
-        let status = self.nucleo.tick(0);
+        let status = self.nucleo.tick(timeout_ms);
 
         // With append optimization, the count includes stale matches until filtering completes.
         // Only use count threshold when not appending (fresh search) or when nucleo is done.
         let count_reliable = !self.is_appending || !status.running;
-        if count_reliable {
+        if count_reliable && !self.rank_all {
             let result_count = self.nucleo.snapshot().matched_item_count();
             if result_count >= self.result_limit as u32 {
                 self.at_threshold = true;
@@ -128,6 +149,7 @@ impl Index {
         self.nucleo.restart(true);
         self.pending_deletions = 0;
         self.current_pattern.clear();
+        self.ranking = None;
 
         let injector = self.nucleo.injector();
         for key in self.injected_keys.difference(&self.tombstones) {
@@ -142,11 +164,36 @@ impl Index {
         self.current_pattern.clear();
     }
 
+    /// Orders the current matches by fuzzy score plus frecency bonus.
+    ///
+    /// Scores only change when the snapshot or the access stats do, so this runs on ticks rather
+    /// than every time results are read.
+    pub(crate) fn rerank(&mut self, frecency: &Frecency) {
+        let snapshot = self.nucleo.snapshot();
+        let pattern = snapshot.pattern().column_pattern(0);
+        let mut matcher = Matcher::new(NucleoConfig::DEFAULT);
+        let mut scored: Vec<(u32, u32)> = snapshot
+            .matched_items(..)
+            .zip(0..)
+            .map(|(item, position)| {
+                let score = pattern
+                    .score(item.matcher_columns[0].slice(..), &mut matcher)
+                    .unwrap_or(0);
+                (score + frecency.bonus(item.data), position)
+            })
+            .collect();
+
+        // Stable sort keeps Nucleo's order among equal scores.
+        scored.sort_by(|(a, _), (b, _)| b.cmp(a));
+        self.ranking = Some(scored.into_iter().map(|(_, position)| position).collect());
+    }
+
     pub(crate) fn results(&self) -> SearchResults<'_> {
         SearchResults {
             snapshot: self.nucleo.snapshot(),
             tombstones: &self.tombstones,
             result_limit: self.result_limit,
+            ranking: self.ranking.as_deref(),
         }
     }
 }
@@ -155,14 +202,67 @@ pub struct SearchResults<'a> {
     pub(crate) snapshot: &'a Snapshot<Key>,
     pub(crate) tombstones: &'a HashSet<Key>,
     pub(crate) result_limit: usize,
+    pub(crate) ranking: Option<&'a [u32]>,
+}
+
+/// A single search result with the data needed to highlight it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch<'a> {
+    pub key: &'a Key,
+    /// Fuzzy match score (higher is better). Excludes any frecency bonus.
+    pub score: u32,
+    /// Sorted, deduplicated char indices (not byte offsets) of matched characters in `key`.
+    pub indices: Vec<u32>,
 }
 
 impl<'a> SearchResults<'a> {
     pub fn iter(&self) -> impl Iterator<Item = &Key> + '_ {
-        self.snapshot
-            .matched_items(..)
-            .filter(|item| !self.tombstones.contains(item.data))
-            .map(|item| item.data)
-            .take(self.result_limit)
+        self.ranked().into_iter().map(|item| item.data)
+    }
+
+    /// Like [`iter`](Self::iter), but also yields each key's score and matched char indices.
+    ///
+    /// Indices are recomputed per result, so this is slightly more expensive than `iter`.
+    pub fn matches(&self) -> impl Iterator<Item = SearchMatch<'a>> + '_ {
+        let pattern = self.snapshot.pattern().column_pattern(0);
+        let mut matcher = Matcher::new(NucleoConfig::DEFAULT);
+
+        self.ranked().into_iter().map(move |item| {
+            let mut indices = Vec::new();
+            let score = pattern
+                .indices(
+                    item.matcher_columns[0].slice(..),
+                    &mut matcher,
+                    &mut indices,
+                )
+                .unwrap_or(0);
+            indices.sort_unstable();
+            indices.dedup();
+
+            SearchMatch {
+                key: item.data,
+                score,
+                indices,
+            }
+        })
+    }
+
+    /// Returns live matches in display order, limited to `result_limit`.
+    fn ranked(&self) -> Vec<Item<'a, Key>> {
+        let is_live = |item: &Item<'a, Key>| !self.tombstones.contains(item.data);
+        match self.ranking {
+            Some(ranking) => ranking
+                .iter()
+                .filter_map(|&position| self.snapshot.get_matched_item(position))
+                .filter(is_live)
+                .take(self.result_limit)
+                .collect(),
+            None => self
+                .snapshot
+                .matched_items(..)
+                .filter(is_live)
+                .take(self.result_limit)
+                .collect(),
+        }
     }
 }
@@ -1,19 +1,30 @@
+mod frecency;
 mod index;
+mod persist;
 mod query;
 
-use crate::config::{CaseMatching, SearchConfig};
+use crate::config::{CaseMatching, Ranking, SearchConfig};
+use frecency::Frecency;
 use index::Index;
-use keva_core::types::Key;
+use keva_core::types::{AccessStats, Key};
 use nucleo::pattern::{CaseMatching as NucleoCaseMatching, Normalization};
+use persist::IndexSnapshot;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-pub use index::SearchResults;
+pub use index::{SearchMatch, SearchResults};
 pub use query::SearchQuery;
 
 pub struct SearchEngine {
     active: Index,
     trash: Index,
     config: SearchConfig,
+    access_stats: HashMap<Key, AccessStats>,
+    /// Access stats changed since active results were last ranked.
+    stats_changed: bool,
 }
 
 impl SearchEngine {
@@ -29,15 +40,19 @@ impl SearchEngine {
                 active,
                 config.rebuild_threshold,
                 config.active_result_limit,
+                config.ranking == Ranking::Frecency,
                 notify.clone(),
             ),
             trash: Index::new(
                 trashed,
                 config.rebuild_threshold,
                 config.trashed_result_limit,
+                false,
                 notify,
             ),
             config,
+            access_stats: HashMap::new(),
+            stats_changed: false,
         }
     }
 }
@@ -62,9 +77,13 @@ impl SearchEngine {
     pub fn remove(&mut self, key: &Key) {
         self.active.remove(key);
         self.trash.remove(key);
+        self.access_stats.remove(key);
     }
 
     pub fn rename(&mut self, old: &Key, new: Key) {
+        if let Some(stats) = self.access_stats.remove(old) {
+            self.access_stats.insert(new.clone(), stats);
+        }
         if self.active.is_present(old) {
             self.active.remove(old);
             self.active.insert(new);
@@ -77,6 +96,28 @@ impl SearchEngine {
     }
 }
 
+/// Access tracking for frecency ranking.
+impl SearchEngine {
+    /// Records access stats for a key, typically from the value returned by `KevaCore::touch`
+    /// (`Metadata::access_stats`). Only used when `SearchConfig::ranking` is `Frecency`; results
+    /// are re-ranked on the next [`tick`](Self::tick).
+    pub fn set_access_stats(&mut self, key: Key, stats: AccessStats) {
+        self.access_stats.insert(key, stats);
+        self.stats_changed = self.is_frecency_ranked();
+    }
+
+    /// Replaces all recorded access stats, e.g. with `KevaCore::access_stats` after building the
+    /// engine from the store.
+    pub fn load_access_stats(&mut self, stats: impl IntoIterator<Item = (Key, AccessStats)>) {
+        self.access_stats = stats.into_iter().collect();
+        self.stats_changed = self.is_frecency_ranked();
+    }
+
+    fn is_frecency_ranked(&self) -> bool {
+        self.config.ranking == Ranking::Frecency
+    }
+}
+
 /// Search operations.
 impl SearchEngine {
     pub fn set_query(&mut self, query: SearchQuery) {
@@ -101,14 +142,33 @@ impl SearchEngine {
     }
 
     /// Returns true if results may have changed.
+    ///
+    /// With frecency ranking, active results are re-ranked here whenever they or the access
+    /// stats changed, so reading them stays cheap.
     pub fn tick(&mut self) -> bool {
-        let active_changed = self.active.tick();
-        let trash_changed = self.trash.tick();
+        self.tick_timeout(Duration::ZERO)
+    }
+
+    /// Like [`tick`](Self::tick), but waits up to `timeout` for each index to finish matching,
+    /// for callers that want complete results rather than a responsive UI.
+    pub fn tick_timeout(&mut self, timeout: Duration) -> bool {
+        let timeout_ms = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+        let mut active_changed = self.active.tick(timeout_ms);
+        if self.is_frecency_ranked() && (active_changed || self.stats_changed) {
+            let frecency = Frecency {
+                stats: &self.access_stats,
+                now: SystemTime::now(),
+            };
+            self.active.rerank(&frecency);
+            self.stats_changed = false;
+            active_changed = true;
+        }
+        let trash_changed = self.trash.tick(timeout_ms);
         active_changed || trash_changed
     }
 
     pub fn is_done(&self) -> bool {
-        self.active.is_done() && self.trash.is_done()
+        self.active.is_done() && self.trash.is_done() && !self.stats_changed
     }
 
     pub fn active_results(&self) -> SearchResults<'_> {
@@ -138,6 +198,47 @@ impl SearchEngine {
     }
 }
 
+/// Persistence operations.
+impl SearchEngine {
+    /// Restores an engine from a snapshot written by [`SearchEngine::save`]. The keys are still
+    /// injected into Nucleo; the snapshot only saves scanning the store for them.
+    ///
+    /// Returns `None` if the snapshot is missing, unreadable, or was taken at a different store
+    /// generation. Callers should then fall back to [`SearchEngine::new`].
+    pub fn load(
+        path: &Path,
+        generation: u64,
+        config: SearchConfig,
+        notify: Arc<dyn Fn() + Send + Sync>,
+    ) -> Option<Self> {
+        let snapshot = IndexSnapshot::read(path)?;
+        if snapshot.generation != generation {
+            return None;
+        }
+        let mut engine = Self::new(snapshot.active, snapshot.trashed, config, notify);
+        engine.load_access_stats(snapshot.access_stats);
+        Some(engine)
+    }
+
+    /// Saves the live keys of both indexes and the recorded access stats.
+    ///
+    /// `generation` must be the store generation (`KevaCore::generation`) that the indexes
+    /// currently reflect.
+    pub fn save(&self, path: &Path, generation: u64) -> io::Result<()> {
+        IndexSnapshot {
+            generation,
+            active: self.active.live_keys(),
+            trashed: self.trash.live_keys(),
+            access_stats: self
+                .access_stats
+                .iter()
+                .map(|(key, stats)| (key.clone(), *stats))
+                .collect(),
+        }
+        .write(path)
+    }
+}
+
 /// Maintenance operations.
 impl SearchEngine {
     /// Triggers index rebuild if pending deletions exceed the threshold.
@@ -0,0 +1,44 @@
+//! On-disk snapshot of the search indexes.
+//!
+//! Nucleo state can't be serialized, so a snapshot stores the live key sets (plus frecency access
+//! stats) and re-injects them on load. The saving is the store scan only: reading the snapshot
+//! replaces `active_keys`, `trashed_keys` and `access_stats`, while injecting the keys into
+//! Nucleo costs the same either way (see keva_search.md for measurements).
+//! Each snapshot records the store generation it reflects; on a mismatch the caller rebuilds
+//! from the store instead.
+
+use keva_core::types::{AccessStats, Key};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+/// Increment when the snapshot layout changes. Snapshots with another version are ignored.
+const SNAPSHOT_VERSION: u8 = 2;
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct IndexSnapshot {
+    pub(crate) generation: u64,
+    pub(crate) active: Vec<Key>,
+    pub(crate) trashed: Vec<Key>,
+    pub(crate) access_stats: Vec<(Key, AccessStats)>,
+}
+
+impl IndexSnapshot {
+    /// Returns `None` if the file is missing, truncated, or from another snapshot version.
+    pub(crate) fn read(path: &Path) -> Option<Self> {
+        let bytes = std::fs::read(path).ok()?;
+        let (version, data) = bytes.split_first()?;
+        if *version != SNAPSHOT_VERSION {
+            return None;
+        }
+        postcard::from_bytes(data).ok()
+    }
+
+    /// Writes through a temporary file so an interrupted save never leaves a partial snapshot.
+    pub(crate) fn write(&self, path: &Path) -> io::Result<()> {
+        let bytes = postcard::to_extend(self, vec![SNAPSHOT_VERSION]).map_err(io::Error::other)?;
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, bytes)?;
+        std::fs::rename(&tmp_path, path)
+    }
+}
@@ -10,18 +10,24 @@
 //!   - `tombstones`: keys to filter out from search results
 //! - Search filters out stale Nucleo entries using tombstones.
 //! - Heavy compaction/rebuild runs during periodic maintenance, not on every search.
+//! - Active results can optionally be ranked by frecency (fuzzy score blended with access
+//!   recency and frequency).
+//! - Live key sets can be saved to disk and reloaded on startup, validated against the store
+//!   generation from keva_core.
 //!
 //! # Non-blocking API
 //!
 //! - `set_query()`: Sets the search pattern
 //! - `tick()`: Drives search forward without blocking (calls nucleo.tick(0))
+//! - `tick_timeout()`: Like `tick()`, but waits for matching to finish, for callers without a UI
 //! - `active_results()`, `trashed_results()`: Get search results
+//! - `SearchResults::matches()`: Results with scores and matched char indices for highlighting
 
 mod config;
 mod engine;
 
-pub use config::{CaseMatching, SearchConfig};
-pub use engine::{SearchEngine, SearchQuery, SearchResults};
+pub use config::{CaseMatching, Ranking, SearchConfig};
+pub use engine::{SearchEngine, SearchMatch, SearchQuery, SearchResults};
 
 #[cfg(test)]
 mod tests;
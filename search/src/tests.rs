@@ -538,6 +538,19 @@ mod tick {
         assert!(!engine.tick());
     }
 
+    #[test]
+    fn test_tick_timeout_waits_for_matching() {
+        let keys: Vec<String> = (0..20_000).map(|i| format!("key{i}")).collect();
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        let mut engine = create_engine_with_active(&keys);
+
+        engine.set_query(SearchQuery::Fuzzy("zzz".to_string()));
+        engine.tick_timeout(Duration::from_secs(10));
+
+        assert!(engine.is_done());
+        assert_eq!(engine.active_results().matches().count(), 0);
+    }
+
     #[test]
     fn test_tick_set_query_resets_threshold() {
         let mut engine = create_engine_with_active(&["key"]);
@@ -579,3 +592,335 @@ mod threshold {
         assert!(!engine.tick());
     }
 }
+
+mod persistence {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn sorted_results(engine: &SearchEngine) -> (Vec<Key>, Vec<Key>) {
+        let mut active: Vec<Key> = engine.active_results().iter().cloned().collect();
+        let mut trashed: Vec<Key> = engine.trashed_results().iter().cloned().collect();
+        active.sort();
+        trashed.sort();
+        (active, trashed)
+    }
+
+    #[test]
+    fn test_save_and_load_roundtrip() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("search.idx");
+        let engine = create_engine_with_both(&["alpha", "beta"], &["gamma"]);
+
+        engine.save(&path, 7).unwrap();
+
+        let mut loaded = SearchEngine::load(&path, 7, test_config(), no_op_notify()).unwrap();
+        search(&mut loaded, "");
+
+        let (active, trashed) = sorted_results(&loaded);
+        assert_eq!(active, vec![make_key("alpha"), make_key("beta")]);
+        assert_eq!(trashed, vec![make_key("gamma")]);
+    }
+
+    #[test]
+    fn test_load_rejects_generation_mismatch() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("search.idx");
+        let engine = create_engine_with_active(&["alpha"]);
+
+        engine.save(&path, 7).unwrap();
+
+        assert!(SearchEngine::load(&path, 8, test_config(), no_op_notify()).is_none());
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("search.idx");
+
+        assert!(SearchEngine::load(&path, 0, test_config(), no_op_notify()).is_none());
+    }
+
+    #[test]
+    fn test_load_rejects_corrupt_file() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("search.idx");
+        std::fs::write(&path, b"\x01garbage").unwrap();
+
+        assert!(SearchEngine::load(&path, 0, test_config(), no_op_notify()).is_none());
+    }
+
+    #[test]
+    fn test_save_excludes_removed_keys() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("search.idx");
+        let mut engine = create_engine_with_active(&["alpha", "beta", "gamma"]);
+
+        engine.remove(&make_key("beta"));
+        engine.trash(&make_key("gamma"));
+        engine.save(&path, 1).unwrap();
+
+        let mut loaded = SearchEngine::load(&path, 1, test_config(), no_op_notify()).unwrap();
+        search(&mut loaded, "");
+
+        let (active, trashed) = sorted_results(&loaded);
+        assert_eq!(active, vec![make_key("alpha")]);
+        assert_eq!(trashed, vec![make_key("gamma")]);
+        assert!(!loaded.has_key(&make_key("beta")));
+    }
+
+    #[test]
+    fn test_save_overwrites_previous_snapshot() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("search.idx");
+
+        create_engine_with_active(&["old"]).save(&path, 1).unwrap();
+        create_engine_with_active(&["new"]).save(&path, 2).unwrap();
+
+        let loaded = SearchEngine::load(&path, 2, test_config(), no_op_notify()).unwrap();
+        assert!(loaded.has_active(&make_key("new")));
+        assert!(!loaded.has_active(&make_key("old")));
+    }
+}
+
+mod frecency {
+    use super::*;
+    use keva_core::types::AccessStats;
+    use std::time::SystemTime;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn frecency_config() -> SearchConfig {
+        SearchConfig {
+            ranking: Ranking::Frecency,
+            ..SearchConfig::default()
+        }
+    }
+
+    fn create_frecency_engine(keys: &[&str]) -> SearchEngine {
+        let active = keys.iter().map(|s| make_key(s)).collect();
+        SearchEngine::new(active, vec![], frecency_config(), no_op_notify())
+    }
+
+    fn stats(age: Duration, access_count: u64) -> AccessStats {
+        AccessStats {
+            last_accessed: SystemTime::now() - age,
+            access_count,
+        }
+    }
+
+    fn active_order(engine: &SearchEngine) -> Vec<String> {
+        engine
+            .active_results()
+            .iter()
+            .map(|k| k.as_str().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_frequent_key_ranks_first_on_empty_query() {
+        let mut engine = create_frecency_engine(&["alpha", "beta", "gamma"]);
+        engine.set_access_stats(make_key("gamma"), stats(Duration::ZERO, 20));
+        engine.set_access_stats(make_key("beta"), stats(Duration::ZERO, 2));
+
+        search(&mut engine, "");
+
+        assert_eq!(active_order(&engine), vec!["gamma", "beta", "alpha"]);
+    }
+
+    #[test]
+    fn test_recent_key_beats_stale_key_with_same_count() {
+        let mut engine = create_frecency_engine(&["old", "new"]);
+        engine.set_access_stats(make_key("old"), stats(DAY * 100, 5));
+        engine.set_access_stats(make_key("new"), stats(Duration::ZERO, 5));
+
+        search(&mut engine, "");
+
+        assert_eq!(active_order(&engine), vec!["new", "old"]);
+    }
+
+    #[test]
+    fn test_frecency_applies_to_short_queries() {
+        let mut engine = create_frecency_engine(&["note/a", "note/b"]);
+        engine.set_access_stats(make_key("note/b"), stats(Duration::ZERO, 30));
+
+        search(&mut engine, "no");
+
+        assert_eq!(active_order(&engine), vec!["note/b", "note/a"]);
+    }
+
+    #[test]
+    fn test_score_ranking_ignores_access_stats() {
+        let mut engine = create_engine_with_active(&["alpha", "beta"]);
+        engine.set_access_stats(make_key("beta"), stats(Duration::ZERO, 100));
+
+        search(&mut engine, "");
+
+        assert_eq!(active_order(&engine), vec!["alpha", "beta"]);
+    }
+
+    #[test]
+    fn test_frecency_respects_result_limit() {
+        let config = SearchConfig {
+            active_result_limit: 2,
+            ..frecency_config()
+        };
+        let active = (0..10).map(|i| make_key(&format!("key{i}"))).collect();
+        let mut engine = SearchEngine::new(active, vec![], config, no_op_notify());
+        engine.set_access_stats(make_key("key9"), stats(Duration::ZERO, 10));
+
+        search(&mut engine, "");
+
+        let results = active_order(&engine);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0], "key9");
+    }
+
+    #[test]
+    fn test_rename_carries_access_stats() {
+        let mut engine = create_frecency_engine(&["alpha", "beta"]);
+        engine.set_access_stats(make_key("beta"), stats(Duration::ZERO, 10));
+
+        engine.rename(&make_key("beta"), make_key("renamed"));
+        search(&mut engine, "");
+
+        assert_eq!(active_order(&engine), vec!["renamed", "alpha"]);
+    }
+
+    #[test]
+    fn test_remove_drops_access_stats() {
+        let mut engine = create_frecency_engine(&["alpha", "beta"]);
+        engine.set_access_stats(make_key("beta"), stats(Duration::ZERO, 10));
+
+        engine.remove(&make_key("beta"));
+        engine.add_active(make_key("beta"));
+        search(&mut engine, "");
+
+        assert_eq!(active_order(&engine), vec!["alpha", "beta"]);
+    }
+
+    #[test]
+    fn test_access_stats_change_reranks_on_next_tick() {
+        let mut engine = create_frecency_engine(&["alpha", "beta"]);
+        search(&mut engine, "");
+        assert_eq!(active_order(&engine), vec!["alpha", "beta"]);
+
+        engine.set_access_stats(make_key("beta"), stats(Duration::ZERO, 10));
+        assert!(!engine.is_done());
+        assert!(engine.tick());
+
+        assert!(engine.is_done());
+        assert_eq!(active_order(&engine), vec!["beta", "alpha"]);
+    }
+
+    #[test]
+    fn test_load_access_stats_replaces_all() {
+        let mut engine = create_frecency_engine(&["alpha", "beta", "gamma"]);
+        engine.set_access_stats(make_key("alpha"), stats(Duration::ZERO, 50));
+
+        engine.load_access_stats([(make_key("gamma"), stats(Duration::ZERO, 10))]);
+        search(&mut engine, "");
+
+        assert_eq!(active_order(&engine), vec!["gamma", "alpha", "beta"]);
+    }
+
+    #[test]
+    fn test_score_ranking_stays_done_after_access_stats_change() {
+        let mut engine = create_engine_with_active(&["alpha"]);
+        search(&mut engine, "");
+
+        engine.set_access_stats(make_key("alpha"), stats(Duration::ZERO, 1));
+
+        assert!(engine.is_done());
+    }
+
+    #[test]
+    fn test_snapshot_preserves_access_stats() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("search.idx");
+        let mut engine = create_frecency_engine(&["alpha", "beta"]);
+        engine.set_access_stats(make_key("beta"), stats(Duration::ZERO, 10));
+        engine.save(&path, 1).unwrap();
+
+        let mut loaded = SearchEngine::load(&path, 1, frecency_config(), no_op_notify()).unwrap();
+        search(&mut loaded, "");
+
+        assert_eq!(active_order(&loaded)[0], "beta");
+    }
+}
+
+mod matches {
+    use super::*;
+
+    #[test]
+    fn test_matches_reports_indices() {
+        let mut engine = create_engine_with_active(&["hello"]);
+
+        search(&mut engine, "heo");
+
+        let results = engine.active_results();
+        let matches: Vec<SearchMatch> = results.matches().collect();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].key, &make_key("hello"));
+        assert_eq!(matches[0].indices, vec![0, 1, 4]);
+        assert!(matches[0].score > 0);
+    }
+
+    #[test]
+    fn test_matches_uses_char_indices() {
+        let mut engine = create_engine_with_active(&["日本/note"]);
+
+        search(&mut engine, "note");
+
+        let results = engine.active_results();
+        let matches: Vec<SearchMatch> = results.matches().collect();
+        assert_eq!(matches[0].indices, vec![3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_matches_empty_query_has_no_indices() {
+        let mut engine = create_engine_with_active(&["alpha", "beta"]);
+
+        search(&mut engine, "");
+
+        let results = engine.active_results();
+        for m in results.matches() {
+            assert!(m.indices.is_empty());
+            assert_eq!(m.score, 0);
+        }
+    }
+
+    #[test]
+    fn test_matches_follows_iter_order() {
+        let mut engine = create_engine_with_active(&["abc", "a_b_c", "xabcx"]);
+
+        search(&mut engine, "abc");
+
+        let results = engine.active_results();
+        let from_iter: Vec<&Key> = results.iter().collect();
+        let from_matches: Vec<&Key> = results.matches().map(|m| m.key).collect();
+        assert_eq!(from_iter, from_matches);
+    }
+
+    #[test]
+    fn test_matches_excludes_tombstones() {
+        let mut engine = create_engine_with_active(&["apple", "apricot"]);
+        engine.remove(&make_key("apple"));
+
+        search(&mut engine, "ap");
+
+        let results = engine.active_results();
+        let keys: Vec<&Key> = results.matches().map(|m| m.key).collect();
+        assert_eq!(keys, vec![&make_key("apricot")]);
+    }
+
+    #[test]
+    fn test_matches_on_trashed_results() {
+        let mut engine = create_engine_with_both(&[], &["trashed"]);
+
+        search(&mut engine, "tr");
+
+        let results = engine.trashed_results();
+        let matches: Vec<SearchMatch> = results.matches().collect();
+        assert_eq!(matches[0].indices, vec![0, 1]);
+    }
+}
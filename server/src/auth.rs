@@ -0,0 +1,16 @@
+//! Checking the API token (see `keva_core::core::load_or_create_token`).
+
+/// Checks an `Authorization` header value against the token without short-circuiting on the
+/// first differing byte.
+pub(crate) fn is_authorized(header: Option<&str>, token: &str) -> bool {
+    let Some(presented) = header.and_then(|value| value.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    let (presented, token) = (presented.trim().as_bytes(), token.as_bytes());
+    presented.len() == token.len()
+        && presented
+            .iter()
+            .zip(token)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
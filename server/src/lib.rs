@@ -0,0 +1,151 @@
+//! Local HTTP/JSON API over a Keva store.
+//!
+//! Lets scripts, browser extensions and editor plugins talk to Keva. The store's database is
+//! locked by the process that opens it, so serving a running Keva means embedding [`Server`] in
+//! the app rather than running the `keva_server` binary next to it.
+//!
+//! # Design
+//!
+//! - Binds to `127.0.0.1` only; the API is never reachable from other machines.
+//! - Every request must carry `Authorization: Bearer <token>`. The token is generated on first
+//!   start and stored in the data directory (`server.token`), readable by local tools.
+//! - Requests are served one at a time on the calling thread, which owns `KevaCore` and
+//!   `SearchEngine`; the search index is updated alongside every key mutation.
+//! - Keys and attachment filenames are percent-encoded path segments, so `/` in a key is `%2F`.
+//! - Content and attachment uploads are streamed into the store; JSON bodies are capped at 64 KiB.
+//!
+//! # Routes
+//!
+//! | Method | Path | Action |
+//! |---|---|---|
+//! | GET | `/keys` | List Active and Trash keys |
+//! | POST | `/keys` | Create key, body `{"key": ...}` |
+//! | GET | `/keys/{key}` | Get value |
+//! | DELETE | `/keys/{key}` | Trash key; `?purge=true` deletes it permanently |
+//! | POST | `/keys/{key}/restore` | Restore from Trash |
+//! | POST | `/keys/{key}/rename` | Rename, body `{"to": ...}` |
+//! | GET, PUT | `/keys/{key}/content` | Read or replace markdown content |
+//! | GET, PUT, DELETE | `/keys/{key}/attachments/{filename}` | Download, upload or remove |
+//! | GET | `/search?q=...` | Fuzzy search |
+//! | POST | `/maintenance` | Run garbage collection |
+
+mod auth;
+mod routes;
+
+use keva_core::core::{KevaCore, load_or_create_token};
+use keva_core::types::{Config, GcConfig};
+use keva_search::SearchEngine;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum ServerError {
+        #[error("IO error: {0}")]
+        Io(#[from] std::io::Error),
+
+        #[error("Failed to bind: {0}")]
+        Bind(#[source] Box<dyn std::error::Error + Send + Sync>),
+    }
+}
+
+use error::ServerError;
+
+/// Port used by the `keva_server` binary unless another is given.
+pub const DEFAULT_PORT: u16 = 7690;
+
+pub struct ServerConfig {
+    /// Port on `127.0.0.1`; 0 picks a free port.
+    pub port: u16,
+    /// Used by `POST /maintenance`.
+    pub gc_config: GcConfig,
+}
+
+/// The state requests operate on.
+pub(crate) struct State {
+    pub(crate) keva: KevaCore,
+    pub(crate) search: SearchEngine,
+    pub(crate) gc_config: GcConfig,
+}
+
+pub struct Server {
+    http: Arc<tiny_http::Server>,
+    token: String,
+    state: State,
+}
+
+/// Stops a running [`Server`] from another thread.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    http: Arc<tiny_http::Server>,
+}
+
+impl ShutdownHandle {
+    /// Makes [`Server::run`] return once the request in progress is answered.
+    pub fn shutdown(&self) {
+        self.http.unblock();
+    }
+}
+
+impl Server {
+    /// Binds to `127.0.0.1`, loading the API token from the data directory or creating it.
+    pub fn bind(
+        keva: KevaCore,
+        search: SearchEngine,
+        config: ServerConfig,
+    ) -> Result<Self, ServerError> {
+        let token_path = Config {
+            base_path: keva.data_dir().to_path_buf(),
+        }
+        .server_token_path();
+        let token = load_or_create_token(&token_path)?;
+
+        let http = tiny_http::Server::http((Ipv4Addr::LOCALHOST, config.port))
+            .map_err(ServerError::Bind)?;
+
+        Ok(Self {
+            http: Arc::new(http),
+            token,
+            state: State {
+                keva,
+                search,
+                gc_config: config.gc_config,
+            },
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.http
+            .server_addr()
+            .to_ip()
+            .expect("server is bound to an IP address")
+    }
+
+    /// The token clients must send as `Authorization: Bearer <token>`.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            http: self.http.clone(),
+        }
+    }
+
+    /// Serves requests until [`ShutdownHandle::shutdown`] is called.
+    pub fn run(&mut self) {
+        for request in self.http.incoming_requests() {
+            routes::respond(&mut self.state, &self.token, request);
+        }
+    }
+
+    /// Returns the store and search engine, e.g. to save the search index after shutdown.
+    pub fn into_inner(self) -> (KevaCore, SearchEngine) {
+        (self.state.keva, self.state.search)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,563 @@
+//! Request routing and handlers.
+
+use crate::State;
+use crate::auth;
+use keva_core::core::error::KevaError;
+use keva_core::core::{FileReader, ImageFilter, QuotaError};
+use keva_core::error::DatabaseError;
+use keva_core::types::{AttachmentName, ImageMetadata, Key, LifecycleState, SealState, Value};
+use keva_search::{SearchQuery, SearchResults};
+use keva_worker::touch_key;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::time::{Duration, SystemTime};
+use tiny_http::{Header, Method, Request, Response};
+
+/// Largest JSON request body accepted; content and attachment uploads are streamed instead.
+const MAX_JSON_BODY: u64 = 64 * 1024;
+
+/// How long each search tick waits for matching to finish before checking again.
+const SEARCH_TICK_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// A successful response.
+enum Reply {
+    Json(u16, serde_json::Value),
+    /// A file streamed with its content type, and its length when known without reading it.
+    File(String, FileReader, Option<u64>),
+    NoContent,
+}
+
+/// An error response, sent as `{"error": message}`.
+#[derive(Debug)]
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn not_found() -> Self {
+        Self::new(404, "Not found")
+    }
+}
+
+impl From<KevaError> for ApiError {
+    fn from(e: KevaError) -> Self {
+        let status = match &e {
+            KevaError::InvalidKey(_) => 400,
+            KevaError::Locked => 423,
+            KevaError::DestinationExists => 409,
+            KevaError::Database(db) => match db {
+                DatabaseError::NotFound | DatabaseError::AttachmentNotFound(_) => 404,
+                DatabaseError::AlreadyExists
+                | DatabaseError::Trashed
+                | DatabaseError::NotTrashed => 409,
+                _ => 500,
+            },
+            KevaError::QuotaExceeded(QuotaError::AttachmentTooLarge { .. }) => 413,
+            KevaError::QuotaExceeded(QuotaError::StoreFull { .. }) => 507,
+            _ => 500,
+        };
+        Self::new(status, e.to_string())
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(e: std::io::Error) -> Self {
+        Self::new(500, e.to_string())
+    }
+}
+
+/// Answers a request; failures to send the response are ignored, as the client went away.
+pub(crate) fn respond(state: &mut State, token: &str, mut request: Request) {
+    let authorization = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .map(|h| h.value.as_str());
+
+    let result = if auth::is_authorized(authorization, token) {
+        let (method, url) = (request.method().clone(), request.url().to_string());
+        route(state, &method, &url, request.as_reader())
+    } else {
+        Err(ApiError::new(401, "Missing or invalid token"))
+    };
+
+    let response = match result {
+        Ok(Reply::Json(status, json)) => json_response(status, &json).boxed(),
+        Ok(Reply::File(content_type, reader, len)) => {
+            let headers = vec![content_type_header(&content_type)];
+            let len = len.and_then(|len| usize::try_from(len).ok());
+            Response::new(200.into(), headers, reader, len, None).boxed()
+        }
+        Ok(Reply::NoContent) => Response::from_data(Vec::new())
+            .with_status_code(204)
+            .boxed(),
+        Err(e) => json_response(e.status, &serde_json::json!({ "error": e.message })).boxed(),
+    };
+    let _ = request.respond(response);
+}
+
+fn json_response(status: u16, json: &serde_json::Value) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_data(json.to_string())
+        .with_status_code(status)
+        .with_header(content_type_header("application/json"))
+}
+
+fn content_type_header(value: &str) -> Header {
+    Header::from_bytes("Content-Type", value).expect("static header is valid")
+}
+
+fn route(
+    state: &mut State,
+    method: &Method,
+    url: &str,
+    body: &mut dyn Read,
+) -> Result<Reply, ApiError> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let segments = path
+        .trim_matches('/')
+        .split('/')
+        .map(percent_decode)
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| ApiError::new(400, "Malformed percent-encoding"))?;
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    match (method, segments.as_slice()) {
+        (Method::Get, ["keys"]) => list_keys(state),
+        (Method::Post, ["keys"]) => create(state, parse_json(body)?),
+        (Method::Get, ["keys", key]) => get_value(state, &parse_key(key)?),
+        (Method::Delete, ["keys", key]) => {
+            let purge = query_param(query, "purge").is_some_and(|v| v == "true");
+            delete(state, &parse_key(key)?, purge)
+        }
+        (Method::Post, ["keys", key, "restore"]) => restore(state, &parse_key(key)?),
+        (Method::Post, ["keys", key, "rename"]) => {
+            rename(state, &parse_key(key)?, parse_json(body)?)
+        }
+        (Method::Get, ["keys", key, "content"]) => get_content(state, &parse_key(key)?),
+        (Method::Put, ["keys", key, "content"]) => put_content(state, &parse_key(key)?, body),
+        (Method::Get, ["keys", key, "attachments", filename]) => {
+            get_attachment(state, &parse_key(key)?, filename)
+        }
+        (Method::Put, ["keys", key, "attachments", filename]) => {
+            put_attachment(state, &parse_key(key)?, filename, body)
+        }
+        (Method::Delete, ["keys", key, "attachments", filename]) => {
+            delete_attachment(state, &parse_key(key)?, filename)
+        }
+        (Method::Get, ["search"]) => search(state, &query_param(query, "q").unwrap_or_default()),
+        (Method::Get, ["search", "attachments"]) => {
+            search_attachments(state, &query_param(query, "q").unwrap_or_default())
+        }
+        (Method::Get, ["images"]) => find_images(state, parse_image_filter(query)?),
+        (Method::Post, ["maintenance"]) => maintenance(state),
+        _ => Err(ApiError::not_found()),
+    }
+}
+
+fn parse_key(s: &str) -> Result<Key, ApiError> {
+    Key::try_from(s).map_err(|e| ApiError::new(400, format!("Invalid key: {e}")))
+}
+
+fn parse_json<T: for<'de> Deserialize<'de>>(body: &mut dyn Read) -> Result<T, ApiError> {
+    let mut bytes = Vec::new();
+    body.take(MAX_JSON_BODY + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > MAX_JSON_BODY {
+        return Err(ApiError::new(413, "Body too large"));
+    }
+    serde_json::from_slice(&bytes).map_err(|e| ApiError::new(400, format!("Invalid body: {e}")))
+}
+
+/// Decodes `%XX` escapes, returning `None` for malformed escapes or non-UTF-8 results.
+pub(crate) fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Returns the decoded value of `name` in a `a=1&b=2` query string, treating `+` as a space.
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+        (k == name).then(|| percent_decode(&v.replace('+', " ")))?
+    })
+}
+
+/// Reads an [`ImageFilter`] from the `min_width`, `min_height`, `captured_from`,
+/// `captured_to` and `camera` query parameters.
+fn parse_image_filter(query: &str) -> Result<ImageFilter, ApiError> {
+    let number = |name: &str| {
+        query_param(query, name)
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| ApiError::new(400, format!("Invalid {name}: {v}")))
+            })
+            .transpose()
+    };
+    Ok(ImageFilter {
+        min_width: number("min_width")?,
+        min_height: number("min_height")?,
+        captured_from: query_param(query, "captured_from"),
+        captured_to: query_param(query, "captured_to"),
+        camera: query_param(query, "camera"),
+    })
+}
+
+#[derive(Deserialize)]
+struct CreateBody {
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct RenameBody {
+    to: String,
+}
+
+#[derive(Serialize)]
+struct KeyList {
+    active: Vec<String>,
+    trashed: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct AttachmentBody {
+    filename: String,
+    size: u64,
+    /// Only present for images.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<ImageBody>,
+    mime: Option<String>,
+    /// Hex-encoded blake3 hash of the contents.
+    hash: Option<String>,
+    /// Seconds since the Unix epoch.
+    added_at: Option<u64>,
+    modified_at: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct ImageBody {
+    width: u32,
+    height: u32,
+    captured_at: Option<String>,
+    camera: Option<String>,
+}
+
+impl From<ImageMetadata> for ImageBody {
+    fn from(image: ImageMetadata) -> Self {
+        Self {
+            width: image.width,
+            height: image.height,
+            captured_at: image.captured_at,
+            camera: image.camera,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AttachmentMatchBody {
+    key: String,
+    filename: String,
+    snippet: String,
+}
+
+#[derive(Serialize)]
+struct ImageMatchBody {
+    key: String,
+    filename: String,
+    image: ImageBody,
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[derive(Serialize)]
+struct ValueBody {
+    key: String,
+    trashed: bool,
+    /// Seconds since the Unix epoch: last access for Active keys, trash time otherwise.
+    updated_at: u64,
+    access_count: u64,
+    /// `"unsealed"`, `"locked"` or `"unlocked"`.
+    seal: &'static str,
+    attachments: Vec<AttachmentBody>,
+}
+
+impl ValueBody {
+    fn new(key: &Key, value: Value) -> Self {
+        let (trashed, at) = match value.metadata.lifecycle_state {
+            LifecycleState::Active { last_accessed } => (false, last_accessed),
+            LifecycleState::Trash { trashed_at } => (true, trashed_at),
+        };
+        let seal = match value.metadata.seal {
+            SealState::Unsealed => "unsealed",
+            SealState::Locked => "locked",
+            SealState::Unlocked => "unlocked",
+        };
+        Self {
+            key: key.as_str().to_string(),
+            trashed,
+            updated_at: unix_secs(at),
+            access_count: value.metadata.access_count,
+            seal,
+            attachments: value
+                .attachments
+                .into_iter()
+                .map(|a| AttachmentBody {
+                    filename: a.filename,
+                    size: a.size,
+                    image: a.image.map(ImageBody::from),
+                    mime: a.mime,
+                    hash: a.hash.map(|hash| hash.to_hex().to_string()),
+                    added_at: a.added_at.map(unix_secs),
+                    modified_at: a.modified_at.map(unix_secs),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct MatchBody {
+    key: String,
+    score: u32,
+    /// Char indices of matched characters, for highlighting.
+    indices: Vec<u32>,
+}
+
+#[derive(Serialize)]
+struct SearchBody {
+    active: Vec<MatchBody>,
+    trashed: Vec<MatchBody>,
+}
+
+#[derive(Serialize)]
+struct MaintenanceBody {
+    keys_trashed: Vec<String>,
+    keys_purged: Vec<String>,
+    orphaned_files_removed: usize,
+    text_extracted: usize,
+}
+
+fn json(status: u16, body: impl Serialize) -> Result<Reply, ApiError> {
+    let json = serde_json::to_value(body).expect("response bodies serialize to JSON");
+    Ok(Reply::Json(status, json))
+}
+
+fn value_reply(state: &State, key: &Key, status: u16) -> Result<Reply, ApiError> {
+    let value = state.keva.get(key)?.ok_or_else(ApiError::not_found)?;
+    json(status, ValueBody::new(key, value))
+}
+
+fn list_keys(state: &State) -> Result<Reply, ApiError> {
+    let names = |keys: Vec<Key>| keys.iter().map(|k| k.as_str().to_string()).collect();
+    json(
+        200,
+        KeyList {
+            active: names(state.keva.active_keys()?),
+            trashed: names(state.keva.trashed_keys()?),
+        },
+    )
+}
+
+fn create(state: &mut State, body: CreateBody) -> Result<Reply, ApiError> {
+    let key = parse_key(&body.key)?;
+    state.keva.create(&key, SystemTime::now())?;
+    state.search.add_active(key.clone());
+    value_reply(state, &key, 201)
+}
+
+fn get_value(state: &State, key: &Key) -> Result<Reply, ApiError> {
+    value_reply(state, key, 200)
+}
+
+fn delete(state: &mut State, key: &Key, purge: bool) -> Result<Reply, ApiError> {
+    if purge {
+        state.keva.purge(key)?;
+        state.search.remove(key);
+    } else {
+        state.keva.trash(key, SystemTime::now())?;
+        state.search.trash(key);
+    }
+    Ok(Reply::NoContent)
+}
+
+fn restore(state: &mut State, key: &Key) -> Result<Reply, ApiError> {
+    state.keva.restore(key, SystemTime::now())?;
+    state.search.restore(key);
+    value_reply(state, key, 200)
+}
+
+fn rename(state: &mut State, key: &Key, body: RenameBody) -> Result<Reply, ApiError> {
+    let to = parse_key(&body.to)?;
+    if state.keva.get(key)?.is_none() {
+        return Err(ApiError::not_found());
+    }
+    state.keva.rename(key, &to, SystemTime::now())?;
+    state.search.rename(key, to.clone());
+    value_reply(state, &to, 200)
+}
+
+/// Encrypted content is sent chunked, as its length is only known once decrypted.
+fn get_content(state: &State, key: &Key) -> Result<Reply, ApiError> {
+    let value = state.keva.get(key)?.ok_or_else(ApiError::not_found)?;
+    let reader = state.keva.open_content(key)?;
+    let len = if !state.keva.is_encrypted() && value.metadata.seal == SealState::Unsealed {
+        std::fs::metadata(state.keva.content_path(key))
+            .map(|m| m.len())
+            .ok()
+    } else {
+        None
+    };
+    Ok(Reply::File(
+        "text/markdown; charset=utf-8".to_string(),
+        reader,
+        len,
+    ))
+}
+
+/// Replaces the content and touches the key, as an editor save would.
+fn put_content(state: &mut State, key: &Key, body: &mut dyn Read) -> Result<Reply, ApiError> {
+    state.keva.get(key)?.ok_or_else(ApiError::not_found)?;
+    let mut writer = state.keva.write_content(key)?;
+    std::io::copy(body, &mut writer)?;
+    writer.finish()?;
+    touch_key(&mut state.keva, &mut state.search, key, SystemTime::now())?;
+    Ok(Reply::NoContent)
+}
+
+fn get_attachment(state: &State, key: &Key, filename: &str) -> Result<Reply, ApiError> {
+    let value = state.keva.get(key)?.ok_or_else(ApiError::not_found)?;
+    let attachment = value
+        .attachments
+        .into_iter()
+        .find(|a| a.filename == filename)
+        .ok_or_else(ApiError::not_found)?;
+    let reader = state.keva.open_attachment(key, filename)?;
+    // Attachments added before MIME types were recorded have none
+    let content_type = attachment
+        .mime
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    Ok(Reply::File(content_type, reader, Some(attachment.size)))
+}
+
+/// Streams the body into an attachment, overwriting one with the same name.
+fn put_attachment(
+    state: &mut State,
+    key: &Key,
+    filename: &str,
+    body: &mut dyn Read,
+) -> Result<Reply, ApiError> {
+    let name = AttachmentName::try_new_visible(filename)
+        .map_err(|e| ApiError::new(400, format!("Invalid filename '{filename}': {e}")))?;
+    state.keva.get(key)?.ok_or_else(ApiError::not_found)?;
+
+    state
+        .keva
+        .add_attachment_from_reader(key, &name, body, SystemTime::now())?;
+
+    value_reply(state, key, 201)
+}
+
+fn delete_attachment(state: &mut State, key: &Key, filename: &str) -> Result<Reply, ApiError> {
+    state
+        .keva
+        .remove_attachment(key, filename, SystemTime::now())?;
+    Ok(Reply::NoContent)
+}
+
+fn search(state: &mut State, query: &str) -> Result<Reply, ApiError> {
+    state
+        .search
+        .set_query(SearchQuery::Fuzzy(query.to_string()));
+    while !state.search.is_done() {
+        state.search.tick_timeout(SEARCH_TICK_TIMEOUT);
+    }
+
+    let matches = |results: SearchResults| {
+        results
+            .matches()
+            .map(|m| MatchBody {
+                key: m.key.as_str().to_string(),
+                score: m.score,
+                indices: m.indices,
+            })
+            .collect()
+    };
+    json(
+        200,
+        SearchBody {
+            active: matches(state.search.active_results()),
+            trashed: matches(state.search.trashed_results()),
+        },
+    )
+}
+
+fn search_attachments(state: &State, query: &str) -> Result<Reply, ApiError> {
+    let matches: Vec<AttachmentMatchBody> = state
+        .keva
+        .search_attachment_text(query)?
+        .into_iter()
+        .map(|m| AttachmentMatchBody {
+            key: m.key.as_str().to_string(),
+            filename: m.filename,
+            snippet: m.snippet,
+        })
+        .collect();
+    json(200, matches)
+}
+
+fn find_images(state: &State, filter: ImageFilter) -> Result<Reply, ApiError> {
+    let images: Vec<ImageMatchBody> = state
+        .keva
+        .find_images(&filter)?
+        .into_iter()
+        .map(|m| ImageMatchBody {
+            key: m.key.as_str().to_string(),
+            filename: m.filename,
+            image: m.image.into(),
+        })
+        .collect();
+    json(200, images)
+}
+
+fn maintenance(state: &mut State) -> Result<Reply, ApiError> {
+    let outcome = state.keva.maintenance(SystemTime::now(), state.gc_config)?;
+    for key in &outcome.keys_trashed {
+        state.search.trash(key);
+    }
+    for key in &outcome.keys_purged {
+        state.search.remove(key);
+    }
+    state.search.maintenance_compact();
+
+    let names = |keys: &[Key]| keys.iter().map(|k| k.as_str().to_string()).collect();
+    json(
+        200,
+        MaintenanceBody {
+            keys_trashed: names(&outcome.keys_trashed),
+            keys_purged: names(&outcome.keys_purged),
+            orphaned_files_removed: outcome.orphaned_files_removed,
+            text_extracted: outcome.text_extracted,
+        },
+    )
+}
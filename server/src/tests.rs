@@ -0,0 +1,443 @@
+use super::*;
+use common::start;
+use keva_search::SearchConfig;
+use tempfile::TempDir;
+
+mod common {
+    use super::*;
+    use std::thread::JoinHandle;
+
+    pub(super) struct TestServer {
+        pub(super) url: String,
+        pub(super) token: String,
+        pub(super) data_dir: TempDir,
+        shutdown: ShutdownHandle,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl TestServer {
+        pub(super) fn request(&self, method: &str, path: &str) -> ureq::Request {
+            ureq::request(method, &format!("{}{path}", self.url))
+                .set("Authorization", &format!("Bearer {}", self.token))
+        }
+
+        /// Returns the status code whether or not the request succeeded.
+        pub(super) fn status(&self, result: Result<ureq::Response, ureq::Error>) -> u16 {
+            match result {
+                Ok(response) => response.status(),
+                Err(ureq::Error::Status(code, _)) => code,
+                Err(e) => panic!("transport error: {e}"),
+            }
+        }
+
+        pub(super) fn json(&self, method: &str, path: &str) -> serde_json::Value {
+            self.request(method, path)
+                .call()
+                .unwrap()
+                .into_json()
+                .unwrap()
+        }
+    }
+
+    impl Drop for TestServer {
+        fn drop(&mut self) {
+            self.shutdown.shutdown();
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    pub(super) fn start_in(data_dir: TempDir) -> TestServer {
+        let keva = KevaCore::open(Config {
+            base_path: data_dir.path().to_path_buf(),
+        })
+        .unwrap();
+        let search = SearchEngine::new(
+            keva.active_keys().unwrap(),
+            keva.trashed_keys().unwrap(),
+            SearchConfig::default(),
+            Arc::new(|| {}),
+        );
+        let config = ServerConfig {
+            port: 0,
+            gc_config: GcConfig::from(&keva_core::types::LifecycleConfig::default()),
+        };
+
+        let mut server = Server::bind(keva, search, config).unwrap();
+        let url = format!("http://{}", server.local_addr());
+        let token = server.token().to_string();
+        let shutdown = server.shutdown_handle();
+        let thread = std::thread::spawn(move || server.run());
+
+        TestServer {
+            url,
+            token,
+            data_dir,
+            shutdown,
+            thread: Some(thread),
+        }
+    }
+
+    pub(super) fn start() -> TestServer {
+        start_in(TempDir::new().unwrap())
+    }
+}
+
+mod auth {
+    use super::*;
+
+    #[test]
+    fn test_binds_to_localhost() {
+        let server = start();
+        assert!(server.url.starts_with("http://127.0.0.1:"));
+    }
+
+    #[test]
+    fn test_missing_or_wrong_token_is_rejected() {
+        let server = start();
+        let url = format!("{}/keys", server.url);
+
+        assert_eq!(server.status(ureq::get(&url).call()), 401);
+        let wrong = ureq::get(&url).set("Authorization", "Bearer nope").call();
+        assert_eq!(server.status(wrong), 401);
+        assert_eq!(server.status(server.request("GET", "/keys").call()), 200);
+    }
+
+    #[test]
+    fn test_token_is_stored_and_reused() {
+        let server = start();
+        let token_path = Config {
+            base_path: server.data_dir.path().to_path_buf(),
+        }
+        .server_token_path();
+        assert_eq!(std::fs::read_to_string(&token_path).unwrap(), server.token);
+
+        let data_dir = TempDir::new().unwrap();
+        std::fs::copy(&token_path, data_dir.path().join("server.token")).unwrap();
+        let restarted = common::start_in(data_dir);
+        assert_eq!(restarted.token, server.token);
+    }
+}
+
+mod keys {
+    use super::*;
+
+    #[test]
+    fn test_create_get_and_list() {
+        let server = start();
+
+        let response = server
+            .request("POST", "/keys")
+            .send_json(serde_json::json!({ "key": "project/notes" }))
+            .unwrap();
+        assert_eq!(response.status(), 201);
+
+        let value = server.json("GET", "/keys/project%2Fnotes");
+        assert_eq!(value["key"], "project/notes");
+        assert_eq!(value["trashed"], false);
+        assert_eq!(value["attachments"], serde_json::json!([]));
+
+        let list = server.json("GET", "/keys");
+        assert_eq!(list["active"], serde_json::json!(["project/notes"]));
+    }
+
+    #[test]
+    fn test_error_statuses() {
+        let server = start();
+        let create = |key: &str| {
+            let result = server
+                .request("POST", "/keys")
+                .send_json(serde_json::json!({ "key": key }));
+            server.status(result)
+        };
+
+        assert_eq!(create("a"), 201);
+        assert_eq!(create("a"), 409);
+        assert_eq!(create(""), 400);
+        assert_eq!(
+            server.status(server.request("GET", "/keys/missing").call()),
+            404
+        );
+        assert_eq!(server.status(server.request("GET", "/nowhere").call()), 404);
+
+        let oversized = server
+            .request("POST", "/keys")
+            .send_json(serde_json::json!({ "key": "a".repeat(100 * 1024) }));
+        assert_eq!(server.status(oversized), 413);
+    }
+
+    #[test]
+    fn test_rename_trash_restore_purge() {
+        let server = start();
+        server
+            .request("POST", "/keys")
+            .send_json(serde_json::json!({ "key": "old" }))
+            .unwrap();
+
+        let renamed = server
+            .request("POST", "/keys/old/rename")
+            .send_json(serde_json::json!({ "to": "new" }))
+            .unwrap();
+        assert_eq!(
+            renamed.into_json::<serde_json::Value>().unwrap()["key"],
+            "new"
+        );
+
+        assert_eq!(
+            server.status(server.request("DELETE", "/keys/new").call()),
+            204
+        );
+        assert_eq!(server.json("GET", "/keys/new")["trashed"], true);
+
+        server.request("POST", "/keys/new/restore").call().unwrap();
+        assert_eq!(server.json("GET", "/keys/new")["trashed"], false);
+
+        server
+            .request("DELETE", "/keys/new?purge=true")
+            .call()
+            .unwrap();
+        let list = server.json("GET", "/keys");
+        assert_eq!(list["active"], serde_json::json!([]));
+        assert_eq!(list["trashed"], serde_json::json!([]));
+    }
+}
+
+mod files {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_content_roundtrip() {
+        let server = start();
+        server
+            .request("POST", "/keys")
+            .send_json(serde_json::json!({ "key": "note" }))
+            .unwrap();
+
+        let put = server
+            .request("PUT", "/keys/note/content")
+            .send_string("# Title\nbody");
+        assert_eq!(server.status(put), 204);
+
+        let response = server.request("GET", "/keys/note/content").call().unwrap();
+        assert_eq!(response.content_type(), "text/markdown");
+        assert_eq!(response.header("Content-Length"), Some("12"));
+        assert_eq!(response.into_string().unwrap(), "# Title\nbody");
+        assert_eq!(server.json("GET", "/keys/note")["access_count"], 1);
+    }
+
+    #[test]
+    fn test_attachment_upload_download_delete() {
+        let server = start();
+        server
+            .request("POST", "/keys")
+            .send_json(serde_json::json!({ "key": "note" }))
+            .unwrap();
+
+        let uploaded: serde_json::Value = server
+            .request("PUT", "/keys/note/attachments/my%20file.bin")
+            .send_bytes(&[0, 1, 2, 255])
+            .unwrap()
+            .into_json()
+            .unwrap();
+        let attachment = &uploaded["attachments"][0];
+        assert_eq!(attachment["filename"], "my file.bin");
+        assert_eq!(attachment["size"], 4);
+        assert_eq!(attachment["mime"], "application/octet-stream");
+        assert_eq!(attachment["hash"].as_str().unwrap().len(), 64);
+        assert_eq!(attachment["added_at"], attachment["modified_at"]);
+        assert_eq!(uploaded["attachments"].as_array().unwrap().len(), 1);
+
+        let response = server
+            .request("GET", "/keys/note/attachments/my%20file.bin")
+            .call()
+            .unwrap();
+        assert_eq!(response.header("Content-Length"), Some("4"));
+        let mut bytes = Vec::new();
+        response.into_reader().read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, [0, 1, 2, 255]);
+
+        server
+            .request("DELETE", "/keys/note/attachments/my%20file.bin")
+            .call()
+            .unwrap();
+        let download = server
+            .request("GET", "/keys/note/attachments/my%20file.bin")
+            .call();
+        assert_eq!(server.status(download), 404);
+    }
+
+    #[test]
+    fn test_attachment_content_type_is_sniffed() {
+        let server = start();
+        server
+            .request("POST", "/keys")
+            .send_json(serde_json::json!({ "key": "note" }))
+            .unwrap();
+        server
+            .request("PUT", "/keys/note/attachments/scan.bin")
+            .send_bytes(b"%PDF-1.7\n")
+            .unwrap();
+
+        let response = server
+            .request("GET", "/keys/note/attachments/scan.bin")
+            .call()
+            .unwrap();
+        assert_eq!(response.content_type(), "application/pdf");
+    }
+
+    #[test]
+    fn test_attachment_filename_cannot_escape() {
+        let server = start();
+        server
+            .request("POST", "/keys")
+            .send_json(serde_json::json!({ "key": "note" }))
+            .unwrap();
+
+        let upload = server
+            .request("PUT", "/keys/note/attachments/..%2Fescape")
+            .send_bytes(b"x");
+        assert_eq!(server.status(upload), 400);
+
+        for name in ["CON.txt", "trailing.", "a%3Ab.txt", ".env", "._a.txt"] {
+            let upload = server
+                .request("PUT", &format!("/keys/note/attachments/{name}"))
+                .send_bytes(b"x");
+            assert_eq!(server.status(upload), 400, "{name}");
+        }
+    }
+}
+
+mod search {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_search_tracks_mutations() {
+        let server = start();
+        for key in ["project/alpha", "project/beta", "other"] {
+            server
+                .request("POST", "/keys")
+                .send_json(serde_json::json!({ "key": key }))
+                .unwrap();
+        }
+        server
+            .request("DELETE", "/keys/project%2Fbeta")
+            .call()
+            .unwrap();
+
+        let results = server.json("GET", "/search?q=proj+alp");
+        let active: Vec<_> = results["active"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["key"].as_str().unwrap())
+            .collect();
+        assert_eq!(active, vec!["project/alpha"]);
+        assert!(
+            !results["active"][0]["indices"]
+                .as_array()
+                .unwrap()
+                .is_empty()
+        );
+
+        let results = server.json("GET", "/search?q=beta");
+        assert_eq!(results["active"], serde_json::json!([]));
+        assert_eq!(results["trashed"][0]["key"], "project/beta");
+    }
+}
+
+mod attachment_text {
+    use super::*;
+
+    #[test]
+    fn test_search_attachments_by_text() {
+        let server = start();
+        server
+            .request("POST", "/keys")
+            .send_json(serde_json::json!({ "key": "notes" }))
+            .unwrap();
+        server
+            .request("PUT", "/keys/notes/attachments/minutes.txt")
+            .send_bytes(b"intro\nMeeting about the Budget\n")
+            .unwrap();
+
+        assert_eq!(
+            server.json("GET", "/search/attachments?q=budget"),
+            serde_json::json!([{
+                "key": "notes",
+                "filename": "minutes.txt",
+                "snippet": "Meeting about the Budget",
+            }])
+        );
+        assert_eq!(
+            server.json("GET", "/search/attachments?q=nowhere"),
+            serde_json::json!([])
+        );
+    }
+}
+
+mod images {
+    use super::*;
+
+    #[test]
+    fn test_find_images_by_size() {
+        let server = start();
+        server
+            .request("POST", "/keys")
+            .send_json(serde_json::json!({ "key": "photos" }))
+            .unwrap();
+        for (name, size) in [("wide.png", 64), ("icon.png", 8)] {
+            let mut png = std::io::Cursor::new(Vec::new());
+            image::RgbImage::new(size, size)
+                .write_to(&mut png, image::ImageFormat::Png)
+                .unwrap();
+            server
+                .request("PUT", &format!("/keys/photos/attachments/{name}"))
+                .send_bytes(png.get_ref())
+                .unwrap();
+        }
+
+        let images = server.json("GET", "/images?min_width=32");
+        assert_eq!(
+            images,
+            serde_json::json!([{
+                "key": "photos",
+                "filename": "wide.png",
+                "image": { "width": 64, "height": 64, "captured_at": null, "camera": null },
+            }])
+        );
+        assert_eq!(server.json("GET", "/images").as_array().unwrap().len(), 2);
+        let invalid = server.request("GET", "/images?min_width=wide").call();
+        assert_eq!(server.status(invalid), 400);
+    }
+}
+
+mod maintenance {
+    use super::*;
+
+    #[test]
+    fn test_maintenance_reports_outcome() {
+        let server = start();
+        let outcome = server.json("POST", "/maintenance");
+        assert_eq!(outcome["keys_trashed"], serde_json::json!([]));
+        assert_eq!(outcome["keys_purged"], serde_json::json!([]));
+    }
+}
+
+mod percent_decode {
+    use crate::routes::percent_decode;
+
+    #[test]
+    fn test_decodes_escapes() {
+        assert_eq!(percent_decode("a%2Fb%20c").as_deref(), Some("a/b c"));
+        assert_eq!(percent_decode("%E2%9C%93").as_deref(), Some("✓"));
+    }
+
+    #[test]
+    fn test_rejects_malformed_escapes() {
+        assert_eq!(percent_decode("%2"), None);
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%FF"), None);
+    }
+}
@@ -0,0 +1,511 @@
+//! Application state and key handling.
+
+use crate::editor::Editor;
+use keva_core::core::KevaCore;
+use keva_core::types::{Attachment, Config, GcConfig, Key, LifecycleState};
+use keva_search::{SearchEngine, SearchQuery};
+use keva_worker::{MAINTENANCE_INTERVAL, save_search_index, touch_key};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// The pane receiving key input. Only one is active at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Search,
+    Keys,
+    Trash,
+    Editor,
+    Attachments,
+}
+
+/// What the event loop should do after a key press.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Continue,
+    Quit,
+    /// Suspend the UI, run `$EDITOR` on the file, then call [`App::after_external_edit`].
+    ExternalEditor(PathBuf),
+}
+
+pub struct App {
+    pub(crate) keva: KevaCore,
+    pub(crate) search: SearchEngine,
+    pub(crate) query: String,
+    pub(crate) focus: Focus,
+    /// Displayed search results.
+    pub(crate) active: Vec<Key>,
+    pub(crate) trashed: Vec<Key>,
+    /// Key selected in the key list or trash section; persists while other panes are active.
+    pub(crate) selected: Option<Key>,
+    /// Content of the target key.
+    pub(crate) editor: Option<Editor>,
+    pub(crate) attachments: Vec<Attachment>,
+    pub(crate) attachment_index: usize,
+    /// Last error or notice, shown in the status line until the next key press.
+    pub(crate) status: Option<String>,
+}
+
+impl App {
+    /// Shows all keys. Runs maintenance first if it has not run for a day.
+    pub fn new(mut keva: KevaCore, mut search: SearchEngine, gc_config: GcConfig) -> Self {
+        let now = SystemTime::now();
+        if keva.should_run_maintenance(now, MAINTENANCE_INTERVAL)
+            && let Ok(outcome) = keva.maintenance(now, gc_config)
+        {
+            for key in &outcome.keys_trashed {
+                search.trash(key);
+            }
+            for key in &outcome.keys_purged {
+                search.remove(key);
+            }
+        }
+
+        let mut app = Self {
+            keva,
+            search,
+            query: String::new(),
+            focus: Focus::Search,
+            active: Vec::new(),
+            trashed: Vec::new(),
+            selected: None,
+            editor: None,
+            attachments: Vec::new(),
+            attachment_index: 0,
+            status: None,
+        };
+        app.requery();
+        app
+    }
+
+    /// Advances the search; call once per event loop iteration.
+    pub fn tick(&mut self) {
+        if self.search.tick() {
+            self.refresh_results();
+        }
+    }
+
+    /// Saves pending edits and the search index.
+    pub fn close(mut self) {
+        self.save_editor();
+        let index_path = Config {
+            base_path: self.keva.data_dir().to_path_buf(),
+        }
+        .search_index_path();
+        save_search_index(&self.keva, &self.search, &index_path);
+    }
+
+    /// Key shown in the right panes: the selection, else the exact match of the query.
+    pub(crate) fn target_key(&self) -> Option<Key> {
+        self.selected.clone().or_else(|| {
+            let key = Key::try_from(self.query.as_str()).ok()?;
+            self.search.has_key(&key).then_some(key)
+        })
+    }
+
+    pub fn handle_key(&mut self, event: KeyEvent) -> Command {
+        self.status = None;
+        let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+
+        match event.code {
+            KeyCode::Char('q') if ctrl => return Command::Quit,
+            KeyCode::Char('s') if ctrl => {
+                self.set_focus(Focus::Search);
+                return Command::Continue;
+            }
+            KeyCode::Tab => {
+                self.cycle_focus(true);
+                return Command::Continue;
+            }
+            KeyCode::BackTab => {
+                self.cycle_focus(false);
+                return Command::Continue;
+            }
+            _ => {}
+        }
+
+        match self.focus {
+            Focus::Search => self.handle_search_key(event),
+            Focus::Keys | Focus::Trash => self.handle_list_key(event),
+            Focus::Editor => return self.handle_editor_key(event),
+            Focus::Attachments => self.handle_attachments_key(event),
+        }
+        Command::Continue
+    }
+
+    /// Reloads the target key after `$EDITOR` wrote its content file.
+    pub fn after_external_edit(&mut self) {
+        if let Some(key) = self.editor.take().map(|editor| editor.key) {
+            let _ = touch_key(&mut self.keva, &mut self.search, &key, SystemTime::now());
+            self.load_target();
+        }
+    }
+}
+
+/// Focus operations.
+impl App {
+    fn set_focus(&mut self, focus: Focus) {
+        if self.focus == Focus::Editor && focus != Focus::Editor {
+            self.save_editor();
+        }
+        match focus {
+            Focus::Keys if !self.is_selected_in(Focus::Keys) => {
+                self.select(self.active.first().cloned());
+            }
+            Focus::Trash if !self.is_selected_in(Focus::Trash) => {
+                self.select(self.trashed.first().cloned());
+            }
+            _ => {}
+        }
+        self.focus = focus;
+    }
+
+    /// Moves to the next pane, skipping empty lists and panes without a target key.
+    fn cycle_focus(&mut self, forward: bool) {
+        const ORDER: [Focus; 5] = [
+            Focus::Search,
+            Focus::Keys,
+            Focus::Trash,
+            Focus::Editor,
+            Focus::Attachments,
+        ];
+        let mut index = ORDER.iter().position(|f| *f == self.focus).unwrap_or(0);
+        loop {
+            index = if forward {
+                (index + 1) % ORDER.len()
+            } else {
+                (index + ORDER.len() - 1) % ORDER.len()
+            };
+            let available = match ORDER[index] {
+                Focus::Search => true,
+                Focus::Keys => !self.active.is_empty(),
+                Focus::Trash => !self.trashed.is_empty(),
+                Focus::Editor | Focus::Attachments => self.editor.is_some(),
+            };
+            if available {
+                self.set_focus(ORDER[index]);
+                return;
+            }
+        }
+    }
+
+    fn is_selected_in(&self, focus: Focus) -> bool {
+        let list = if focus == Focus::Trash {
+            &self.trashed
+        } else {
+            &self.active
+        };
+        self.selected.as_ref().is_some_and(|key| list.contains(key))
+    }
+
+    fn select(&mut self, key: Option<Key>) {
+        self.selected = key;
+        self.load_target();
+    }
+}
+
+/// Search bar.
+impl App {
+    fn handle_search_key(&mut self, event: KeyEvent) {
+        match event.code {
+            KeyCode::Char(c) if !event.modifiers.contains(KeyModifiers::CONTROL) => {
+                let mut query = std::mem::take(&mut self.query);
+                query.push(c);
+                self.set_query(query);
+            }
+            KeyCode::Backspace => {
+                let mut query = std::mem::take(&mut self.query);
+                query.pop();
+                self.set_query(query);
+            }
+            KeyCode::Down if !self.active.is_empty() => self.set_focus(Focus::Keys),
+            KeyCode::Enter => self.select_or_create(),
+            _ => {}
+        }
+    }
+
+    /// Typing clears the selection so the right panes follow the query.
+    fn set_query(&mut self, query: String) {
+        self.query = query;
+        self.selected = None;
+        self.requery();
+    }
+
+    fn requery(&mut self) {
+        self.search
+            .set_query(SearchQuery::Fuzzy(self.query.clone()));
+        self.search.tick();
+        self.refresh_results();
+    }
+
+    fn refresh_results(&mut self) {
+        self.active = self.search.active_results().iter().cloned().collect();
+        self.trashed = self.search.trashed_results().iter().cloned().collect();
+        if self
+            .selected
+            .as_ref()
+            .is_some_and(|key| !self.search.has_key(key))
+        {
+            self.selected = None;
+        }
+        self.load_target();
+    }
+
+    /// Enter in the search bar: select the exact match or create the key, then edit it.
+    fn select_or_create(&mut self) {
+        let key = match Key::try_from(self.query.as_str()) {
+            Ok(key) => key,
+            Err(e) => {
+                self.status = Some(format!("Invalid key: {e}"));
+                return;
+            }
+        };
+
+        if !self.search.has_key(&key) {
+            if let Err(e) = self.keva.create(&key, SystemTime::now()) {
+                self.status = Some(format!("Failed to create '{}': {e}", key.as_str()));
+                return;
+            }
+            self.search.add_active(key.clone());
+            self.requery();
+        }
+        self.select(Some(key));
+        self.set_focus(Focus::Editor);
+    }
+}
+
+/// Key list and trash section.
+impl App {
+    fn handle_list_key(&mut self, event: KeyEvent) {
+        let in_trash = self.focus == Focus::Trash;
+        let list = if in_trash {
+            &self.trashed
+        } else {
+            &self.active
+        };
+        let index = self
+            .selected
+            .as_ref()
+            .and_then(|key| list.iter().position(|k| k == key));
+
+        match event.code {
+            KeyCode::Up => match index {
+                Some(0) | None if !in_trash => self.set_focus(Focus::Search),
+                Some(i) if i > 0 => self.select(list.get(i - 1).cloned()),
+                _ => {}
+            },
+            KeyCode::Down => {
+                let next = index.map_or(0, |i| i + 1);
+                if next < list.len() {
+                    self.select(list.get(next).cloned());
+                }
+            }
+            KeyCode::Enter if self.editor.is_some() => self.set_focus(Focus::Editor),
+            KeyCode::Delete => {
+                if let Some(key) = self.selected.clone() {
+                    self.delete_key(&key, in_trash);
+                }
+            }
+            KeyCode::Char('r') if in_trash => {
+                if let Some(key) = self.selected.clone() {
+                    self.restore_key(&key);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Trashes an Active key, or permanently removes a trashed one.
+    fn delete_key(&mut self, key: &Key, trashed: bool) {
+        self.save_editor();
+        let result = if trashed {
+            self.keva.purge(key).map(|()| self.search.remove(key))
+        } else {
+            let now = SystemTime::now();
+            self.keva.trash(key, now).map(|()| self.search.trash(key))
+        };
+        if let Err(e) = result {
+            self.status = Some(format!("Failed to delete '{}': {e}", key.as_str()));
+            return;
+        }
+        self.selected = None;
+        self.requery();
+        if self.focus == Focus::Trash && self.trashed.is_empty() {
+            self.set_focus(Focus::Search);
+        } else if self.focus != Focus::Search {
+            self.set_focus(self.focus);
+        }
+    }
+
+    fn restore_key(&mut self, key: &Key) {
+        if let Err(e) = self.keva.restore(key, SystemTime::now()) {
+            self.status = Some(format!("Failed to restore '{}': {e}", key.as_str()));
+            return;
+        }
+        self.search.restore(key);
+        self.requery();
+        self.select(Some(key.clone()));
+        self.set_focus(Focus::Keys);
+    }
+}
+
+/// Editor pane.
+impl App {
+    fn handle_editor_key(&mut self, event: KeyEvent) -> Command {
+        let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+        if event.code == KeyCode::Esc {
+            self.set_focus(if self.selected.is_some() {
+                Focus::Keys
+            } else {
+                Focus::Search
+            });
+            return Command::Continue;
+        }
+        if event.code == KeyCode::Char('e') && ctrl {
+            return self.external_editor();
+        }
+
+        let Some(editor) = self.editor.as_mut() else {
+            return Command::Continue;
+        };
+        match event.code {
+            KeyCode::Char(c) if !ctrl => editor.insert_char(c),
+            KeyCode::Enter => editor.insert_newline(),
+            KeyCode::Backspace => editor.backspace(),
+            KeyCode::Left => editor.move_left(),
+            KeyCode::Right => editor.move_right(),
+            KeyCode::Up => editor.move_up(),
+            KeyCode::Down => editor.move_down(),
+            KeyCode::Home => editor.home(),
+            KeyCode::End => editor.end(),
+            _ => {}
+        }
+        Command::Continue
+    }
+
+    /// Hands the content file to `$EDITOR`. Not available for encrypted stores, whose files
+    /// hold ciphertext.
+    fn external_editor(&mut self) -> Command {
+        let Some(editor) = &self.editor else {
+            return Command::Continue;
+        };
+        if editor.read_only {
+            self.status = Some("Trashed keys are read-only".to_string());
+            return Command::Continue;
+        }
+        if self.keva.is_encrypted() {
+            self.status = Some("External editor is unavailable for encrypted stores".to_string());
+            return Command::Continue;
+        }
+        let key = editor.key.clone();
+        self.save_editor();
+        Command::ExternalEditor(self.keva.content_path(&key))
+    }
+
+    /// Loads content and attachments of the target key, saving the previous one first.
+    pub(crate) fn load_target(&mut self) {
+        let target = self.target_key();
+        if self.editor.as_ref().map(|editor| &editor.key) == target.as_ref() {
+            return;
+        }
+        self.save_editor();
+        self.editor = None;
+        self.attachments.clear();
+        self.attachment_index = 0;
+
+        let Some(key) = target else {
+            return;
+        };
+        let Ok(Some(value)) = self.keva.get(&key) else {
+            return;
+        };
+        let read_only = matches!(value.metadata.lifecycle_state, LifecycleState::Trash { .. });
+
+        let mut text = String::new();
+        let loaded = self
+            .keva
+            .open_content(&key)
+            .map_err(|e| e.to_string())
+            .and_then(|mut reader| reader.read_to_string(&mut text).map_err(|e| e.to_string()));
+        if let Err(e) = loaded {
+            self.status = Some(format!("Failed to open '{}': {e}", key.as_str()));
+            return;
+        }
+
+        if !read_only {
+            let _ = touch_key(&mut self.keva, &mut self.search, &key, SystemTime::now());
+        }
+        self.editor = Some(Editor::new(key, &text, read_only));
+        self.attachments = value.attachments;
+    }
+
+    /// Writes the editor content back if it changed.
+    fn save_editor(&mut self) {
+        let Some(editor) = self.editor.as_mut().filter(|editor| editor.dirty) else {
+            return;
+        };
+        let saved = (|| -> Result<(), Box<dyn std::error::Error>> {
+            let mut writer = self.keva.write_content(&editor.key)?;
+            writer.write_all(editor.text().as_bytes())?;
+            writer.finish()?;
+            touch_key(
+                &mut self.keva,
+                &mut self.search,
+                &editor.key,
+                SystemTime::now(),
+            )?;
+            Ok(())
+        })();
+        match saved {
+            Ok(()) => editor.dirty = false,
+            Err(e) => self.status = Some(format!("Save failed: {e}")),
+        }
+    }
+}
+
+/// Attachments pane.
+impl App {
+    fn handle_attachments_key(&mut self, event: KeyEvent) {
+        match event.code {
+            KeyCode::Up => self.attachment_index = self.attachment_index.saturating_sub(1),
+            KeyCode::Down if self.attachment_index + 1 < self.attachments.len() => {
+                self.attachment_index += 1;
+            }
+            KeyCode::Delete => self.remove_attachment(),
+            _ => {}
+        }
+    }
+
+    fn remove_attachment(&mut self) {
+        let Some(editor) = &self.editor else {
+            return;
+        };
+        if editor.read_only {
+            self.status = Some("Trashed keys are read-only".to_string());
+            return;
+        }
+        let Some(filename) = self
+            .attachments
+            .get(self.attachment_index)
+            .map(|a| a.filename.clone())
+        else {
+            return;
+        };
+
+        let key = editor.key.clone();
+        if let Err(e) = self
+            .keva
+            .remove_attachment(&key, &filename, SystemTime::now())
+        {
+            self.status = Some(format!("Failed to remove '{filename}': {e}"));
+            return;
+        }
+        if let Ok(Some(value)) = self.keva.get(&key) {
+            self.attachments = value.attachments;
+        }
+        self.attachment_index = self
+            .attachment_index
+            .min(self.attachments.len().saturating_sub(1));
+    }
+}
@@ -0,0 +1,143 @@
+//! Line-based text buffer for the right top pane.
+
+use keva_core::types::Key;
+
+pub(crate) struct Editor {
+    pub(crate) key: Key,
+    pub(crate) read_only: bool,
+    pub(crate) dirty: bool,
+    lines: Vec<String>,
+    /// Cursor line.
+    row: usize,
+    /// Cursor position within the line, in chars.
+    col: usize,
+}
+
+impl Editor {
+    pub(crate) fn new(key: Key, text: &str, read_only: bool) -> Self {
+        let mut lines: Vec<String> = text.split('\n').map(str::to_string).collect();
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        Self {
+            key,
+            read_only,
+            dirty: false,
+            lines,
+            row: 0,
+            col: 0,
+        }
+    }
+
+    pub(crate) fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub(crate) fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Cursor as (line, char column).
+    pub(crate) fn cursor(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    fn byte_index(&self) -> usize {
+        let line = &self.lines[self.row];
+        line.char_indices()
+            .nth(self.col)
+            .map_or(line.len(), |(i, _)| i)
+    }
+
+    fn line_len(&self, row: usize) -> usize {
+        self.lines[row].chars().count()
+    }
+}
+
+/// Editing operations. All are no-ops on a read-only buffer.
+impl Editor {
+    pub(crate) fn insert_char(&mut self, c: char) {
+        if self.read_only {
+            return;
+        }
+        let at = self.byte_index();
+        self.lines[self.row].insert(at, c);
+        self.col += 1;
+        self.dirty = true;
+    }
+
+    pub(crate) fn insert_newline(&mut self) {
+        if self.read_only {
+            return;
+        }
+        let at = self.byte_index();
+        let rest = self.lines[self.row].split_off(at);
+        self.row += 1;
+        self.col = 0;
+        self.lines.insert(self.row, rest);
+        self.dirty = true;
+    }
+
+    /// Deletes the char before the cursor, joining lines at the start of a line.
+    pub(crate) fn backspace(&mut self) {
+        if self.read_only {
+            return;
+        }
+        if self.col > 0 {
+            self.col -= 1;
+            let at = self.byte_index();
+            self.lines[self.row].remove(at);
+        } else if self.row > 0 {
+            let line = self.lines.remove(self.row);
+            self.row -= 1;
+            self.col = self.line_len(self.row);
+            self.lines[self.row].push_str(&line);
+        } else {
+            return;
+        }
+        self.dirty = true;
+    }
+}
+
+/// Cursor movement.
+impl Editor {
+    pub(crate) fn move_left(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = self.line_len(self.row);
+        }
+    }
+
+    pub(crate) fn move_right(&mut self) {
+        if self.col < self.line_len(self.row) {
+            self.col += 1;
+        } else if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = 0;
+        }
+    }
+
+    pub(crate) fn move_up(&mut self) {
+        if self.row > 0 {
+            self.row -= 1;
+            self.col = self.col.min(self.line_len(self.row));
+        }
+    }
+
+    pub(crate) fn move_down(&mut self) {
+        if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = self.col.min(self.line_len(self.row));
+        }
+    }
+
+    pub(crate) fn home(&mut self) {
+        self.col = 0;
+    }
+
+    pub(crate) fn end(&mut self) {
+        self.col = self.line_len(self.row);
+    }
+}
@@ -0,0 +1,32 @@
+//! Terminal frontend for Keva, for use over SSH and other places the WebView app can't run.
+//!
+//! Mirrors the GUI's four panes (search bar, key list with trash section, markdown editor,
+//! attachments) on top of `KevaCore` and `SearchEngine`. [`App`] holds the state and handles
+//! key presses; [`draw`] renders it to any ratatui backend, so rendering is tested against
+//! `TestBackend`.
+//!
+//! # Keys
+//!
+//! | Pane | Key | Action |
+//! |---|---|---|
+//! | Any | `Tab` / `Shift+Tab` | Next / previous pane |
+//! | Any | `Ctrl+S` | Focus search bar |
+//! | Any | `Ctrl+Q` | Quit |
+//! | Search | `Enter` | Open exact match, or create the key |
+//! | Search | `Down` | Key list |
+//! | Keys | `Enter` | Edit selected key |
+//! | Keys | `Delete` | Trash selected key |
+//! | Trash | `r` / `Delete` | Restore / delete permanently |
+//! | Editor | `Esc` | Back to key list, saving changes |
+//! | Editor | `Ctrl+E` | Edit in `$EDITOR` |
+//! | Attachments | `Delete` | Remove selected attachment |
+
+mod app;
+mod editor;
+mod ui;
+
+pub use app::{App, Command, Focus};
+pub use ui::draw;
+
+#[cfg(test)]
+mod tests;
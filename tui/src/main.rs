@@ -0,0 +1,112 @@
+//! Runs the terminal UI: `keva_tui [data-dir]`.
+//!
+//! Without a data directory, opens the platform default (see `Config::default_data_dir`).
+
+use keva_core::core::KevaCore;
+use keva_core::types::{AppConfig, Config, GcConfig};
+use keva_tui::{App, Command};
+use keva_worker::load_search_engine;
+use ratatui::crossterm::event::{self, Event, KeyEventKind};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long to wait for input before advancing the search again.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+fn main() -> ExitCode {
+    let Some(base_path) = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .or_else(Config::default_data_dir)
+    else {
+        eprintln!("No data directory given and none could be resolved.");
+        eprintln!("Usage: keva_tui [data-dir]");
+        return ExitCode::FAILURE;
+    };
+
+    let config = Config { base_path };
+    let (app_config, problems) = AppConfig::load_or_default(&AppConfig::path(&config.base_path));
+    for problem in problems {
+        eprintln!("Config: {problem}; using the default");
+    }
+
+    if let Err(e) = std::fs::create_dir_all(&config.base_path) {
+        eprintln!("Failed to create {}: {e}", config.base_path.display());
+        return ExitCode::FAILURE;
+    }
+    let mut keva = match KevaCore::open(config.clone()) {
+        Ok(keva) => keva,
+        Err(e) => {
+            eprintln!("Failed to open {}: {e}", config.base_path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    keva.set_quota_config(app_config.quotas);
+    let search = load_search_engine(&keva, &config.search_index_path(), Arc::new(|| {}));
+    let app = App::new(keva, search, GcConfig::from(&app_config.lifecycle));
+
+    match run(app) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(mut app: App) -> std::io::Result<()> {
+    let mut terminal = ratatui::try_init()?;
+    let result = loop {
+        app.tick();
+        if let Err(e) = terminal.draw(|frame| keva_tui::draw(frame, &app)) {
+            break Err(e);
+        }
+        match event::poll(POLL_INTERVAL) {
+            Ok(false) => continue,
+            Ok(true) => {}
+            Err(e) => break Err(e),
+        }
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(e) => break Err(e),
+        };
+
+        match app.handle_key(key) {
+            Command::Continue => {}
+            Command::Quit => break Ok(()),
+            Command::ExternalEditor(path) => {
+                ratatui::restore();
+                launch_editor(&path);
+                terminal = match ratatui::try_init() {
+                    Ok(terminal) => terminal,
+                    Err(e) => break Err(e),
+                };
+                app.after_external_edit();
+            }
+        }
+    };
+    ratatui::restore();
+    app.close();
+    result
+}
+
+/// Runs `$VISUAL` or `$EDITOR` (default `vi`) on the file and waits for it to exit.
+fn launch_editor(path: &Path) {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut parts = editor.split_whitespace();
+    let Some(program) = parts.next() else {
+        return;
+    };
+    if let Err(e) = std::process::Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+    {
+        eprintln!("Failed to run {editor}: {e}");
+    }
+}
@@ -0,0 +1,398 @@
+use super::*;
+use common::{make_app, press, render, type_str};
+use keva_core::core::KevaCore;
+use keva_core::types::{
+    AttachmentConflictResolution, AttachmentName, Config, GcConfig, Key, LifecycleConfig,
+};
+use keva_search::{SearchConfig, SearchEngine};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::io::Read;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+mod common {
+    use super::*;
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+
+    pub(super) fn make_app(temp: &TempDir, keys: &[&str]) -> App {
+        let mut keva = KevaCore::open(Config {
+            base_path: temp.path().to_path_buf(),
+        })
+        .unwrap();
+        for key in keys {
+            keva.create(&Key::try_from(*key).unwrap(), std::time::SystemTime::now())
+                .unwrap();
+        }
+        let search = SearchEngine::new(
+            keva.active_keys().unwrap(),
+            keva.trashed_keys().unwrap(),
+            SearchConfig::default(),
+            Arc::new(|| {}),
+        );
+        let mut app = App::new(keva, search, GcConfig::from(&LifecycleConfig::default()));
+        settle(&mut app);
+        app
+    }
+
+    /// Ticks the search engine until matching finishes.
+    pub(super) fn settle(app: &mut App) {
+        while !app.search.is_done() {
+            app.tick();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        app.tick();
+    }
+
+    pub(super) fn press(app: &mut App, code: KeyCode) -> Command {
+        let command = app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+        settle(app);
+        command
+    }
+
+    pub(super) fn type_str(app: &mut App, text: &str) {
+        for c in text.chars() {
+            press(app, KeyCode::Char(c));
+        }
+    }
+
+    /// Renders to an in-memory terminal and returns the screen as lines.
+    pub(super) fn render(app: &App, width: u16, height: u16) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| draw(frame, app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect::<String>()
+            })
+            .collect()
+    }
+
+    pub(super) fn ctrl(app: &mut App, c: char) -> Command {
+        app.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL))
+    }
+}
+
+mod search {
+    use super::*;
+
+    #[test]
+    fn test_typing_filters_key_list() {
+        let temp = TempDir::new().unwrap();
+        let mut app = make_app(&temp, &["project/alpha", "project/beta", "other"]);
+        assert_eq!(app.active.len(), 3);
+
+        type_str(&mut app, "alp");
+        assert_eq!(app.query, "alp");
+        let keys: Vec<_> = app.active.iter().map(|k| k.as_str()).collect();
+        assert_eq!(keys, vec!["project/alpha"]);
+    }
+
+    #[test]
+    fn test_enter_creates_key_and_focuses_editor() {
+        let temp = TempDir::new().unwrap();
+        let mut app = make_app(&temp, &[]);
+
+        type_str(&mut app, "new-note");
+        press(&mut app, KeyCode::Enter);
+
+        assert_eq!(app.focus, Focus::Editor);
+        assert_eq!(app.editor.as_ref().unwrap().key.as_str(), "new-note");
+        assert!(
+            app.keva
+                .get(&Key::try_from("new-note").unwrap())
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_exact_match_is_target_without_selection() {
+        let temp = TempDir::new().unwrap();
+        let mut app = make_app(&temp, &["note", "notes"]);
+
+        type_str(&mut app, "note");
+        assert!(app.selected.is_none());
+        assert_eq!(app.editor.as_ref().unwrap().key.as_str(), "note");
+    }
+}
+
+mod keys {
+    use super::*;
+
+    #[test]
+    fn test_arrow_navigation_between_search_and_list() {
+        let temp = TempDir::new().unwrap();
+        let mut app = make_app(&temp, &["a", "b"]);
+
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.focus, Focus::Keys);
+        let first = app.selected.clone().unwrap();
+
+        press(&mut app, KeyCode::Down);
+        assert_ne!(app.selected.clone().unwrap(), first);
+        press(&mut app, KeyCode::Up);
+        press(&mut app, KeyCode::Up);
+        assert_eq!(app.focus, Focus::Search);
+        assert_eq!(app.selected, Some(first));
+    }
+
+    #[test]
+    fn test_trash_restore_and_purge() {
+        let temp = TempDir::new().unwrap();
+        let mut app = make_app(&temp, &["note"]);
+
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Delete);
+        assert!(app.active.is_empty());
+        assert_eq!(app.trashed.len(), 1);
+
+        press(&mut app, KeyCode::Tab);
+        assert_eq!(app.focus, Focus::Trash);
+        assert!(app.editor.as_ref().unwrap().read_only);
+        press(&mut app, KeyCode::Char('r'));
+        assert_eq!(app.focus, Focus::Keys);
+        assert_eq!(app.active.len(), 1);
+        assert!(app.trashed.is_empty());
+
+        press(&mut app, KeyCode::Delete);
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Delete);
+        assert!(app.active.is_empty() && app.trashed.is_empty());
+        assert_eq!(app.focus, Focus::Search);
+    }
+}
+
+mod editor {
+    use super::*;
+    use common::ctrl;
+
+    fn content(app: &App, key: &str) -> String {
+        let mut text = String::new();
+        app.keva
+            .open_content(&Key::try_from(key).unwrap())
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn test_edits_are_saved_on_leaving_editor() {
+        let temp = TempDir::new().unwrap();
+        let mut app = make_app(&temp, &["note"]);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.focus, Focus::Editor);
+
+        type_str(&mut app, "# Title");
+        press(&mut app, KeyCode::Enter);
+        type_str(&mut app, "bodyx");
+        press(&mut app, KeyCode::Backspace);
+        assert!(app.editor.as_ref().unwrap().dirty);
+
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.focus, Focus::Keys);
+        assert_eq!(content(&app, "note"), "# Title\nbody");
+    }
+
+    #[test]
+    fn test_editor_cursor_movement() {
+        let temp = TempDir::new().unwrap();
+        let mut app = make_app(&temp, &["note"]);
+        type_str(&mut app, "note");
+        press(&mut app, KeyCode::Enter);
+
+        type_str(&mut app, "ab");
+        press(&mut app, KeyCode::Enter);
+        type_str(&mut app, "cd");
+        press(&mut app, KeyCode::Up);
+        press(&mut app, KeyCode::End);
+        type_str(&mut app, "é");
+        press(&mut app, KeyCode::Right);
+        press(&mut app, KeyCode::Backspace);
+        assert_eq!(app.editor.as_ref().unwrap().text(), "abécd");
+    }
+
+    #[test]
+    fn test_switching_keys_saves_previous() {
+        let temp = TempDir::new().unwrap();
+        let mut app = make_app(&temp, &["a", "b"]);
+        type_str(&mut app, "a");
+        press(&mut app, KeyCode::Enter);
+        type_str(&mut app, "text for a");
+
+        ctrl(&mut app, 's');
+        press(&mut app, KeyCode::Backspace);
+        type_str(&mut app, "b");
+        assert_eq!(app.editor.as_ref().unwrap().key.as_str(), "b");
+        assert_eq!(content(&app, "a"), "text for a");
+    }
+
+    #[test]
+    fn test_external_editor_targets_content_file() {
+        let temp = TempDir::new().unwrap();
+        let mut app = make_app(&temp, &["note"]);
+        type_str(&mut app, "note");
+        press(&mut app, KeyCode::Enter);
+
+        let key = Key::try_from("note").unwrap();
+        let Command::ExternalEditor(path) = ctrl(&mut app, 'e') else {
+            panic!("expected external editor command");
+        };
+        assert_eq!(path, app.keva.content_path(&key));
+
+        std::fs::write(&path, "from outside").unwrap();
+        app.after_external_edit();
+        assert_eq!(app.editor.as_ref().unwrap().text(), "from outside");
+    }
+
+    #[test]
+    fn test_trashed_key_is_read_only() {
+        let temp = TempDir::new().unwrap();
+        let mut app = make_app(&temp, &["note"]);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Delete);
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Tab);
+        assert_eq!(app.focus, Focus::Editor);
+
+        type_str(&mut app, "x");
+        assert_eq!(app.editor.as_ref().unwrap().text(), "");
+        assert_eq!(ctrl(&mut app, 'e'), Command::Continue);
+        assert!(app.status.is_some());
+    }
+}
+
+mod attachments {
+    use super::*;
+
+    #[test]
+    fn test_remove_attachment() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("file.txt");
+        std::fs::write(&source, "data").unwrap();
+        let mut app = make_app(&temp, &["note"]);
+        app.keva
+            .add_attachments(
+                &Key::try_from("note").unwrap(),
+                vec![
+                    (
+                        source.clone(),
+                        AttachmentName::try_from("a.txt").unwrap(),
+                        AttachmentConflictResolution::Fail,
+                    ),
+                    (
+                        source,
+                        AttachmentName::try_from("b.txt").unwrap(),
+                        AttachmentConflictResolution::Fail,
+                    ),
+                ],
+                std::time::SystemTime::now(),
+            )
+            .unwrap();
+
+        type_str(&mut app, "note");
+        assert_eq!(app.attachments.len(), 2);
+        press(&mut app, KeyCode::BackTab);
+        assert_eq!(app.focus, Focus::Attachments);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Delete);
+
+        let names: Vec<_> = app
+            .attachments
+            .iter()
+            .map(|a| a.filename.as_str())
+            .collect();
+        assert_eq!(names, vec!["a.txt"]);
+        assert_eq!(app.attachment_index, 0);
+    }
+}
+
+mod render {
+    use super::*;
+
+    fn contains(screen: &[String], text: &str) -> bool {
+        screen.iter().any(|line| line.contains(text))
+    }
+
+    #[test]
+    fn test_renders_four_panes() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("spec.pdf");
+        std::fs::write(&source, vec![0u8; 2048]).unwrap();
+        let mut app = make_app(&temp, &["notes", "old"]);
+        app.keva
+            .add_attachments(
+                &Key::try_from("notes").unwrap(),
+                vec![(
+                    source,
+                    AttachmentName::try_from("spec.pdf").unwrap(),
+                    AttachmentConflictResolution::Fail,
+                )],
+                std::time::SystemTime::now(),
+            )
+            .unwrap();
+        app.keva
+            .trash(&Key::try_from("old").unwrap(), std::time::SystemTime::now())
+            .unwrap();
+        app.search.trash(&Key::try_from("old").unwrap());
+
+        type_str(&mut app, "notes");
+        press(&mut app, KeyCode::Enter);
+        type_str(&mut app, "# Notes");
+
+        let screen = render(&app, 80, 24);
+        assert!(screen[1].contains("notes"));
+        assert!(contains(&screen, " Keys "));
+        assert!(contains(&screen, "# Notes"));
+        assert!(contains(&screen, "notes [modified]"));
+        assert!(contains(&screen, "spec.pdf (2.0 KB)"));
+        assert!(!contains(&screen, "Trash ("));
+
+        press(&mut app, KeyCode::Esc);
+        ctrl_s_and_clear(&mut app);
+        let screen = render(&app, 80, 24);
+        assert!(contains(&screen, "Trash (1)"));
+        assert!(contains(&screen, "old"));
+    }
+
+    fn ctrl_s_and_clear(app: &mut App) {
+        common::ctrl(app, 's');
+        for _ in 0..5 {
+            press(app, KeyCode::Backspace);
+        }
+    }
+
+    #[test]
+    fn test_status_line_shows_hints_and_errors() {
+        let temp = TempDir::new().unwrap();
+        let mut app = make_app(&temp, &[]);
+
+        let screen = render(&app, 100, 12);
+        assert!(screen[11].contains("Enter: open/create"));
+
+        press(&mut app, KeyCode::Enter);
+        let screen = render(&app, 100, 12);
+        assert!(screen[11].starts_with("Invalid key"));
+    }
+
+    #[test]
+    fn test_empty_state() {
+        let temp = TempDir::new().unwrap();
+        let app = make_app(&temp, &[]);
+        let screen = render(&app, 60, 12);
+        assert!(contains(&screen, "No key selected"));
+        assert!(contains(&screen, "Attachments (0)"));
+    }
+
+    #[test]
+    fn test_format_size() {
+        use crate::ui::format_size;
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(340 * 1024), "340.0 KB");
+        assert_eq!(format_size(1258291), "1.2 MB");
+    }
+}
@@ -0,0 +1,198 @@
+//! Four-pane rendering, mirroring the GUI layout in Spec.md.
+
+use crate::app::{App, Focus};
+use ratatui::Frame;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+
+/// Rows of the trash section, about twice a key row plus borders.
+const TRASH_HEIGHT: u16 = 4;
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [search_area, body, status_area] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [left, right] =
+        Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)]).areas(body);
+    let trash_height = if app.trashed.is_empty() {
+        0
+    } else {
+        TRASH_HEIGHT
+    };
+    let [keys_area, trash_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(trash_height)]).areas(left);
+    let [editor_area, attachments_area] =
+        Layout::vertical([Constraint::Percentage(65), Constraint::Percentage(35)]).areas(right);
+
+    draw_search(frame, app, search_area);
+    draw_key_list(frame, app, keys_area, Focus::Keys);
+    if trash_height > 0 {
+        draw_key_list(frame, app, trash_area, Focus::Trash);
+    }
+    draw_editor(frame, app, editor_area);
+    draw_attachments(frame, app, attachments_area);
+    draw_status(frame, app, status_area);
+}
+
+fn pane(title: String, active: bool) -> Block<'static> {
+    let border = if active {
+        Style::new().fg(Color::Cyan)
+    } else {
+        Style::new().fg(Color::DarkGray)
+    };
+    Block::bordered().title(title).border_style(border)
+}
+
+/// Highlighted when the pane is active, dimmed when the selection persists in the background.
+fn selection_style(active: bool) -> Style {
+    if active {
+        Style::new().add_modifier(Modifier::REVERSED)
+    } else {
+        Style::new().bg(Color::DarkGray)
+    }
+}
+
+fn draw_search(frame: &mut Frame, app: &App, area: Rect) {
+    let active = app.focus == Focus::Search;
+    let has_exact_match = app.target_key().is_some() && app.selected.is_none();
+    let action = if app.query.is_empty() || has_exact_match {
+        ""
+    } else {
+        "[Enter: create]"
+    };
+    let block =
+        pane(" Search ".to_string(), active).title_bottom(Line::from(action).right_aligned());
+    let style = if active {
+        Style::new()
+    } else {
+        Style::new().add_modifier(Modifier::DIM)
+    };
+    frame.render_widget(
+        Paragraph::new(app.query.as_str()).style(style).block(block),
+        area,
+    );
+    if active {
+        let x = area.x + 1 + app.query.chars().count() as u16;
+        frame.set_cursor_position((x.min(area.right().saturating_sub(2)), area.y + 1));
+    }
+}
+
+fn draw_key_list(frame: &mut Frame, app: &App, area: Rect, section: Focus) {
+    let (keys, title) = if section == Focus::Trash {
+        (&app.trashed, format!(" Trash ({}) ", app.trashed.len()))
+    } else {
+        (&app.active, " Keys ".to_string())
+    };
+    let active = app.focus == section;
+    let selected = app
+        .selected
+        .as_ref()
+        .and_then(|key| keys.iter().position(|k| k == key));
+
+    let items: Vec<ListItem> = keys.iter().map(|k| ListItem::new(k.as_str())).collect();
+    let list = List::new(items)
+        .block(pane(title, active))
+        .highlight_style(selection_style(active));
+    let mut state = ListState::default();
+    state.select(selected);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_editor(frame: &mut Frame, app: &App, area: Rect) {
+    let active = app.focus == Focus::Editor;
+    let Some(editor) = &app.editor else {
+        frame.render_widget(
+            Paragraph::new("No key selected")
+                .style(Style::new().fg(Color::DarkGray))
+                .block(pane(" Content ".to_string(), active)),
+            area,
+        );
+        return;
+    };
+
+    let mut title = format!(" {} ", editor.key.as_str());
+    if editor.read_only {
+        title.push_str("[read-only] ");
+    } else if editor.dirty {
+        title.push_str("[modified] ");
+    }
+
+    let (row, col) = editor.cursor();
+    let height = area.height.saturating_sub(2) as usize;
+    let scroll = row.saturating_sub(height.saturating_sub(1));
+    let lines: Vec<Line> = editor
+        .lines()
+        .iter()
+        .map(|line| Line::from(line.as_str()))
+        .collect();
+    let placeholder = editor.lines() == [String::new()] && !editor.read_only;
+    let paragraph = if placeholder {
+        Paragraph::new("Type something...").style(Style::new().fg(Color::DarkGray))
+    } else {
+        Paragraph::new(lines).scroll((scroll as u16, 0))
+    };
+    frame.render_widget(paragraph.block(pane(title, active)), area);
+
+    if active {
+        let x = area.x + 1 + col as u16;
+        let y = area.y + 1 + (row - scroll) as u16;
+        frame.set_cursor_position((x.min(area.right().saturating_sub(2)), y));
+    }
+}
+
+fn draw_attachments(frame: &mut Frame, app: &App, area: Rect) {
+    let active = app.focus == Focus::Attachments;
+    let items: Vec<ListItem> = app
+        .attachments
+        .iter()
+        .map(|a| ListItem::new(format!("{} ({})", a.filename, format_size(a.size))))
+        .collect();
+    let list = List::new(items)
+        .block(pane(
+            format!(" Attachments ({}) ", app.attachments.len()),
+            active,
+        ))
+        .highlight_style(selection_style(active));
+    let mut state = ListState::default();
+    if !app.attachments.is_empty() {
+        state.select(Some(app.attachment_index));
+    }
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_status(frame: &mut Frame, app: &App, area: Rect) {
+    let (text, style) = match &app.status {
+        Some(status) => (status.as_str(), Style::new().fg(Color::Red)),
+        None => {
+            let hints = match app.focus {
+                Focus::Search => "Enter: open/create  Down: keys  Tab: next pane  Ctrl+Q: quit",
+                Focus::Keys => "Enter: edit  Del: trash  Up/Down: move  Tab: next pane",
+                Focus::Trash => "r: restore  Del: delete forever  Tab: next pane",
+                Focus::Editor => "Esc: back  Ctrl+E: $EDITOR  Ctrl+S: search",
+                Focus::Attachments => "Del: remove  Up/Down: move  Tab: next pane",
+            };
+            (hints, Style::new().fg(Color::DarkGray))
+        }
+    };
+    frame.render_widget(Paragraph::new(text).style(style), area);
+}
+
+/// Human-readable size, e.g. `1.2 MB`.
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
@@ -0,0 +1,25 @@
+//! Checking the WebDAV password (see `keva_core::core::load_or_create_token`).
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+/// Checks a Basic `Authorization` header value against the token without short-circuiting on
+/// the first differing byte. Any user name is accepted.
+pub(crate) fn is_authorized(header: Option<&str>, token: &str) -> bool {
+    let Some(credentials) = header
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+    else {
+        return false;
+    };
+    let Some(colon) = credentials.iter().position(|&b| b == b':') else {
+        return false;
+    };
+    let (presented, token) = (&credentials[colon + 1..], token.as_bytes());
+    presented.len() == token.len()
+        && presented
+            .iter()
+            .zip(token)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
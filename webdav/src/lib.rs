@@ -0,0 +1,140 @@
+//! WebDAV view of a Keva store, so keys can be browsed and edited from a file manager or any
+//! editor that speaks WebDAV.
+//!
+//! # Design
+//!
+//! - Binds to `127.0.0.1` only; the share is never reachable from other machines.
+//! - Every request must carry HTTP Basic credentials whose password is the token stored in the
+//!   data directory (`webdav.token`); the user name is ignored.
+//! - Requests are served one at a time on the calling thread, which owns `KevaCore`. Mutations
+//!   bump the store generation, so a persisted search index is rebuilt on its next load.
+//! - WebDAV class 1 only: no locks, so clients that insist on `LOCK` may mount read-only.
+//!
+//! # Layout
+//!
+//! Keys form a tree split on `/`, with Active keys only:
+//!
+//! | Path | Resource |
+//! |---|---|
+//! | `/project/` | Directory, while any Active key lies below `project` |
+//! | `/project/notes.md` | Content of key `project/notes` |
+//! | `/project/notes.attachments/` | Attachments of `project/notes`, listed once it has any |
+//! | `/project/notes.attachments/doc.pdf` | Attachment `doc.pdf` |
+//!
+//! A path inside a `.attachments` folder always names an attachment, so keys with a segment
+//! ending in `.attachments` are not shown.
+//!
+//! # Methods
+//!
+//! | Method | Action |
+//! |---|---|
+//! | `PROPFIND` | List, `Depth: 0` or `1` |
+//! | `GET`, `HEAD` | Read content or attachment |
+//! | `PUT` | `.md`: create the key if missing, write content and touch; attachment: add or overwrite |
+//! | `DELETE` | `.md`: trash key; directory: trash subtree; attachment: remove |
+//! | `MOVE` | `.md`: rename key; directory: rename subtree; attachment: rename, or move to another key |
+//! | `MKCOL` | Accepted for the `.attachments` folder of an existing key |
+
+mod auth;
+mod resource;
+mod routes;
+
+use keva_core::core::{KevaCore, load_or_create_token};
+use keva_core::types::Config;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum WebDavError {
+        #[error("IO error: {0}")]
+        Io(#[from] std::io::Error),
+
+        #[error("Failed to bind: {0}")]
+        Bind(#[source] Box<dyn std::error::Error + Send + Sync>),
+    }
+}
+
+use error::WebDavError;
+
+/// Port used by the `keva_webdav` binary unless another is given.
+pub const DEFAULT_PORT: u16 = 7691;
+
+pub struct WebDavConfig {
+    /// Port on `127.0.0.1`; 0 picks a free port.
+    pub port: u16,
+}
+
+pub struct WebDavServer {
+    http: Arc<tiny_http::Server>,
+    token: String,
+    keva: KevaCore,
+}
+
+/// Stops a running [`WebDavServer`] from another thread.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    http: Arc<tiny_http::Server>,
+}
+
+impl ShutdownHandle {
+    /// Makes [`WebDavServer::run`] return once the request in progress is answered.
+    pub fn shutdown(&self) {
+        self.http.unblock();
+    }
+}
+
+impl WebDavServer {
+    /// Binds to `127.0.0.1`, loading the password from the data directory or creating it.
+    pub fn bind(keva: KevaCore, config: WebDavConfig) -> Result<Self, WebDavError> {
+        let token_path = Config {
+            base_path: keva.data_dir().to_path_buf(),
+        }
+        .webdav_token_path();
+        let token = load_or_create_token(&token_path)?;
+
+        let http = tiny_http::Server::http((Ipv4Addr::LOCALHOST, config.port))
+            .map_err(WebDavError::Bind)?;
+
+        Ok(Self {
+            http: Arc::new(http),
+            token,
+            keva,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.http
+            .server_addr()
+            .to_ip()
+            .expect("server is bound to an IP address")
+    }
+
+    /// The password clients must send with HTTP Basic authentication.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            http: self.http.clone(),
+        }
+    }
+
+    /// Serves requests until [`ShutdownHandle::shutdown`] is called.
+    pub fn run(&mut self) {
+        for request in self.http.incoming_requests() {
+            routes::respond(&mut self.keva, &self.token, request);
+        }
+    }
+
+    /// Returns the store.
+    pub fn into_inner(self) -> KevaCore {
+        self.keva
+    }
+}
+
+#[cfg(test)]
+mod tests;
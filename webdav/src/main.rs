@@ -0,0 +1,75 @@
+//! Serves a data directory over WebDAV: `keva_webdav [data-dir [port]]`.
+//!
+//! Without a data directory, serves the platform default (see `Config::default_data_dir`).
+
+use keva_core::core::KevaCore;
+use keva_core::types::{AppConfig, Config};
+use keva_webdav::{DEFAULT_PORT, WebDavConfig, WebDavServer};
+use keva_worker::{load_search_engine, save_search_index, shutdown_on_signal};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let Some(base_path) = args
+        .next()
+        .map(PathBuf::from)
+        .or_else(Config::default_data_dir)
+    else {
+        eprintln!("No data directory given and none could be resolved.");
+        eprintln!("Usage: keva_webdav [data-dir [port]]");
+        return ExitCode::FAILURE;
+    };
+    let port = match args.next().map(|p| p.parse::<u16>()) {
+        None => DEFAULT_PORT,
+        Some(Ok(port)) => port,
+        Some(Err(e)) => {
+            eprintln!("Invalid port: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let config = Config { base_path };
+    let (app_config, problems) = AppConfig::load_or_default(&AppConfig::path(&config.base_path));
+    for problem in problems {
+        eprintln!("Config: {problem}; using the default");
+    }
+
+    let mut keva = match KevaCore::open(config.clone()) {
+        Ok(keva) => keva,
+        Err(e) => {
+            eprintln!("Failed to open {}: {e}", config.base_path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    keva.set_quota_config(app_config.quotas);
+
+    let mut server = match WebDavServer::bind(keva, WebDavConfig { port }) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let shutdown = server.shutdown_handle();
+    if let Err(e) = shutdown_on_signal(move || shutdown.shutdown()) {
+        eprintln!("Warning: Ctrl+C won't save the search index: {e}");
+    }
+
+    println!(
+        "Serving WebDAV on http://{} (any user name, password in {})",
+        server.local_addr(),
+        config.webdav_token_path().display()
+    );
+    server.run();
+
+    // Keys created, renamed or deleted over WebDAV make the saved search index stale; bring it
+    // up to date so the next frontend doesn't rebuild it.
+    let keva = server.into_inner();
+    let index_path = config.search_index_path();
+    let search = load_search_engine(&keva, &index_path, Arc::new(|| {}));
+    save_search_index(&keva, &search, &index_path);
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,139 @@
+//! Mapping between URL paths and store resources.
+
+use keva_core::types::Key;
+
+/// Extension of the file holding a key's content.
+pub(crate) const CONTENT_EXTENSION: &str = ".md";
+
+/// Suffix of the folder holding a key's attachments.
+pub(crate) const ATTACHMENTS_SUFFIX: &str = ".attachments";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Resource {
+    /// A directory of the key tree; `""` is the root.
+    Dir(String),
+    /// `{key}.md`
+    Content(Key),
+    /// `{key}.attachments/`
+    Attachments(Key),
+    /// `{key}.attachments/{filename}`
+    Attachment(Key, String),
+}
+
+impl Resource {
+    /// Parses a request path, without query string.
+    ///
+    /// Returns `None` for malformed percent-encoding, empty segments or names that can't be keys.
+    pub(crate) fn parse(path: &str) -> Option<Self> {
+        let trimmed = path.trim_matches('/');
+        if trimmed.is_empty() {
+            return Some(Self::Dir(String::new()));
+        }
+        let segments = trimmed
+            .split('/')
+            .map(percent_decode)
+            .collect::<Option<Vec<_>>>()?;
+        if segments
+            .iter()
+            .any(|s| s.is_empty() || s == "." || s == "..")
+        {
+            return None;
+        }
+
+        let (last, parents) = segments.split_last()?;
+        if let Some((folder, parents)) = parents.split_last()
+            && let Some(name) = folder.strip_suffix(ATTACHMENTS_SUFFIX)
+        {
+            return Some(Self::Attachment(join_key(parents, name)?, last.to_string()));
+        }
+        if let Some(name) = last.strip_suffix(ATTACHMENTS_SUFFIX) {
+            return Some(Self::Attachments(join_key(parents, name)?));
+        }
+        if let Some(name) = last.strip_suffix(CONTENT_EXTENSION) {
+            return Some(Self::Content(join_key(parents, name)?));
+        }
+        Some(Self::Dir(segments.join("/")))
+    }
+
+    /// Parses a `Destination` header, which is an absolute URL or an absolute path.
+    pub(crate) fn parse_destination(destination: &str) -> Option<Self> {
+        let path = match destination.split_once("://") {
+            Some((_, rest)) => &rest[rest.find('/').unwrap_or(rest.len())..],
+            None => destination,
+        };
+        let path = path.split_once('?').map_or(path, |(path, _)| path);
+        Self::parse(path)
+    }
+
+    /// Percent-encoded path, with a trailing `/` for collections.
+    pub(crate) fn href(&self) -> String {
+        match self {
+            Self::Dir(prefix) if prefix.is_empty() => "/".to_string(),
+            Self::Dir(prefix) => format!("/{}/", encode_path(prefix)),
+            Self::Content(key) => format!("/{}{CONTENT_EXTENSION}", encode_path(key)),
+            Self::Attachments(key) => format!("/{}{ATTACHMENTS_SUFFIX}/", encode_path(key)),
+            Self::Attachment(key, filename) => format!(
+                "/{}{ATTACHMENTS_SUFFIX}/{}",
+                encode_path(key),
+                percent_encode(filename)
+            ),
+        }
+    }
+
+    pub(crate) fn is_collection(&self) -> bool {
+        matches!(self, Self::Dir(_) | Self::Attachments(_))
+    }
+}
+
+fn join_key(parents: &[String], name: &str) -> Option<Key> {
+    if name.is_empty() {
+        return None;
+    }
+    let mut key = parents.join("/");
+    if !key.is_empty() {
+        key.push('/');
+    }
+    key.push_str(name);
+    // Keys are trimmed on creation, so a name that changes under trimming can't round-trip.
+    Key::try_from(key.as_str())
+        .ok()
+        .filter(|k| k.as_str() == key)
+}
+
+/// Encodes each `/`-separated segment of `path`.
+fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(percent_encode)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Encodes everything but RFC 3986 unreserved characters.
+pub(crate) fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for &b in s.as_bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Decodes `%XX` escapes, returning `None` for malformed escapes or non-UTF-8 results.
+pub(crate) fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
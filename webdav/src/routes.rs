@@ -0,0 +1,543 @@
+//! Request handling for each WebDAV method.
+
+use crate::auth;
+use crate::resource::{ATTACHMENTS_SUFFIX, Resource};
+use keva_core::core::QuotaError;
+use keva_core::core::error::KevaError;
+use keva_core::core::{FileReader, KevaCore};
+use keva_core::error::DatabaseError;
+use keva_core::types::{Attachment, AttachmentName, Key, LifecycleState, SealState, Value};
+use std::io::Read;
+use std::path::Path;
+use std::time::SystemTime;
+use tiny_http::{Header, Request, Response};
+
+const ALLOWED_METHODS: &str = "OPTIONS, PROPFIND, GET, HEAD, PUT, DELETE, MOVE, MKCOL";
+
+const MARKDOWN_TYPE: &str = "text/markdown; charset=utf-8";
+/// For attachments added before MIME types were recorded.
+const ATTACHMENT_TYPE: &str = "application/octet-stream";
+
+/// A successful response.
+enum Reply {
+    Status(u16),
+    /// A file streamed from the store; `len` is sent as Content-Length when known.
+    File {
+        content_type: String,
+        modified: Option<SystemTime>,
+        reader: FileReader,
+        len: Option<u64>,
+    },
+    MultiStatus(String),
+    Options,
+}
+
+/// An error response, sent as a plain-text message.
+#[derive(Debug)]
+struct DavError {
+    status: u16,
+    message: String,
+}
+
+impl DavError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn not_found() -> Self {
+        Self::new(404, "Not found")
+    }
+
+    fn method_not_allowed() -> Self {
+        Self::new(405, "Method not allowed on this resource")
+    }
+}
+
+impl From<KevaError> for DavError {
+    fn from(e: KevaError) -> Self {
+        let status = match &e {
+            KevaError::InvalidKey(_) => 400,
+            KevaError::Locked => 423,
+            KevaError::DestinationExists => 412,
+            KevaError::Database(db) => match db {
+                DatabaseError::NotFound | DatabaseError::AttachmentNotFound(_) => 404,
+                DatabaseError::AlreadyExists
+                | DatabaseError::Trashed
+                | DatabaseError::NotTrashed => 409,
+                _ => 500,
+            },
+            KevaError::QuotaExceeded(QuotaError::AttachmentTooLarge { .. }) => 413,
+            KevaError::QuotaExceeded(QuotaError::StoreFull { .. }) => 507,
+            _ => 500,
+        };
+        Self::new(status, e.to_string())
+    }
+}
+
+impl From<std::io::Error> for DavError {
+    fn from(e: std::io::Error) -> Self {
+        Self::new(500, e.to_string())
+    }
+}
+
+/// The request headers the handlers look at.
+#[derive(Default)]
+struct DavHeaders {
+    depth: Option<String>,
+    destination: Option<String>,
+    overwrite: Option<String>,
+}
+
+/// Answers a request; failures to send the response are ignored, as the client went away.
+pub(crate) fn respond(keva: &mut KevaCore, token: &str, mut request: Request) {
+    let mut authorization = None;
+    let mut headers = DavHeaders::default();
+    for header in request.headers() {
+        let value = Some(header.value.as_str().to_string());
+        if header.field.equiv("Authorization") {
+            authorization = value;
+        } else if header.field.equiv("Depth") {
+            headers.depth = value;
+        } else if header.field.equiv("Destination") {
+            headers.destination = value;
+        } else if header.field.equiv("Overwrite") {
+            headers.overwrite = value;
+        }
+    }
+
+    let result = if auth::is_authorized(authorization.as_deref(), token) {
+        let (method, url) = (
+            request.method().as_str().to_string(),
+            request.url().to_string(),
+        );
+        route(keva, &method, &url, &headers, request.as_reader())
+    } else {
+        Err(DavError::new(401, "Missing or invalid credentials"))
+    };
+
+    let response = match result {
+        Ok(Reply::Status(status)) => Response::from_data(Vec::new())
+            .with_status_code(status)
+            .boxed(),
+        Ok(Reply::File {
+            content_type,
+            modified,
+            reader,
+            len,
+        }) => {
+            let mut headers = vec![header("Content-Type", &content_type)];
+            if let Some(time) = modified {
+                headers.push(header("Last-Modified", &httpdate::fmt_http_date(time)));
+            }
+            let len = len.and_then(|len| usize::try_from(len).ok());
+            Response::new(200.into(), headers, reader, len, None).boxed()
+        }
+        Ok(Reply::MultiStatus(xml)) => Response::from_data(xml)
+            .with_status_code(207)
+            .with_header(header("Content-Type", "application/xml; charset=utf-8"))
+            .boxed(),
+        Ok(Reply::Options) => Response::from_data(Vec::new())
+            .with_header(header("DAV", "1"))
+            .with_header(header("Allow", ALLOWED_METHODS))
+            .boxed(),
+        Err(e) => {
+            let response = Response::from_data(e.message)
+                .with_status_code(e.status)
+                .with_header(header("Content-Type", "text/plain; charset=utf-8"));
+            if e.status == 401 {
+                response
+                    .with_header(header("WWW-Authenticate", "Basic realm=\"Keva\""))
+                    .boxed()
+            } else {
+                response.boxed()
+            }
+        }
+    };
+    let _ = request.respond(response);
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field, value).expect("header is valid")
+}
+
+fn route(
+    keva: &mut KevaCore,
+    method: &str,
+    url: &str,
+    headers: &DavHeaders,
+    body: &mut dyn Read,
+) -> Result<Reply, DavError> {
+    let path = url.split_once('?').map_or(url, |(path, _)| path);
+    let resource = Resource::parse(path).ok_or_else(|| DavError::new(400, "Invalid path"))?;
+
+    match method {
+        "OPTIONS" => Ok(Reply::Options),
+        "PROPFIND" => propfind(keva, &resource, headers.depth.as_deref()),
+        "GET" | "HEAD" => get(keva, &resource),
+        "PUT" => put(keva, &resource, body),
+        "DELETE" => delete(keva, &resource),
+        "MOVE" => {
+            let destination = headers
+                .destination
+                .as_deref()
+                .and_then(Resource::parse_destination)
+                .ok_or_else(|| DavError::new(400, "Missing or invalid Destination"))?;
+            let overwrite = headers.overwrite.as_deref() != Some("F");
+            move_resource(keva, &resource, &destination, overwrite)
+        }
+        "MKCOL" => mkcol(keva, &resource, body),
+        _ => Err(DavError::new(501, format!("{method} is not supported"))),
+    }
+}
+
+/// A resource as listed by `PROPFIND`.
+struct Entry {
+    resource: Resource,
+    len: Option<u64>,
+    modified: Option<SystemTime>,
+    /// Attachments only.
+    mime: Option<String>,
+}
+
+impl Entry {
+    fn collection(resource: Resource) -> Self {
+        Self {
+            resource,
+            len: None,
+            modified: None,
+            mime: None,
+        }
+    }
+
+    fn content(keva: &KevaCore, key: Key, value: &Value) -> Self {
+        Self {
+            len: content_len(keva, &key, value),
+            modified: modified(&keva.content_path(&key)),
+            mime: None,
+            resource: Resource::Content(key),
+        }
+    }
+
+    fn attachment(keva: &KevaCore, key: &Key, attachment: Attachment) -> Self {
+        let modified = attachment.modified_at.or_else(|| {
+            let path = keva.attachment_path(key, &attachment.filename).ok()?;
+            modified(&path)
+        });
+        Self {
+            resource: Resource::Attachment(key.clone(), attachment.filename),
+            len: Some(attachment.size),
+            modified,
+            mime: attachment.mime,
+        }
+    }
+
+    fn content_type(&self) -> String {
+        match &self.resource {
+            Resource::Content(_) => MARKDOWN_TYPE.to_string(),
+            _ => self.mime.as_deref().unwrap_or(ATTACHMENT_TYPE).to_string(),
+        }
+    }
+}
+
+/// Returns the value of an Active key, which is all the view shows.
+fn active_value(keva: &KevaCore, key: &Key) -> Result<Option<Value>, DavError> {
+    Ok(keva
+        .get(key)?
+        .filter(|v| matches!(v.metadata.lifecycle_state, LifecycleState::Active { .. })))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Returns the resource's entry, or `None` if it doesn't exist.
+fn lookup(keva: &KevaCore, resource: &Resource) -> Result<Option<Entry>, DavError> {
+    match resource {
+        Resource::Dir(prefix) => {
+            let exists = prefix.is_empty() || !keva.children(prefix)?.is_empty();
+            Ok(exists.then(|| Entry::collection(resource.clone())))
+        }
+        Resource::Content(key) => {
+            let Some(value) = active_value(keva, key)? else {
+                return Ok(None);
+            };
+            Ok(Some(Entry::content(keva, key.clone(), &value)))
+        }
+        Resource::Attachments(key) => {
+            Ok(active_value(keva, key)?.map(|_| Entry::collection(resource.clone())))
+        }
+        Resource::Attachment(key, filename) => {
+            let Some(value) = active_value(keva, key)? else {
+                return Ok(None);
+            };
+            Ok(value
+                .attachments
+                .into_iter()
+                .find(|a| &a.filename == filename)
+                .map(|a| Entry::attachment(keva, key, a)))
+        }
+    }
+}
+
+/// Plaintext length of the content; encrypted content has to be read to find it.
+fn content_len(keva: &KevaCore, key: &Key, value: &Value) -> Option<u64> {
+    if !keva.is_encrypted() && value.metadata.seal == SealState::Unsealed {
+        return std::fs::metadata(keva.content_path(key))
+            .map(|m| m.len())
+            .ok();
+    }
+    let mut reader = keva.open_content(key).ok()?;
+    std::io::copy(&mut reader, &mut std::io::sink()).ok()
+}
+
+/// Lists the members of a collection.
+fn members(keva: &KevaCore, resource: &Resource) -> Result<Vec<Entry>, DavError> {
+    let mut entries = Vec::new();
+    match resource {
+        Resource::Dir(prefix) => {
+            for node in keva.children(prefix)? {
+                // Shadowed by the attachment folders; see the crate docs.
+                if node.name.ends_with(ATTACHMENTS_SUFFIX) {
+                    continue;
+                }
+                if node.descendant_count > 0 {
+                    entries.push(Entry::collection(Resource::Dir(node.path.clone())));
+                }
+                if !node.is_key {
+                    continue;
+                }
+                let key = Key::try_from(node.path.as_str())
+                    .map_err(|e| DavError::new(500, e.to_string()))?;
+                let Some(value) = active_value(keva, &key)? else {
+                    continue;
+                };
+                entries.push(Entry::content(keva, key.clone(), &value));
+                if !value.attachments.is_empty() {
+                    entries.push(Entry::collection(Resource::Attachments(key)));
+                }
+            }
+        }
+        Resource::Attachments(key) => {
+            if let Some(value) = active_value(keva, key)? {
+                for attachment in value.attachments {
+                    entries.push(Entry::attachment(keva, key, attachment));
+                }
+            }
+        }
+        Resource::Content(_) | Resource::Attachment(..) => {}
+    }
+    Ok(entries)
+}
+
+/// Lists the resource, and its members unless `Depth: 0`. Infinite depth is served as depth 1.
+fn propfind(keva: &KevaCore, resource: &Resource, depth: Option<&str>) -> Result<Reply, DavError> {
+    let entry = lookup(keva, resource)?.ok_or_else(DavError::not_found)?;
+    let mut entries = vec![entry];
+    if depth != Some("0") && resource.is_collection() {
+        entries.extend(members(keva, resource)?);
+    }
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<D:multistatus xmlns:D=\"DAV:\">\n");
+    for entry in &entries {
+        // Hrefs are percent-encoded, so they need no XML escaping.
+        xml.push_str(&format!(
+            "<D:response><D:href>{}</D:href><D:propstat><D:prop>",
+            entry.resource.href()
+        ));
+        match &entry.resource {
+            Resource::Dir(_) | Resource::Attachments(_) => {
+                xml.push_str("<D:resourcetype><D:collection/></D:resourcetype>");
+            }
+            Resource::Content(_) | Resource::Attachment(..) => {
+                xml.push_str("<D:resourcetype/>");
+                xml.push_str(&format!(
+                    "<D:getcontenttype>{}</D:getcontenttype>",
+                    entry.content_type()
+                ));
+            }
+        }
+        if let Some(len) = entry.len {
+            xml.push_str(&format!("<D:getcontentlength>{len}</D:getcontentlength>"));
+        }
+        if let Some(time) = entry.modified {
+            xml.push_str(&format!(
+                "<D:getlastmodified>{}</D:getlastmodified>",
+                httpdate::fmt_http_date(time)
+            ));
+        }
+        xml.push_str("</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n");
+    }
+    xml.push_str("</D:multistatus>\n");
+    Ok(Reply::MultiStatus(xml))
+}
+
+fn get(keva: &KevaCore, resource: &Resource) -> Result<Reply, DavError> {
+    let entry = lookup(keva, resource)?.ok_or_else(DavError::not_found)?;
+    let reader = match resource {
+        Resource::Content(key) => keva.open_content(key)?,
+        Resource::Attachment(key, filename) => keva.open_attachment(key, filename)?,
+        Resource::Dir(_) | Resource::Attachments(_) => {
+            return Err(DavError::method_not_allowed());
+        }
+    };
+    Ok(Reply::File {
+        content_type: entry.content_type(),
+        modified: entry.modified,
+        reader,
+        len: entry.len,
+    })
+}
+
+fn put(keva: &mut KevaCore, resource: &Resource, body: &mut dyn Read) -> Result<Reply, DavError> {
+    match resource {
+        Resource::Content(key) => put_content(keva, key, body),
+        Resource::Attachment(key, filename) => put_attachment(keva, key, filename, body),
+        Resource::Dir(_) | Resource::Attachments(_) => Err(DavError::new(
+            403,
+            "Only .md files and attachments can be written",
+        )),
+    }
+}
+
+/// Creates the key if needed, then replaces the content and touches the key, as an editor save
+/// would.
+fn put_content(keva: &mut KevaCore, key: &Key, body: &mut dyn Read) -> Result<Reply, DavError> {
+    let now = SystemTime::now();
+    let created = match keva.get(key)? {
+        None => {
+            keva.create(key, now)?;
+            true
+        }
+        Some(value) if matches!(value.metadata.lifecycle_state, LifecycleState::Trash { .. }) => {
+            return Err(DavError::new(409, "Key is in the trash"));
+        }
+        Some(_) => false,
+    };
+
+    let mut writer = keva.write_content(key)?;
+    std::io::copy(body, &mut writer)?;
+    writer.finish()?;
+    keva.touch(key, now)?;
+    Ok(Reply::Status(if created { 201 } else { 204 }))
+}
+
+/// Validates the name of an attachment being created.
+fn attachment_name(filename: &str) -> Result<AttachmentName, DavError> {
+    AttachmentName::try_new_visible(filename)
+        .map_err(|e| DavError::new(403, format!("Invalid filename '{filename}': {e}")))
+}
+
+/// Streams the body into an attachment, overwriting one with the same name.
+fn put_attachment(
+    keva: &mut KevaCore,
+    key: &Key,
+    filename: &str,
+    body: &mut dyn Read,
+) -> Result<Reply, DavError> {
+    let name = attachment_name(filename)?;
+    let value = active_value(keva, key)?.ok_or_else(|| DavError::new(409, "Key does not exist"))?;
+    let existed = value.attachments.iter().any(|a| a.filename == filename);
+
+    keva.add_attachment_from_reader(key, &name, body, SystemTime::now())?;
+    Ok(Reply::Status(if existed { 204 } else { 201 }))
+}
+
+fn delete(keva: &mut KevaCore, resource: &Resource) -> Result<Reply, DavError> {
+    lookup(keva, resource)?.ok_or_else(DavError::not_found)?;
+    let now = SystemTime::now();
+    match resource {
+        Resource::Dir(prefix) if prefix.is_empty() => {
+            return Err(DavError::new(403, "The root can't be deleted"));
+        }
+        Resource::Dir(prefix) => {
+            keva.trash_subtree(&dir_key(prefix)?, now)?;
+        }
+        Resource::Content(key) => keva.trash(key, now)?,
+        Resource::Attachments(_) => {
+            return Err(DavError::new(403, "Delete attachments one at a time"));
+        }
+        Resource::Attachment(key, filename) => keva.remove_attachment(key, filename, now)?,
+    }
+    Ok(Reply::Status(204))
+}
+
+fn dir_key(prefix: &str) -> Result<Key, DavError> {
+    Key::try_from(prefix).map_err(|e| DavError::new(400, format!("Invalid key: {e}")))
+}
+
+/// Moves a key, a directory or an attachment. With `overwrite`, an existing destination is
+/// replaced; the replaced key or attachment can be brought back with undo. Within a key, the
+/// destination is only dropped once the rename is known to succeed (see
+/// `KevaCore::rename_replacing` and `KevaCore::rename_attachment_replacing`).
+fn move_resource(
+    keva: &mut KevaCore,
+    source: &Resource,
+    destination: &Resource,
+    overwrite: bool,
+) -> Result<Reply, DavError> {
+    lookup(keva, source)?.ok_or_else(DavError::not_found)?;
+    if source == destination {
+        return Err(DavError::new(403, "Source and destination are the same"));
+    }
+    let replaced = lookup(keva, destination)?.is_some();
+    if replaced && !overwrite {
+        return Err(DavError::new(412, "Destination exists"));
+    }
+    let now = SystemTime::now();
+
+    match (source, destination) {
+        (Resource::Content(from), Resource::Content(to)) => {
+            keva.rename_replacing(from, to, now)?;
+        }
+        (Resource::Dir(from), Resource::Dir(to)) if !from.is_empty() && !to.is_empty() => {
+            if replaced {
+                return Err(DavError::new(412, "Directories can't be merged"));
+            }
+            keva.rename_subtree(&dir_key(from)?, &dir_key(to)?, now)?;
+        }
+        (Resource::Attachment(from_key, from), Resource::Attachment(to_key, to))
+            if from_key == to_key =>
+        {
+            let to = attachment_name(to)?;
+            keva.rename_attachment_replacing(from_key, from, &to, now)?;
+        }
+        (Resource::Attachment(from_key, from), Resource::Attachment(to_key, to)) => {
+            let to = attachment_name(to)?;
+            active_value(keva, to_key)?
+                .ok_or_else(|| DavError::new(409, "Destination key does not exist"))?;
+            let reader = keva.open_attachment(from_key, from)?;
+            keva.add_attachment_from_reader(to_key, &to, reader, now)?;
+            keva.remove_attachment(from_key, from, now)?;
+        }
+        _ => {
+            return Err(DavError::new(
+                403,
+                "Only keys, directories and attachments can be moved, each to its own kind",
+            ));
+        }
+    }
+    Ok(Reply::Status(if replaced { 204 } else { 201 }))
+}
+
+/// Attachment folders always exist for Active keys, so creating one just succeeds; directories
+/// exist only while they contain keys.
+fn mkcol(keva: &KevaCore, resource: &Resource, body: &mut dyn Read) -> Result<Reply, DavError> {
+    if body.read(&mut [0])? > 0 {
+        return Err(DavError::new(415, "MKCOL bodies are not supported"));
+    }
+    match resource {
+        Resource::Attachments(key) => {
+            active_value(keva, key)?.ok_or_else(|| DavError::new(409, "Key does not exist"))?;
+            Ok(Reply::Status(201))
+        }
+        _ if lookup(keva, resource)?.is_some() => Err(DavError::method_not_allowed()),
+        _ => Err(DavError::new(
+            403,
+            "Directories appear once a key is created inside them",
+        )),
+    }
+}
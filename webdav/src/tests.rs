@@ -0,0 +1,577 @@
+use super::*;
+use common::{key, start};
+use keva_core::types::{Key, LifecycleState};
+use tempfile::TempDir;
+
+mod common {
+    use super::*;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use std::thread::JoinHandle;
+
+    pub(super) struct TestServer {
+        pub(super) url: String,
+        pub(super) token: String,
+        pub(super) data_dir: TempDir,
+        shutdown: ShutdownHandle,
+        thread: Option<JoinHandle<KevaCore>>,
+    }
+
+    impl TestServer {
+        pub(super) fn request(&self, method: &str, path: &str) -> ureq::Request {
+            let credentials = STANDARD.encode(format!("anyone:{}", self.token));
+            ureq::request(method, &format!("{}{path}", self.url))
+                .set("Authorization", &format!("Basic {credentials}"))
+        }
+
+        /// Returns the status code whether or not the request succeeded.
+        pub(super) fn status(&self, result: Result<ureq::Response, ureq::Error>) -> u16 {
+            match result {
+                Ok(response) => response.status(),
+                Err(ureq::Error::Status(code, _)) => code,
+                Err(e) => panic!("transport error: {e}"),
+            }
+        }
+
+        pub(super) fn put(&self, path: &str, body: &[u8]) -> u16 {
+            self.status(self.request("PUT", path).send_bytes(body))
+        }
+
+        pub(super) fn get(&self, path: &str) -> String {
+            self.request("GET", path)
+                .call()
+                .unwrap()
+                .into_string()
+                .unwrap()
+        }
+
+        /// Returns the `PROPFIND` response body.
+        pub(super) fn propfind(&self, path: &str, depth: &str) -> String {
+            let response = self
+                .request("PROPFIND", path)
+                .set("Depth", depth)
+                .call()
+                .unwrap();
+            assert_eq!(response.status(), 207);
+            response.into_string().unwrap()
+        }
+
+        pub(super) fn move_to(&self, from: &str, to: &str, overwrite: bool) -> u16 {
+            let result = self
+                .request("MOVE", from)
+                .set("Destination", &format!("{}{to}", self.url))
+                .set("Overwrite", if overwrite { "T" } else { "F" })
+                .call();
+            self.status(result)
+        }
+
+        /// Stops the server and returns the store, to check what requests did to it.
+        pub(super) fn into_keva(mut self) -> (KevaCore, TempDir) {
+            self.shutdown.shutdown();
+            let keva = self.thread.take().unwrap().join().unwrap();
+            let data_dir = TempDir::new().unwrap();
+            (keva, std::mem::replace(&mut self.data_dir, data_dir))
+        }
+    }
+
+    impl Drop for TestServer {
+        fn drop(&mut self) {
+            self.shutdown.shutdown();
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    pub(super) fn start_in(data_dir: TempDir) -> TestServer {
+        let keva = KevaCore::open(Config {
+            base_path: data_dir.path().to_path_buf(),
+        })
+        .unwrap();
+
+        let mut server = WebDavServer::bind(keva, WebDavConfig { port: 0 }).unwrap();
+        let url = format!("http://{}", server.local_addr());
+        let token = server.token().to_string();
+        let shutdown = server.shutdown_handle();
+        let thread = std::thread::spawn(move || {
+            server.run();
+            server.into_inner()
+        });
+
+        TestServer {
+            url,
+            token,
+            data_dir,
+            shutdown,
+            thread: Some(thread),
+        }
+    }
+
+    pub(super) fn start() -> TestServer {
+        start_in(TempDir::new().unwrap())
+    }
+
+    pub(super) fn key(s: &str) -> Key {
+        Key::try_from(s).unwrap()
+    }
+}
+
+mod auth {
+    use super::*;
+
+    #[test]
+    fn test_binds_to_localhost() {
+        let server = start();
+        assert!(server.url.starts_with("http://127.0.0.1:"));
+    }
+
+    #[test]
+    fn test_missing_or_wrong_password_is_rejected() {
+        let server = start();
+        let url = format!("{}/", server.url);
+
+        let response = ureq::request("PROPFIND", &url).call();
+        match response {
+            Err(ureq::Error::Status(401, response)) => {
+                assert_eq!(
+                    response.header("WWW-Authenticate"),
+                    Some("Basic realm=\"Keva\"")
+                );
+            }
+            other => panic!("expected 401, got {other:?}"),
+        }
+        // "user:nope"
+        let wrong = ureq::request("PROPFIND", &url)
+            .set("Authorization", "Basic dXNlcjpub3Bl")
+            .call();
+        assert_eq!(server.status(wrong), 401);
+        assert_eq!(server.status(server.request("PROPFIND", "/").call()), 207);
+    }
+
+    #[test]
+    fn test_password_is_stored_and_reused() {
+        let server = start();
+        let token_path = Config {
+            base_path: server.data_dir.path().to_path_buf(),
+        }
+        .webdav_token_path();
+        assert_eq!(std::fs::read_to_string(&token_path).unwrap(), server.token);
+
+        let data_dir = TempDir::new().unwrap();
+        std::fs::copy(&token_path, data_dir.path().join("webdav.token")).unwrap();
+        let restarted = common::start_in(data_dir);
+        assert_eq!(restarted.token, server.token);
+    }
+
+    #[test]
+    fn test_options_advertises_class_1() {
+        let server = start();
+        let response = server.request("OPTIONS", "/").call().unwrap();
+        assert_eq!(response.header("DAV"), Some("1"));
+        assert!(response.header("Allow").unwrap().contains("PROPFIND"));
+    }
+}
+
+mod listing {
+    use super::*;
+
+    #[test]
+    fn test_root_lists_directories_files_and_attachment_folders() {
+        let server = start();
+        assert_eq!(server.put("/project/notes.md", b"# Notes"), 201);
+        assert_eq!(server.put("/project/notes/todo.md", b"- [ ] a"), 201);
+        assert_eq!(server.put("/readme.md", b"hi"), 201);
+        assert_eq!(server.put("/readme.attachments/a.txt", b"x"), 201);
+
+        let root = server.propfind("/", "1");
+        assert!(root.contains("<D:href>/</D:href>"));
+        assert!(root.contains("<D:href>/project/</D:href>"));
+        assert!(root.contains("<D:href>/readme.md</D:href>"));
+        assert!(root.contains("<D:href>/readme.attachments/</D:href>"));
+        assert!(!root.contains("/project/notes.md"));
+
+        let project = server.propfind("/project/", "1");
+        assert!(project.contains("<D:href>/project/notes.md</D:href>"));
+        assert!(project.contains("<D:getcontentlength>7</D:getcontentlength>"));
+        assert!(project.contains("<D:href>/project/notes/</D:href>"));
+        // No attachments, so no folder.
+        assert!(!project.contains("notes.attachments"));
+
+        let attachments = server.propfind("/readme.attachments/", "1");
+        assert!(attachments.contains("<D:href>/readme.attachments/a.txt</D:href>"));
+    }
+
+    #[test]
+    fn test_depth_zero_lists_only_the_resource() {
+        let server = start();
+        server.put("/a.md", b"");
+
+        let root = server.propfind("/", "0");
+        assert!(root.contains("<D:collection/>"));
+        assert!(!root.contains("a.md"));
+
+        let file = server.propfind("/a.md", "0");
+        assert!(file.contains("<D:resourcetype/>"));
+        assert!(file.contains("<D:getlastmodified>"));
+    }
+
+    #[test]
+    fn test_names_are_percent_encoded() {
+        let server = start();
+        assert_eq!(server.put("/my%20notes/caf%C3%A9.md", b""), 201);
+
+        let dir = server.propfind("/my%20notes/", "1");
+        assert!(dir.contains("<D:href>/my%20notes/caf%C3%A9.md</D:href>"));
+        assert_eq!(server.get("/my%20notes/caf%C3%A9.md"), "");
+    }
+
+    #[test]
+    fn test_missing_and_trashed_are_not_found() {
+        let server = start();
+        server.put("/gone.md", b"");
+        server.request("DELETE", "/gone.md").call().unwrap();
+
+        for path in ["/gone.md", "/nowhere/", "/gone.attachments/"] {
+            let result = server.request("PROPFIND", path).set("Depth", "0").call();
+            assert_eq!(server.status(result), 404, "{path}");
+        }
+        assert!(!server.propfind("/", "1").contains("gone"));
+    }
+}
+
+mod content {
+    use super::*;
+
+    #[test]
+    fn test_put_creates_writes_and_touches() {
+        let server = start();
+        assert_eq!(server.put("/note.md", b"first"), 201);
+        assert_eq!(server.put("/note.md", b"# Title\nbody"), 204);
+
+        let response = server.request("GET", "/note.md").call().unwrap();
+        assert_eq!(response.content_type(), "text/markdown");
+        assert!(response.header("Last-Modified").is_some());
+        assert_eq!(response.into_string().unwrap(), "# Title\nbody");
+
+        let (keva, _dir) = server.into_keva();
+        let value = keva.get(&key("note")).unwrap().unwrap();
+        assert_eq!(value.metadata.access_count, 2);
+    }
+
+    #[test]
+    fn test_head_has_no_body() {
+        let server = start();
+        server.put("/note.md", b"hello");
+
+        let response = server.request("HEAD", "/note.md").call().unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.into_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_put_into_trashed_key_conflicts() {
+        let server = start();
+        server.put("/note.md", b"");
+        server.request("DELETE", "/note.md").call().unwrap();
+        assert_eq!(server.put("/note.md", b"again"), 409);
+    }
+
+    #[test]
+    fn test_put_outside_md_files_is_refused() {
+        let server = start();
+        assert_eq!(server.put("/note.txt", b""), 403);
+        assert_eq!(server.put("/.md", b""), 400);
+    }
+
+    #[test]
+    fn test_delete_trashes_key() {
+        let server = start();
+        server.put("/note.md", b"");
+        assert_eq!(
+            server.status(server.request("DELETE", "/note.md").call()),
+            204
+        );
+
+        let (keva, _dir) = server.into_keva();
+        let value = keva.get(&key("note")).unwrap().unwrap();
+        assert!(matches!(
+            value.metadata.lifecycle_state,
+            LifecycleState::Trash { .. }
+        ));
+    }
+
+    #[test]
+    fn test_delete_directory_trashes_subtree() {
+        let server = start();
+        server.put("/dir/a.md", b"");
+        server.put("/dir/sub/b.md", b"");
+        server.put("/other.md", b"");
+        assert_eq!(server.status(server.request("DELETE", "/dir/").call()), 204);
+
+        let (keva, _dir) = server.into_keva();
+        assert_eq!(keva.active_keys().unwrap(), vec![key("other")]);
+        assert_eq!(keva.trashed_keys().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_move_renames_key() {
+        let server = start();
+        server.put("/old.md", b"body");
+        assert_eq!(server.move_to("/old.md", "/dir/new.md", false), 201);
+        assert_eq!(server.get("/dir/new.md"), "body");
+
+        let (keva, _dir) = server.into_keva();
+        assert_eq!(keva.active_keys().unwrap(), vec![key("dir/new")]);
+    }
+
+    #[test]
+    fn test_move_respects_overwrite() {
+        let server = start();
+        server.put("/a.md", b"a");
+        server.put("/b.md", b"b");
+
+        assert_eq!(server.move_to("/a.md", "/b.md", false), 412);
+        assert_eq!(server.move_to("/a.md", "/b.md", true), 204);
+        assert_eq!(server.get("/b.md"), "a");
+
+        let (mut keva, _dir) = server.into_keva();
+        assert_eq!(keva.active_keys().unwrap(), vec![key("b")]);
+
+        // The replaced key comes back with the same undo as the rename
+        keva.undo(std::time::SystemTime::now()).unwrap();
+        let mut keys = keva.active_keys().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec![key("a"), key("b")]);
+        assert_eq!(keva.next_undo(), None);
+    }
+
+    #[test]
+    fn test_move_directory_renames_subtree() {
+        let server = start();
+        server.put("/dir/a.md", b"");
+        server.put("/dir/sub/b.md", b"");
+        assert_eq!(server.move_to("/dir/", "/moved/", false), 201);
+
+        let (keva, _dir) = server.into_keva();
+        assert_eq!(
+            keva.active_keys().unwrap(),
+            vec![key("moved/a"), key("moved/sub/b")]
+        );
+    }
+}
+
+mod attachments {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_upload_download_delete() {
+        let server = start();
+        server.put("/note.md", b"");
+        assert_eq!(
+            server.put("/note.attachments/my%20file.bin", &[0, 1, 255]),
+            201
+        );
+        assert_eq!(server.put("/note.attachments/my%20file.bin", &[7]), 204);
+
+        let response = server
+            .request("GET", "/note.attachments/my%20file.bin")
+            .call()
+            .unwrap();
+        assert_eq!(response.header("Content-Length"), Some("1"));
+        let mut bytes = Vec::new();
+        response.into_reader().read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, [7]);
+
+        let delete = server
+            .request("DELETE", "/note.attachments/my%20file.bin")
+            .call();
+        assert_eq!(server.status(delete), 204);
+        let download = server
+            .request("GET", "/note.attachments/my%20file.bin")
+            .call();
+        assert_eq!(server.status(download), 404);
+    }
+
+    #[test]
+    fn test_content_type_is_sniffed() {
+        let server = start();
+        server.put("/note.md", b"");
+        server.put("/note.attachments/logo.dat", b"<svg xmlns=\"\"/>");
+
+        let listing = server.propfind("/note.attachments/", "1");
+        assert!(listing.contains("<D:getcontenttype>image/svg+xml</D:getcontenttype>"));
+        let response = server
+            .request("GET", "/note.attachments/logo.dat")
+            .call()
+            .unwrap();
+        assert_eq!(response.content_type(), "image/svg+xml");
+    }
+
+    #[test]
+    fn test_upload_needs_an_active_key() {
+        let server = start();
+        assert_eq!(server.put("/missing.attachments/a.txt", b"x"), 409);
+    }
+
+    #[test]
+    fn test_hidden_files_are_refused() {
+        let server = start();
+        server.put("/note.md", b"");
+        assert_eq!(server.put("/note.attachments/._a.txt", b"x"), 403);
+        assert_eq!(server.put("/note.attachments/.DS_Store", b"x"), 403);
+    }
+
+    #[test]
+    fn test_invalid_names_are_refused() {
+        let server = start();
+        server.put("/note.md", b"");
+        assert_eq!(server.put("/note.attachments/nul.txt", b"x"), 403);
+        assert_eq!(server.put("/note.attachments/a%3Ab.txt", b"x"), 403);
+        assert_eq!(server.put("/note.attachments/a.txt", b"x"), 201);
+        assert_eq!(
+            server.move_to("/note.attachments/a.txt", "/note.attachments/CON", false),
+            403
+        );
+    }
+
+    #[test]
+    fn test_mkcol_accepts_attachment_folders_only() {
+        let server = start();
+        server.put("/note.md", b"");
+
+        let mkcol = |path: &str| server.status(server.request("MKCOL", path).call());
+        assert_eq!(mkcol("/note.attachments/"), 201);
+        assert_eq!(mkcol("/missing.attachments/"), 409);
+        assert_eq!(mkcol("/newdir/"), 403);
+    }
+
+    #[test]
+    fn test_move_renames_within_key() {
+        let server = start();
+        server.put("/note.md", b"");
+        server.put("/note.attachments/a.txt", b"x");
+        assert_eq!(
+            server.move_to("/note.attachments/a.txt", "/note.attachments/b.txt", false),
+            201
+        );
+
+        let (keva, _dir) = server.into_keva();
+        let value = keva.get(&key("note")).unwrap().unwrap();
+        let names: Vec<_> = value
+            .attachments
+            .iter()
+            .map(|a| a.filename.as_str())
+            .collect();
+        assert_eq!(names, ["b.txt"]);
+    }
+
+    #[test]
+    fn test_move_over_attachment_is_one_undo() {
+        let server = start();
+        server.put("/note.md", b"");
+        server.put("/note.attachments/a.txt", b"moved");
+        server.put("/note.attachments/b.txt", b"older");
+        assert_eq!(
+            server.move_to("/note.attachments/a.txt", "/note.attachments/b.txt", true),
+            204
+        );
+        assert_eq!(server.get("/note.attachments/b.txt"), "moved");
+
+        let (mut keva, _dir) = server.into_keva();
+        keva.undo(std::time::SystemTime::now()).unwrap();
+        let mut a = String::new();
+        keva.open_attachment(&key("note"), "a.txt")
+            .unwrap()
+            .read_to_string(&mut a)
+            .unwrap();
+        let mut b = String::new();
+        keva.open_attachment(&key("note"), "b.txt")
+            .unwrap()
+            .read_to_string(&mut b)
+            .unwrap();
+        assert_eq!((a.as_str(), b.as_str()), ("moved", "older"));
+    }
+
+    #[test]
+    fn test_move_to_another_key() {
+        let server = start();
+        server.put("/a.md", b"");
+        server.put("/b.md", b"");
+        server.put("/a.attachments/doc.txt", b"payload");
+        assert_eq!(
+            server.move_to("/a.attachments/doc.txt", "/b.attachments/doc.txt", false),
+            201
+        );
+        assert_eq!(server.get("/b.attachments/doc.txt"), "payload");
+
+        let (keva, _dir) = server.into_keva();
+        assert!(keva.get(&key("a")).unwrap().unwrap().attachments.is_empty());
+    }
+
+    #[test]
+    fn test_move_between_kinds_is_refused() {
+        let server = start();
+        server.put("/note.md", b"");
+        server.put("/note.attachments/a.txt", b"x");
+        assert_eq!(
+            server.move_to("/note.attachments/a.txt", "/a.md", false),
+            403
+        );
+    }
+}
+
+mod paths {
+    use super::*;
+    use crate::resource::Resource;
+
+    #[test]
+    fn test_parses_each_kind() {
+        assert_eq!(Resource::parse("/"), Some(Resource::Dir(String::new())));
+        assert_eq!(
+            Resource::parse("/a/b/"),
+            Some(Resource::Dir("a/b".to_string()))
+        );
+        assert_eq!(
+            Resource::parse("/a/b.md"),
+            Some(Resource::Content(key("a/b")))
+        );
+        assert_eq!(
+            Resource::parse("/a/b.attachments"),
+            Some(Resource::Attachments(key("a/b")))
+        );
+        assert_eq!(
+            Resource::parse("/a/b.attachments/c.md"),
+            Some(Resource::Attachment(key("a/b"), "c.md".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_rejects_unrepresentable_paths() {
+        assert_eq!(Resource::parse("/a//b.md"), None);
+        assert_eq!(Resource::parse("/../b.md"), None);
+        assert_eq!(Resource::parse("/%20b.md"), None);
+        assert_eq!(Resource::parse("/%zz.md"), None);
+    }
+
+    #[test]
+    fn test_href_roundtrips() {
+        for resource in [
+            Resource::Dir("a b/c".to_string()),
+            Resource::Content(key("dir/naïve #1")),
+            Resource::Attachment(key("x"), "100% done.txt".to_string()),
+        ] {
+            assert_eq!(Resource::parse(&resource.href()), Some(resource));
+        }
+    }
+
+    #[test]
+    fn test_destination_accepts_urls_and_paths() {
+        let expected = Some(Resource::Content(key("a")));
+        assert_eq!(
+            Resource::parse_destination("http://127.0.0.1:7691/a.md"),
+            expected
+        );
+        assert_eq!(Resource::parse_destination("/a.md"), expected);
+    }
+}
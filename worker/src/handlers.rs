@@ -0,0 +1,439 @@
+//! Request handlers.
+
+use crate::{
+    AttachmentInfo, ExactMatch, RenameResultType, Response, ResponseSink, Thumbnail, Worker,
+    touch_key,
+};
+use keva_core::core::KevaCore;
+use keva_core::core::error::KevaError;
+use keva_core::types::{
+    AttachmentConflictResolution, AttachmentName, Key, LifecycleState, ThumbnailPreset,
+};
+use keva_search::SearchQuery;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Thumbnails shown next to attachment names. Scale 2 keeps them sharp on high-DPI displays;
+/// the frontend downscales on others.
+const THUMBNAIL_PRESET: ThumbnailPreset = ThumbnailPreset::ListIcon;
+const THUMBNAIL_SCALE: u32 = 2;
+
+/// Value operations.
+impl<S: ResponseSink> Worker<S> {
+    pub(crate) fn handle_get_value(&mut self, key_str: &str) {
+        let Some((value, read_only, key)) = (|| {
+            let now = SystemTime::now();
+            let key = Key::try_from(key_str).ok()?;
+            let value = self.keva.get(&key).ok().flatten()?;
+            let read_only = matches!(value.metadata.lifecycle_state, LifecycleState::Trash { .. });
+            if !read_only {
+                let _ = self.touch(&key, now);
+            }
+            Some((value, read_only, key))
+        })() else {
+            return;
+        };
+
+        let key_hash = KevaCore::key_to_path(&key).to_string_lossy().into_owned();
+
+        let content = match self.read_content(&key) {
+            Ok(content) => content,
+            Err(e) => {
+                self.toast(format!("Failed to read '{key}': {e}"));
+                return;
+            }
+        };
+
+        let mut thumbnail_paths = self
+            .keva
+            .thumbnail_paths(&key, THUMBNAIL_PRESET, THUMBNAIL_SCALE)
+            .unwrap_or_default();
+        let attachments = value
+            .attachments
+            .into_iter()
+            .map(|att| AttachmentInfo {
+                thumbnail: thumbnail_paths
+                    .remove(&att.filename)
+                    .and_then(|path| self.read_thumbnail(&key, &att.filename, &path)),
+                dimensions: att.image.map(|image| (image.width, image.height)),
+                filename: att.filename,
+                size: att.size,
+            })
+            .collect();
+
+        self.sink.send(Response::Value {
+            key: key_str.to_string(),
+            key_hash,
+            content,
+            read_only,
+            attachments,
+        });
+    }
+
+    fn read_content(&self, key: &Key) -> Result<String, Box<dyn std::error::Error>> {
+        let mut content = String::new();
+        self.keva.open_content(key)?.read_to_string(&mut content)?;
+        Ok(content)
+    }
+
+    /// Reads the thumbnail `thumbnail_paths` returned as `path`. Missing or unreadable thumbnails
+    /// are left out, like ones still rendering.
+    fn read_thumbnail(&self, key: &Key, filename: &str, path: &Path) -> Option<Thumbnail> {
+        let mut bytes = Vec::new();
+        self.keva
+            .open_thumbnail(key, filename, THUMBNAIL_PRESET, THUMBNAIL_SCALE)
+            .ok()?
+            .read_to_end(&mut bytes)
+            .ok()?;
+        Some(Thumbnail {
+            extension: path.extension()?.to_string_lossy().into_owned(),
+            bytes,
+        })
+    }
+
+    pub(crate) fn handle_save(&mut self, key_str: &str, content: &str) {
+        let save_failed = |message: String| Response::SaveFailed {
+            key: key_str.to_string(),
+            message,
+        };
+
+        let Ok(key) = Key::try_from(key_str) else {
+            self.sink
+                .send(save_failed(format!("Invalid key: '{key_str}'")));
+            return;
+        };
+
+        let written = (|| -> Result<(), Box<dyn std::error::Error>> {
+            let mut writer = self.keva.write_content(&key)?;
+            writer.write_all(content.as_bytes())?;
+            writer.finish()?;
+            Ok(())
+        })();
+        if let Err(e) = written {
+            self.sink.send(save_failed(format!("Write failed: {e}")));
+            return;
+        }
+
+        if let Err(e) = self.touch(&key, SystemTime::now()) {
+            // Content was saved but timestamp update failed - just log, don't show error
+            eprintln!("Warning: failed to update timestamp for '{key_str}': {e}");
+        }
+    }
+
+    pub(crate) fn handle_touch(&mut self, key_str: &str) {
+        if let Ok(key) = Key::try_from(key_str) {
+            let _ = self.touch(&key, SystemTime::now());
+        }
+    }
+
+    fn touch(&mut self, key: &Key, now: SystemTime) -> Result<(), KevaError> {
+        touch_key(&mut self.keva, &mut self.search, key, now).map(|_| ())
+    }
+}
+
+/// Attachment operations.
+impl<S: ResponseSink> Worker<S> {
+    pub(crate) fn handle_files_selected(&mut self, key_str: &str, files: Vec<PathBuf>) {
+        let files = files
+            .into_iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+
+        self.sink.send(Response::FilesSelected {
+            key: key_str.to_string(),
+            files,
+        });
+    }
+
+    /// Adds `files` as (source_path, target_filename, resolution) triples. `what` names them in
+    /// error toasts.
+    pub(crate) fn handle_add_files(
+        &mut self,
+        key_str: &str,
+        files: Vec<(PathBuf, String, AttachmentConflictResolution)>,
+        what: &str,
+    ) {
+        let Ok(key) = Key::try_from(key_str) else {
+            self.toast(format!("Failed to add {what}: invalid key"));
+            return;
+        };
+        let files = match files
+            .into_iter()
+            .map(|(path, name, resolution)| {
+                AttachmentName::try_from(name.as_str())
+                    .map(|valid| (path, valid, resolution))
+                    .map_err(|e| format!("'{name}': {e}"))
+            })
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(files) => files,
+            Err(e) => {
+                self.toast(format!("Failed to add {what}: invalid file name {e}"));
+                return;
+            }
+        };
+
+        let added = match self.keva.add_attachments(&key, files, SystemTime::now()) {
+            Ok(added) => added,
+            Err(e) => {
+                self.toast(format!("Failed to add {what}: {e}"));
+                return;
+            }
+        };
+
+        self.sink.send(Response::AttachmentsAdded {
+            key: key_str.to_string(),
+            filenames: added.into_iter().flatten().map(String::from).collect(),
+        });
+        self.handle_get_value(key_str);
+    }
+
+    pub(crate) fn handle_remove_attachment(&mut self, key_str: &str, filename: &str) {
+        let Ok(key) = Key::try_from(key_str) else {
+            self.toast("Failed to remove attachment: invalid key".to_string());
+            return;
+        };
+
+        if let Err(e) = self
+            .keva
+            .remove_attachment(&key, filename, SystemTime::now())
+        {
+            self.toast(format!("Failed to remove '{filename}': {e}"));
+            return;
+        }
+
+        self.handle_get_value(key_str);
+    }
+
+    pub(crate) fn handle_thumbnail_ready(&mut self, key_str: &str, filename: &str) {
+        let Ok(key) = Key::try_from(key_str) else {
+            return;
+        };
+        // Nothing to show if rendering failed or the attachment is gone by now.
+        let Some(thumbnail) = self
+            .keva
+            .thumbnail_paths(&key, THUMBNAIL_PRESET, THUMBNAIL_SCALE)
+            .ok()
+            .and_then(|mut paths| paths.remove(filename))
+            .and_then(|path| self.read_thumbnail(&key, filename, &path))
+        else {
+            return;
+        };
+
+        self.sink.send(Response::ThumbnailReady {
+            key: key_str.to_string(),
+            filename: filename.to_string(),
+            thumbnail,
+        });
+    }
+
+    pub(crate) fn handle_rename_attachment(
+        &mut self,
+        key_str: &str,
+        old_filename: &str,
+        new_filename: &str,
+        force: bool,
+    ) {
+        let Ok(key) = Key::try_from(key_str) else {
+            self.toast("Failed to rename attachment: invalid key".to_string());
+            return;
+        };
+        let new_name = match AttachmentName::try_from(new_filename) {
+            Ok(name) => name,
+            Err(e) => {
+                self.toast(format!("Failed to rename '{old_filename}': {e}"));
+                return;
+            }
+        };
+
+        let now = SystemTime::now();
+        let result = if force {
+            self.keva
+                .rename_attachment_replacing(&key, old_filename, &new_name, now)
+        } else {
+            self.keva
+                .rename_attachment(&key, old_filename, &new_name, now)
+        };
+        if let Err(e) = result {
+            self.toast(format!("Failed to rename '{old_filename}': {e}"));
+            return;
+        }
+
+        self.handle_get_value(key_str);
+    }
+
+    fn toast(&self, message: String) {
+        self.sink.send(Response::Toast { message });
+    }
+}
+
+/// Key lifecycle operations.
+impl<S: ResponseSink> Worker<S> {
+    pub(crate) fn handle_create(&mut self, key_str: &str) {
+        let success = self.try_create(key_str).is_some();
+
+        self.sink.send(Response::KeyCreated {
+            key: key_str.to_string(),
+            success,
+        });
+
+        if success {
+            self.refresh_search();
+        }
+    }
+
+    fn try_create(&mut self, key_str: &str) -> Option<()> {
+        let key = Key::try_from(key_str).ok()?;
+        self.keva.create(&key, SystemTime::now()).ok()?;
+        self.search.add_active(key);
+        Some(())
+    }
+
+    pub(crate) fn handle_rename(&mut self, old_key_str: &str, new_key_str: &str, force: bool) {
+        let result = self.try_rename(old_key_str, new_key_str, force);
+        self.sink.send(Response::RenameResult {
+            old_key: old_key_str.to_string(),
+            new_key: new_key_str.to_string(),
+            result: result.unwrap_or_else(|e| e),
+        });
+    }
+
+    fn try_rename(
+        &mut self,
+        old_key_str: &str,
+        new_key_str: &str,
+        force: bool,
+    ) -> Result<RenameResultType, RenameResultType> {
+        let old_key = Key::try_from(old_key_str).map_err(|_| RenameResultType::InvalidKey)?;
+        let new_key = Key::try_from(new_key_str).map_err(|_| RenameResultType::InvalidKey)?;
+
+        let replaced = self.keva.get(&new_key).ok().flatten().is_some();
+        if replaced && !force {
+            return Err(RenameResultType::DestinationExists);
+        }
+
+        let now = SystemTime::now();
+        let renamed = if replaced {
+            self.keva.rename_replacing(&old_key, &new_key, now)
+        } else {
+            self.keva.rename(&old_key, &new_key, now)
+        };
+        renamed.map_err(|_| RenameResultType::NotFound)?;
+        if replaced {
+            self.search.remove(&new_key);
+        }
+        self.search.rename(&old_key, new_key);
+        Ok(RenameResultType::Success)
+    }
+
+    pub(crate) fn handle_trash(&mut self, key_str: &str) {
+        if let Ok(key) = Key::try_from(key_str)
+            && self.keva.trash(&key, SystemTime::now()).is_ok()
+        {
+            self.search.trash(&key);
+            self.refresh_search();
+        }
+    }
+
+    pub(crate) fn handle_restore(&mut self, key_str: &str) {
+        if let Ok(key) = Key::try_from(key_str)
+            && self.keva.restore(&key, SystemTime::now()).is_ok()
+        {
+            self.search.restore(&key);
+            self.refresh_search();
+        }
+    }
+
+    pub(crate) fn handle_purge(&mut self, key_str: &str) {
+        if let Ok(key) = Key::try_from(key_str)
+            && self.keva.purge(&key).is_ok()
+        {
+            self.search.remove(&key);
+            self.refresh_search();
+        }
+    }
+}
+
+/// Maintenance operations.
+impl<S: ResponseSink> Worker<S> {
+    pub(crate) fn handle_maintenance(&mut self, force: bool) {
+        let now = SystemTime::now();
+
+        if !force
+            && !self
+                .keva
+                .should_run_maintenance(now, crate::MAINTENANCE_INTERVAL)
+        {
+            return;
+        }
+
+        let Ok(outcome) = self.keva.maintenance(now, self.gc_config) else {
+            return;
+        };
+
+        // Update search engine with auto-trashed and purged keys
+        for key in &outcome.keys_trashed {
+            self.search.trash(key);
+        }
+        for key in &outcome.keys_purged {
+            self.search.remove(key);
+        }
+
+        if !outcome.keys_trashed.is_empty() || !outcome.keys_purged.is_empty() {
+            self.refresh_search();
+        }
+    }
+}
+
+/// Search operations.
+impl<S: ResponseSink> Worker<S> {
+    pub(crate) fn handle_search(&mut self, query: String) {
+        self.current_query = query.clone();
+        self.search.set_query(SearchQuery::Fuzzy(query));
+        self.search.tick();
+    }
+
+    /// Re-runs the current query after the key set changed and sends the results.
+    fn refresh_search(&mut self) {
+        self.search
+            .set_query(SearchQuery::Fuzzy(self.current_query.clone()));
+        self.search.tick();
+        self.send_search_results();
+    }
+
+    pub(crate) fn send_search_results(&self) {
+        let active_keys = self
+            .search
+            .active_results()
+            .iter()
+            .map(|k| k.as_str().to_string())
+            .collect();
+
+        let trashed_keys = self
+            .search
+            .trashed_results()
+            .iter()
+            .map(|k| k.as_str().to_string())
+            .collect();
+
+        let exact_match = Key::try_from(self.current_query.as_str())
+            .ok()
+            .map(|key| {
+                if self.search.has_active(&key) {
+                    ExactMatch::Active
+                } else if self.search.has_trashed(&key) {
+                    ExactMatch::Trashed
+                } else {
+                    ExactMatch::None
+                }
+            })
+            .unwrap_or(ExactMatch::None);
+
+        self.sink.send(Response::SearchResults {
+            active_keys,
+            trashed_keys,
+            exact_match,
+        });
+    }
+}
@@ -0,0 +1,251 @@
+//! Platform-neutral request handling for Keva frontends.
+//!
+//! A frontend spawns a thread that owns a [`Worker`], sends it [`Request`]s over a channel and
+//! receives [`Response`]s through its [`ResponseSink`]. The worker owns `KevaCore` and
+//! `SearchEngine`, keeps the search index in step with key mutations and runs scheduled
+//! maintenance. Window handles, dialogs and message posting stay in the platform shell.
+
+mod handlers;
+mod request;
+mod response;
+
+pub use request::Request;
+pub use response::{AttachmentInfo, ExactMatch, RenameResultType, Response, Thumbnail};
+
+use keva_core::core::KevaCore;
+use keva_core::core::error::KevaError;
+use keva_core::types::{GcConfig, Key, Value};
+use keva_search::{Ranking, SearchConfig, SearchEngine, SearchQuery};
+use std::io;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime};
+
+/// 24 hours interval for periodic maintenance check.
+pub const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Delivers responses to the frontend.
+pub trait ResponseSink {
+    fn send(&self, response: Response);
+
+    /// Whether the UI is on screen. Scheduled maintenance is skipped while it is, to avoid jank.
+    fn is_visible(&self) -> bool;
+}
+
+pub struct Worker<S: ResponseSink> {
+    keva: KevaCore,
+    search: SearchEngine,
+    gc_config: GcConfig,
+    welcome_shown: bool,
+    index_path: PathBuf,
+    current_query: String,
+    next_maintenance: Instant,
+    sink: S,
+}
+
+impl<S: ResponseSink> Worker<S> {
+    /// Creates a worker. `index_path` is where the search index is saved on shutdown.
+    pub fn new(
+        keva: KevaCore,
+        search: SearchEngine,
+        gc_config: GcConfig,
+        welcome_shown: bool,
+        index_path: PathBuf,
+        sink: S,
+    ) -> Self {
+        Self {
+            keva,
+            search,
+            gc_config,
+            welcome_shown,
+            index_path,
+            current_query: String::new(),
+            next_maintenance: Instant::now() + MAINTENANCE_INTERVAL,
+            sink,
+        }
+    }
+
+    /// Handles requests until [`Request::Shutdown`] or until every sender is dropped.
+    ///
+    /// Runs maintenance on start if it is due, then every [`MAINTENANCE_INTERVAL`] while the
+    /// UI is hidden.
+    pub fn run(mut self, requests: Receiver<Request>) {
+        // Set empty query to trigger initial SearchResults
+        self.search.set_query(SearchQuery::Fuzzy(String::new()));
+
+        self.handle_maintenance(false);
+        self.next_maintenance = Instant::now() + MAINTENANCE_INTERVAL;
+
+        loop {
+            let timeout = self
+                .next_maintenance
+                .saturating_duration_since(Instant::now());
+            match requests.recv_timeout(timeout) {
+                Ok(request) => {
+                    if self.handle(request).is_break() {
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if !self.sink.is_visible() {
+                        self.handle_maintenance(false);
+                    }
+                    self.next_maintenance = Instant::now() + MAINTENANCE_INTERVAL;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+
+    /// Handles one request. Returns `Break` once the worker has shut down.
+    pub fn handle(&mut self, request: Request) -> ControlFlow<()> {
+        match request {
+            Request::WebviewReady => {
+                self.sink.send(Response::CoreReady);
+                if !self.welcome_shown {
+                    self.sink.send(Response::ShowWelcome);
+                }
+            }
+            Request::GetValue { key } => self.handle_get_value(&key),
+            Request::Save { key, content } => self.handle_save(&key, &content),
+            Request::Create { key } => self.handle_create(&key),
+            Request::Rename {
+                old_key,
+                new_key,
+                force,
+            } => self.handle_rename(&old_key, &new_key, force),
+            Request::Trash { key } => self.handle_trash(&key),
+            Request::Restore { key } => self.handle_restore(&key),
+            Request::Purge { key } => self.handle_purge(&key),
+            Request::Search { query } => self.handle_search(query),
+            Request::SearchTick => {
+                if self.search.tick() {
+                    self.send_search_results();
+                }
+            }
+            Request::Touch { key } => self.handle_touch(&key),
+            Request::FilesSelected { key, files } => self.handle_files_selected(&key, files),
+            Request::AddAttachments { key, files } => {
+                let files = files
+                    .into_iter()
+                    .map(|(path, name, resolution)| (PathBuf::from(path), name, resolution))
+                    .collect();
+                self.handle_add_files(&key, files, "attachments");
+            }
+            Request::RemoveAttachment { key, filename } => {
+                self.handle_remove_attachment(&key, &filename)
+            }
+            Request::RenameAttachment {
+                key,
+                old_filename,
+                new_filename,
+                force,
+            } => self.handle_rename_attachment(&key, &old_filename, &new_filename, force),
+            Request::ThumbnailReady { key, filename } => {
+                self.handle_thumbnail_ready(&key, &filename)
+            }
+            Request::AddFiles { key, files } => self.handle_add_files(&key, files, "files"),
+            Request::Maintenance { force } => {
+                self.handle_maintenance(force);
+                // Reset timer after any maintenance (manual or scheduled)
+                self.next_maintenance = Instant::now() + MAINTENANCE_INTERVAL;
+            }
+            Request::UpdateGcConfig { lifecycle } => {
+                self.gc_config = GcConfig::from(&lifecycle);
+            }
+            Request::Shutdown => {
+                save_search_index(&self.keva, &self.search, &self.index_path);
+                self.sink.send(Response::ShutdownComplete);
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Loads the persisted search index, or rebuilds it from the store if it is missing or stale.
+/// Encrypted stores always rebuild it (see [`save_search_index`]).
+///
+/// Active results are ranked by frecency; a rebuilt index reads the access stats from the store.
+/// `notify` should make the worker receive [`Request::SearchTick`].
+pub fn load_search_engine(
+    keva: &KevaCore,
+    index_path: &Path,
+    notify: Arc<dyn Fn() + Send + Sync>,
+) -> SearchEngine {
+    let config = SearchConfig {
+        ranking: Ranking::Frecency,
+        ..SearchConfig::default()
+    };
+    if !keva.is_encrypted()
+        && let Ok(generation) = keva.generation()
+        && let Some(search) =
+            SearchEngine::load(index_path, generation, config.clone(), notify.clone())
+    {
+        return search;
+    }
+
+    let active_keys = keva.active_keys().unwrap_or_default();
+    let trashed_keys = keva.trashed_keys().unwrap_or_default();
+    let mut search = SearchEngine::new(active_keys, trashed_keys, config, notify);
+    search.load_access_stats(keva.access_stats().unwrap_or_default());
+    search
+}
+
+/// Touches a key and passes its new access stats on to the search engine's frecency ranking.
+pub fn touch_key(
+    keva: &mut KevaCore,
+    search: &mut SearchEngine,
+    key: &Key,
+    now: SystemTime,
+) -> Result<Value, KevaError> {
+    let value = keva.touch(key, now)?;
+    if let Some(stats) = value.metadata.access_stats() {
+        search.set_access_stats(key.clone(), stats);
+    }
+    Ok(value)
+}
+
+/// Saves the search index for the store's current generation, so the next
+/// [`load_search_engine`] doesn't have to rebuild it. Failures are only reported.
+///
+/// Encrypted stores get no snapshot, since it would hold key names and access stats in
+/// plaintext; any left from before is removed.
+pub fn save_search_index(keva: &KevaCore, search: &SearchEngine, index_path: &Path) {
+    if keva.is_encrypted() {
+        if let Err(e) = std::fs::remove_file(index_path)
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            eprintln!("Warning: failed to remove search index: {e}");
+        }
+        return;
+    }
+    let Ok(generation) = keva.generation() else {
+        return;
+    };
+    if let Err(e) = search.save(index_path, generation) {
+        eprintln!("Warning: failed to save search index: {e}");
+    }
+}
+
+/// Calls `shutdown` once the process receives Ctrl+C or SIGTERM, for servers to stop serving and
+/// save their state instead of being killed.
+pub fn shutdown_on_signal(shutdown: impl FnOnce() + Send + 'static) -> io::Result<()> {
+    let signalled = Arc::new(AtomicBool::new(false));
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        signal_hook::flag::register(signal, signalled.clone())?;
+    }
+    std::thread::spawn(move || {
+        while !signalled.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        shutdown();
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
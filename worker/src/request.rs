@@ -0,0 +1,89 @@
+//! Requests from a frontend to the worker.
+
+use keva_core::types::{AttachmentConflictResolution, LifecycleConfig};
+use std::path::PathBuf;
+
+pub enum Request {
+    /// Frontend is ready - respond with CoreReady after init is done.
+    WebviewReady,
+    GetValue {
+        key: String,
+    },
+    Save {
+        key: String,
+        content: String,
+    },
+    Create {
+        key: String,
+    },
+    Rename {
+        old_key: String,
+        new_key: String,
+        force: bool,
+    },
+    Trash {
+        key: String,
+    },
+    Restore {
+        key: String,
+    },
+    Purge {
+        key: String,
+    },
+    Search {
+        query: String,
+    },
+    /// Sent by the search engine's notify callback when results may have changed.
+    SearchTick,
+    /// Update timestamp after content save done by the frontend itself.
+    Touch {
+        key: String,
+    },
+    /// Files selected from picker - send to frontend for conflict check.
+    FilesSelected {
+        key: String,
+        files: Vec<PathBuf>,
+    },
+    /// Add attachments with target filenames from frontend.
+    AddAttachments {
+        key: String,
+        /// (source_path, target_filename, resolution if the name is taken)
+        files: Vec<(String, String, AttachmentConflictResolution)>,
+    },
+    /// Remove an attachment from a key.
+    RemoveAttachment {
+        key: String,
+        filename: String,
+    },
+    /// Rename an attachment.
+    RenameAttachment {
+        key: String,
+        old_filename: String,
+        new_filename: String,
+        /// If true, overwrite existing file with same name.
+        force: bool,
+    },
+    /// Sent by the store's thumbnail callback when an attachment's thumbnail is rendered or has
+    /// failed; see `KevaCore::set_thumbnail_callback`.
+    ThumbnailReady {
+        key: String,
+        filename: String,
+    },
+    /// Add files from drop or clipboard.
+    AddFiles {
+        key: String,
+        /// (source_path, target_filename, resolution if the name is taken)
+        files: Vec<(PathBuf, String, AttachmentConflictResolution)>,
+    },
+    /// Run maintenance (GC and orphan cleanup).
+    /// If force is false, only runs if should_run_maintenance returns true.
+    Maintenance {
+        force: bool,
+    },
+    /// Update GC configuration (TTL settings changed in settings dialog).
+    UpdateGcConfig {
+        lifecycle: LifecycleConfig,
+    },
+    /// Save the search index, respond with ShutdownComplete and stop.
+    Shutdown,
+}
@@ -0,0 +1,100 @@
+//! Responses from the worker to a frontend.
+
+use serde::Serialize;
+
+/// Attachment metadata for display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachmentInfo {
+    pub filename: String,
+    pub size: u64,
+    /// The attachment's thumbnail, if one exists.
+    pub thumbnail: Option<Thumbnail>,
+    /// Width and height of image attachments, as displayed.
+    pub dimensions: Option<(u32, u32)>,
+}
+
+/// A thumbnail read through the store, so it is decrypted in encrypted stores.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    /// File extension of the image format, such as `png`.
+    pub extension: String,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// Core is ready. Hide splash screen.
+    CoreReady,
+    /// Show welcome dialog on first run.
+    ShowWelcome,
+    KeyCreated {
+        key: String,
+        success: bool,
+    },
+    SearchResults {
+        active_keys: Vec<String>,
+        trashed_keys: Vec<String>,
+        exact_match: ExactMatch,
+    },
+    RenameResult {
+        old_key: String,
+        new_key: String,
+        result: RenameResultType,
+    },
+    /// Files selected from file picker. Frontend should check conflicts and send AddAttachments.
+    FilesSelected {
+        key: String,
+        files: Vec<String>,
+    },
+    Value {
+        key: String,
+        key_hash: String,
+        /// Content read through the store; edits go back through [`Request::Save`].
+        ///
+        /// [`Request::Save`]: crate::Request::Save
+        content: String,
+        read_only: bool,
+        attachments: Vec<AttachmentInfo>,
+    },
+    /// Files were added as attachments, sent before the updated Value. `filenames` holds the
+    /// names they were stored under after conflict resolution; skipped files are left out.
+    AttachmentsAdded {
+        key: String,
+        filenames: Vec<String>,
+    },
+    /// An attachment's thumbnail finished rendering after its Value was sent.
+    ThumbnailReady {
+        key: String,
+        filename: String,
+        thumbnail: Thumbnail,
+    },
+    /// Informational message or non-critical error.
+    Toast {
+        message: String,
+    },
+    /// Save operation failed. Frontend should offer a retry.
+    SaveFailed {
+        key: String,
+        message: String,
+    },
+    /// The search index is saved and the worker has stopped.
+    ShutdownComplete,
+}
+
+/// Exact match status for current search query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExactMatch {
+    None,
+    Active,
+    Trashed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RenameResultType {
+    Success,
+    DestinationExists,
+    InvalidKey,
+    NotFound,
+}
@@ -0,0 +1,559 @@
+use super::*;
+use common::{make_worker, search_results, settle};
+use keva_core::types::{AttachmentConflictResolution, Config, Key, LifecycleConfig};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::mpsc;
+use tempfile::TempDir;
+
+mod common {
+    use super::*;
+
+    /// Records responses; clones share the same log.
+    #[derive(Clone, Default)]
+    pub(super) struct RecordingSink {
+        pub(super) responses: Rc<RefCell<Vec<Response>>>,
+        pub(super) visible: Rc<Cell<bool>>,
+    }
+
+    impl RecordingSink {
+        pub(super) fn take(&self) -> Vec<Response> {
+            std::mem::take(&mut self.responses.borrow_mut())
+        }
+    }
+
+    impl ResponseSink for RecordingSink {
+        fn send(&self, response: Response) {
+            self.responses.borrow_mut().push(response);
+        }
+
+        fn is_visible(&self) -> bool {
+            self.visible.get()
+        }
+    }
+
+    pub(super) fn make_worker(temp: &TempDir) -> (Worker<RecordingSink>, RecordingSink) {
+        let config = Config {
+            base_path: temp.path().to_path_buf(),
+        };
+        let keva = KevaCore::open(config.clone()).unwrap();
+        let search = load_search_engine(&keva, &config.search_index_path(), Arc::new(|| {}));
+        let sink = RecordingSink::default();
+        let worker = Worker::new(
+            keva,
+            search,
+            GcConfig::from(&LifecycleConfig::default()),
+            false,
+            config.search_index_path(),
+            sink.clone(),
+        );
+        (worker, sink)
+    }
+
+    /// Ticks the search engine to completion and sends the final results.
+    pub(super) fn settle(worker: &mut Worker<RecordingSink>) {
+        while !worker.search.is_done() {
+            if !worker.search.tick() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+        worker.send_search_results();
+    }
+
+    /// The most recent SearchResults response.
+    pub(super) fn search_results(
+        responses: &[Response],
+    ) -> Option<(Vec<String>, Vec<String>, ExactMatch)> {
+        responses.iter().rev().find_map(|r| match r {
+            Response::SearchResults {
+                active_keys,
+                trashed_keys,
+                exact_match,
+            } => Some((active_keys.clone(), trashed_keys.clone(), *exact_match)),
+            _ => None,
+        })
+    }
+}
+
+mod lifecycle {
+    use super::*;
+
+    #[test]
+    fn test_webview_ready_shows_welcome_until_shown() {
+        let temp = TempDir::new().unwrap();
+        let (mut worker, sink) = make_worker(&temp);
+
+        let _ = worker.handle(Request::WebviewReady);
+        assert_eq!(
+            sink.take(),
+            vec![Response::CoreReady, Response::ShowWelcome]
+        );
+
+        worker.welcome_shown = true;
+        let _ = worker.handle(Request::WebviewReady);
+        assert_eq!(sink.take(), vec![Response::CoreReady]);
+    }
+
+    #[test]
+    fn test_run_stops_on_shutdown_and_saves_index() {
+        let temp = TempDir::new().unwrap();
+        let (worker, sink) = make_worker(&temp);
+        let (tx, rx) = mpsc::channel();
+        tx.send(Request::Create {
+            key: "a".to_string(),
+        })
+        .unwrap();
+        tx.send(Request::Shutdown).unwrap();
+        tx.send(Request::Create {
+            key: "b".to_string(),
+        })
+        .unwrap();
+
+        worker.run(rx);
+
+        let responses = sink.take();
+        assert_eq!(responses.last(), Some(&Response::ShutdownComplete));
+        assert!(!responses.iter().any(|r| matches!(
+            r,
+            Response::KeyCreated { key, .. } if key == "b"
+        )));
+        assert!(
+            Config {
+                base_path: temp.path().to_path_buf(),
+            }
+            .search_index_path()
+            .exists()
+        );
+    }
+
+    #[test]
+    fn test_run_stops_when_senders_drop() {
+        let temp = TempDir::new().unwrap();
+        let (worker, sink) = make_worker(&temp);
+        let (tx, rx) = mpsc::channel();
+        drop(tx);
+
+        worker.run(rx);
+        assert!(!sink.take().contains(&Response::ShutdownComplete));
+    }
+}
+
+mod keys {
+    use super::*;
+
+    #[test]
+    fn test_create_reports_and_updates_search() {
+        let temp = TempDir::new().unwrap();
+        let (mut worker, sink) = make_worker(&temp);
+
+        let _ = worker.handle(Request::Create {
+            key: "note".to_string(),
+        });
+        let _ = worker.handle(Request::Create {
+            key: "note".to_string(),
+        });
+        settle(&mut worker);
+
+        let responses = sink.take();
+        let created: Vec<_> = responses
+            .iter()
+            .filter_map(|r| match r {
+                Response::KeyCreated { success, .. } => Some(*success),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(created, vec![true, false]);
+        let (active, trashed, _) = search_results(&responses).unwrap();
+        assert_eq!(active, vec!["note"]);
+        assert!(trashed.is_empty());
+    }
+
+    #[test]
+    fn test_rename_results() {
+        let temp = TempDir::new().unwrap();
+        let (mut worker, sink) = make_worker(&temp);
+        for key in ["a", "b"] {
+            let _ = worker.handle(Request::Create {
+                key: key.to_string(),
+            });
+        }
+        sink.take();
+
+        let mut rename = |old: &str, new: &str, force: bool| {
+            let _ = worker.handle(Request::Rename {
+                old_key: old.to_string(),
+                new_key: new.to_string(),
+                force,
+            });
+            match sink.take().pop() {
+                Some(Response::RenameResult { result, .. }) => result,
+                other => panic!("unexpected response: {other:?}"),
+            }
+        };
+
+        assert_eq!(rename("a", "b", false), RenameResultType::DestinationExists);
+        assert_eq!(rename("a", "", false), RenameResultType::InvalidKey);
+        assert_eq!(rename("missing", "c", false), RenameResultType::NotFound);
+        assert_eq!(rename("a", "b", true), RenameResultType::Success);
+
+        let b = Key::try_from("b").unwrap();
+        assert!(
+            worker
+                .keva
+                .get(&Key::try_from("a").unwrap())
+                .unwrap()
+                .is_none()
+        );
+        assert!(worker.search.has_active(&b));
+    }
+
+    #[test]
+    fn test_trash_restore_purge_and_exact_match() {
+        let temp = TempDir::new().unwrap();
+        let (mut worker, sink) = make_worker(&temp);
+        let _ = worker.handle(Request::Create {
+            key: "note".to_string(),
+        });
+        let _ = worker.handle(Request::Search {
+            query: "note".to_string(),
+        });
+
+        let _ = worker.handle(Request::Trash {
+            key: "note".to_string(),
+        });
+        settle(&mut worker);
+        let (_, trashed, exact) = search_results(&sink.take()).unwrap();
+        assert_eq!(trashed, vec!["note"]);
+        assert_eq!(exact, ExactMatch::Trashed);
+
+        let _ = worker.handle(Request::GetValue {
+            key: "note".to_string(),
+        });
+        assert!(matches!(
+            sink.take().as_slice(),
+            [Response::Value {
+                read_only: true,
+                ..
+            }]
+        ));
+
+        let _ = worker.handle(Request::Restore {
+            key: "note".to_string(),
+        });
+        settle(&mut worker);
+        assert_eq!(search_results(&sink.take()).unwrap().2, ExactMatch::Active);
+
+        let _ = worker.handle(Request::Purge {
+            key: "note".to_string(),
+        });
+        settle(&mut worker);
+        let (active, trashed, exact) = search_results(&sink.take()).unwrap();
+        assert!(active.is_empty() && trashed.is_empty());
+        assert_eq!(exact, ExactMatch::None);
+    }
+}
+
+mod content {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_save_writes_content_and_touches() {
+        let temp = TempDir::new().unwrap();
+        let (mut worker, sink) = make_worker(&temp);
+        let _ = worker.handle(Request::Create {
+            key: "note".to_string(),
+        });
+        sink.take();
+
+        let _ = worker.handle(Request::Save {
+            key: "note".to_string(),
+            content: "# Hello".to_string(),
+        });
+        assert!(sink.take().is_empty());
+
+        let key = Key::try_from("note").unwrap();
+        let mut content = String::new();
+        worker
+            .keva
+            .open_content(&key)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "# Hello");
+        assert_eq!(
+            worker
+                .keva
+                .get(&key)
+                .unwrap()
+                .unwrap()
+                .metadata
+                .access_count,
+            1
+        );
+    }
+
+    #[test]
+    fn test_encrypted_store_reads_value_and_skips_index_snapshot() {
+        let temp = TempDir::new().unwrap();
+        let config = Config {
+            base_path: temp.path().to_path_buf(),
+        };
+        let index_path = config.search_index_path();
+        let keva = KevaCore::open_encrypted(config, "passphrase").unwrap();
+        let search = load_search_engine(&keva, &index_path, Arc::new(|| {}));
+        let sink = common::RecordingSink::default();
+        let mut worker = Worker::new(
+            keva,
+            search,
+            GcConfig::from(&LifecycleConfig::default()),
+            false,
+            index_path.clone(),
+            sink.clone(),
+        );
+        std::fs::write(&index_path, "stale").unwrap();
+
+        let _ = worker.handle(Request::Create {
+            key: "note".to_string(),
+        });
+        let _ = worker.handle(Request::Save {
+            key: "note".to_string(),
+            content: "secret".to_string(),
+        });
+        sink.take();
+        let _ = worker.handle(Request::GetValue {
+            key: "note".to_string(),
+        });
+        match sink.take().as_slice() {
+            [Response::Value { content, .. }] => assert_eq!(content, "secret"),
+            other => panic!("unexpected responses: {other:?}"),
+        }
+
+        save_search_index(&worker.keva, &worker.search, &index_path);
+        assert!(!index_path.exists());
+    }
+
+    #[test]
+    fn test_save_invalid_key_fails() {
+        let temp = TempDir::new().unwrap();
+        let (mut worker, sink) = make_worker(&temp);
+
+        let _ = worker.handle(Request::Save {
+            key: String::new(),
+            content: "x".to_string(),
+        });
+        assert!(matches!(
+            sink.take().as_slice(),
+            [Response::SaveFailed { .. }]
+        ));
+    }
+}
+
+mod attachments {
+    use super::*;
+
+    fn attachment_names(responses: &[Response]) -> Vec<String> {
+        match responses.last() {
+            Some(Response::Value { attachments, .. }) => {
+                attachments.iter().map(|a| a.filename.clone()).collect()
+            }
+            other => panic!("unexpected response: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_add_rename_remove() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("source.txt");
+        std::fs::write(&source, "data").unwrap();
+        let (mut worker, sink) = make_worker(&temp);
+        let _ = worker.handle(Request::Create {
+            key: "note".to_string(),
+        });
+        sink.take();
+
+        let _ = worker.handle(Request::AddAttachments {
+            key: "note".to_string(),
+            files: vec![(
+                source.to_string_lossy().into_owned(),
+                "a.txt".to_string(),
+                AttachmentConflictResolution::Fail,
+            )],
+        });
+        let _ = worker.handle(Request::AddFiles {
+            key: "note".to_string(),
+            files: vec![(
+                source.clone(),
+                "b.txt".to_string(),
+                AttachmentConflictResolution::Fail,
+            )],
+        });
+        assert_eq!(attachment_names(&sink.take()), vec!["a.txt", "b.txt"]);
+
+        let _ = worker.handle(Request::RenameAttachment {
+            key: "note".to_string(),
+            old_filename: "a.txt".to_string(),
+            new_filename: "b.txt".to_string(),
+            force: false,
+        });
+        assert!(matches!(sink.take().as_slice(), [Response::Toast { .. }]));
+
+        let _ = worker.handle(Request::RenameAttachment {
+            key: "note".to_string(),
+            old_filename: "a.txt".to_string(),
+            new_filename: "b.txt".to_string(),
+            force: true,
+        });
+        assert_eq!(attachment_names(&sink.take()), vec!["b.txt"]);
+
+        let _ = worker.handle(Request::RemoveAttachment {
+            key: "note".to_string(),
+            filename: "b.txt".to_string(),
+        });
+        assert!(attachment_names(&sink.take()).is_empty());
+    }
+
+    #[test]
+    fn test_thumbnail_ready_follows_value() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("dot.svg");
+        std::fs::write(
+            &source,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="8" height="8"><rect width="8" height="8"/></svg>"#,
+        )
+        .unwrap();
+        let (mut worker, sink) = make_worker(&temp);
+        let (tx, rx) = mpsc::channel();
+        worker
+            .keva
+            .set_thumbnail_callback(Some(Arc::new(move |key, filename, _| {
+                let _ = tx.send(Request::ThumbnailReady {
+                    key: key.to_string(),
+                    filename: filename.to_string(),
+                });
+            })));
+        let _ = worker.handle(Request::Create {
+            key: "note".to_string(),
+        });
+        let _ = worker.handle(Request::AddAttachments {
+            key: "note".to_string(),
+            files: vec![(
+                source.to_string_lossy().into_owned(),
+                "dot.svg".to_string(),
+                AttachmentConflictResolution::Fail,
+            )],
+        });
+        worker.keva.wait_for_thumbnails();
+        sink.take();
+
+        let _ = worker.handle(rx.try_recv().unwrap());
+        match sink.take().as_slice() {
+            [
+                Response::ThumbnailReady {
+                    key,
+                    filename,
+                    thumbnail,
+                },
+            ] => {
+                assert_eq!((key.as_str(), filename.as_str()), ("note", "dot.svg"));
+                assert_eq!(thumbnail.extension, "png");
+                assert!(thumbnail.bytes.starts_with(b"\x89PNG"));
+            }
+            other => panic!("unexpected responses: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_added_filenames_reported() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("source.txt");
+        std::fs::write(&source, "data").unwrap();
+        let (mut worker, sink) = make_worker(&temp);
+        let _ = worker.handle(Request::Create {
+            key: "note".to_string(),
+        });
+        let add = |resolution| Request::AddFiles {
+            key: "note".to_string(),
+            files: vec![(source.clone(), "a.txt".to_string(), resolution)],
+        };
+        let _ = worker.handle(add(AttachmentConflictResolution::Fail));
+        sink.take();
+
+        let _ = worker.handle(add(AttachmentConflictResolution::Rename));
+        let responses = sink.take();
+        assert_eq!(
+            responses[0],
+            Response::AttachmentsAdded {
+                key: "note".to_string(),
+                filenames: vec!["a (1).txt".to_string()],
+            }
+        );
+        assert_eq!(attachment_names(&responses), vec!["a.txt", "a (1).txt"]);
+
+        let _ = worker.handle(add(AttachmentConflictResolution::Fail));
+        assert!(matches!(sink.take().as_slice(), [Response::Toast { .. }]));
+    }
+
+    #[test]
+    fn test_add_to_invalid_key_toasts() {
+        let temp = TempDir::new().unwrap();
+        let (mut worker, sink) = make_worker(&temp);
+
+        let _ = worker.handle(Request::AddFiles {
+            key: String::new(),
+            files: vec![],
+        });
+        assert_eq!(
+            sink.take(),
+            vec![Response::Toast {
+                message: "Failed to add files: invalid key".to_string()
+            }]
+        );
+    }
+}
+
+mod maintenance {
+    use super::*;
+
+    #[test]
+    fn test_forced_maintenance_trashes_expired_keys() {
+        let temp = TempDir::new().unwrap();
+        let (mut worker, sink) = make_worker(&temp);
+        let _ = worker.handle(Request::Create {
+            key: "old".to_string(),
+        });
+        let _ = worker.handle(Request::UpdateGcConfig {
+            lifecycle: LifecycleConfig {
+                trash_ttl_days: 0,
+                ..LifecycleConfig::default()
+            },
+        });
+        sink.take();
+
+        let _ = worker.handle(Request::Maintenance { force: true });
+        settle(&mut worker);
+        let (active, trashed, _) = search_results(&sink.take()).unwrap();
+        assert!(active.is_empty());
+        assert_eq!(trashed, vec!["old"]);
+    }
+
+    #[test]
+    fn test_unforced_maintenance_skipped_after_recent_run() {
+        let temp = TempDir::new().unwrap();
+        let (mut worker, sink) = make_worker(&temp);
+        let _ = worker.handle(Request::Maintenance { force: true });
+        let _ = worker.handle(Request::Create {
+            key: "old".to_string(),
+        });
+        let _ = worker.handle(Request::UpdateGcConfig {
+            lifecycle: LifecycleConfig {
+                trash_ttl_days: 0,
+                ..LifecycleConfig::default()
+            },
+        });
+        sink.take();
+
+        let _ = worker.handle(Request::Maintenance { force: false });
+        assert!(sink.take().is_empty());
+    }
+}